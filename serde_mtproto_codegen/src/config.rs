@@ -0,0 +1,209 @@
+//! Code generation settings.
+
+use std::fs;
+use std::path::Path;
+
+use crate::error::Error;
+use crate::generator;
+use crate::naming::{DefaultNaming, Naming};
+
+
+/// How generated items are laid out in modules.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Layout {
+    /// Namespaced items go to modules named after their namespace, e.g.
+    /// `types::auth::SentCode` for `auth.sentCode`.
+    Namespaced,
+    /// All items go to the same module with the namespace prefixed to their
+    /// name, e.g. `types::AuthSentCode` for `auth.sentCode`.
+    Flat,
+}
+
+
+/// Settings of code generation which also run it.
+///
+/// The generated code consists of these modules (names are configurable
+/// with [`modules()`](#method.modules)):
+///
+/// * `types` with a struct per constructor;
+/// * `enums` with an enum per boxed type, which has a variant per
+///   constructor of that type holding its struct (boxed unless it has no
+///   fields to allow recursive types);
+/// * `functions` with a struct per function, serialized as a request.
+///
+/// If the schema has a `// LAYER N` comment, a `LAYER` constant is also
+/// generated.
+///
+/// Structs and enums derive the traits from [`derives()`](#method.derives)
+/// and carry `#[mtproto_identifiable(tl = "...")]` attributes, so
+/// `MtProtoIdentifiable` must be among them. Function structs also derive
+/// `MtProtoRemoteCall` with their result type (see
+/// [`remote_call_derive()`](#method.remote_call_derive)).
+#[derive(Clone, Debug)]
+pub struct Config<N = DefaultNaming> {
+    pub(crate) naming: N,
+    pub(crate) layout: Layout,
+    pub(crate) derives: Vec<String>,
+    pub(crate) remote_call_derive: Option<String>,
+    pub(crate) types_module: String,
+    pub(crate) enums_module: String,
+    pub(crate) functions_module: String,
+    pub(crate) skip: Vec<String>,
+    pub(crate) layers: Option<String>,
+}
+
+impl Config {
+    /// Create the default settings: the namespaced layout, `DefaultNaming`
+    /// and derives of `Clone`, `Debug`, `PartialEq`, `Serialize`,
+    /// `Deserialize`, `MtProtoIdentifiable` and `MtProtoSized` via
+    /// `serde_derive` and `serde_mtproto_derive` crates, as well as
+    /// `MtProtoRemoteCall` for functions.
+    pub fn new() -> Config {
+        Config {
+            naming: DefaultNaming,
+            layout: Layout::Namespaced,
+            derives: [
+                "Clone",
+                "Debug",
+                "PartialEq",
+                "serde_derive::Serialize",
+                "serde_derive::Deserialize",
+                "serde_mtproto_derive::MtProtoIdentifiable",
+                "serde_mtproto_derive::MtProtoSized",
+            ].iter().map(|&derive| derive.to_owned()).collect(),
+            remote_call_derive: Some("serde_mtproto_derive::MtProtoRemoteCall".to_owned()),
+            types_module: "types".to_owned(),
+            enums_module: "enums".to_owned(),
+            functions_module: "functions".to_owned(),
+            skip: Vec::new(),
+            layers: None,
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config::new()
+    }
+}
+
+impl<N: Naming> Config<N> {
+    /// Use other naming rules.
+    pub fn naming<M: Naming>(self, naming: M) -> Config<M> {
+        Config {
+            naming,
+            layout: self.layout,
+            derives: self.derives,
+            remote_call_derive: self.remote_call_derive,
+            types_module: self.types_module,
+            enums_module: self.enums_module,
+            functions_module: self.functions_module,
+            skip: self.skip,
+            layers: self.layers,
+        }
+    }
+
+    /// Use another module layout.
+    pub fn layout(self, layout: Layout) -> Config<N> {
+        Config { layout, ..self }
+    }
+
+    /// Replace the list of derived traits, given as paths like
+    /// `serde_derive::Serialize`.
+    pub fn derives<I>(self, derives: I) -> Config<N>
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        Config { derives: derives.into_iter().map(Into::into).collect(), ..self }
+    }
+
+    /// Add a derived trait to the list, e.g. `Eq`.
+    pub fn add_derive(mut self, derive: &str) -> Config<N> {
+        self.derives.push(derive.to_owned());
+        self
+    }
+
+    /// Set the path of the `MtProtoRemoteCall` derive for function structs,
+    /// which get a `#[mtproto_remote_call(returns = "...")]` attribute with
+    /// the Rust type of the function result, or don't derive it with
+    /// `None`.
+    pub fn remote_call_derive(self, derive: Option<&str>) -> Config<N> {
+        Config { remote_call_derive: derive.map(str::to_owned), ..self }
+    }
+
+    /// Rename the top-level modules for constructors, boxed types and
+    /// functions.
+    pub fn modules(self, types: &str, enums: &str, functions: &str) -> Config<N> {
+        Config {
+            types_module: types.to_owned(),
+            enums_module: enums.to_owned(),
+            functions_module: functions.to_owned(),
+            ..self
+        }
+    }
+
+    /// Don't generate code for a constructor or a function with this TL
+    /// name (e.g. `auth.sentCode`) or for all constructors of a boxed type
+    /// with this name (e.g. `auth.SentCode`).
+    ///
+    /// Definitions that use a skipped type fail to generate.
+    pub fn skip(mut self, name: &str) -> Config<N> {
+        self.skip.push(name.to_owned());
+        self
+    }
+
+    /// Annotate all constructors and functions as valid for a range of API
+    /// layers like `133..=140`, `133..` or `..=132`, so that code generated
+    /// from schemas of several layers can be registered together.
+    pub fn layers(self, layers: &str) -> Config<N> {
+        Config { layers: Some(layers.to_owned()), ..self }
+    }
+
+    /// Generate Rust code from TL schema text.
+    pub fn generate(&self, source: &str) -> Result<String, Error> {
+        let schema = serde_mtproto_tl::parse(source)?;
+        generator::generate(self, source, &schema)
+    }
+
+    /// Generate Rust code from the TL schema in the `input` file and write it
+    /// to the `output` file, e.g. from a build script.
+    ///
+    /// # Examples
+    ///
+    /// In `build.rs`:
+    ///
+    /// ```no_run
+    /// use std::env;
+    /// use std::path::Path;
+    ///
+    /// fn main() {
+    ///     let out_dir = env::var("OUT_DIR").unwrap();
+    ///
+    ///     serde_mtproto_codegen::Config::new()
+    ///         .generate_file("schema/api.tl", Path::new(&out_dir).join("api.rs"))
+    ///         .unwrap();
+    ///
+    ///     println!("cargo:rerun-if-changed=schema/api.tl");
+    /// }
+    /// ```
+    ///
+    /// And then in the crate:
+    ///
+    /// ```ignore
+    /// pub mod api {
+    ///     include!(concat!(env!("OUT_DIR"), "/api.rs"));
+    /// }
+    /// ```
+    pub fn generate_file<P, Q>(&self, input: P, output: Q) -> Result<(), Error>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+    {
+        let source = fs::read_to_string(input)?;
+        let code = self.generate(&source)?;
+        fs::write(output, code)?;
+
+        Ok(())
+    }
+}
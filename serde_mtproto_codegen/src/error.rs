@@ -0,0 +1,84 @@
+//! When generating code goes wrong.
+
+use std::error;
+use std::fmt;
+use std::io;
+
+use serde_mtproto_tl::ParseError;
+
+
+/// An error of code generation.
+#[derive(Debug)]
+pub enum Error {
+    /// Reading the schema or writing the generated code failed.
+    Io(io::Error),
+    /// The schema is malformed, stores all parse errors.
+    Parse(Vec<ParseError>),
+    /// A definition can't be represented in Rust, skip it with
+    /// [`Config::skip`](struct.Config.html#method.skip) if it's not needed.
+    Unsupported {
+        /// Name of the definition.
+        definition: String,
+        /// Why the definition is not supported.
+        reason: String,
+    },
+    /// The id of a definition is not the CRC32 of its combinator, which
+    /// usually means the definition is mistyped.
+    IdMismatch {
+        /// Name of the definition.
+        definition: String,
+        /// The id written in the schema.
+        id: u32,
+        /// The id computed from the combinator.
+        computed_id: u32,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Error::Io(ref e) => {
+                write!(f, "I/O error: {}", e)
+            },
+            Error::Parse(ref errors) => {
+                f.write_str("invalid TL schema:")?;
+                for error in errors {
+                    write!(f, "\n    {}", error)?;
+                }
+                Ok(())
+            },
+            Error::Unsupported { ref definition, ref reason } => {
+                write!(f, "cannot generate code for `{}`: {}", definition, reason)
+            },
+            Error::IdMismatch { ref definition, id, computed_id } => {
+                write!(f, "id {:#010x} of `{}` doesn't match {:#010x} computed from its combinator",
+                       id, definition, computed_id)
+            },
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        "TL code generation error"
+    }
+
+    fn cause(&self) -> Option<&dyn error::Error> {
+        match *self {
+            Error::Io(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
+impl From<Vec<ParseError>> for Error {
+    fn from(errors: Vec<ParseError>) -> Error {
+        Error::Parse(errors)
+    }
+}
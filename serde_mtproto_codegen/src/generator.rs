@@ -0,0 +1,498 @@
+use std::collections::BTreeMap;
+
+use serde_mtproto_tl::{Definition, FieldType, Schema, Section, Type, TypeParamKind};
+
+use crate::config::{Config, Layout};
+use crate::error::Error;
+use crate::naming::{Naming, escape_keyword};
+
+
+/// Boxed types which are mapped to Rust types instead of being generated,
+/// so their constructors are skipped.
+const BUILTIN_TYPES: &[&str] = &[
+    "Bool", "Bytes", "Double", "Int", "Int128", "Int256", "Long", "String", "True", "Vector",
+];
+
+/// TL types mapped to Rust types directly.
+const SIMPLE_TYPES: &[(&str, &str)] = &[
+    ("int", "i32"),
+    ("long", "i64"),
+    ("double", "f64"),
+    ("string", "String"),
+    ("bytes", "::serde_mtproto::ByteBuf"),
+    ("int128", "::serde_mtproto::Int128"),
+    ("int256", "::serde_mtproto::Int256"),
+    ("Bool", "bool"),
+    ("Int", "::serde_mtproto::Boxed<i32>"),
+    ("Long", "::serde_mtproto::Boxed<i64>"),
+    ("Double", "::serde_mtproto::Boxed<f64>"),
+    ("String", "::serde_mtproto::Boxed<String>"),
+];
+
+
+pub(crate) fn generate<N: Naming>(config: &Config<N>, source: &str, schema: &Schema) -> Result<String, Error> {
+    let is_skipped = |name: String| config.skip.contains(&name);
+
+    let mut constructors = Vec::new();
+    let mut functions = Vec::new();
+    // Boxed types in order of appearance with their constructors
+    let mut types: Vec<(String, Vec<&Definition>)> = Vec::new();
+
+    for definition in schema.definitions() {
+        if is_skipped(definition.name.to_string()) {
+            continue;
+        }
+
+        match definition.section {
+            Section::Types => {
+                let ty = definition.result.name.to_string();
+                if is_skipped(ty.clone()) || BUILTIN_TYPES.contains(&ty.as_str()) {
+                    continue;
+                }
+
+                constructors.push(definition);
+                match types.iter_mut().find(|entry| entry.0 == ty) {
+                    Some(entry) => entry.1.push(definition),
+                    None => types.push((ty, vec![definition])),
+                }
+            },
+            Section::Functions => functions.push(definition),
+        }
+    }
+
+    let generator = Generator { config, source, constructors, types };
+    let mut out = Output::default();
+
+    out.line("// This file is generated by serde_mtproto_codegen from a TL schema, do not edit.");
+
+    if let Some(layer) = schema.layer() {
+        out.separate();
+        out.line("/// The layer of the schema this code is generated from.");
+        out.line(format!("pub const LAYER: i32 = {};", layer));
+    }
+
+    let struct_entries = generator.constructors.iter().map(|&definition| Entry::Struct(definition));
+    generator.write_module(&mut out, Module::Types, struct_entries)?;
+
+    let enum_entries = generator.types.iter().map(|entry| Entry::Enum(&entry.0, &entry.1));
+    generator.write_module(&mut out, Module::Enums, enum_entries)?;
+
+    let function_entries = functions.into_iter().map(Entry::Struct);
+    generator.write_module(&mut out, Module::Functions, function_entries)?;
+
+    Ok(out.text)
+}
+
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Module {
+    Types,
+    Enums,
+    Functions,
+}
+
+/// An item to generate.
+enum Entry<'a> {
+    /// A struct for a constructor or a function.
+    Struct(&'a Definition),
+    /// An enum for a boxed type with its constructors.
+    Enum(&'a str, &'a [&'a Definition]),
+}
+
+impl<'a> Entry<'a> {
+    fn namespace(&self) -> Option<&'a str> {
+        match *self {
+            Entry::Struct(definition) => definition.name.namespace.as_ref().map(|namespace| &namespace[..]),
+            Entry::Enum(ty, _) => split_namespace(ty).0,
+        }
+    }
+}
+
+
+struct Generator<'a, N> {
+    config: &'a Config<N>,
+    source: &'a str,
+    constructors: Vec<&'a Definition>,
+    types: Vec<(String, Vec<&'a Definition>)>,
+}
+
+impl<'a, N: Naming> Generator<'a, N> {
+    fn write_module<'e, I>(&self, out: &mut Output, module: Module, entries: I) -> Result<(), Error>
+    where
+        I: Iterator<Item = Entry<'e>>,
+    {
+        // Root items go first because `None` is the least
+        let mut by_module: BTreeMap<Option<String>, Vec<Entry<'e>>> = BTreeMap::new();
+        for entry in entries {
+            let module_name = entry.namespace().and_then(|namespace| self.namespace_module(namespace));
+            by_module.entry(module_name).or_default().push(entry);
+        }
+
+        if by_module.is_empty() {
+            return Ok(());
+        }
+
+        out.separate();
+        out.line("#[allow(missing_docs)]");
+        out.open(format!("pub mod {} {{", self.module_name(module)));
+
+        for (module_name, entries) in by_module {
+            let depth = if module_name.is_some() { 2 } else { 1 };
+
+            if let Some(ref module_name) = module_name {
+                out.separate();
+                out.open(format!("pub mod {} {{", module_name));
+            }
+
+            for entry in entries {
+                out.separate();
+                match entry {
+                    Entry::Struct(definition) => self.write_struct(out, definition, depth)?,
+                    Entry::Enum(ty, constructors) => self.write_enum(out, ty, constructors, depth)?,
+                }
+            }
+
+            if module_name.is_some() {
+                out.close("}");
+            }
+        }
+
+        out.close("}");
+
+        Ok(())
+    }
+
+    fn write_struct(&self, out: &mut Output, definition: &Definition, depth: usize) -> Result<(), Error> {
+        let unsupported = |reason: String| Error::Unsupported { definition: definition.name.to_string(), reason };
+
+        let mut params = Vec::new();
+        for param in &definition.type_params {
+            match param.kind {
+                TypeParamKind::Type => params.push(param.name.as_str()),
+                TypeParamKind::Nat => {
+                    return Err(unsupported(format!("`{}:#` type parameters are not supported", param.name)));
+                },
+            }
+        }
+
+        let ident = self.struct_ident(&definition.name.namespace, &definition.name.name);
+        let self_ty = match params.len() {
+            0 => ident.clone(),
+            _ => format!("{}<{}>", ident, params.join(", ")),
+        };
+
+        let flags_fields_count = definition.fields.iter().filter(|field| field.ty == FieldType::Nat).count();
+
+        let mut fields = Vec::new();
+        for field in &definition.fields {
+            let tl_name = match field.name {
+                Some(ref name) => name,
+                None => return Err(unsupported("anonymous fields are not supported".to_owned())),
+            };
+            let name = self.field_ident(tl_name);
+
+            let mut attrs = Vec::new();
+            if name != *tl_name {
+                attrs.push(format!("#[serde(rename = \"{}\")]", tl_name));
+            }
+
+            let ty = match field.ty {
+                FieldType::Nat => format!("::serde_mtproto::Flags<{}>", self_ty),
+                FieldType::Type(ref ty) => self.rust_type(ty, depth, &params).map_err(unsupported)?,
+                FieldType::Conditional { ref flag, ref ty } => {
+                    if flags_fields_count > 1 {
+                        let flags_field = self.field_ident(&flag.field);
+                        attrs.push(format!("#[mtproto_flags(field = \"{}\", bit = {})]", flags_field, flag.bit));
+                    } else {
+                        attrs.push(format!("#[mtproto_flags(bit = {})]", flag.bit));
+                    }
+
+                    if field.is_true_flag() {
+                        "bool".to_owned()
+                    } else {
+                        format!("Option<{}>", self.rust_type(ty, depth, &params).map_err(unsupported)?)
+                    }
+                },
+                FieldType::Repeated(_) => {
+                    return Err(unsupported("repeated fields are not supported".to_owned()));
+                },
+            };
+
+            fields.push((attrs, name, ty));
+        }
+
+        let returns = match (definition.section, &self.config.remote_call_derive) {
+            (Section::Functions, Some(derive)) => {
+                Some((derive, self.return_type(definition, depth, &params).map_err(unsupported)?))
+            },
+            _ => None,
+        };
+
+        let computed_id = definition.computed_id();
+        if let Some(id) = definition.id.filter(|&id| id != computed_id) {
+            return Err(Error::IdMismatch { definition: definition.name.to_string(), id, computed_id });
+        }
+
+        let text = self.definition_text(definition);
+
+        out.line(format!("/// `{}`", text));
+        match returns {
+            Some((derive, _)) => {
+                let derives = self.config.derives.iter().chain(Some(derive)).cloned().collect::<Vec<_>>();
+                out.line(format!("#[derive({})]", derives.join(", ")));
+            },
+            None => self.write_derives(out),
+        }
+        out.line(self.identifiable_attr("tl", &text));
+        if let Some((_, return_type)) = returns {
+            out.line(format!("#[mtproto_remote_call(returns = \"{}\")]", return_type));
+        }
+
+        if fields.is_empty() {
+            out.line(format!("pub struct {};", self_ty));
+            return Ok(());
+        }
+
+        out.open(format!("pub struct {} {{", self_ty));
+        for (attrs, name, ty) in fields {
+            for attr in attrs {
+                out.line(attr);
+            }
+            out.line(format!("pub {}: {},", name, ty));
+        }
+        out.close("}");
+
+        Ok(())
+    }
+
+    fn write_enum(&self, out: &mut Output, ty: &str, constructors: &[&Definition], depth: usize) -> Result<(), Error> {
+        let (namespace, name) = split_namespace(ty);
+        let ident = escape_keyword(self.item_name(namespace, name, N::enum_name));
+
+        out.line(format!("/// `{}`", ty));
+        self.write_derives(out);
+        out.open(format!("pub enum {} {{", ident));
+
+        for definition in constructors {
+            if !definition.type_params.is_empty() {
+                let reason = format!("constructors of generic boxed type `{}` are not supported", ty);
+                return Err(Error::Unsupported { definition: definition.name.to_string(), reason });
+            }
+
+            let variant = escape_keyword(self.config.naming.variant_name(&definition.name.name, name));
+            let id = definition.computed_id();
+            let path = self.struct_path(definition, depth);
+
+            out.line(self.identifiable_attr("id", &format!("{:#010x}", id)));
+            if definition.fields.is_empty() {
+                out.line(format!("{}({}),", variant, path));
+            } else {
+                out.line(format!("{}(Box<{}>),", variant, path));
+            }
+        }
+
+        out.close("}");
+
+        Ok(())
+    }
+
+    fn identifiable_attr(&self, key: &str, value: &str) -> String {
+        match self.config.layers {
+            Some(ref layers) => format!("#[mtproto_identifiable({} = \"{}\", layers = \"{}\")]", key, value, layers),
+            None => format!("#[mtproto_identifiable({} = \"{}\")]", key, value),
+        }
+    }
+
+    fn write_derives(&self, out: &mut Output) {
+        if !self.config.derives.is_empty() {
+            out.line(format!("#[derive({})]", self.config.derives.join(", ")));
+        }
+    }
+
+    /// Map a TL type to a Rust type relative to a module at `depth` or
+    /// return why it's impossible.
+    fn rust_type(&self, ty: &Type, depth: usize, params: &[&str]) -> Result<String, String> {
+        let name = ty.name.to_string();
+        let is_param = ty.name.namespace.is_none() && params.contains(&ty.name.name.as_str());
+
+        if ty.generic_ref || is_param {
+            return if is_param && ty.args.is_empty() {
+                Ok(ty.name.name.clone())
+            } else {
+                Err(format!("`{}` is not a type parameter", ty))
+            };
+        }
+
+        if name == "Vector" || name == "vector" {
+            let element = match ty.args.len() {
+                1 => self.rust_type(&ty.args[0], depth, params)?,
+                _ => return Err(format!("`{}` must have exactly one type argument", ty)),
+            };
+
+            return Ok(if ty.bare {
+                format!("Vec<{}>", element)
+            } else {
+                format!("::serde_mtproto::Boxed<Vec<{}>>", element)
+            });
+        }
+
+        if !ty.args.is_empty() {
+            return Err(format!("type arguments of `{}` are not supported", ty));
+        }
+
+        if let Some(&(_, rust_type)) = SIMPLE_TYPES.iter().find(|&&(tl_type, _)| tl_type == name) {
+            return Ok(rust_type.to_owned());
+        }
+
+        if name == "true" || name == "True" {
+            return Err(format!("`{}` is only supported in `flags.N?true` fields", ty));
+        }
+
+        if ty.bare && ty.name.name.starts_with(|c: char| c.is_ascii_lowercase()) {
+            return match self.constructors.iter().find(|definition| definition.name.to_string() == name) {
+                Some(definition) => Ok(self.struct_path(definition, depth)),
+                None => Err(format!("unknown constructor `{}`", name)),
+            };
+        }
+
+        let constructors = match self.types.iter().find(|entry| entry.0 == name) {
+            Some(entry) => &entry.1,
+            None => return Err(format!("unknown type `{}`", name)),
+        };
+
+        if ty.bare {
+            return match constructors.len() {
+                1 => Ok(self.struct_path(constructors[0], depth)),
+                _ => Err(format!("bare type `{}` has several constructors", ty)),
+            };
+        }
+
+        let (namespace, type_name) = split_namespace(&name);
+        let ident = escape_keyword(self.item_name(namespace, type_name, N::enum_name));
+
+        Ok(format!("::serde_mtproto::Boxed<{}>", self.path(Module::Enums, namespace, &ident, depth)))
+    }
+
+    /// Map the result type of a function to the `Return` type of its
+    /// `RemoteCall` implementation.
+    fn return_type(&self, definition: &Definition, depth: usize, params: &[&str]) -> Result<String, String> {
+        let result = &definition.result;
+
+        // `{X:Type} query:!X = X` returns whatever the wrapped query returns
+        if result.name.namespace.is_none() && result.args.is_empty() && params.contains(&result.name.name.as_str()) {
+            return Ok(format!("{}::Return", result.name.name));
+        }
+
+        self.rust_type(result, depth, &[])
+    }
+
+    /// The definition text with whitespace collapsed.
+    fn definition_text(&self, definition: &Definition) -> String {
+        let text = &self.source[definition.span.start..definition.span.end];
+        text.split_whitespace().collect::<Vec<_>>().join(" ")
+    }
+
+    fn struct_path(&self, definition: &Definition, depth: usize) -> String {
+        let module = match definition.section {
+            Section::Types => Module::Types,
+            Section::Functions => Module::Functions,
+        };
+        let namespace = definition.name.namespace.as_ref().map(|namespace| &namespace[..]);
+        let ident = self.struct_ident(&definition.name.namespace, &definition.name.name);
+
+        self.path(module, namespace, &ident, depth)
+    }
+
+    /// Path to an item from a module at `depth`.
+    fn path(&self, module: Module, namespace: Option<&str>, ident: &str, depth: usize) -> String {
+        let mut path = "super::".repeat(depth);
+        path.push_str(&self.module_name(module));
+
+        if let Some(module_name) = namespace.and_then(|namespace| self.namespace_module(namespace)) {
+            path.push_str("::");
+            path.push_str(&module_name);
+        }
+
+        path.push_str("::");
+        path.push_str(ident);
+        path
+    }
+
+    fn struct_ident(&self, namespace: &Option<String>, name: &str) -> String {
+        let namespace = namespace.as_ref().map(|namespace| &namespace[..]);
+        escape_keyword(self.item_name(namespace, name, N::struct_name))
+    }
+
+    fn field_ident(&self, name: &str) -> String {
+        escape_keyword(self.config.naming.field_name(name))
+    }
+
+    /// Name an item, prefixing the namespace in the flat layout.
+    fn item_name(&self, namespace: Option<&str>, name: &str, naming: fn(&N, &str) -> String) -> String {
+        match (self.config.layout, namespace) {
+            (Layout::Flat, Some(namespace)) => naming(&self.config.naming, &format!("{}_{}", namespace, name)),
+            _ => naming(&self.config.naming, name),
+        }
+    }
+
+    /// Module of a namespace in the namespaced layout.
+    fn namespace_module(&self, namespace: &str) -> Option<String> {
+        match self.config.layout {
+            Layout::Namespaced => Some(escape_keyword(self.config.naming.module_name(namespace))),
+            Layout::Flat => None,
+        }
+    }
+
+    fn module_name(&self, module: Module) -> String {
+        match module {
+            Module::Types => self.config.types_module.clone(),
+            Module::Enums => self.config.enums_module.clone(),
+            Module::Functions => self.config.functions_module.clone(),
+        }
+    }
+}
+
+
+fn split_namespace(name: &str) -> (Option<&str>, &str) {
+    match name.find('.') {
+        Some(index) => (Some(&name[..index]), &name[index + 1..]),
+        None => (None, name),
+    }
+}
+
+
+/// Generated code with indentation.
+#[derive(Default)]
+struct Output {
+    text: String,
+    indent: usize,
+}
+
+impl Output {
+    fn line<S: AsRef<str>>(&mut self, line: S) {
+        for _ in 0..self.indent {
+            self.text.push_str("    ");
+        }
+        self.text.push_str(line.as_ref());
+        self.text.push('\n');
+    }
+
+    /// Write a line ending with `{` and indent the following ones.
+    fn open<S: AsRef<str>>(&mut self, line: S) {
+        self.line(line);
+        self.indent += 1;
+    }
+
+    /// Unindent and write a line starting with `}`.
+    fn close<S: AsRef<str>>(&mut self, line: S) {
+        self.indent -= 1;
+        self.line(line);
+    }
+
+    /// Separate items with an empty line unless at the start of a block.
+    fn separate(&mut self) {
+        if !self.text.is_empty() && !self.text.ends_with("{\n") && !self.text.ends_with("\n\n") {
+            self.text.push('\n');
+        }
+    }
+}
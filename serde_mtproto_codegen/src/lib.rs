@@ -0,0 +1,61 @@
+//! # Serde MTProto Codegen
+//!
+//! This crate generates Rust types for `serde_mtproto` from
+//! [TL schemas](https://core.telegram.org/mtproto/TL), which is meant to be
+//! done from a build script (see [`Config::generate_file`]).
+//!
+//! TL definitions are mapped as follows:
+//!
+//! * a constructor becomes a struct in the `types` module;
+//! * a boxed type becomes an enum in the `enums` module with a variant per
+//!   constructor;
+//! * a function becomes a struct in the `functions` module which implements
+//!   `RemoteCall` with its result type;
+//! * `flags:#` becomes `Flags<Self>`, `flags.N?T` becomes `Option<T>` and
+//!   `flags.N?true` becomes `bool`;
+//! * `Vector<T>` becomes `Boxed<Vec<T>>` and `vector<T>` becomes `Vec<T>`;
+//! * a boxed type `T` becomes `Boxed<enums::T>` and a bare one becomes the
+//!   struct of its constructor;
+//! * `int`, `long`, `double`, `string`, `bytes`, `int128`, `int256` and
+//!   `Bool` become `i32`, `i64`, `f64`, `String`, `ByteBuf`, `Int128`,
+//!   `Int256` and `bool`;
+//! * `{X:Type}` type parameters become generic parameters.
+//!
+//! Naming, derives, module layout and the range of API layers generated
+//! items are valid for are configurable with [`Config`].
+//!
+//! [`Config`]: struct.Config.html
+//! [`Config::generate_file`]: struct.Config.html#method.generate_file
+//!
+//! # Examples
+//!
+//! ```
+//! let code = serde_mtproto_codegen::Config::new().generate("
+//!     peerUser#59511722 user_id:long = Peer;
+//!     peerChat#36c6019a chat_id:long = Peer;
+//! ").unwrap();
+//!
+//! assert!(code.contains("pub struct PeerUser {"));
+//! assert!(code.contains("pub enum Peer {"));
+//! assert!(code.contains("User(Box<super::types::PeerUser>),"));
+//! ```
+
+#![warn(
+    missing_debug_implementations,
+    missing_docs,
+    rust_2018_idioms,
+    trivial_casts,
+    trivial_numeric_casts,
+    unused_import_braces,
+)]
+
+
+mod config;
+mod error;
+mod generator;
+mod naming;
+
+
+pub use crate::config::{Config, Layout};
+pub use crate::error::Error;
+pub use crate::naming::{DefaultNaming, Naming, snake_case, upper_camel_case};
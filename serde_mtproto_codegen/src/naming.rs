@@ -0,0 +1,112 @@
+//! How TL names become Rust names.
+
+
+/// Rules for naming generated items.
+///
+/// Names are passed without namespaces, which become modules in the
+/// [`Layout::Namespaced`](enum.Layout.html#variant.Namespaced) layout. In
+/// the [`Layout::Flat`](enum.Layout.html#variant.Flat) layout namespaced
+/// names are passed as `namespace_name` instead, e.g. `auth_sentCode`.
+///
+/// Names which turn out to be Rust keywords get a `_` appended.
+pub trait Naming {
+    /// Name of the struct for a constructor or a function, e.g. `PeerUser`
+    /// for `peerUser`.
+    fn struct_name(&self, name: &str) -> String {
+        upper_camel_case(name)
+    }
+
+    /// Name of the enum for a boxed type, e.g. `Peer` for `Peer`.
+    fn enum_name(&self, name: &str) -> String {
+        upper_camel_case(name)
+    }
+
+    /// Name of the enum variant for a constructor of a boxed type, e.g.
+    /// `User` for `peerUser` of `Peer`.
+    ///
+    /// By default the type name is stripped from the beginning of the
+    /// constructor name unless nothing meaningful is left.
+    fn variant_name(&self, constructor: &str, ty: &str) -> String {
+        let constructor = upper_camel_case(constructor);
+        let ty = upper_camel_case(ty);
+
+        if constructor.starts_with(&ty) && constructor[ty.len()..].starts_with(|c: char| c.is_ascii_uppercase()) {
+            constructor[ty.len()..].to_owned()
+        } else {
+            constructor
+        }
+    }
+
+    /// Name of a struct field for a TL field, e.g. `user_id` for
+    /// `user_id`.
+    fn field_name(&self, name: &str) -> String {
+        snake_case(name)
+    }
+
+    /// Name of the module for a namespace, e.g. `auth` for `auth`.
+    fn module_name(&self, namespace: &str) -> String {
+        snake_case(namespace)
+    }
+}
+
+
+/// The default naming rules: `UpperCamelCase` for types and variants and
+/// `snake_case` for fields and modules.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct DefaultNaming;
+
+impl Naming for DefaultNaming {}
+
+
+/// Convert `peerUser` or `msgs_ack` to `PeerUser` or `MsgsAck`.
+pub fn upper_camel_case(name: &str) -> String {
+    let mut result = String::with_capacity(name.len());
+
+    for word in name.split('_') {
+        let mut chars = word.chars();
+        if let Some(first) = chars.next() {
+            result.push(first.to_ascii_uppercase());
+            result.extend(chars);
+        }
+    }
+
+    result
+}
+
+/// Convert `userId` or `user_id` to `user_id`.
+pub fn snake_case(name: &str) -> String {
+    let mut result = String::with_capacity(name.len());
+    let mut prev_lowercase = false;
+
+    for c in name.chars() {
+        if c.is_ascii_uppercase() {
+            if prev_lowercase {
+                result.push('_');
+            }
+            result.push(c.to_ascii_lowercase());
+        } else {
+            result.push(c);
+        }
+
+        prev_lowercase = c.is_ascii_lowercase() || c.is_ascii_digit();
+    }
+
+    result
+}
+
+/// Append `_` to Rust keywords.
+pub(crate) fn escape_keyword(name: String) -> String {
+    const KEYWORDS: &[&str] = &[
+        "abstract", "as", "async", "await", "become", "box", "break", "const", "continue", "crate", "do", "dyn",
+        "else", "enum", "extern", "false", "final", "fn", "for", "if", "impl", "in", "let", "loop", "macro",
+        "match", "mod", "move", "mut", "override", "priv", "pub", "ref", "return", "self", "Self", "static",
+        "struct", "super", "trait", "true", "try", "type", "typeof", "unsafe", "unsized", "use", "virtual",
+        "where", "while", "yield",
+    ];
+
+    if KEYWORDS.contains(&name.as_str()) {
+        name + "_"
+    } else {
+        name
+    }
+}
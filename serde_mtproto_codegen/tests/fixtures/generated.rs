@@ -0,0 +1,239 @@
+// This file is generated by serde_mtproto_codegen from a TL schema, do not edit.
+
+/// The layer of the schema this code is generated from.
+pub const LAYER: i32 = 1;
+
+#[allow(missing_docs)]
+pub mod types {
+    /// `peerUser#59511722 user_id:long = Peer`
+    #[derive(Clone, Debug, PartialEq, serde_derive::Serialize, serde_derive::Deserialize, serde_mtproto_derive::MtProtoIdentifiable, serde_mtproto_derive::MtProtoSized)]
+    #[mtproto_identifiable(tl = "peerUser#59511722 user_id:long = Peer")]
+    pub struct PeerUser {
+        pub user_id: i64,
+    }
+
+    /// `peerChat#36c6019a chat_id:long = Peer`
+    #[derive(Clone, Debug, PartialEq, serde_derive::Serialize, serde_derive::Deserialize, serde_mtproto_derive::MtProtoIdentifiable, serde_mtproto_derive::MtProtoSized)]
+    #[mtproto_identifiable(tl = "peerChat#36c6019a chat_id:long = Peer")]
+    pub struct PeerChat {
+        pub chat_id: i64,
+    }
+
+    /// `inputPeerEmpty#7f3b18ea = InputPeer`
+    #[derive(Clone, Debug, PartialEq, serde_derive::Serialize, serde_derive::Deserialize, serde_mtproto_derive::MtProtoIdentifiable, serde_mtproto_derive::MtProtoSized)]
+    #[mtproto_identifiable(tl = "inputPeerEmpty#7f3b18ea = InputPeer")]
+    pub struct InputPeerEmpty;
+
+    /// `inputPeerUser#dde8a54c user_id:long access_hash:long = InputPeer`
+    #[derive(Clone, Debug, PartialEq, serde_derive::Serialize, serde_derive::Deserialize, serde_mtproto_derive::MtProtoIdentifiable, serde_mtproto_derive::MtProtoSized)]
+    #[mtproto_identifiable(tl = "inputPeerUser#dde8a54c user_id:long access_hash:long = InputPeer")]
+    pub struct InputPeerUser {
+        pub user_id: i64,
+        pub access_hash: i64,
+    }
+
+    /// `userEmpty#d3bc4b7a id:long = User`
+    #[derive(Clone, Debug, PartialEq, serde_derive::Serialize, serde_derive::Deserialize, serde_mtproto_derive::MtProtoIdentifiable, serde_mtproto_derive::MtProtoSized)]
+    #[mtproto_identifiable(tl = "userEmpty#d3bc4b7a id:long = User")]
+    pub struct UserEmpty {
+        pub id: i64,
+    }
+
+    /// `fileHash#f39b035c offset:long limit:int hash:bytes = FileHash`
+    #[derive(Clone, Debug, PartialEq, serde_derive::Serialize, serde_derive::Deserialize, serde_mtproto_derive::MtProtoIdentifiable, serde_mtproto_derive::MtProtoSized)]
+    #[mtproto_identifiable(tl = "fileHash#f39b035c offset:long limit:int hash:bytes = FileHash")]
+    pub struct FileHash {
+        pub offset: i64,
+        pub limit: i32,
+        pub hash: ::serde_mtproto::ByteBuf,
+    }
+
+    /// `dialog#81a3f886 flags:# pinned:flags.2?true peer:Peer flags2:# folder_id:flags2.1?int unread_count:flags.0?int = Dialog`
+    #[derive(Clone, Debug, PartialEq, serde_derive::Serialize, serde_derive::Deserialize, serde_mtproto_derive::MtProtoIdentifiable, serde_mtproto_derive::MtProtoSized)]
+    #[mtproto_identifiable(tl = "dialog#81a3f886 flags:# pinned:flags.2?true peer:Peer flags2:# folder_id:flags2.1?int unread_count:flags.0?int = Dialog")]
+    pub struct Dialog {
+        pub flags: ::serde_mtproto::Flags<Dialog>,
+        #[mtproto_flags(field = "flags", bit = 2)]
+        pub pinned: bool,
+        pub peer: ::serde_mtproto::Boxed<super::enums::Peer>,
+        pub flags2: ::serde_mtproto::Flags<Dialog>,
+        #[mtproto_flags(field = "flags2", bit = 1)]
+        pub folder_id: Option<i32>,
+        #[mtproto_flags(field = "flags", bit = 0)]
+        pub unread_count: Option<i32>,
+    }
+
+    /// `pageBlockCover#39f23300 cover:PageBlock = PageBlock`
+    #[derive(Clone, Debug, PartialEq, serde_derive::Serialize, serde_derive::Deserialize, serde_mtproto_derive::MtProtoIdentifiable, serde_mtproto_derive::MtProtoSized)]
+    #[mtproto_identifiable(tl = "pageBlockCover#39f23300 cover:PageBlock = PageBlock")]
+    pub struct PageBlockCover {
+        pub cover: ::serde_mtproto::Boxed<super::enums::PageBlock>,
+    }
+
+    /// `pageBlockDivider#db20b188 = PageBlock`
+    #[derive(Clone, Debug, PartialEq, serde_derive::Serialize, serde_derive::Deserialize, serde_mtproto_derive::MtProtoIdentifiable, serde_mtproto_derive::MtProtoSized)]
+    #[mtproto_identifiable(tl = "pageBlockDivider#db20b188 = PageBlock")]
+    pub struct PageBlockDivider;
+
+    pub mod auth {
+        /// `auth.sentCode#5e002502 flags:# type:auth.SentCodeType phone_code_hash:string next_type:flags.1?auth.CodeType timeout:flags.2?int = auth.SentCode`
+        #[derive(Clone, Debug, PartialEq, serde_derive::Serialize, serde_derive::Deserialize, serde_mtproto_derive::MtProtoIdentifiable, serde_mtproto_derive::MtProtoSized)]
+        #[mtproto_identifiable(tl = "auth.sentCode#5e002502 flags:# type:auth.SentCodeType phone_code_hash:string next_type:flags.1?auth.CodeType timeout:flags.2?int = auth.SentCode")]
+        pub struct SentCode {
+            pub flags: ::serde_mtproto::Flags<SentCode>,
+            #[serde(rename = "type")]
+            pub type_: ::serde_mtproto::Boxed<super::super::enums::auth::SentCodeType>,
+            pub phone_code_hash: String,
+            #[mtproto_flags(bit = 1)]
+            pub next_type: Option<::serde_mtproto::Boxed<super::super::enums::auth::CodeType>>,
+            #[mtproto_flags(bit = 2)]
+            pub timeout: Option<i32>,
+        }
+
+        /// `auth.sentCodeTypeApp#3dbb5986 length:int = auth.SentCodeType`
+        #[derive(Clone, Debug, PartialEq, serde_derive::Serialize, serde_derive::Deserialize, serde_mtproto_derive::MtProtoIdentifiable, serde_mtproto_derive::MtProtoSized)]
+        #[mtproto_identifiable(tl = "auth.sentCodeTypeApp#3dbb5986 length:int = auth.SentCodeType")]
+        pub struct SentCodeTypeApp {
+            pub length: i32,
+        }
+
+        /// `auth.codeTypeSms#72a3158c = auth.CodeType`
+        #[derive(Clone, Debug, PartialEq, serde_derive::Serialize, serde_derive::Deserialize, serde_mtproto_derive::MtProtoIdentifiable, serde_mtproto_derive::MtProtoSized)]
+        #[mtproto_identifiable(tl = "auth.codeTypeSms#72a3158c = auth.CodeType")]
+        pub struct CodeTypeSms;
+
+        /// `auth.codeTypeCall#741cd3e3 = auth.CodeType`
+        #[derive(Clone, Debug, PartialEq, serde_derive::Serialize, serde_derive::Deserialize, serde_mtproto_derive::MtProtoIdentifiable, serde_mtproto_derive::MtProtoSized)]
+        #[mtproto_identifiable(tl = "auth.codeTypeCall#741cd3e3 = auth.CodeType")]
+        pub struct CodeTypeCall;
+    }
+
+    pub mod messages {
+        /// `messages.peers peers:Vector<Peer> ids:vector<long> = messages.Peers`
+        #[derive(Clone, Debug, PartialEq, serde_derive::Serialize, serde_derive::Deserialize, serde_mtproto_derive::MtProtoIdentifiable, serde_mtproto_derive::MtProtoSized)]
+        #[mtproto_identifiable(tl = "messages.peers peers:Vector<Peer> ids:vector<long> = messages.Peers")]
+        pub struct Peers {
+            pub peers: ::serde_mtproto::Boxed<Vec<::serde_mtproto::Boxed<super::super::enums::Peer>>>,
+            pub ids: Vec<i64>,
+        }
+    }
+}
+
+#[allow(missing_docs)]
+pub mod enums {
+    /// `Peer`
+    #[derive(Clone, Debug, PartialEq, serde_derive::Serialize, serde_derive::Deserialize, serde_mtproto_derive::MtProtoIdentifiable, serde_mtproto_derive::MtProtoSized)]
+    pub enum Peer {
+        #[mtproto_identifiable(id = "0x59511722")]
+        User(Box<super::types::PeerUser>),
+        #[mtproto_identifiable(id = "0x36c6019a")]
+        Chat(Box<super::types::PeerChat>),
+    }
+
+    /// `InputPeer`
+    #[derive(Clone, Debug, PartialEq, serde_derive::Serialize, serde_derive::Deserialize, serde_mtproto_derive::MtProtoIdentifiable, serde_mtproto_derive::MtProtoSized)]
+    pub enum InputPeer {
+        #[mtproto_identifiable(id = "0x7f3b18ea")]
+        Empty(super::types::InputPeerEmpty),
+        #[mtproto_identifiable(id = "0xdde8a54c")]
+        User(Box<super::types::InputPeerUser>),
+    }
+
+    /// `User`
+    #[derive(Clone, Debug, PartialEq, serde_derive::Serialize, serde_derive::Deserialize, serde_mtproto_derive::MtProtoIdentifiable, serde_mtproto_derive::MtProtoSized)]
+    pub enum User {
+        #[mtproto_identifiable(id = "0xd3bc4b7a")]
+        Empty(Box<super::types::UserEmpty>),
+    }
+
+    /// `FileHash`
+    #[derive(Clone, Debug, PartialEq, serde_derive::Serialize, serde_derive::Deserialize, serde_mtproto_derive::MtProtoIdentifiable, serde_mtproto_derive::MtProtoSized)]
+    pub enum FileHash {
+        #[mtproto_identifiable(id = "0xf39b035c")]
+        FileHash(Box<super::types::FileHash>),
+    }
+
+    /// `Dialog`
+    #[derive(Clone, Debug, PartialEq, serde_derive::Serialize, serde_derive::Deserialize, serde_mtproto_derive::MtProtoIdentifiable, serde_mtproto_derive::MtProtoSized)]
+    pub enum Dialog {
+        #[mtproto_identifiable(id = "0x81a3f886")]
+        Dialog(Box<super::types::Dialog>),
+    }
+
+    /// `PageBlock`
+    #[derive(Clone, Debug, PartialEq, serde_derive::Serialize, serde_derive::Deserialize, serde_mtproto_derive::MtProtoIdentifiable, serde_mtproto_derive::MtProtoSized)]
+    pub enum PageBlock {
+        #[mtproto_identifiable(id = "0x39f23300")]
+        Cover(Box<super::types::PageBlockCover>),
+        #[mtproto_identifiable(id = "0xdb20b188")]
+        Divider(super::types::PageBlockDivider),
+    }
+
+    pub mod auth {
+        /// `auth.SentCode`
+        #[derive(Clone, Debug, PartialEq, serde_derive::Serialize, serde_derive::Deserialize, serde_mtproto_derive::MtProtoIdentifiable, serde_mtproto_derive::MtProtoSized)]
+        pub enum SentCode {
+            #[mtproto_identifiable(id = "0x5e002502")]
+            SentCode(Box<super::super::types::auth::SentCode>),
+        }
+
+        /// `auth.SentCodeType`
+        #[derive(Clone, Debug, PartialEq, serde_derive::Serialize, serde_derive::Deserialize, serde_mtproto_derive::MtProtoIdentifiable, serde_mtproto_derive::MtProtoSized)]
+        pub enum SentCodeType {
+            #[mtproto_identifiable(id = "0x3dbb5986")]
+            App(Box<super::super::types::auth::SentCodeTypeApp>),
+        }
+
+        /// `auth.CodeType`
+        #[derive(Clone, Debug, PartialEq, serde_derive::Serialize, serde_derive::Deserialize, serde_mtproto_derive::MtProtoIdentifiable, serde_mtproto_derive::MtProtoSized)]
+        pub enum CodeType {
+            #[mtproto_identifiable(id = "0x72a3158c")]
+            Sms(super::super::types::auth::CodeTypeSms),
+            #[mtproto_identifiable(id = "0x741cd3e3")]
+            Call(super::super::types::auth::CodeTypeCall),
+        }
+    }
+
+    pub mod messages {
+        /// `messages.Peers`
+        #[derive(Clone, Debug, PartialEq, serde_derive::Serialize, serde_derive::Deserialize, serde_mtproto_derive::MtProtoIdentifiable, serde_mtproto_derive::MtProtoSized)]
+        pub enum Peers {
+            #[mtproto_identifiable(id = "0x4687aa4d")]
+            Peers(Box<super::super::types::messages::Peers>),
+        }
+    }
+}
+
+#[allow(missing_docs)]
+pub mod functions {
+    /// `invokeWithLayer#da9b0d0d {X:Type} layer:int query:!X = X`
+    #[derive(Clone, Debug, PartialEq, serde_derive::Serialize, serde_derive::Deserialize, serde_mtproto_derive::MtProtoIdentifiable, serde_mtproto_derive::MtProtoSized, serde_mtproto_derive::MtProtoRemoteCall)]
+    #[mtproto_identifiable(tl = "invokeWithLayer#da9b0d0d {X:Type} layer:int query:!X = X")]
+    #[mtproto_remote_call(returns = "X::Return")]
+    pub struct InvokeWithLayer<X> {
+        pub layer: i32,
+        pub query: X,
+    }
+
+    pub mod auth {
+        /// `auth.sendCode#af95bca8 phone_number:string api_id:int api_hash:string = auth.SentCode`
+        #[derive(Clone, Debug, PartialEq, serde_derive::Serialize, serde_derive::Deserialize, serde_mtproto_derive::MtProtoIdentifiable, serde_mtproto_derive::MtProtoSized, serde_mtproto_derive::MtProtoRemoteCall)]
+        #[mtproto_identifiable(tl = "auth.sendCode#af95bca8 phone_number:string api_id:int api_hash:string = auth.SentCode")]
+        #[mtproto_remote_call(returns = "::serde_mtproto::Boxed<super::super::enums::auth::SentCode>")]
+        pub struct SendCode {
+            pub phone_number: String,
+            pub api_id: i32,
+            pub api_hash: String,
+        }
+    }
+
+    pub mod users {
+        /// `users.getUsers#590eeada id:Vector<InputPeer> = Vector<User>`
+        #[derive(Clone, Debug, PartialEq, serde_derive::Serialize, serde_derive::Deserialize, serde_mtproto_derive::MtProtoIdentifiable, serde_mtproto_derive::MtProtoSized, serde_mtproto_derive::MtProtoRemoteCall)]
+        #[mtproto_identifiable(tl = "users.getUsers#590eeada id:Vector<InputPeer> = Vector<User>")]
+        #[mtproto_remote_call(returns = "::serde_mtproto::Boxed<Vec<::serde_mtproto::Boxed<super::super::enums::User>>>")]
+        pub struct GetUsers {
+            pub id: ::serde_mtproto::Boxed<Vec<::serde_mtproto::Boxed<super::super::enums::InputPeer>>>,
+        }
+    }
+}
@@ -0,0 +1,169 @@
+//! Testing code generation from TL schemas.
+
+use std::env;
+use std::fs;
+use std::path::Path;
+
+use pretty_assertions::assert_eq;
+use serde_mtproto::{Boxed, Flags, Identifiable, from_bytes, to_bytes};
+use serde_mtproto::rpc::return_from_bytes;
+use serde_mtproto_codegen::{Config, Error, Layout, Naming, upper_camel_case};
+
+
+#[allow(dead_code)]
+mod generated {
+    include!("fixtures/generated.rs");
+}
+
+use crate::generated::{enums, functions, types};
+
+
+const SCHEMA: &str = include_str!("fixtures/schema.tl");
+
+/// Set this environment variable to overwrite the fixture with the
+/// generated code after changing the generator.
+const UPDATE_ENV_VAR: &str = "SERDE_MTPROTO_CODEGEN_UPDATE_FIXTURES";
+
+
+#[test]
+fn test_fixture_is_up_to_date() {
+    let code = Config::new().generate(SCHEMA).unwrap();
+    let fixture_path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/generated.rs");
+
+    if env::var_os(UPDATE_ENV_VAR).is_some() {
+        fs::write(&fixture_path, &code).unwrap();
+    }
+
+    let fixture = fs::read_to_string(&fixture_path).unwrap();
+    assert!(code == fixture, "generated code differs from the fixture, rerun tests with {} set", UPDATE_ENV_VAR);
+}
+
+#[test]
+fn test_generated_types() {
+    assert_eq!(generated::LAYER, 1);
+    assert_eq!(types::PeerUser::all_type_ids(), &[0x59511722]);
+    assert_eq!(enums::Peer::all_type_ids(), &[0x59511722, 0x36c6019a]);
+    assert_eq!(enums::auth::CodeType::all_type_ids(), &[0x72a3158c, 0x741cd3e3]);
+    assert_eq!(types::messages::Peers::all_type_ids(), &[0x4687aa4d]);
+    assert_eq!(types::FileHash::all_type_ids(), &[0xf39b035c]);
+
+    let dialog = enums::Dialog::Dialog(Box::new(types::Dialog {
+        flags: Flags::new(),
+        pinned: true,
+        peer: Boxed::new(enums::Peer::User(Box::new(types::PeerUser { user_id: 7 }))),
+        flags2: Flags::new(),
+        folder_id: Some(1),
+        unread_count: None,
+    }));
+
+    let bytes = to_bytes(&Boxed::new(dialog.clone())).unwrap();
+    assert_eq!(bytes, vec![
+        0x86, 0xf8, 0xa3, 0x81,         // id of dialog
+        0b100, 0, 0, 0,                 // flags: only `pinned` is set
+        0x22, 0x17, 0x51, 0x59,         // id of peerUser
+        7, 0, 0, 0, 0, 0, 0, 0,         // user_id
+        0b10, 0, 0, 0,                  // flags2: only `folder_id` is set
+        1, 0, 0, 0,                     // folder_id
+    ]);
+
+    let deserialized: Boxed<enums::Dialog> = from_bytes(&bytes, &[]).unwrap();
+    assert_eq!(deserialized.into_inner(), dialog);
+}
+
+#[test]
+fn test_generated_functions() {
+    let query = functions::InvokeWithLayer {
+        layer: generated::LAYER,
+        query: Boxed::new(functions::auth::SendCode {
+            phone_number: "1".to_owned(),
+            api_id: 2,
+            api_hash: "3".to_owned(),
+        }),
+    };
+
+    let bytes = to_bytes(&Boxed::new(query)).unwrap();
+    assert_eq!(bytes, vec![
+        0x0d, 0x0d, 0x9b, 0xda,         // id of invokeWithLayer
+        1, 0, 0, 0,                     // layer
+        0xa8, 0xbc, 0x95, 0xaf,         // id of auth.sendCode
+        1, b'1', 0, 0,                  // phone_number
+        2, 0, 0, 0,                     // api_id
+        1, b'3', 0, 0,                  // api_hash
+    ]);
+
+    let users = Boxed::new(vec![Boxed::new(enums::User::Empty(Box::new(types::UserEmpty { id: 5 })))]);
+    let bytes = to_bytes(&users).unwrap();
+    let returned = return_from_bytes::<functions::InvokeWithLayer<Boxed<functions::users::GetUsers>>>(&bytes);
+    assert_eq!(returned.unwrap(), users);
+}
+
+#[test]
+fn test_layout_and_naming() {
+    struct Prefixed;
+
+    impl Naming for Prefixed {
+        fn struct_name(&self, name: &str) -> String {
+            format!("Tl{}", upper_camel_case(name))
+        }
+    }
+
+    let code = Config::new()
+        .naming(Prefixed)
+        .layout(Layout::Flat)
+        .derives(vec!["Debug", "MtProtoIdentifiable"])
+        .add_derive("Eq")
+        .modules("constructors", "boxed", "requests")
+        .generate(SCHEMA)
+        .unwrap();
+
+    assert!(code.contains("    #[derive(Debug, MtProtoIdentifiable, Eq)]\n"));
+    assert!(code.contains("\npub mod constructors {\n"));
+    assert!(code.contains("    pub struct TlAuthSentCode {\n"));
+    assert!(code.contains("    pub enum AuthSentCode {\n"));
+    assert!(code.contains("        SentCode(Box<super::constructors::TlAuthSentCode>),\n"));
+    assert!(code.contains("        pub next_type: Option<::serde_mtproto::Boxed<super::boxed::AuthCodeType>>,\n"));
+    assert!(code.contains("    pub struct TlInvokeWithLayer<X> {\n"));
+    assert!(code.contains("    #[derive(Debug, MtProtoIdentifiable, Eq, serde_mtproto_derive::MtProtoRemoteCall)]\n"));
+    assert!(code.contains("    #[mtproto_remote_call(returns = \"X::Return\")]\n"));
+    assert!(!code.contains("pub mod auth"));
+
+    let code = Config::new().remote_call_derive(None).generate(SCHEMA).unwrap();
+    assert!(!code.contains("RemoteCall") && !code.contains("mtproto_remote_call"));
+
+    let code = Config::new().layers("1..=2").generate(SCHEMA).unwrap();
+    assert!(code.contains("    #[mtproto_identifiable(tl = \"peerUser#59511722 user_id:long = Peer\", layers = \"1..=2\")]\n"));
+    assert!(code.contains("        #[mtproto_identifiable(id = \"0x59511722\", layers = \"1..=2\")]\n"));
+}
+
+#[test]
+fn test_errors() {
+    let unsupported = |error| match error {
+        Error::Unsupported { definition, reason } => format!("{}: {}", definition, reason),
+        other => panic!("unexpected error: {:?}", other),
+    };
+
+    let schema = "peerUser#59511722 user_id:long = Peer; foo x:Bar = Foo;";
+    let error = Config::new().generate(schema).unwrap_err();
+    assert_eq!(error.to_string(), "cannot generate code for `foo`: unknown type `Bar`");
+    assert!(Config::new().skip("foo").generate(schema).is_ok());
+    assert!(Config::new().skip("Foo").generate(schema).is_ok());
+
+    let error = Config::new().generate("ints n:# v:n*[ int ] = Ints;").unwrap_err();
+    assert!(match error { Error::Parse(ref errors) => errors.len() == 1, _ => false });
+
+    let error = Config::new().generate("vec {t:Type} # [ t ] = Vec t;").unwrap_err();
+    assert_eq!(unsupported(error), "vec: anonymous fields are not supported");
+
+    let error = Config::new().generate("a = A; b = B; ab x:%A y:%Foo = AB;").unwrap_err();
+    assert_eq!(unsupported(error), "ab: unknown type `Foo`");
+
+    let error = Config::new().generate("a1 = A; a2 = A; ab x:%A = AB;").unwrap_err();
+    assert_eq!(unsupported(error), "ab: bare type `%A` has several constructors");
+
+    let error = Config::new().generate("---functions--- getFoo = Foo;").unwrap_err();
+    assert_eq!(unsupported(error), "getFoo: unknown type `Foo`");
+    assert!(Config::new().remote_call_derive(None).generate("---functions--- getFoo = Foo;").is_ok());
+
+    let error = Config::new().generate("peerUser#59511723 user_id:long = Peer;").unwrap_err();
+    assert_eq!(error.to_string(), "id 0x59511723 of `peerUser` doesn't match 0x59511722 computed from its combinator");
+}
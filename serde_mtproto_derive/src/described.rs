@@ -0,0 +1,185 @@
+use quote::{ToTokens, quote};
+
+use crate::ast;
+use crate::ext::IteratorResultExt;
+use crate::flags::{get_flag_attr, is_true_flag_field, serde_name, type_ident_is};
+use crate::identifiable::{get_id_from_attrs, get_layers_from_attrs, get_tl_name_from_attrs};
+
+
+pub(crate) fn impl_derive(mut container: ast::Container) -> proc_macro2::TokenStream {
+    match impl_derive_or_error(&mut container) {
+        Ok(tokens) => tokens,
+        Err(e) => e.iter().map(syn::Error::to_compile_error).collect(),
+    }
+}
+
+fn impl_derive_or_error(
+    container: &mut ast::Container,
+) -> Result<proc_macro2::TokenStream, Vec<syn::Error>> {
+    add_tl_described_trait_bounds(container);
+    let (item_impl_generics, item_ty_generics, item_where_clause) =
+        container.generics.split_for_impl();
+
+    let item_name = &container.ident;
+    let dummy_const = ident!("_IMPL_MT_PROTO_DESCRIBED_FOR_{}", item_name);
+
+    let (tl_type_body, constructors) = match container.data {
+        ast::Data::Struct(ref data_struct) => {
+            let id = get_id_from_attrs(&container.attrs, &data_struct.fields, (&*container).into_token_stream())
+                .map_err(|e| vec![e])?;
+            let constructor = constructor(&item_name.to_string(), id, &container.attrs, &data_struct.fields)?;
+
+            (quote!(_serde_mtproto::registry::TlType::Bare(#id)), vec![constructor])
+        },
+        ast::Data::Enum(ref data_enum) => {
+            // Bare enums don't identify their variants, so they are only
+            // described as boxed values
+            let constructors = data_enum.variants.iter().map(|variant| {
+                let id = get_id_from_attrs(&variant.attrs, &variant.fields, variant.into_token_stream())
+                    .map_err(|e| vec![e])?;
+
+                constructor(&variant.ident.to_string(), id, &variant.attrs, &variant.fields)
+            }).collect_results()
+                .map_err(|errors| errors.into_iter().flatten().collect::<Vec<_>>())?;
+
+            (quote!(_serde_mtproto::registry::TlType::Boxed), constructors)
+        },
+    };
+
+    Ok(quote! {
+        #[allow(non_upper_case_globals)]
+        const #dummy_const: () = {
+            extern crate serde_mtproto as _serde_mtproto;
+
+            impl #item_impl_generics _serde_mtproto::registry::TlDescribed for #item_name #item_ty_generics
+                #item_where_clause
+            {
+                fn tl_type() -> _serde_mtproto::registry::TlType {
+                    #tl_type_body
+                }
+
+                fn tl_constructors() -> Vec<_serde_mtproto::registry::Constructor> {
+                    vec![#(#constructors),*]
+                }
+            }
+        };
+    })
+}
+
+
+/// Describe a constructor, named after the TL combinator if it's given and
+/// after the Rust item otherwise.
+fn constructor(
+    rust_name: &str,
+    id: u32,
+    attrs: &[syn::Attribute],
+    fields: &syn::Fields,
+) -> Result<proc_macro2::TokenStream, Vec<syn::Error>> {
+    let name = get_tl_name_from_attrs(attrs).unwrap_or_else(|| rust_name.to_owned());
+    let (layers_start, layers_end) = get_layers_from_attrs(attrs).map_err(|e| vec![e])?;
+    let layers_start = quote_option(layers_start);
+    let layers_end = quote_option(layers_end);
+
+    // Serde names of flags fields: (Rust name, Serde name)
+    let flags_fields = fields.iter()
+        .filter(|f| type_ident_is(&f.ty, "Flags"))
+        .filter_map(|f| f.ident.as_ref().map(|ident| (ident.to_string(), serde_name(f))))
+        .collect::<Vec<_>>();
+
+    let fields = fields.iter()
+        .enumerate()
+        .filter(|&(_, f)| !is_serde_skipped_field(f))
+        .map(|(i, f)| field(i, f, &flags_fields))
+        .collect_results()?;
+
+    Ok(quote! {
+        _serde_mtproto::registry::Constructor {
+            name: #name.to_owned(),
+            id: #id,
+            fields: vec![#(#fields),*],
+            layers: _serde_mtproto::layer::LayerRange::new(#layers_start, #layers_end),
+        }
+    })
+}
+
+fn quote_option<T: quote::ToTokens>(value: Option<T>) -> proc_macro2::TokenStream {
+    match value {
+        Some(value) => quote!(Some(#value)),
+        None => quote!(None),
+    }
+}
+
+fn field(
+    index: usize,
+    field: &syn::Field,
+    flags_fields: &[(String, String)],
+) -> syn::Result<proc_macro2::TokenStream> {
+    let name = match field.ident {
+        Some(_) => serde_name(field),
+        None => index.to_string(),
+    };
+
+    let ty = if is_true_flag_field(field) {
+        quote!(_serde_mtproto::registry::TlType::True)
+    } else {
+        let field_ty = &field.ty;
+        let func = quote_spanned_by! {field=>
+            <#field_ty as _serde_mtproto::registry::TlDescribed>::tl_type
+        };
+
+        quote!(#func())
+    };
+
+    let attr = match get_flag_attr(field)? {
+        Some(attr) => attr,
+        None => return Ok(quote!(_serde_mtproto::registry::Field::new(#name, #ty))),
+    };
+
+    let flags_field = match attr.flags_field {
+        Some(ref flags_field) => flags_fields.iter().find(|f| f.0 == *flags_field),
+        None => flags_fields.first(),
+    };
+
+    let flags_field_serde_name = match flags_field {
+        Some(flags_field) => &flags_field.1,
+        None => {
+            return Err(syn::Error::new_spanned(
+                field,
+                "conditional fields must be bound to a `Flags` field of the same constructor",
+            ));
+        },
+    };
+
+    if attr.bit >= 32 {
+        return Err(syn::Error::new_spanned(field, "flags bit must be in 0..32 range"));
+    }
+
+    let bit = attr.bit as u32;
+
+    Ok(quote!(_serde_mtproto::registry::Field::conditional(#name, #ty, #flags_field_serde_name, #bit)))
+}
+
+fn add_tl_described_trait_bounds(container: &mut ast::Container) {
+    for param in &mut container.generics.params {
+        if let syn::GenericParam::Type(ref mut type_param) = *param {
+            type_param.bounds.push(syn::parse_quote!(_serde_mtproto::registry::TlDescribed));
+        }
+    }
+}
+
+pub(crate) fn is_serde_skipped_field(field: &syn::Field) -> bool {
+    control_flow_chain! {
+        for attr in &field.attrs;
+        if let syn::AttrStyle::Outer = attr.style;
+        if let Ok(syn::Meta::List(list)) = attr.parse_meta();
+        if list.ident == "serde";
+        for nested_meta in list.nested;
+        if let syn::NestedMeta::Meta(syn::Meta::Word(ident)) = nested_meta;
+        if ident == "skip";
+        then {
+            return true;
+        }
+    }
+
+    false
+}
@@ -0,0 +1,252 @@
+use quote::quote;
+
+use crate::ast;
+use crate::ext::IteratorResultExt;
+
+
+/// A parsed `#[mtproto_flags(...)]` attribute of a conditional field.
+pub(crate) struct FlagAttr {
+    pub(crate) flags_field: Option<String>,
+    pub(crate) bit: u64,
+}
+
+
+pub(crate) fn impl_flags_layout(
+    container: &ast::Container,
+) -> Result<proc_macro2::TokenStream, Vec<syn::Error>> {
+    let has_flags_fields = match container.data {
+        ast::Data::Struct(ref data_struct) => has_flags_fields(&data_struct.fields),
+        ast::Data::Enum(ref data_enum) => data_enum.variants.iter().any(|v| has_flags_fields(&v.fields)),
+    };
+
+    let entries = match container.data {
+        ast::Data::Struct(ref data_struct) => {
+            layout_entries(&data_struct.fields, None)?
+        },
+        ast::Data::Enum(ref data_enum) => {
+            let entries = data_enum.variants
+                .iter()
+                .map(|v| layout_entries(&v.fields, Some(&variant_serde_name(v))))
+                .collect_results()
+                .map_err(|errors| errors.into_iter().flatten().collect::<Vec<_>>())?;
+
+            entries.into_iter().flatten().collect()
+        },
+    };
+
+    // Conditional fields without a flags field are reported by `layout_entries`
+    if !has_flags_fields {
+        return Ok(proc_macro2::TokenStream::new());
+    }
+
+    let (item_impl_generics, item_ty_generics, item_where_clause) =
+        container.generics.split_for_impl();
+
+    let item_name = &container.ident;
+    let dummy_const = ident!("_IMPL_MT_PROTO_FLAGS_LAYOUT_FOR_{}", item_name);
+
+    Ok(quote! {
+        #[allow(non_upper_case_globals)]
+        const #dummy_const: () = {
+            extern crate serde_mtproto as _serde_mtproto;
+
+            impl #item_impl_generics _serde_mtproto::FlagsLayout for #item_name #item_ty_generics
+                #item_where_clause
+            {
+                fn flags_layout() -> &'static [&'static str] {
+                    &[#(#entries),*]
+                }
+            }
+        };
+    })
+}
+
+/// Whether the field corresponds to a `flags.N?true` TL field, i.e. is
+/// represented by a flags bit only.
+pub(crate) fn is_true_flag_field(field: &syn::Field) -> bool {
+    has_flag_attr(field) && type_ident_is(&field.ty, "bool")
+}
+
+
+fn layout_entries(
+    fields: &syn::Fields,
+    variant: Option<&str>,
+) -> Result<Vec<proc_macro2::Literal>, Vec<syn::Error>> {
+    let named = match *fields {
+        syn::Fields::Named(ref named) => named,
+        syn::Fields::Unnamed(ref unnamed) => {
+            return unnamed.unnamed.iter()
+                .filter(|f| has_flag_attr(f) || type_ident_is(&f.ty, "Flags"))
+                .map(|f| Err(syn::Error::new_spanned(
+                    f,
+                    "flags and conditional fields are only supported for named fields",
+                )))
+                .collect_results();
+        },
+        syn::Fields::Unit => return Ok(Vec::new()),
+    };
+
+    // Flags fields seen so far: (Rust name, Serde name)
+    let mut flags_fields = Vec::new();
+    let all_flags_count = named.named.iter().filter(|f| type_ident_is(&f.ty, "Flags")).count();
+
+    let mut entries = Vec::new();
+    let mut errors = Vec::new();
+
+    for field in &named.named {
+        let ident = field.ident.as_ref().expect("named fields must have identifiers");
+
+        if type_ident_is(&field.ty, "Flags") {
+            flags_fields.push((ident.to_string(), serde_name(field)));
+            continue;
+        }
+
+        let attr = match get_flag_attr(field) {
+            Ok(Some(attr)) => attr,
+            Ok(None) => continue,
+            Err(e) => {
+                errors.push(e);
+                continue;
+            },
+        };
+
+        if !type_ident_is(&field.ty, "Option") && !type_ident_is(&field.ty, "bool") {
+            errors.push(syn::Error::new_spanned(
+                &field.ty,
+                "conditional fields must be either `Option<T>` for `flags.N?T` or `bool` for `flags.N?true`",
+            ));
+            continue;
+        }
+
+        if attr.bit >= 32 {
+            errors.push(syn::Error::new_spanned(field, "flags bit must be in 0..32 range"));
+            continue;
+        }
+
+        let flags_field = match attr.flags_field {
+            Some(ref name) => flags_fields.iter().find(|f| f.0 == *name),
+            None if all_flags_count > 1 => {
+                errors.push(syn::Error::new_spanned(
+                    field,
+                    "there are several flags fields, choose one with `#[mtproto_flags(field = \"...\", bit = N)]`",
+                ));
+                continue;
+            },
+            None => flags_fields.first(),
+        };
+
+        let flags_field_serde_name = match flags_field {
+            Some(flags_field) => &flags_field.1,
+            None => {
+                errors.push(syn::Error::new_spanned(
+                    field,
+                    "conditional fields must be preceded by the `Flags` field they are bound to",
+                ));
+                continue;
+            },
+        };
+
+        let entry = match variant {
+            Some(variant) => format!("{}::{}:{}.{}", variant, serde_name(field), flags_field_serde_name, attr.bit),
+            None => format!("{}:{}.{}", serde_name(field), flags_field_serde_name, attr.bit),
+        };
+
+        entries.push(proc_macro2::Literal::string(&entry));
+    }
+
+    match errors.len() {
+        0 => Ok(entries),
+        _ => Err(errors),
+    }
+}
+
+fn has_flags_fields(fields: &syn::Fields) -> bool {
+    fields.iter().any(|f| type_ident_is(&f.ty, "Flags"))
+}
+
+fn has_flag_attr(field: &syn::Field) -> bool {
+    field.attrs.iter().any(|attr| attr.path.is_ident("mtproto_flags"))
+}
+
+pub(crate) fn get_flag_attr(field: &syn::Field) -> syn::Result<Option<FlagAttr>> {
+    let attr = match field.attrs.iter().find(|attr| attr.path.is_ident("mtproto_flags")) {
+        Some(attr) => attr,
+        None => return Ok(None),
+    };
+
+    const ERROR_MESSAGE: &str =
+        "expected `#[mtproto_flags(bit = N)]` or `#[mtproto_flags(field = \"...\", bit = N)]`";
+
+    let list = match attr.parse_meta() {
+        Ok(syn::Meta::List(list)) => list,
+        _ => return Err(syn::Error::new_spanned(attr, ERROR_MESSAGE)),
+    };
+
+    let mut flags_field = None;
+    let mut bit = None;
+
+    for nested_meta in &list.nested {
+        match *nested_meta {
+            syn::NestedMeta::Meta(syn::Meta::NameValue(ref name_value)) => {
+                match name_value.lit {
+                    syn::Lit::Int(ref lit_int) if name_value.ident == "bit" => {
+                        bit = Some(lit_int.value());
+                    },
+                    syn::Lit::Str(ref lit_str) if name_value.ident == "field" => {
+                        flags_field = Some(lit_str.value());
+                    },
+                    _ => return Err(syn::Error::new_spanned(name_value, ERROR_MESSAGE)),
+                }
+            },
+            _ => return Err(syn::Error::new_spanned(nested_meta, ERROR_MESSAGE)),
+        }
+    }
+
+    match bit {
+        Some(bit) => Ok(Some(FlagAttr { flags_field, bit })),
+        None => Err(syn::Error::new_spanned(attr, ERROR_MESSAGE)),
+    }
+}
+
+/// Get the name Serde uses for the field, taking `#[serde(rename = "...")]`
+/// into account.
+pub(crate) fn serde_name(field: &syn::Field) -> String {
+    serde_rename(&field.attrs)
+        .unwrap_or_else(|| field.ident.as_ref().expect("named fields must have identifiers").to_string())
+}
+
+/// Get the name Serde uses for the enum variant, taking
+/// `#[serde(rename = "...")]` into account.
+pub(crate) fn variant_serde_name(variant: &syn::Variant) -> String {
+    serde_rename(&variant.attrs).unwrap_or_else(|| variant.ident.to_string())
+}
+
+fn serde_rename(attrs: &[syn::Attribute]) -> Option<String> {
+    control_flow_chain! {
+        for attr in attrs;
+        if let syn::AttrStyle::Outer = attr.style;
+        if let Ok(syn::Meta::List(list)) = attr.parse_meta();
+        if list.ident == "serde";
+        for nested_meta in list.nested;
+        if let syn::NestedMeta::Meta(syn::Meta::NameValue(name_value)) = nested_meta;
+        if name_value.ident == "rename";
+        if let syn::Lit::Str(lit_str) = name_value.lit;
+        then {
+            return Some(lit_str.value());
+        }
+    }
+
+    None
+}
+
+pub(crate) fn type_ident_is(ty: &syn::Type, name: &str) -> bool {
+    match *ty {
+        syn::Type::Path(ref type_path) => {
+            match type_path.path.segments.iter().last() {
+                Some(segment) => segment.ident == name,
+                None => false,
+            }
+        },
+        _ => false,
+    }
+}
@@ -1,7 +1,9 @@
 use quote::{ToTokens, quote};
 
 use crate::ast;
+use crate::described::is_serde_skipped_field;
 use crate::ext::IteratorResultExt;
+use crate::flags::{get_flag_attr, is_true_flag_field, serde_name, type_ident_is, variant_serde_name};
 
 
 pub(crate) fn impl_derive(container: ast::Container) -> proc_macro2::TokenStream {
@@ -21,23 +23,24 @@ fn impl_derive_or_error(
 
     let dummy_const = ident!("_IMPL_MT_PROTO_IDENTIFIABLE_FOR_{}", item_name);
 
-    let all_type_ids_value = match container.data {
-        ast::Data::Struct(_) => {
-            let id = get_id_from_attrs(&container.attrs, (&container).into_token_stream())
+    let ids = match container.data {
+        ast::Data::Struct(ref data_struct) => {
+            let id = get_id_from_attrs(&container.attrs, &data_struct.fields, (&container).into_token_stream())
                 .map_err(|e| vec![e])?;
 
-            quote!(&[#id])
+            vec![id]
         },
         ast::Data::Enum(ref data_enum) => {
-            let ids = data_enum.variants
+            data_enum.variants
                 .iter()
-                .map(|v| get_id_from_attrs(&v.attrs, v.into_token_stream()))
-                .collect_results()?;
-
-            quote!(&[#(#ids),*])
+                .map(|v| get_id_from_attrs(&v.attrs, &v.fields, v.into_token_stream()))
+                .collect_results()?
         },
     };
 
+    let all_type_ids = ids.iter();
+    let all_type_ids_value = quote!(&[#(#all_type_ids),*]);
+
     let all_enum_variant_names_value = match container.data {
         ast::Data::Struct(_) => {
             quote!(None)
@@ -45,7 +48,7 @@ fn impl_derive_or_error(
         ast::Data::Enum(ref data_enum) => {
             let names = data_enum.variants
                 .iter()
-                .map(|v| proc_macro2::Literal::string(&v.ident.to_string()));
+                .map(|v| proc_macro2::Literal::string(&variant_serde_name(v)));
 
             quote!(Some(&[#(#names),*]))
         },
@@ -53,15 +56,15 @@ fn impl_derive_or_error(
 
     let type_id_body = match container.data {
         ast::Data::Struct(_) => {
-            let id = get_asserted_id_from_attrs(&container.attrs, (&container).into_token_stream())
+            let id = get_asserted_id(ids[0], &container.attrs)
                 .map_err(|e| vec![e])?;
 
             quote!(#id)
         },
         ast::Data::Enum(ref data_enum) => {
-            let variants = data_enum.variants.iter().map(|variant| {
+            let variants = data_enum.variants.iter().zip(&ids).map(|(variant, &id)| {
                 let variant_name = &variant.ident;
-                let id = get_asserted_id_from_attrs(&variant.attrs, variant.into_token_stream())?;
+                let id = get_asserted_id(id, &variant.attrs)?;
 
                 Ok(quote! {
                     #item_name::#variant_name { .. } => #id,
@@ -76,6 +79,8 @@ fn impl_derive_or_error(
         },
     };
 
+    let flags_layout_impl = crate::flags::impl_flags_layout(&container)?;
+
     let enum_variant_id_body = match container.data {
         ast::Data::Struct(_) => {
             quote! { None }
@@ -83,7 +88,7 @@ fn impl_derive_or_error(
         ast::Data::Enum(ref data_enum) => {
             let variants = data_enum.variants.iter().map(|variant| {
                 let variant_name = &variant.ident;
-                let variant_name_string = proc_macro2::Literal::string(&variant_name.to_string());
+                let variant_name_string = proc_macro2::Literal::string(&variant_serde_name(variant));
 
                 quote! {
                     #item_name::#variant_name { .. } => #variant_name_string,
@@ -125,15 +130,13 @@ fn impl_derive_or_error(
                 }
             }
         };
+
+        #flags_layout_impl
     })
 }
 
 
-fn get_asserted_id_from_attrs(
-    attrs: &[syn::Attribute],
-    input_tokens: proc_macro2::TokenStream,
-) -> syn::Result<proc_macro2::TokenStream> {
-    let id = get_id_from_attrs(attrs, input_tokens)?;
+fn get_asserted_id(id: u32, attrs: &[syn::Attribute]) -> syn::Result<proc_macro2::TokenStream> {
     let check_expr = quote!(Self::all_type_ids().contains(&#id));
 
     control_flow_chain! {
@@ -168,10 +171,23 @@ fn get_asserted_id_from_attrs(
     Ok(quote!({ assert!(#check_expr); #id }))
 }
 
-fn get_id_from_attrs(
+/// Get the id from either `#[mtproto_identifiable(id = "...")]` or
+/// `#[mtproto_identifiable(tl = "...")]` attribute.
+///
+/// For the latter the id is computed from the TL combinator and checked
+/// against the explicit id in the combinator and the `id` attribute if
+/// there are any, and TL fields are checked against `fields`.
+pub(crate) fn get_id_from_attrs(
     attrs: &[syn::Attribute],
+    fields: &syn::Fields,
     input_tokens: proc_macro2::TokenStream,
 ) -> syn::Result<u32> {
+    // Layers are not a part of the id, but are checked along with it
+    get_layers_from_attrs(attrs)?;
+
+    let mut id = None;
+    let mut tl = None;
+
     control_flow_chain! {
         for attr in attrs;
         if let syn::AttrStyle::Outer = attr.style;
@@ -179,38 +195,268 @@ fn get_id_from_attrs(
         if list.ident == "mtproto_identifiable";
         for nested_meta in list.nested;
         if let syn::NestedMeta::Meta(syn::Meta::NameValue(name_value)) = nested_meta;
-        if name_value.ident == "id";
+        if name_value.ident == "id" || name_value.ident == "tl";
         then {
-            if let syn::Lit::Str(lit_str) = name_value.lit {
-                // Found an identifier
-                let str_value = lit_str.value();
-
-                if str_value.len() >= 2 {
-                    match str_value.split_at(2) {
-                        ("0x", hex) => return Ok(u32::from_str_radix(hex, 16).unwrap()),
-                        ("0b", bin) => return Ok(u32::from_str_radix(bin, 2).unwrap()),
-                        ("0o", oct) => return Ok(u32::from_str_radix(oct, 8).unwrap()),
-                        _ => (),
-                    }
-                }
-
-                return Ok(u32::from_str_radix(&str_value, 10).unwrap());
+            let lit_str = match name_value.lit {
+                syn::Lit::Str(lit_str) => lit_str,
+                lit => {
+                    let msg = format!(
+                        "expected mtproto {} attribute to be a string: `{} = \"...\"`",
+                        name_value.ident,
+                        name_value.ident,
+                    );
+
+                    return Err(syn::Error::new_spanned(lit, msg));
+                },
+            };
+
+            if name_value.ident == "id" {
+                id = Some((parse_id(&lit_str)?, lit_str));
             } else {
-                return Err(syn::Error::new_spanned(
-                    name_value.lit,
-                    "expected mtproto id attribute to be a string: `id = \"...\"`",
-                ));
+                tl = Some(lit_str);
             }
         }
     }
 
+    match (id, tl) {
+        (Some((id, _)), None) => return Ok(id),
+        (id, Some(tl)) => return get_id_from_tl(&tl, id, fields),
+        (None, None) => (),
+    }
+
     const ERROR_MESSAGE: &str = "\
         #[derive(MtProtoIdentifiable)] requires an #[mtproto_identifiable(id = \"...\")] attribute\n    \
         where id can can be either:\n    \
         - hexadecimal with 0x prefix,\n    \
         - binary with 0b,\n    \
         - octal with 0o\n    \
-        - or decimal with no prefix.";
+        - or decimal with no prefix,\n    \
+        or an #[mtproto_identifiable(tl = \"...\")] attribute with a TL combinator\n    \
+        like `message#beefdead id:int text:string = Message` to compute the id from.";
 
     Err(syn::Error::new_spanned(input_tokens, ERROR_MESSAGE))
 }
+
+fn parse_id(lit_str: &syn::LitStr) -> syn::Result<u32> {
+    let str_value = lit_str.value();
+
+    let res = if str_value.len() >= 2 {
+        match str_value.split_at(2) {
+            ("0x", hex) => u32::from_str_radix(hex, 16),
+            ("0b", bin) => u32::from_str_radix(bin, 2),
+            ("0o", oct) => u32::from_str_radix(oct, 8),
+            _ => str_value.parse(),
+        }
+    } else {
+        str_value.parse()
+    };
+
+    res.map_err(|e| syn::Error::new_spanned(lit_str, format!("invalid mtproto id {:?}: {}", str_value, e)))
+}
+
+/// Get the range of API layers from the
+/// `#[mtproto_identifiable(layers = "...")]` attribute as its inclusive
+/// bounds, which are `None` if the attribute or a bound is missing.
+pub(crate) fn get_layers_from_attrs(attrs: &[syn::Attribute]) -> syn::Result<(Option<i32>, Option<i32>)> {
+    control_flow_chain! {
+        for attr in attrs;
+        if let syn::AttrStyle::Outer = attr.style;
+        if let Ok(syn::Meta::List(list)) = attr.parse_meta();
+        if list.ident == "mtproto_identifiable";
+        for nested_meta in list.nested;
+        if let syn::NestedMeta::Meta(syn::Meta::NameValue(name_value)) = nested_meta;
+        if name_value.ident == "layers";
+        then {
+            return match name_value.lit {
+                syn::Lit::Str(ref lit_str) => parse_layers(lit_str),
+                ref lit => {
+                    let msg = "expected mtproto layers attribute to be a string: `layers = \"...\"`";
+                    Err(syn::Error::new_spanned(lit, msg))
+                },
+            };
+        }
+    }
+
+    Ok((None, None))
+}
+
+fn parse_layers(lit_str: &syn::LitStr) -> syn::Result<(Option<i32>, Option<i32>)> {
+    let str_value = lit_str.value();
+
+    let parse_bound = |bound: &str| match bound.trim() {
+        "" => Ok(None),
+        bound => bound.parse().map(Some).map_err(|_| ()),
+    };
+
+    let res = match str_value.find("..") {
+        Some(index) => {
+            let (start, end) = (&str_value[..index], &str_value[index + 2..]);
+
+            if end.is_empty() {
+                parse_bound(start).map(|start| (start, None))
+            } else if end.starts_with('=') && !end[1..].trim().is_empty() {
+                parse_bound(start).and_then(|start| parse_bound(&end[1..]).map(|end| (start, end)))
+            } else {
+                Err(())
+            }
+        },
+        None => parse_bound(&str_value).and_then(|layer| layer.map(|layer| (Some(layer), Some(layer))).ok_or(())),
+    };
+
+    match res {
+        Ok((Some(start), Some(end))) if start > end => {
+            let msg = format!("invalid mtproto layers {:?}: the range is empty", str_value);
+            Err(syn::Error::new_spanned(lit_str, msg))
+        },
+        Ok(layers) => Ok(layers),
+        Err(()) => {
+            let msg = format!("invalid mtproto layers {:?}: expected `N`, `N..`, `..=M` or `N..=M`", str_value);
+            Err(syn::Error::new_spanned(lit_str, msg))
+        },
+    }
+}
+
+/// Get the name of the TL combinator from the
+/// `#[mtproto_identifiable(tl = "...")]` attribute, if it's there and valid.
+pub(crate) fn get_tl_name_from_attrs(attrs: &[syn::Attribute]) -> Option<String> {
+    control_flow_chain! {
+        for attr in attrs;
+        if let syn::AttrStyle::Outer = attr.style;
+        if let Ok(syn::Meta::List(list)) = attr.parse_meta();
+        if list.ident == "mtproto_identifiable";
+        for nested_meta in list.nested;
+        if let syn::NestedMeta::Meta(syn::Meta::NameValue(name_value)) = nested_meta;
+        if name_value.ident == "tl";
+        if let syn::Lit::Str(lit_str) = name_value.lit;
+        then {
+            return serde_mtproto_tl::parse_definition(&lit_str.value(), serde_mtproto_tl::Section::Types)
+                .ok()
+                .map(|definition| definition.name.to_string());
+        }
+    }
+
+    None
+}
+
+fn get_id_from_tl(
+    lit_str: &syn::LitStr,
+    id: Option<(u32, syn::LitStr)>,
+    fields: &syn::Fields,
+) -> syn::Result<u32> {
+    let definition = serde_mtproto_tl::parse_definition(&lit_str.value(), serde_mtproto_tl::Section::Types)
+        .map_err(|e| syn::Error::new_spanned(lit_str, format!("invalid TL combinator: {}", e)))?;
+
+    let computed_id = definition.computed_id();
+
+    if let Some(tl_id) = definition.id {
+        if tl_id != computed_id {
+            let msg = format!(
+                "TL combinator id {:#010x} doesn't match {:#010x} computed from `{}`",
+                tl_id,
+                computed_id,
+                definition.combinator(),
+            );
+
+            return Err(syn::Error::new_spanned(lit_str, msg));
+        }
+    }
+
+    if let Some((id, id_lit_str)) = id {
+        if id != computed_id {
+            let msg = format!("id {:#010x} doesn't match {:#010x} computed from the TL combinator", id, computed_id);
+            return Err(syn::Error::new_spanned(id_lit_str, msg));
+        }
+    }
+
+    check_tl_fields(lit_str, &definition, fields)?;
+
+    Ok(computed_id)
+}
+
+/// Check that TL fields correspond to Rust fields in order: names (unless
+/// either is anonymous), flags fields and conditional fields with their
+/// flags field and bit.
+fn check_tl_fields(
+    lit_str: &syn::LitStr,
+    definition: &serde_mtproto_tl::Definition,
+    fields: &syn::Fields,
+) -> syn::Result<()> {
+    use serde_mtproto_tl::FieldType;
+
+    let rust_fields = fields.iter().filter(|f| !is_serde_skipped_field(f)).collect::<Vec<_>>();
+
+    if rust_fields.len() != definition.fields.len() {
+        let msg = format!(
+            "TL combinator has {} fields, but there are {} fields to serialize",
+            definition.fields.len(),
+            rust_fields.len(),
+        );
+
+        return Err(syn::Error::new_spanned(lit_str, msg));
+    }
+
+    // Flags fields: (Rust name, Serde name)
+    let flags_fields = rust_fields.iter()
+        .filter(|f| type_ident_is(&f.ty, "Flags"))
+        .filter_map(|f| f.ident.as_ref().map(|ident| (ident.to_string(), serde_name(f))))
+        .collect::<Vec<_>>();
+
+    for (tl_field, field) in definition.fields.iter().zip(rust_fields) {
+        let tl_name = tl_field.name.as_ref().map_or("_", String::as_str);
+
+        if let (Some(tl_name), Some(_)) = (tl_field.name.as_ref(), field.ident.as_ref()) {
+            let name = serde_name(field);
+
+            if *tl_name != name {
+                let msg = format!("field `{}` doesn't match TL field `{}`", name, tl_name);
+                return Err(syn::Error::new_spanned(field, msg));
+            }
+        }
+
+        let mismatch = |expected: &str| {
+            let msg = format!("TL field `{}` is {}", tl_name, expected);
+            Err(syn::Error::new_spanned(field, msg))
+        };
+
+        match tl_field.ty {
+            FieldType::Nat => {
+                if !type_ident_is(&field.ty, "Flags") {
+                    return mismatch("a flags field, expected `Flags<...>`");
+                }
+            },
+            FieldType::Type(_) => {
+                if type_ident_is(&field.ty, "Flags") || get_flag_attr(field)?.is_some() {
+                    return mismatch("neither a flags field nor a conditional one");
+                }
+            },
+            FieldType::Conditional { ref flag, .. } => {
+                let attr = match get_flag_attr(field)? {
+                    Some(attr) => attr,
+                    None => return mismatch("conditional, expected a `#[mtproto_flags(...)]` attribute"),
+                };
+
+                if tl_field.is_true_flag() != is_true_flag_field(field) {
+                    if tl_field.is_true_flag() {
+                        return mismatch("`flags.N?true`, expected `bool`");
+                    } else {
+                        return mismatch("`flags.N?T`, expected `Option<T>`");
+                    }
+                }
+
+                let flags_field = match attr.flags_field {
+                    Some(ref name) => flags_fields.iter().find(|f| f.0 == *name),
+                    None => flags_fields.first(),
+                };
+
+                if flags_field.map(|f| &f.1) != Some(&flag.field) || attr.bit != u64::from(flag.bit) {
+                    return mismatch(&format!("bound to `{}.{}`", flag.field, flag.bit));
+                }
+            },
+            FieldType::Repeated(_) => {
+                return mismatch("repeated, which is not supported");
+            },
+        }
+    }
+
+    Ok(())
+}
@@ -1,9 +1,10 @@
-//! This crate provides Serde MTProto's two derive macros.
+//! This crate provides Serde MTProto's four derive macros.
 //!
 //! ```
 //! # #[macro_use] extern crate serde_mtproto_derive;
-//! #[derive(MtProtoIdentifiable, MtProtoSized)]
+//! #[derive(MtProtoIdentifiable, MtProtoSized, MtProtoDescribed, MtProtoRemoteCall)]
 //! # #[mtproto_identifiable(id = "0x00000000")]
+//! # #[mtproto_remote_call(returns = "bool")]
 //! # struct Stub;
 //! # fn main() {}
 //! ```
@@ -40,9 +41,82 @@
 //!
 //! # fn main() {}
 //! ```
+//!
+//! Instead of an `id`, the TL combinator can be given with `tl`. The id is
+//! then computed as CRC32 of the combinator, checked against the explicit
+//! `#id` in it and the `id` attribute if there are any, and TL fields are
+//! checked against Rust fields in order:
+//!
+//! ```
+//! extern crate serde_mtproto;
+//! #[macro_use]
+//! extern crate serde_mtproto_derive;
+//!
+//! use serde_mtproto::Identifiable;
+//!
+//! #[derive(MtProtoIdentifiable, MtProtoSized)]
+//! #[mtproto_identifiable(tl = "pong#347773c5 msg_id:long ping_id:long = Pong")]
+//! struct Pong {
+//!     msg_id: u64,
+//!     ping_id: u64,
+//! }
+//!
+//! #[derive(MtProtoIdentifiable, MtProtoSized)]
+//! enum Peer {
+//!     #[mtproto_identifiable(tl = "peerUser user_id:long = Peer")]
+//!     User {
+//!         user_id: i64,
+//!     },
+//! }
+//!
+//! # fn main() {
+//! assert_eq!(Pong::all_type_ids(), &[0x347773c5]);
+//! assert_eq!(Peer::all_type_ids(), &[0x59511722]);
+//! # }
+//! ```
+//!
+//! The range of API layers a constructor is valid for can be given with
+//! `layers` like `#[mtproto_identifiable(id = "...", layers = "23..=44")]`
+//! (also `N`, `N..` and `..=M`), which `MtProtoDescribed` puts into the
+//! constructor layout along with the name of the TL combinator if `tl` is
+//! given.
+//!
+//! TL functions can also be tied to the type of their result with
+//! `MtProtoRemoteCall`. Type parameters used as `X::Return` in the
+//! `returns` type get a `RemoteCall` bound:
+//!
+//! ```
+//! extern crate serde_mtproto;
+//! #[macro_use]
+//! extern crate serde_mtproto_derive;
+//!
+//! use serde_mtproto::{Boxed, RemoteCall};
+//!
+//! // `messages.receivedQueue#55a5bb66 max_qts:int = Vector<long>`
+//! #[derive(MtProtoIdentifiable, MtProtoRemoteCall)]
+//! #[mtproto_identifiable(id = "0x55a5bb66")]
+//! #[mtproto_remote_call(returns = "Boxed<Vec<i64>>")]
+//! struct ReceivedQueue {
+//!     max_qts: i32,
+//! }
+//!
+//! #[derive(MtProtoIdentifiable, MtProtoRemoteCall)]
+//! #[mtproto_identifiable(tl = "invokeWithLayer#da9b0d0d {X:Type} layer:int query:!X = X")]
+//! #[mtproto_remote_call(returns = "X::Return")]
+//! struct InvokeWithLayer<X> {
+//!     layer: i32,
+//!     query: X,
+//! }
+//!
+//! fn assert_returns_longs<F: RemoteCall<Return = Boxed<Vec<i64>>>>(_: &F) {}
+//!
+//! # fn main() {
+//! assert_returns_longs(&InvokeWithLayer { layer: 1, query: ReceivedQueue { max_qts: 0 } });
+//! # }
+//! ```
 
 // For `quote!` and `control_flow_chain!` macros
-#![recursion_limit = "79"]
+#![recursion_limit = "96"]
 
 // This lint is not compatible with defensive programming, let's disable it
 #![cfg_attr(feature = "cargo-clippy", allow(clippy::unneeded_field_pattern))]
@@ -54,15 +128,18 @@ extern crate proc_macro;
 mod macros;
 
 mod ast;
+mod described;
 mod ext;
+mod flags;
 mod identifiable;
+mod remote_call;
 mod sized;
 
 
 use proc_macro::TokenStream;
 
 
-#[proc_macro_derive(MtProtoIdentifiable, attributes(mtproto_identifiable))]
+#[proc_macro_derive(MtProtoIdentifiable, attributes(mtproto_identifiable, mtproto_flags))]
 pub fn mt_proto_identifiable(input: TokenStream) -> TokenStream {
     let ast = syn::parse_macro_input!(input as syn::DeriveInput);
     let tokens = match ast::Container::from_derive_input(ast, "mtproto::Identifiable") {
@@ -73,7 +150,7 @@ pub fn mt_proto_identifiable(input: TokenStream) -> TokenStream {
     tokens.into()
 }
 
-#[proc_macro_derive(MtProtoSized, attributes(mtproto_sized))]
+#[proc_macro_derive(MtProtoSized, attributes(mtproto_sized, mtproto_flags))]
 pub fn mt_proto_sized(input: TokenStream) -> TokenStream {
     let ast = syn::parse_macro_input!(input as syn::DeriveInput);
     let tokens = match ast::Container::from_derive_input(ast, "mtproto::MtProtoSized") {
@@ -83,3 +160,25 @@ pub fn mt_proto_sized(input: TokenStream) -> TokenStream {
 
     tokens.into()
 }
+
+#[proc_macro_derive(MtProtoDescribed, attributes(mtproto_identifiable, mtproto_flags))]
+pub fn mt_proto_described(input: TokenStream) -> TokenStream {
+    let ast = syn::parse_macro_input!(input as syn::DeriveInput);
+    let tokens = match ast::Container::from_derive_input(ast, "mtproto::registry::TlDescribed") {
+        Ok(container) => crate::described::impl_derive(container),
+        Err(e) => e.to_compile_error(),
+    };
+
+    tokens.into()
+}
+
+#[proc_macro_derive(MtProtoRemoteCall, attributes(mtproto_remote_call))]
+pub fn mt_proto_remote_call(input: TokenStream) -> TokenStream {
+    let ast = syn::parse_macro_input!(input as syn::DeriveInput);
+    let tokens = match ast::Container::from_derive_input(ast, "mtproto::RemoteCall") {
+        Ok(container) => crate::remote_call::impl_derive(container),
+        Err(e) => e.to_compile_error(),
+    };
+
+    tokens.into()
+}
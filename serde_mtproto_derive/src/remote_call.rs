@@ -0,0 +1,97 @@
+use quote::{ToTokens, quote};
+
+use crate::ast;
+
+
+pub(crate) fn impl_derive(mut container: ast::Container) -> proc_macro2::TokenStream {
+    match impl_derive_or_error(&mut container) {
+        Ok(tokens) => tokens,
+        Err(e) => e.to_compile_error(),
+    }
+}
+
+fn impl_derive_or_error(container: &mut ast::Container) -> syn::Result<proc_macro2::TokenStream> {
+    let return_ty = get_return_type_from_attrs(&container.attrs, (&*container).into_token_stream())?;
+    add_remote_call_trait_bounds(container, &return_ty);
+    let (item_impl_generics, item_ty_generics, item_where_clause) =
+        container.generics.split_for_impl();
+
+    let item_name = &container.ident;
+    let dummy_const = ident!("_IMPL_MT_PROTO_REMOTE_CALL_FOR_{}", item_name);
+
+    Ok(quote! {
+        #[allow(non_upper_case_globals)]
+        const #dummy_const: () = {
+            extern crate serde_mtproto as _serde_mtproto;
+
+            impl #item_impl_generics _serde_mtproto::RemoteCall for #item_name #item_ty_generics
+                #item_where_clause
+            {
+                type Return = #return_ty;
+            }
+        };
+    })
+}
+
+fn get_return_type_from_attrs(
+    attrs: &[syn::Attribute],
+    input_tokens: proc_macro2::TokenStream,
+) -> syn::Result<syn::Type> {
+    control_flow_chain! {
+        for attr in attrs;
+        if let syn::AttrStyle::Outer = attr.style;
+        if let Ok(syn::Meta::List(list)) = attr.parse_meta();
+        if list.ident == "mtproto_remote_call";
+        for nested_meta in list.nested;
+        if let syn::NestedMeta::Meta(syn::Meta::NameValue(name_value)) = nested_meta;
+        if name_value.ident == "returns";
+        then {
+            return match name_value.lit {
+                syn::Lit::Str(ref lit_str) => lit_str.parse().map_err(|e| {
+                    syn::Error::new_spanned(lit_str, format!("invalid mtproto return type: {}", e))
+                }),
+                ref lit => {
+                    let msg = "expected mtproto returns attribute to be a string: `returns = \"...\"`";
+                    Err(syn::Error::new_spanned(lit, msg))
+                },
+            };
+        }
+    }
+
+    const ERROR_MESSAGE: &str = "\
+        #[derive(MtProtoRemoteCall)] requires an #[mtproto_remote_call(returns = \"...\")] attribute\n    \
+        with the type of the function result, e.g. `returns = \"Boxed<Pong>\"`.";
+
+    Err(syn::Error::new_spanned(input_tokens, ERROR_MESSAGE))
+}
+
+/// Add `RemoteCall` bounds to type parameters whose associated items are
+/// used in the return type, e.g. to `X` for `X::Return`.
+fn add_remote_call_trait_bounds(container: &mut ast::Container, return_ty: &syn::Type) {
+    let return_tokens = return_ty.into_token_stream();
+
+    for param in &mut container.generics.params {
+        if let syn::GenericParam::Type(ref mut type_param) = *param {
+            if has_associated_item_path(return_tokens.clone(), &type_param.ident) {
+                type_param.bounds.push(syn::parse_quote!(_serde_mtproto::RemoteCall));
+            }
+        }
+    }
+}
+
+fn has_associated_item_path(tokens: proc_macro2::TokenStream, ident: &proc_macro2::Ident) -> bool {
+    let tokens = tokens.into_iter().collect::<Vec<_>>();
+
+    tokens.iter().enumerate().any(|(i, token)| match *token {
+        proc_macro2::TokenTree::Ident(ref token_ident) => {
+            token_ident == ident && match (tokens.get(i + 1), tokens.get(i + 2)) {
+                (Some(proc_macro2::TokenTree::Punct(first)), Some(proc_macro2::TokenTree::Punct(second))) => {
+                    first.as_char() == ':' && second.as_char() == ':'
+                },
+                _ => false,
+            }
+        },
+        proc_macro2::TokenTree::Group(ref group) => has_associated_item_path(group.stream(), ident),
+        _ => false,
+    })
+}
@@ -73,7 +73,7 @@ pub(crate) fn impl_derive(mut container: ast::Container) -> proc_macro2::TokenSt
                         }).unzip::<_, _, Vec<_>, Vec<_>>();
 
                         quote! {
-                            #item_name::#variant_name { #(#patterns),* } => {
+                            #item_name::#variant_name { #(#patterns,)* .. } => {
                                 Ok(0 #(+ #size_hints)*)
                             }
                         }
@@ -167,6 +167,11 @@ fn add_mt_proto_sized_trait_bound_if_missing(container: &mut ast::Container) {
 }
 
 fn is_skippable_field(field: &syn::Field) -> bool {
+    // `flags.N?true` fields are only represented by a bit in flags
+    if crate::flags::is_true_flag_field(field) {
+        return true;
+    }
+
     control_flow_chain! {
         for attr in &field.attrs;
         if let syn::AttrStyle::Outer = attr.style;
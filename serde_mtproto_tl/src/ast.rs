@@ -0,0 +1,373 @@
+//! Syntax tree of a TL schema.
+
+use std::fmt;
+
+
+/// A byte range in the schema source.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct Span {
+    /// Offset of the first byte.
+    pub start: usize,
+    /// Offset past the last byte.
+    pub end: usize,
+}
+
+impl Span {
+    /// Create a span of `start..end`.
+    pub fn new(start: usize, end: usize) -> Span {
+        Span { start, end }
+    }
+
+    /// Create a span covering both `self` and `other`.
+    pub fn to(self, other: Span) -> Span {
+        Span { start: self.start.min(other.start), end: self.end.max(other.end) }
+    }
+}
+
+
+/// A parsed schema: definitions interleaved with section separators and
+/// layer comments, in source order.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Schema {
+    /// Items of the schema.
+    pub items: Vec<Item>,
+}
+
+impl Schema {
+    /// Iterate over constructor and function definitions.
+    pub fn definitions(&self) -> impl Iterator<Item = &Definition> {
+        self.items.iter().filter_map(|item| match *item {
+            Item::Definition(ref definition) => Some(definition),
+            _ => None,
+        })
+    }
+
+    /// Return the layer from the last `// LAYER N` comment, if any.
+    pub fn layer(&self) -> Option<u32> {
+        self.items.iter().rev().filter_map(|item| match *item {
+            Item::Layer { layer, .. } => Some(layer),
+            _ => None,
+        }).next()
+    }
+}
+
+
+/// An item of a schema.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Item {
+    /// A constructor or function definition.
+    Definition(Definition),
+    /// A built-in type declaration like `int ? = Int;`.
+    Builtin(Builtin),
+    /// A `---types---` or `---functions---` separator.
+    Section {
+        /// The section which starts after the separator.
+        section: Section,
+        /// Location of the separator.
+        span: Span,
+    },
+    /// A `// LAYER N` comment.
+    Layer {
+        /// The layer number.
+        layer: u32,
+        /// Location of the comment.
+        span: Span,
+    },
+}
+
+/// A section of a schema.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Section {
+    /// Constructors, which is where a schema starts.
+    Types,
+    /// Functions.
+    Functions,
+}
+
+
+/// A constructor or function definition, e.g.
+///
+/// ```text
+/// msgs_ack#62d6b459 msg_ids:Vector<long> = MsgsAck;
+/// ```
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Definition {
+    /// Name of the constructor or function.
+    pub name: Name,
+    /// Explicit id after `#`, if any.
+    pub id: Option<u32>,
+    /// Type parameters in braces, e.g. `{X:Type}`.
+    pub type_params: Vec<TypeParam>,
+    /// Fields in order.
+    pub fields: Vec<Field>,
+    /// The type constructed by the constructor or returned by the function.
+    pub result: Type,
+    /// Whether the definition is a constructor or a function.
+    pub section: Section,
+    /// Location of the definition without the trailing `;`.
+    pub span: Span,
+}
+
+impl Definition {
+    /// Check whether the definition is a function.
+    pub fn is_function(&self) -> bool {
+        self.section == Section::Functions
+    }
+
+    /// Return the combinator text the id is computed from: the definition
+    /// without the id, braces around type parameters, angle brackets and
+    /// `flags.N?true` fields, and with `bytes` field types written as
+    /// `string`, e.g.
+    ///
+    /// ```text
+    /// msgs_ack msg_ids:Vector long = MsgsAck
+    /// ```
+    pub fn combinator(&self) -> String {
+        let mut parts = vec![self.name.to_string()];
+
+        for param in &self.type_params {
+            match param.kind {
+                TypeParamKind::Type => parts.push(format!("{}:Type", param.name)),
+                TypeParamKind::Nat => parts.push(format!("{}:#", param.name)),
+            }
+        }
+
+        for field in &self.fields {
+            if !field.is_true_flag() {
+                write_combinator_field(field, &mut parts);
+            }
+        }
+
+        parts.push("=".to_owned());
+        parts.push(combinator_type(&self.result));
+
+        parts.join(" ")
+    }
+
+    /// Compute the id as the CRC32 of [`combinator()`](#method.combinator).
+    ///
+    /// Unlike `id`, this is always available, and the two are equal for
+    /// well-formed definitions.
+    pub fn computed_id(&self) -> u32 {
+        crc32(self.combinator().as_bytes())
+    }
+}
+
+/// A built-in type declaration, e.g. `int ? = Int;`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Builtin {
+    /// Name of the bare type.
+    pub name: Name,
+    /// Name of the boxed type.
+    pub result: Type,
+    /// Location of the declaration without the trailing `;`.
+    pub span: Span,
+}
+
+
+/// A possibly namespaced identifier, e.g. `auth.sentCode`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Name {
+    /// Namespace before the dot, if any.
+    pub namespace: Option<String>,
+    /// The identifier itself.
+    pub name: String,
+    /// Location of the identifier.
+    pub span: Span,
+}
+
+impl fmt::Display for Name {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.namespace {
+            Some(ref namespace) => write!(f, "{}.{}", namespace, self.name),
+            None => f.write_str(&self.name),
+        }
+    }
+}
+
+
+/// A type parameter, e.g. `{X:Type}`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TypeParam {
+    /// Name of the parameter.
+    pub name: String,
+    /// What the parameter stands for.
+    pub kind: TypeParamKind,
+    /// Location of the parameter including braces.
+    pub span: Span,
+}
+
+/// What a type parameter stands for.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum TypeParamKind {
+    /// Any type, `Type`.
+    Type,
+    /// A natural number, `#`.
+    Nat,
+}
+
+
+/// A field of a definition, e.g. `first_name:flags.1?string`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Field {
+    /// Name of the field, `None` for anonymous ones like in `# [ t ]`.
+    pub name: Option<String>,
+    /// Type of the field.
+    pub ty: FieldType,
+    /// Location of the field.
+    pub span: Span,
+}
+
+impl Field {
+    /// Check whether the field is a `flags.N?true` one, which is only
+    /// represented by a flags bit.
+    pub fn is_true_flag(&self) -> bool {
+        match self.ty {
+            FieldType::Conditional { ref ty, .. } => {
+                ty.name.namespace.is_none() && ty.name.name == "true" && ty.args.is_empty()
+            },
+            _ => false,
+        }
+    }
+}
+
+/// Type of a field.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum FieldType {
+    /// A natural number `#`, used for flags.
+    Nat,
+    /// A plain type.
+    Type(Type),
+    /// A type present only if a bit of a flags field is set, e.g.
+    /// `flags.1?string`.
+    Conditional {
+        /// The flags field and the bit.
+        flag: Flag,
+        /// The type of the field when present.
+        ty: Type,
+    },
+    /// Repeated fields in brackets, e.g. `[ t ]`.
+    Repeated(Vec<Field>),
+}
+
+/// A bit of a flags field, e.g. `flags.1`.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Flag {
+    /// Name of the flags field.
+    pub field: String,
+    /// Index of the bit.
+    pub bit: u32,
+}
+
+
+/// A type expression, e.g. `Vector<%Message>` or `!X`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Type {
+    /// Name of the type.
+    pub name: Name,
+    /// Whether the type is bare: either prefixed with `%` or starting with
+    /// a lowercase letter.
+    pub bare: bool,
+    /// Whether the type is a reference to a type parameter prefixed with
+    /// `!`, as in `query:!X`.
+    pub generic_ref: bool,
+    /// Type arguments, e.g. `long` in `Vector<long>` or `t` in `Vector t`.
+    pub args: Vec<Type>,
+    /// Location of the type expression.
+    pub span: Span,
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.generic_ref {
+            f.write_str("!")?;
+        }
+
+        let name = self.name.to_string();
+        if self.bare && !name.starts_with(|c: char| c.is_ascii_lowercase()) {
+            f.write_str("%")?;
+        }
+        f.write_str(&name)?;
+
+        if let Some((first, rest)) = self.args.split_first() {
+            write!(f, "<{}", first)?;
+            for arg in rest {
+                write!(f, ",{}", arg)?;
+            }
+            f.write_str(">")?;
+        }
+
+        Ok(())
+    }
+}
+
+
+fn write_combinator_field(field: &Field, parts: &mut Vec<String>) {
+    let prefix = match field.name {
+        Some(ref name) => format!("{}:", name),
+        None => String::new(),
+    };
+
+    match field.ty {
+        FieldType::Nat => parts.push(format!("{}#", prefix)),
+        FieldType::Type(ref ty) => parts.push(format!("{}{}", prefix, combinator_field_type(ty))),
+        FieldType::Conditional { ref flag, ref ty } => {
+            parts.push(format!("{}{}.{}?{}", prefix, flag.field, flag.bit, combinator_field_type(ty)));
+        },
+        FieldType::Repeated(ref fields) => {
+            parts.push(format!("{}[", prefix));
+            for field in fields {
+                write_combinator_field(field, parts);
+            }
+            parts.push("]".to_owned());
+        },
+    }
+}
+
+/// Format the type of a field for the combinator, where `bytes` is written
+/// as `string` since both have the same representation. Type arguments,
+/// like in `Vector<bytes>`, are kept as is.
+fn combinator_field_type(ty: &Type) -> String {
+    if ty.name.namespace.is_none() && ty.name.name == "bytes" && ty.args.is_empty() && !ty.generic_ref {
+        "string".to_owned()
+    } else {
+        combinator_type(ty)
+    }
+}
+
+/// Format a type like `Display` does, but with type arguments separated by
+/// spaces: `Vector long` instead of `Vector<long>`.
+fn combinator_type(ty: &Type) -> String {
+    let mut result = String::new();
+
+    if ty.generic_ref {
+        result.push('!');
+    }
+
+    let name = ty.name.to_string();
+    if ty.bare && !name.starts_with(|c: char| c.is_ascii_lowercase()) {
+        result.push('%');
+    }
+    result.push_str(&name);
+
+    for arg in &ty.args {
+        result.push(' ');
+        result.push_str(&combinator_type(arg));
+    }
+
+    result
+}
+
+/// CRC32 (IEEE) as used for TL combinator ids.
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+
+    for &byte in bytes {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+
+    !crc
+}
@@ -0,0 +1,107 @@
+//! When parsing a TL schema goes wrong.
+
+use std::error;
+use std::fmt;
+
+use crate::ast::Span;
+
+
+/// An error at a location in the schema source.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ParseError {
+    /// What went wrong.
+    pub kind: ParseErrorKind,
+    /// Where it went wrong.
+    pub span: Span,
+    /// 1-based line of the start of `span`.
+    pub line: usize,
+    /// 1-based column of the start of `span`, in characters.
+    pub column: usize,
+}
+
+impl ParseError {
+    pub(crate) fn new(kind: ParseErrorKind, span: Span, source: &str) -> ParseError {
+        let before = &source[..span.start.min(source.len())];
+        let line_start = before.rfind('\n').map_or(0, |index| index + 1);
+
+        ParseError {
+            kind,
+            span,
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}, column {}: {}", self.line, self.column, self.kind)
+    }
+}
+
+impl error::Error for ParseError {
+    fn description(&self) -> &str {
+        "TL schema parse error"
+    }
+}
+
+
+/// TL schema parse error kinds.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum ParseErrorKind {
+    /// A character which cannot appear in a schema, stores it.
+    UnexpectedChar(char),
+    /// A token other than the expected one, stores descriptions of both.
+    UnexpectedToken {
+        /// What was expected.
+        expected: &'static str,
+        /// What was found instead.
+        found: String,
+    },
+    /// A definition is not terminated with `;`.
+    MissingSemicolon,
+    /// A constructor id which is not a 32-bit hexadecimal number, stores
+    /// it.
+    InvalidId(String),
+    /// An identifier which is not a valid name, stores it.
+    InvalidName(String),
+    /// A `---name---` separator other than `types` and `functions`, stores
+    /// the name.
+    UnknownSection(String),
+    /// A flag which is not `field.bit` with a bit under 32, stores it.
+    InvalidFlag(String),
+    /// A `// LAYER` comment without a valid number, stores the comment
+    /// text.
+    InvalidLayer(String),
+}
+
+impl fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            ParseErrorKind::UnexpectedChar(c) => {
+                write!(f, "unexpected character {:?}", c)
+            },
+            ParseErrorKind::UnexpectedToken { expected, ref found } => {
+                write!(f, "expected {}, found {}", expected, found)
+            },
+            ParseErrorKind::MissingSemicolon => {
+                write!(f, "missing `;` at the end of definition")
+            },
+            ParseErrorKind::InvalidId(ref id) => {
+                write!(f, "invalid constructor id {:?}", id)
+            },
+            ParseErrorKind::InvalidName(ref name) => {
+                write!(f, "invalid name {:?}", name)
+            },
+            ParseErrorKind::UnknownSection(ref name) => {
+                write!(f, "unknown section {:?}", name)
+            },
+            ParseErrorKind::InvalidFlag(ref flag) => {
+                write!(f, "invalid flag {:?}", flag)
+            },
+            ParseErrorKind::InvalidLayer(ref comment) => {
+                write!(f, "invalid layer comment {:?}", comment)
+            },
+        }
+    }
+}
@@ -0,0 +1,156 @@
+use std::fmt;
+
+use crate::ast::Span;
+use crate::error::{ParseError, ParseErrorKind};
+
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum TokenKind<'a> {
+    /// Letters, digits, `_` and `.`, which covers names, ids and flags.
+    Ident(&'a str),
+    /// `---name---`, stores the name.
+    Section(&'a str),
+    /// `// LAYER N`, stores the number.
+    Layer(u32),
+    Hash,
+    Colon,
+    Semicolon,
+    Equals,
+    Question,
+    Bang,
+    Percent,
+    LAngle,
+    RAngle,
+    Comma,
+    LBrace,
+    RBrace,
+    LBracket,
+    RBracket,
+}
+
+impl<'a> fmt::Display for TokenKind<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            TokenKind::Ident(ident) => write!(f, "`{}`", ident),
+            TokenKind::Section(name) => write!(f, "`---{}---`", name),
+            TokenKind::Layer(layer) => write!(f, "`// LAYER {}`", layer),
+            TokenKind::Hash => f.write_str("`#`"),
+            TokenKind::Colon => f.write_str("`:`"),
+            TokenKind::Semicolon => f.write_str("`;`"),
+            TokenKind::Equals => f.write_str("`=`"),
+            TokenKind::Question => f.write_str("`?`"),
+            TokenKind::Bang => f.write_str("`!`"),
+            TokenKind::Percent => f.write_str("`%`"),
+            TokenKind::LAngle => f.write_str("`<`"),
+            TokenKind::RAngle => f.write_str("`>`"),
+            TokenKind::Comma => f.write_str("`,`"),
+            TokenKind::LBrace => f.write_str("`{`"),
+            TokenKind::RBrace => f.write_str("`}`"),
+            TokenKind::LBracket => f.write_str("`[`"),
+            TokenKind::RBracket => f.write_str("`]`"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) struct Token<'a> {
+    pub(crate) kind: TokenKind<'a>,
+    pub(crate) span: Span,
+}
+
+
+/// Split `source` into tokens, skipping whitespace and comments other than
+/// layer ones.
+///
+/// Unexpected characters are reported and skipped.
+pub(crate) fn tokenize(source: &str) -> (Vec<Token<'_>>, Vec<ParseError>) {
+    let mut tokens = Vec::new();
+    let mut errors = Vec::new();
+    let bytes = source.as_bytes();
+    let mut pos = 0;
+
+    while let Some(c) = source[pos..].chars().next() {
+        let start = pos;
+
+        let kind = match c {
+            _ if c.is_whitespace() => {
+                pos += c.len_utf8();
+                continue;
+            },
+            _ if is_ident_char(c) => {
+                pos += source[pos..].find(|c| !is_ident_char(c)).unwrap_or(source.len() - pos);
+                TokenKind::Ident(&source[start..pos])
+            },
+            '/' if bytes.get(pos + 1) == Some(&b'/') => {
+                pos += source[pos..].find('\n').unwrap_or(source.len() - pos);
+
+                match layer_comment(&source[start..pos]) {
+                    Some(Ok(layer)) => TokenKind::Layer(layer),
+                    Some(Err(())) => {
+                        let kind = ParseErrorKind::InvalidLayer(source[start..pos].trim_end().to_owned());
+                        errors.push(ParseError::new(kind, Span::new(start, pos), source));
+                        continue;
+                    },
+                    None => continue,
+                }
+            },
+            '-' if source[pos..].starts_with("---") => {
+                let name_len = source[pos + 3..].find(|c| !is_ident_char(c)).unwrap_or(source.len() - pos - 3);
+                let name_end = pos + 3 + name_len;
+
+                if name_len > 0 && source[name_end..].starts_with("---") {
+                    pos = name_end + 3;
+                    TokenKind::Section(&source[start + 3..name_end])
+                } else {
+                    pos += 1;
+                    errors.push(ParseError::new(ParseErrorKind::UnexpectedChar('-'), Span::new(start, pos), source));
+                    continue;
+                }
+            },
+            _ => {
+                pos += c.len_utf8();
+
+                match c {
+                    '#' => TokenKind::Hash,
+                    ':' => TokenKind::Colon,
+                    ';' => TokenKind::Semicolon,
+                    '=' => TokenKind::Equals,
+                    '?' => TokenKind::Question,
+                    '!' => TokenKind::Bang,
+                    '%' => TokenKind::Percent,
+                    '<' => TokenKind::LAngle,
+                    '>' => TokenKind::RAngle,
+                    ',' => TokenKind::Comma,
+                    '{' => TokenKind::LBrace,
+                    '}' => TokenKind::RBrace,
+                    '[' => TokenKind::LBracket,
+                    ']' => TokenKind::RBracket,
+                    _ => {
+                        errors.push(ParseError::new(ParseErrorKind::UnexpectedChar(c), Span::new(start, pos), source));
+                        continue;
+                    },
+                }
+            },
+        };
+
+        tokens.push(Token { kind, span: Span::new(start, pos) });
+    }
+
+    (tokens, errors)
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '.'
+}
+
+/// Parse the layer number of a `// LAYER N` comment, or return `None` for
+/// other comments.
+fn layer_comment(comment: &str) -> Option<Result<u32, ()>> {
+    let text = comment.trim_start_matches('/').trim();
+
+    if !text.starts_with("LAYER") {
+        return None;
+    }
+
+    Some(text["LAYER".len()..].trim().parse().map_err(|_| ()))
+}
@@ -0,0 +1,69 @@
+//! # Serde MTProto TL
+//!
+//! This crate parses [TL schema](https://core.telegram.org/mtproto/TL)
+//! text into a syntax tree of constructor and function definitions, for
+//! tooling built around `serde_mtproto`.
+//!
+//! The parser understands:
+//!
+//! * definitions with explicit ids, namespaces and type parameters:
+//!   `invokeWithLayer#da9b0d0d {X:Type} layer:int query:!X = X;`;
+//! * flags fields and conditional fields: `flags:# title:flags.0?string`;
+//! * bare types and type arguments: `messages:vector<%Message>`,
+//!   `Vector t`;
+//! * repeated anonymous fields: `# [ t ]`;
+//! * built-in type declarations: `int ? = Int;`;
+//! * `---types---` and `---functions---` section separators;
+//! * `// LAYER N` comments; other comments are skipped.
+//!
+//! Errors carry the byte span, line and column they happened at.
+//!
+//! # Examples
+//!
+//! ```
+//! use serde_mtproto_tl::{FieldType, Section};
+//!
+//! let schema = serde_mtproto_tl::parse("
+//!     // LAYER 1
+//!     pong#347773c5 msg_id:long ping_id:long = Pong;
+//!     ---functions---
+//!     ping#7abe77ec ping_id:long = Pong;
+//! ").unwrap();
+//!
+//! assert_eq!(schema.layer(), Some(1));
+//!
+//! let definitions: Vec<_> = schema.definitions().collect();
+//! assert_eq!(definitions[0].name.name, "pong");
+//! assert_eq!(definitions[0].id, Some(0x347773c5));
+//! assert_eq!(definitions[1].section, Section::Functions);
+//!
+//! match definitions[1].fields[0].ty {
+//!     FieldType::Type(ref ty) => assert_eq!(ty.name.name, "long"),
+//!     _ => unreachable!(),
+//! }
+//!
+//! let errors = serde_mtproto_tl::parse("pong#347773c5 msg_id:long ping_id:long Pong;").unwrap_err();
+//! assert_eq!(errors[0].to_string(), "line 1, column 44: expected `=`, found `;`");
+//! ```
+
+#![warn(
+    missing_debug_implementations,
+    missing_docs,
+    rust_2018_idioms,
+    trivial_casts,
+    trivial_numeric_casts,
+    unused_import_braces,
+)]
+
+
+mod ast;
+mod error;
+mod lexer;
+mod parser;
+
+
+pub use crate::ast::{
+    Builtin, Definition, Field, FieldType, Flag, Item, Name, Schema, Section, Span, Type, TypeParam, TypeParamKind,
+};
+pub use crate::error::{ParseError, ParseErrorKind};
+pub use crate::parser::{parse, parse_definition};
@@ -0,0 +1,388 @@
+use crate::ast::{
+    Builtin, Definition, Field, FieldType, Flag, Item, Name, Schema, Section, Span, Type, TypeParam, TypeParamKind,
+};
+use crate::error::{ParseError, ParseErrorKind};
+use crate::lexer::{self, Token, TokenKind};
+
+
+/// Parse a whole schema.
+///
+/// Malformed definitions are skipped up to the next `;` so that all errors
+/// are reported at once.
+pub fn parse(source: &str) -> Result<Schema, Vec<ParseError>> {
+    let (tokens, mut errors) = lexer::tokenize(source);
+    let mut items = Vec::new();
+    let mut section = Section::Types;
+    let mut statement_start = 0;
+
+    for (index, token) in tokens.iter().enumerate() {
+        let standalone_item = match token.kind {
+            TokenKind::Section(name) => {
+                let new_section = match name {
+                    "types" => Section::Types,
+                    "functions" => Section::Functions,
+                    _ => {
+                        let kind = ParseErrorKind::UnknownSection(name.to_owned());
+                        errors.push(ParseError::new(kind, token.span, source));
+                        section
+                    },
+                };
+
+                Some(Item::Section { section: new_section, span: token.span })
+            },
+            TokenKind::Layer(layer) => Some(Item::Layer { layer, span: token.span }),
+            TokenKind::Semicolon => {
+                let statement = &tokens[statement_start..index];
+                statement_start = index + 1;
+
+                match parse_statement(source, statement, (token.span, "`;`"), section) {
+                    Ok(item) => items.push(item),
+                    Err(error) => errors.push(error),
+                }
+
+                continue;
+            },
+            _ => continue,
+        };
+
+        // Separators and layer comments can't be inside definitions
+        if statement_start < index {
+            let span = tokens[statement_start].span.to(tokens[index - 1].span);
+            errors.push(ParseError::new(ParseErrorKind::MissingSemicolon, span, source));
+        }
+        statement_start = index + 1;
+
+        if let Some(Item::Section { section: new_section, .. }) = standalone_item {
+            section = new_section;
+        }
+        items.extend(standalone_item);
+    }
+
+    if let (Some(first), Some(last)) = (tokens.get(statement_start), tokens.last()) {
+        errors.push(ParseError::new(ParseErrorKind::MissingSemicolon, first.span.to(last.span), source));
+    }
+
+    if errors.is_empty() {
+        Ok(Schema { items })
+    } else {
+        errors.sort_by_key(|error| error.span.start);
+        Err(errors)
+    }
+}
+
+/// Parse a single definition like `msgs_ack#62d6b459 msg_ids:Vector<long> =
+/// MsgsAck;`, which is a constructor unless `section` says otherwise.
+///
+/// The trailing `;` is optional.
+pub fn parse_definition(source: &str, section: Section) -> Result<Definition, ParseError> {
+    let (tokens, errors) = lexer::tokenize(source);
+    if let Some(error) = errors.into_iter().next() {
+        return Err(error);
+    }
+
+    let (statement, end) = match tokens.split_last() {
+        Some((last, rest)) if last.kind == TokenKind::Semicolon => (rest, (last.span, "`;`")),
+        _ => (&tokens[..], (Span::new(source.len(), source.len()), "end of input")),
+    };
+
+    match parse_statement(source, statement, end, section)? {
+        Item::Definition(definition) => Ok(definition),
+        _ => {
+            let found = "a built-in type declaration".to_owned();
+            let kind = ParseErrorKind::UnexpectedToken { expected: "a definition", found };
+            Err(ParseError::new(kind, Span::new(0, source.len()), source))
+        },
+    }
+}
+
+
+fn parse_statement(
+    source: &str,
+    tokens: &[Token<'_>],
+    (end, end_found): (Span, &'static str),
+    section: Section,
+) -> Result<Item, ParseError> {
+    let mut parser = Parser { source, tokens, pos: 0, end, end_found };
+    let item = parser.definition(section)?;
+    parser.expect_end()?;
+
+    Ok(item)
+}
+
+/// A recursive descent parser over the tokens of one definition.
+struct Parser<'s, 't> {
+    source: &'s str,
+    tokens: &'t [Token<'s>],
+    pos: usize,
+    /// Location of the terminating `;` or the end of input.
+    end: Span,
+    /// Description of what terminates the tokens.
+    end_found: &'static str,
+}
+
+impl<'s, 't> Parser<'s, 't> {
+    fn definition(&mut self, section: Section) -> Result<Item, ParseError> {
+        let (name_str, name_span) = self.expect_ident("a name")?;
+        let name = self.name(name_str, name_span)?;
+
+        let mut id = None;
+        if let Some(hash) = self.eat(TokenKind::Hash) {
+            if hash.start == name_span.end {
+                let (id_str, id_span) = self.expect_ident("a constructor id")?;
+                id = Some(self.id(id_str, id_span)?);
+            } else {
+                // It's an anonymous `#` field
+                self.pos -= 1;
+            }
+        }
+
+        if self.eat(TokenKind::Question).is_some() {
+            self.expect(TokenKind::Equals, "`=`")?;
+            let result = self.result_type()?;
+            let span = name_span.to(result.span);
+
+            return Ok(Item::Builtin(Builtin { name, result, span }));
+        }
+
+        let mut type_params = Vec::new();
+        while let Some(open) = self.eat(TokenKind::LBrace) {
+            let (param_name, _) = self.expect_ident("a type parameter name")?;
+            self.expect(TokenKind::Colon, "`:`")?;
+            let kind = match self.next() {
+                Some(Token { kind: TokenKind::Ident("Type"), .. }) => TypeParamKind::Type,
+                Some(Token { kind: TokenKind::Hash, .. }) => TypeParamKind::Nat,
+                _ => return Err(self.unexpected_previous("`Type` or `#`")),
+            };
+            let close = self.expect(TokenKind::RBrace, "`}`")?;
+
+            type_params.push(TypeParam { name: param_name.to_owned(), kind, span: open.to(close) });
+        }
+
+        let mut fields = Vec::new();
+        while self.peek_kind() != Some(TokenKind::Equals) {
+            if self.peek_kind().is_none() {
+                return Err(self.unexpected_next("`=`"));
+            }
+
+            fields.push(self.field()?);
+        }
+
+        self.expect(TokenKind::Equals, "`=`")?;
+        let result = self.result_type()?;
+        let span = name_span.to(result.span);
+
+        Ok(Item::Definition(Definition { name, id, type_params, fields, result, section, span }))
+    }
+
+    fn field(&mut self) -> Result<Field, ParseError> {
+        if let Some(open) = self.eat(TokenKind::LBracket) {
+            let mut fields = Vec::new();
+            let close = loop {
+                if let Some(close) = self.eat(TokenKind::RBracket) {
+                    break close;
+                }
+                if self.peek_kind().is_none() {
+                    return Err(self.unexpected_next("`]`"));
+                }
+
+                fields.push(self.field()?);
+            };
+
+            return Ok(Field { name: None, ty: FieldType::Repeated(fields), span: open.to(close) });
+        }
+
+        let start = self.peek_span();
+        let name = match (self.peek_kind(), self.tokens.get(self.pos + 1).map(|token| token.kind)) {
+            (Some(TokenKind::Ident(name)), Some(TokenKind::Colon)) => {
+                if !is_valid_identifier(name) {
+                    return Err(self.error(ParseErrorKind::InvalidName(name.to_owned()), start));
+                }
+
+                self.pos += 2;
+                Some(name.to_owned())
+            },
+            _ => None,
+        };
+
+        if let Some(hash) = self.eat(TokenKind::Hash) {
+            return Ok(Field { name, ty: FieldType::Nat, span: start.to(hash) });
+        }
+
+        let flag = match (self.peek_kind(), self.tokens.get(self.pos + 1).map(|token| token.kind)) {
+            (Some(TokenKind::Ident(flag)), Some(TokenKind::Question)) => {
+                let flag_span = self.peek_span();
+                self.pos += 2;
+                Some(self.flag(flag, flag_span)?)
+            },
+            _ => None,
+        };
+
+        let ty = self.field_type()?;
+        let span = start.to(ty.span);
+        let ty = match flag {
+            Some(flag) => FieldType::Conditional { flag, ty },
+            None => FieldType::Type(ty),
+        };
+
+        Ok(Field { name, ty, span })
+    }
+
+    /// A type in a field: `!X`, `%Message`, `Vector<long>`.
+    fn field_type(&mut self) -> Result<Type, ParseError> {
+        let start = self.peek_span();
+        let generic_ref = self.eat(TokenKind::Bang).is_some();
+        let percent = self.eat(TokenKind::Percent).is_some();
+
+        let (name_str, name_span) = self.expect_ident("a type")?;
+        let name = self.name(name_str, name_span)?;
+
+        let mut args = Vec::new();
+        let mut end = name_span;
+        if self.eat(TokenKind::LAngle).is_some() {
+            loop {
+                args.push(self.field_type()?);
+
+                if let Some(close) = self.eat(TokenKind::RAngle) {
+                    end = close;
+                    break;
+                }
+                self.expect(TokenKind::Comma, "`,` or `>`")?;
+            }
+        }
+
+        let bare = percent || starts_with_lowercase(&name.name);
+        Ok(Type { name, bare, generic_ref, args, span: start.to(end) })
+    }
+
+    /// A result type: `Vector t` or the same as in a field.
+    fn result_type(&mut self) -> Result<Type, ParseError> {
+        let mut ty = self.field_type()?;
+
+        while let Some(TokenKind::Ident(_)) = self.peek_kind() {
+            let arg = self.field_type()?;
+            ty.span = ty.span.to(arg.span);
+            ty.args.push(arg);
+        }
+
+        Ok(ty)
+    }
+
+    fn name(&self, ident: &str, span: Span) -> Result<Name, ParseError> {
+        let (namespace, name) = match ident.find('.') {
+            Some(index) => (Some(&ident[..index]), &ident[index + 1..]),
+            None => (None, ident),
+        };
+
+        if !namespace.into_iter().chain(Some(name)).all(is_valid_identifier) {
+            return Err(self.error(ParseErrorKind::InvalidName(ident.to_owned()), span));
+        }
+
+        Ok(Name { namespace: namespace.map(str::to_owned), name: name.to_owned(), span })
+    }
+
+    fn id(&self, id: &str, span: Span) -> Result<u32, ParseError> {
+        if id.is_empty() || id.len() > 8 {
+            return Err(self.error(ParseErrorKind::InvalidId(id.to_owned()), span));
+        }
+
+        u32::from_str_radix(id, 16).map_err(|_| self.error(ParseErrorKind::InvalidId(id.to_owned()), span))
+    }
+
+    fn flag(&self, flag: &str, span: Span) -> Result<Flag, ParseError> {
+        let invalid = || self.error(ParseErrorKind::InvalidFlag(flag.to_owned()), span);
+
+        let index = flag.find('.').ok_or_else(invalid)?;
+        let (field, bit) = (&flag[..index], &flag[index + 1..]);
+        let bit = bit.parse::<u32>().map_err(|_| invalid())?;
+
+        if !is_valid_identifier(field) || bit >= 32 {
+            return Err(invalid());
+        }
+
+        Ok(Flag { field: field.to_owned(), bit })
+    }
+
+
+    // ========== TOKEN HELPERS ========== //
+
+    fn peek_kind(&self) -> Option<TokenKind<'s>> {
+        self.tokens.get(self.pos).map(|token| token.kind)
+    }
+
+    fn peek_span(&self) -> Span {
+        self.tokens.get(self.pos).map_or(self.end, |token| token.span)
+    }
+
+    fn next(&mut self) -> Option<Token<'s>> {
+        let token = self.tokens.get(self.pos).cloned();
+        if token.is_some() {
+            self.pos += 1;
+        }
+
+        token
+    }
+
+    /// Consume the next token if it's of `kind` and return its location.
+    fn eat(&mut self, kind: TokenKind<'_>) -> Option<Span> {
+        match self.tokens.get(self.pos) {
+            Some(token) if token.kind == kind => {
+                self.pos += 1;
+                Some(token.span)
+            },
+            _ => None,
+        }
+    }
+
+    fn expect(&mut self, kind: TokenKind<'_>, expected: &'static str) -> Result<Span, ParseError> {
+        self.eat(kind).ok_or_else(|| self.unexpected_next(expected))
+    }
+
+    fn expect_ident(&mut self, expected: &'static str) -> Result<(&'s str, Span), ParseError> {
+        match self.peek_kind() {
+            Some(TokenKind::Ident(ident)) => {
+                let span = self.peek_span();
+                self.pos += 1;
+                Ok((ident, span))
+            },
+            _ => Err(self.unexpected_next(expected)),
+        }
+    }
+
+    fn expect_end(&self) -> Result<(), ParseError> {
+        match self.peek_kind() {
+            None => Ok(()),
+            Some(_) => Err(self.unexpected_next("`;`")),
+        }
+    }
+
+    fn unexpected_next(&self, expected: &'static str) -> ParseError {
+        self.unexpected_at(self.pos, expected)
+    }
+
+    fn unexpected_previous(&self, expected: &'static str) -> ParseError {
+        self.unexpected_at(self.pos.saturating_sub(1), expected)
+    }
+
+    fn unexpected_at(&self, pos: usize, expected: &'static str) -> ParseError {
+        let (found, span) = match self.tokens.get(pos) {
+            Some(token) => (token.kind.to_string(), token.span),
+            None => (self.end_found.to_owned(), self.end),
+        };
+
+        self.error(ParseErrorKind::UnexpectedToken { expected, found }, span)
+    }
+
+    fn error(&self, kind: ParseErrorKind, span: Span) -> ParseError {
+        ParseError::new(kind, span, self.source)
+    }
+}
+
+
+fn is_valid_identifier(ident: &str) -> bool {
+    ident.starts_with(|c: char| c.is_ascii_alphabetic())
+        && ident.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn starts_with_lowercase(ident: &str) -> bool {
+    ident.starts_with(|c: char| c.is_ascii_lowercase())
+}
@@ -0,0 +1,235 @@
+//! Testing the TL schema parser.
+
+use serde_mtproto_tl::{
+    Definition, Field, FieldType, Flag, Item, ParseError, ParseErrorKind, Section, Type, TypeParamKind,
+    parse, parse_definition,
+};
+
+
+const SCHEMA: &str = "
+int ? = Int;
+long ? = Long;
+string ? = String;
+
+vector#1cb5c415 {t:Type} # [ t ] = Vector t;
+
+resPQ#05162463 nonce:int128 server_nonce:int128 pq:string server_public_key_fingerprints:Vector<long> = ResPQ;
+msg_container#73f1f8dc messages:vector<%Message> = MessageContainer;
+
+// LAYER 158
+
+---functions---
+
+req_pq_multi#be7e8ef1 nonce:int128 = ResPQ;
+invokeWithLayer#da9b0d0d {X:Type} layer:int query:!X = X;
+initConnection#c1cd5ea9 {X:Type} flags:# api_id:int device_model:string
+    proxy:flags.0?InputClientProxy params:flags.1?JSONValue query:!X = X;
+
+---types---
+
+auth.sentCode#5e002502 flags:# type:auth.SentCodeType phone_code_hash:string = auth.SentCode;
+";
+
+
+fn ty(ty: &FieldType) -> &Type {
+    match *ty {
+        FieldType::Type(ref ty) | FieldType::Conditional { ref ty, .. } => ty,
+        ref other => panic!("unexpected field type: {:?}", other),
+    }
+}
+
+fn field_names(definition: &Definition) -> Vec<&str> {
+    definition.fields.iter().map(|field| field.name.as_ref().map_or("_", String::as_str)).collect()
+}
+
+fn find<'a>(definitions: &[&'a Definition], name: &str) -> &'a Definition {
+    definitions.iter().find(|definition| definition.name.to_string() == name).unwrap()
+}
+
+fn single_error(source: &str) -> ParseError {
+    let mut errors = parse(source).unwrap_err();
+    assert_eq!(errors.len(), 1, "{:?}", errors);
+    errors.remove(0)
+}
+
+
+#[test]
+fn test_schema() {
+    let schema = parse(SCHEMA).unwrap();
+    assert_eq!(schema.layer(), Some(158));
+
+    let builtins: Vec<_> = schema.items.iter().filter_map(|item| match *item {
+        Item::Builtin(ref builtin) => Some(format!("{} = {}", builtin.name, builtin.result)),
+        _ => None,
+    }).collect();
+    assert_eq!(builtins, ["int = Int", "long = Long", "string = String"]);
+
+    let definitions: Vec<_> = schema.definitions().collect();
+    assert_eq!(definitions.len(), 7);
+
+    let sections: Vec<_> = definitions.iter().map(|definition| definition.is_function()).collect();
+    assert_eq!(sections, [false, false, false, true, true, true, false]);
+
+    let res_pq = find(&definitions, "resPQ");
+    assert_eq!(res_pq.id, Some(0x05162463));
+    assert_eq!(field_names(res_pq), ["nonce", "server_nonce", "pq", "server_public_key_fingerprints"]);
+    assert_eq!(ty(&res_pq.fields[3].ty).to_string(), "Vector<long>");
+    assert!(!ty(&res_pq.fields[3].ty).bare);
+    assert!(ty(&res_pq.fields[2].ty).bare);
+    assert_eq!(res_pq.result.to_string(), "ResPQ");
+
+    let msg_container = find(&definitions, "msg_container");
+    let messages = ty(&msg_container.fields[0].ty);
+    assert_eq!(messages.to_string(), "vector<%Message>");
+    assert!(messages.args[0].bare);
+
+    let auth_sent_code = find(&definitions, "auth.sentCode");
+    assert_eq!(auth_sent_code.name.namespace, Some("auth".to_owned()));
+    assert_eq!(auth_sent_code.result.to_string(), "auth.SentCode");
+    assert_eq!(auth_sent_code.section, Section::Types);
+}
+
+#[test]
+fn test_vector() {
+    let schema = parse(SCHEMA).unwrap();
+    let vector = schema.definitions().next().unwrap();
+
+    assert_eq!(vector.id, Some(0x1cb5c415));
+    assert_eq!(vector.type_params[0].name, "t");
+    assert_eq!(vector.type_params[0].kind, TypeParamKind::Type);
+    assert_eq!(field_names(vector), ["_", "_"]);
+    assert_eq!(vector.fields[0].ty, FieldType::Nat);
+
+    match vector.fields[1].ty {
+        FieldType::Repeated(ref fields) => {
+            assert_eq!(fields.len(), 1);
+            assert_eq!(ty(&fields[0].ty).name.name, "t");
+        },
+        ref other => panic!("unexpected field type: {:?}", other),
+    }
+
+    assert_eq!(vector.result.name.name, "Vector");
+    assert_eq!(vector.result.args[0].name.name, "t");
+}
+
+#[test]
+fn test_generics_and_flags() {
+    let schema = parse(SCHEMA).unwrap();
+    let definitions: Vec<_> = schema.definitions().collect();
+
+    let invoke_with_layer = find(&definitions, "invokeWithLayer");
+    let query = ty(&invoke_with_layer.fields[1].ty);
+    assert!(query.generic_ref);
+    assert_eq!(query.to_string(), "!X");
+    assert_eq!(invoke_with_layer.result.to_string(), "X");
+
+    let init_connection = find(&definitions, "initConnection");
+    assert_eq!(field_names(init_connection), ["flags", "api_id", "device_model", "proxy", "params", "query"]);
+    assert_eq!(init_connection.fields[0].ty, FieldType::Nat);
+
+    match init_connection.fields[4].ty {
+        FieldType::Conditional { ref flag, ref ty } => {
+            assert_eq!(*flag, Flag { field: "flags".to_owned(), bit: 1 });
+            assert_eq!(ty.name.name, "JSONValue");
+        },
+        ref other => panic!("unexpected field type: {:?}", other),
+    }
+
+    // Spans cover the source text
+    let Field { span, .. } = init_connection.fields[3];
+    assert_eq!(&SCHEMA[span.start..span.end], "proxy:flags.0?InputClientProxy");
+    assert!(SCHEMA[init_connection.span.start..init_connection.span.end].starts_with("initConnection#c1cd5ea9"));
+    assert!(SCHEMA[init_connection.span.start..init_connection.span.end].ends_with("= X"));
+}
+
+#[test]
+fn test_parse_definition() {
+    let definition = parse_definition("ping#7abe77ec ping_id:long = Pong", Section::Functions).unwrap();
+    assert_eq!(definition.name.name, "ping");
+    assert!(definition.is_function());
+
+    let definition = parse_definition("true = True;", Section::Types).unwrap();
+    assert_eq!(definition.id, None);
+    assert!(definition.fields.is_empty());
+
+    let error = parse_definition("ping#7abe77ec ping_id:long", Section::Functions).unwrap_err();
+    assert_eq!(error.to_string(), "line 1, column 27: expected `=`, found end of input");
+}
+
+#[test]
+fn test_computed_id() {
+    let schema = parse(SCHEMA).unwrap();
+
+    for name in &["vector", "resPQ", "req_pq_multi", "invokeWithLayer"] {
+        let definition = schema.definitions().find(|definition| definition.name.name == *name).unwrap();
+        assert_eq!(Some(definition.computed_id()), definition.id, "{}", definition.combinator());
+    }
+
+    let definition = parse_definition("\
+        inputMediaUploadedDocument#5b38c6c1 flags:# nosound_video:flags.3?true force_file:flags.4?true \
+        file:InputFile thumb:flags.2?InputFile mime_type:string attributes:Vector<DocumentAttribute> \
+        stickers:flags.0?Vector<InputDocument> ttl_seconds:flags.1?int = InputMedia", Section::Types).unwrap();
+    assert_eq!(definition.combinator(), "\
+        inputMediaUploadedDocument flags:# file:InputFile thumb:flags.2?InputFile mime_type:string \
+        attributes:Vector DocumentAttribute stickers:flags.0?Vector InputDocument \
+        ttl_seconds:flags.1?int = InputMedia");
+    assert_eq!(definition.computed_id(), 0x5b38c6c1);
+
+    let definition = parse_definition("vector {t:Type} # [ t ] = Vector t", Section::Types).unwrap();
+    assert_eq!(definition.combinator(), "vector t:Type # [ t ] = Vector t");
+    assert!(definition.fields[0].ty == FieldType::Nat && !definition.fields[0].is_true_flag());
+}
+
+#[test]
+fn test_computed_id_bytes() {
+    let definition = parse_definition(
+        "upload.file#096a18d5 type:storage.FileType mtime:int bytes:bytes = upload.File", Section::Types).unwrap();
+    assert_eq!(definition.combinator(), "upload.file type:storage.FileType mtime:int bytes:string = upload.File");
+    assert_eq!(definition.computed_id(), 0x096a18d5);
+
+    let definition = parse_definition("\
+        updateBotCallbackQuery#b9cfc48d flags:# query_id:long user_id:long peer:Peer msg_id:int \
+        chat_instance:long data:flags.0?bytes game_short_name:flags.1?string = Update", Section::Types).unwrap();
+    assert_eq!(definition.computed_id(), 0xb9cfc48d);
+
+    // Only field types are written as `string`, type arguments stay `bytes`
+    let definition = parse_definition("\
+        codeSettings#ad253d78 flags:# allow_flashcall:flags.0?true current_number:flags.1?true \
+        allow_app_hash:flags.4?true allow_missed_call:flags.5?true allow_firebase:flags.7?true \
+        logout_tokens:flags.6?Vector<bytes> token:flags.8?string app_sandbox:flags.8?Bool = CodeSettings",
+        Section::Types).unwrap();
+    assert_eq!(definition.computed_id(), 0xad253d78);
+
+    let definition = parse_definition("\
+        messages.getBotCallbackAnswer#9342ca07 flags:# game:flags.1?true peer:InputPeer msg_id:int \
+        data:flags.0?bytes password:flags.2?InputCheckPasswordSRP = messages.BotCallbackAnswer",
+        Section::Functions).unwrap();
+    assert_eq!(definition.computed_id(), 0x9342ca07);
+
+    let definition = parse_definition(
+        "invokeAfterMsg#cb9f372d {X:Type} msg_id:long query:!X = X", Section::Functions).unwrap();
+    assert_eq!(definition.combinator(), "invokeAfterMsg X:Type msg_id:long query:!X = X");
+    assert_eq!(definition.computed_id(), 0xcb9f372d);
+}
+
+#[test]
+fn test_errors() {
+    let error = single_error("pong#347773c5 msg_id:long\n  ping_id:long Pong;");
+    assert_eq!((error.line, error.column), (2, 20));
+    assert_eq!(error.kind, ParseErrorKind::UnexpectedToken { expected: "`=`", found: "`;`".to_owned() });
+
+    assert_eq!(single_error("pong#34777xyz = Pong;").kind, ParseErrorKind::InvalidId("34777xyz".to_owned()));
+    assert_eq!(single_error("pong#123456789 = Pong;").kind, ParseErrorKind::InvalidId("123456789".to_owned()));
+    assert_eq!(single_error("a.b.c = Pong;").kind, ParseErrorKind::InvalidName("a.b.c".to_owned()));
+    assert_eq!(single_error("x flags:# a:flags.32?int = X;").kind, ParseErrorKind::InvalidFlag("flags.32".to_owned()));
+    assert_eq!(single_error("---methods---").kind, ParseErrorKind::UnknownSection("methods".to_owned()));
+    assert_eq!(single_error("// LAYER next").kind, ParseErrorKind::InvalidLayer("// LAYER next".to_owned()));
+    assert_eq!(single_error("x a:int = X; $").kind, ParseErrorKind::UnexpectedChar('$'));
+    assert_eq!(single_error("x a:int = X\n---functions---").kind, ParseErrorKind::MissingSemicolon);
+    assert_eq!(single_error("x a:int = X").kind, ParseErrorKind::MissingSemicolon);
+
+    // All malformed definitions are reported
+    let errors = parse("a = A;\nb b:int B;\nc = C;\nd# = D;\n").unwrap_err();
+    let lines: Vec<_> = errors.iter().map(|error| error.line).collect();
+    assert_eq!(lines, [2, 4]);
+}
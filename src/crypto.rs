@@ -0,0 +1,445 @@
+//! MTProto 2.0 message encryption.
+//!
+//! Once an authorization key is generated, every message is sent inside an
+//! envelope:
+//!
+//! ```text
+//! auth_key_id:long msg_key:int128 encrypted_data:bytes
+//! ```
+//!
+//! where `encrypted_data` is AES-256-IGE encrypted
+//!
+//! ```text
+//! salt:long session_id:long message_id:long seq_no:int
+//! message_data_length:int message_data:bytes padding:bytes
+//! ```
+//!
+//! with 12 to 1024 bytes of random padding, and `msg_key` is the middle
+//! part of SHA-256 over a fragment of the authorization key and all the
+//! plaintext. The AES key and IV are derived from the authorization key and
+//! `msg_key`, which are different for each direction.
+//!
+//! # Examples
+//!
+//! ```
+//! use serde_mtproto::crypto::{AuthKey, PlainMessage, Side, decrypt_message, encrypt_message};
+//!
+//! # fn run() -> serde_mtproto::Result<()> {
+//! let auth_key = AuthKey::new([0x42; 256]);
+//! let message = PlainMessage {
+//!     salt: 1,
+//!     session_id: 2,
+//!     message_id: 0x5e0b_8000_0000_0004,
+//!     seq_no: 1,
+//!     body: "ping".to_owned(),
+//! };
+//!
+//! let envelope = encrypt_message(&auth_key, Side::Client, &message)?;
+//! let decrypted: PlainMessage<String> = decrypt_message(&auth_key, Side::Server, &envelope, &[])?;
+//!
+//! assert_eq!(decrypted, message);
+//! #     Ok(())
+//! # }
+//! #
+//! # fn main() { run().unwrap(); }
+//! ```
+
+use std::fmt;
+
+use aes::{Aes256, BlockCipher, NewBlockCipher};
+use byteorder::{ByteOrder, LittleEndian};
+use error_chain::bail;
+use rand::{CryptoRng, Rng, RngCore};
+use serde::de::DeserializeOwned;
+use serde::ser::Serialize;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+
+use crate::error::{self, CryptoErrorKind};
+use crate::utils::safe_uint_cast;
+
+
+/// Length of an authorization key in bytes.
+pub const AUTH_KEY_LEN: usize = 256;
+
+/// Length of `salt`, `session_id`, `message_id`, `seq_no` and
+/// `message_data_length` fields of the plaintext.
+const PLAINTEXT_HEADER_LEN: usize = 32;
+/// Length of `auth_key_id` and `msg_key` fields of the envelope.
+const ENVELOPE_HEADER_LEN: usize = 24;
+
+const MIN_PADDING_LEN: usize = 12;
+const MAX_PADDING_LEN: usize = 1024;
+
+const AES_BLOCK_LEN: usize = 16;
+
+
+/// An authorization key shared by a client and a server.
+#[derive(Clone)]
+pub struct AuthKey {
+    bytes: Vec<u8>,
+    id: u64,
+}
+
+impl AuthKey {
+    /// Create an authorization key from its bytes.
+    pub fn new(bytes: [u8; AUTH_KEY_LEN]) -> AuthKey {
+        AuthKey::from_vec(bytes.to_vec())
+    }
+
+    /// Create an authorization key from a slice which must be 256 bytes
+    /// long.
+    pub fn from_slice(bytes: &[u8]) -> error::Result<AuthKey> {
+        if bytes.len() != AUTH_KEY_LEN {
+            bail!(CryptoErrorKind::InvalidAuthKeyLen(bytes.len()));
+        }
+
+        Ok(AuthKey::from_vec(bytes.to_vec()))
+    }
+
+    #[cfg_attr(feature = "cargo-clippy", allow(clippy::indexing_slicing))]
+    fn from_vec(bytes: Vec<u8>) -> AuthKey {
+        // `auth_key_id` is the 64 lower-order bits of SHA-1 of the key
+        let id = LittleEndian::read_u64(&Sha1::digest(&bytes)[12..20]);
+
+        AuthKey { bytes, id }
+    }
+
+    /// The authorization key id which identifies the key in envelopes.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Return an immutable reference to the key bytes.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+// Don't leak the key into logs
+impl fmt::Debug for AuthKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AuthKey")
+            .field("id", &format_args!("{:#018x}", self.id))
+            .finish()
+    }
+}
+
+/// The party of an MTProto connection.
+///
+/// Key derivation differs for messages sent by a client and by a server.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Side {
+    /// The party which connects to a server.
+    Client,
+    /// The party which serves clients.
+    Server,
+}
+
+impl Side {
+    /// Offset into the authorization key for messages sent by this side.
+    fn sender_offset(self) -> usize {
+        match self {
+            Side::Client => 0,
+            Side::Server => 8,
+        }
+    }
+
+    fn peer(self) -> Side {
+        match self {
+            Side::Client => Side::Server,
+            Side::Server => Side::Client,
+        }
+    }
+}
+
+/// A message before encryption or after decryption.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlainMessage<T> {
+    /// Server salt.
+    pub salt: i64,
+    /// Session id chosen by the client.
+    pub session_id: i64,
+    /// Message identifier.
+    pub message_id: i64,
+    /// Message sequence number.
+    pub seq_no: i32,
+    /// Message body, usually a boxed value.
+    pub body: T,
+}
+
+
+/// Encrypt `message` sent by `side` and return the envelope bytes.
+///
+/// Uses `rand::thread_rng()` for padding.
+pub fn encrypt_message<T>(auth_key: &AuthKey, side: Side, message: &PlainMessage<T>) -> error::Result<Vec<u8>>
+    where T: Serialize
+{
+    encrypt_message_with_rng(auth_key, side, message, &mut rand::thread_rng())
+}
+
+/// Encrypt `message` sent by `side` and return the envelope bytes, using
+/// `rng` for padding.
+#[cfg_attr(feature = "cargo-clippy", allow(clippy::indexing_slicing))]
+pub fn encrypt_message_with_rng<T, R>(
+    auth_key: &AuthKey,
+    side: Side,
+    message: &PlainMessage<T>,
+    rng: &mut R,
+) -> error::Result<Vec<u8>>
+    where T: Serialize,
+          R: RngCore + CryptoRng,
+{
+    let body = crate::ser::to_bytes(&message.body)?;
+
+    let mut plaintext = vec![0; PLAINTEXT_HEADER_LEN];
+    LittleEndian::write_i64(&mut plaintext[0..8], message.salt);
+    LittleEndian::write_i64(&mut plaintext[8..16], message.session_id);
+    LittleEndian::write_i64(&mut plaintext[16..24], message.message_id);
+    LittleEndian::write_i32(&mut plaintext[24..28], message.seq_no);
+    LittleEndian::write_u32(&mut plaintext[28..32], safe_uint_cast(body.len())?);
+    plaintext.extend_from_slice(&body);
+
+    // The shortest padding to align the plaintext to blocks and up to 15
+    // more random blocks, which always fits in 12..=1024 bytes
+    let unaligned_len = plaintext.len() + MIN_PADDING_LEN;
+    let padding_len = MIN_PADDING_LEN
+        + (AES_BLOCK_LEN - unaligned_len % AES_BLOCK_LEN) % AES_BLOCK_LEN
+        + AES_BLOCK_LEN * rng.gen_range(0, 16);
+    let mut padding = vec![0; padding_len];
+    rng.fill_bytes(&mut padding);
+    plaintext.extend_from_slice(&padding);
+
+    let x = side.sender_offset();
+    let msg_key = compute_msg_key(auth_key, x, &plaintext);
+    let (aes_key, aes_iv) = aes_key_iv(auth_key, x, &msg_key);
+    aes_ige_encrypt(&aes_key, &aes_iv, &mut plaintext);
+
+    let mut envelope = vec![0; ENVELOPE_HEADER_LEN];
+    LittleEndian::write_u64(&mut envelope[0..8], auth_key.id());
+    envelope[8..24].copy_from_slice(&msg_key);
+    envelope.extend_from_slice(&plaintext);
+
+    Ok(envelope)
+}
+
+/// Decrypt and verify an envelope received by `side` and deserialize its
+/// body.
+///
+/// `enum_variant_ids` is used to deserialize the body like in
+/// [`from_bytes`](../fn.from_bytes.html).
+#[cfg_attr(feature = "cargo-clippy", allow(clippy::indexing_slicing))]
+pub fn decrypt_message<T>(
+    auth_key: &AuthKey,
+    side: Side,
+    envelope: &[u8],
+    enum_variant_ids: &[&'static str],
+) -> error::Result<PlainMessage<T>>
+    where T: DeserializeOwned
+{
+    if envelope.len() < ENVELOPE_HEADER_LEN {
+        bail!(CryptoErrorKind::InvalidEncryptedDataLen(envelope.len()));
+    }
+
+    let (header, encrypted_data) = envelope.split_at(ENVELOPE_HEADER_LEN);
+
+    let auth_key_id = LittleEndian::read_u64(&header[0..8]);
+    if auth_key_id != auth_key.id() {
+        bail!(CryptoErrorKind::AuthKeyIdMismatch(auth_key.id(), auth_key_id));
+    }
+
+    if encrypted_data.len() < PLAINTEXT_HEADER_LEN + MIN_PADDING_LEN
+        || encrypted_data.len() % AES_BLOCK_LEN != 0
+    {
+        bail!(CryptoErrorKind::InvalidEncryptedDataLen(encrypted_data.len()));
+    }
+
+    let mut msg_key = [0; 16];
+    msg_key.copy_from_slice(&header[8..24]);
+
+    let x = side.peer().sender_offset();
+    let (aes_key, aes_iv) = aes_key_iv(auth_key, x, &msg_key);
+    let mut plaintext = encrypted_data.to_vec();
+    aes_ige_decrypt(&aes_key, &aes_iv, &mut plaintext);
+
+    let computed_msg_key = compute_msg_key(auth_key, x, &plaintext);
+    // Don't stop at the first mismatching byte
+    if computed_msg_key.iter().zip(&msg_key).fold(0, |acc, (a, b)| acc | (a ^ b)) != 0 {
+        bail!(CryptoErrorKind::MsgKeyMismatch);
+    }
+
+    let (plaintext_header, data) = plaintext.split_at(PLAINTEXT_HEADER_LEN);
+    let message_data_length = LittleEndian::read_u32(&plaintext_header[28..32]);
+    let message_data_len = safe_uint_cast::<u32, usize>(message_data_length)?;
+
+    if message_data_len > data.len() || message_data_len % 4 != 0 {
+        bail!(CryptoErrorKind::InvalidMessageDataLen(message_data_length));
+    }
+
+    let padding_len = data.len() - message_data_len;
+    if !(MIN_PADDING_LEN..=MAX_PADDING_LEN).contains(&padding_len) {
+        bail!(CryptoErrorKind::InvalidPaddingLen(padding_len));
+    }
+
+    let body = crate::de::from_bytes(&data[..message_data_len], enum_variant_ids)?;
+
+    Ok(PlainMessage {
+        salt: LittleEndian::read_i64(&plaintext_header[0..8]),
+        session_id: LittleEndian::read_i64(&plaintext_header[8..16]),
+        message_id: LittleEndian::read_i64(&plaintext_header[16..24]),
+        seq_no: LittleEndian::read_i32(&plaintext_header[24..28]),
+        body,
+    })
+}
+
+
+/// `msg_key = substr(SHA256(substr(auth_key, 88 + x, 32) + plaintext), 8, 16)`
+#[cfg_attr(feature = "cargo-clippy", allow(clippy::indexing_slicing))]
+fn compute_msg_key(auth_key: &AuthKey, x: usize, plaintext: &[u8]) -> [u8; 16] {
+    let mut hasher = Sha256::new();
+    hasher.input(&auth_key.bytes[88 + x..120 + x]);
+    hasher.input(plaintext);
+    let msg_key_large = hasher.result();
+
+    let mut msg_key = [0; 16];
+    msg_key.copy_from_slice(&msg_key_large[8..24]);
+
+    msg_key
+}
+
+#[cfg_attr(feature = "cargo-clippy", allow(clippy::indexing_slicing))]
+fn aes_key_iv(auth_key: &AuthKey, x: usize, msg_key: &[u8; 16]) -> ([u8; 32], [u8; 32]) {
+    let mut hasher_a = Sha256::new();
+    hasher_a.input(msg_key);
+    hasher_a.input(&auth_key.bytes[x..36 + x]);
+    let sha256_a = hasher_a.result();
+
+    let mut hasher_b = Sha256::new();
+    hasher_b.input(&auth_key.bytes[40 + x..76 + x]);
+    hasher_b.input(msg_key);
+    let sha256_b = hasher_b.result();
+
+    let mut aes_key = [0; 32];
+    aes_key[0..8].copy_from_slice(&sha256_a[0..8]);
+    aes_key[8..24].copy_from_slice(&sha256_b[8..24]);
+    aes_key[24..32].copy_from_slice(&sha256_a[24..32]);
+
+    let mut aes_iv = [0; 32];
+    aes_iv[0..8].copy_from_slice(&sha256_b[0..8]);
+    aes_iv[8..24].copy_from_slice(&sha256_a[8..24]);
+    aes_iv[24..32].copy_from_slice(&sha256_b[24..32]);
+
+    (aes_key, aes_iv)
+}
+
+/// Encrypt `data` in place using AES-256 in IGE mode, as used for messages
+/// and during the authorization key exchange.
+///
+/// # Panics
+///
+/// If the length of `data` is not divisible by 16.
+pub fn aes_ige_encrypt(key: &[u8; 32], iv: &[u8; 32], data: &mut [u8]) {
+    let cipher = Aes256::new(&(*key).into());
+
+    // The IV is the previous ciphertext block followed by the previous
+    // plaintext block
+    let mut prev_ciphertext = [0; AES_BLOCK_LEN];
+    let mut prev_plaintext = [0; AES_BLOCK_LEN];
+    prev_ciphertext.copy_from_slice(&iv[0..16]);
+    prev_plaintext.copy_from_slice(&iv[16..32]);
+
+    for block in data.chunks_mut(AES_BLOCK_LEN) {
+        let mut plaintext = [0; AES_BLOCK_LEN];
+        plaintext.copy_from_slice(block);
+
+        let mut cipher_block = plaintext;
+        xor_in_place(&mut cipher_block, &prev_ciphertext);
+        let mut cipher_block = cipher_block.into();
+        cipher.encrypt_block(&mut cipher_block);
+        block.copy_from_slice(&cipher_block);
+        xor_in_place(block, &prev_plaintext);
+
+        prev_ciphertext.copy_from_slice(block);
+        prev_plaintext = plaintext;
+    }
+}
+
+/// Decrypt `data` in place using AES-256 in IGE mode, as used for messages
+/// and during the authorization key exchange.
+///
+/// # Panics
+///
+/// If the length of `data` is not divisible by 16.
+pub fn aes_ige_decrypt(key: &[u8; 32], iv: &[u8; 32], data: &mut [u8]) {
+    let cipher = Aes256::new(&(*key).into());
+
+    let mut prev_ciphertext = [0; AES_BLOCK_LEN];
+    let mut prev_plaintext = [0; AES_BLOCK_LEN];
+    prev_ciphertext.copy_from_slice(&iv[0..16]);
+    prev_plaintext.copy_from_slice(&iv[16..32]);
+
+    for block in data.chunks_mut(AES_BLOCK_LEN) {
+        let mut ciphertext = [0; AES_BLOCK_LEN];
+        ciphertext.copy_from_slice(block);
+
+        let mut cipher_block = ciphertext;
+        xor_in_place(&mut cipher_block, &prev_plaintext);
+        let mut cipher_block = cipher_block.into();
+        cipher.decrypt_block(&mut cipher_block);
+        block.copy_from_slice(&cipher_block);
+        xor_in_place(block, &prev_ciphertext);
+
+        prev_plaintext.copy_from_slice(block);
+        prev_ciphertext = ciphertext;
+    }
+}
+
+fn xor_in_place(dst: &mut [u8], src: &[u8]) {
+    for (d, s) in dst.iter_mut().zip(src) {
+        *d ^= *s;
+    }
+}
+
+
+/// AES-256 in CTR mode with a 128-bit big-endian counter, which keeps its
+/// position in the keystream between calls.
+pub(crate) struct AesCtr {
+    cipher: Aes256,
+    counter: [u8; AES_BLOCK_LEN],
+    keystream: [u8; AES_BLOCK_LEN],
+    keystream_pos: usize,
+}
+
+impl AesCtr {
+    pub(crate) fn new(key: &[u8; 32], iv: &[u8; AES_BLOCK_LEN]) -> AesCtr {
+        AesCtr {
+            cipher: Aes256::new(&(*key).into()),
+            counter: *iv,
+            keystream: [0; AES_BLOCK_LEN],
+            keystream_pos: AES_BLOCK_LEN,
+        }
+    }
+
+    /// Encrypt or decrypt `data` in place.
+    #[cfg_attr(feature = "cargo-clippy", allow(clippy::indexing_slicing))]
+    pub(crate) fn apply_keystream(&mut self, data: &mut [u8]) {
+        for byte in data {
+            if self.keystream_pos == AES_BLOCK_LEN {
+                let mut block = self.counter.into();
+                self.cipher.encrypt_block(&mut block);
+                self.keystream.copy_from_slice(&block);
+                self.keystream_pos = 0;
+
+                for counter_byte in self.counter.iter_mut().rev() {
+                    *counter_byte = counter_byte.wrapping_add(1);
+                    if *counter_byte != 0 {
+                        break;
+                    }
+                }
+            }
+
+            *byte ^= self.keystream[self.keystream_pos];
+            self.keystream_pos += 1;
+        }
+    }
+}
@@ -1,28 +1,250 @@
 //! Deserialize MTProto binary representation to a Rust data structure.
 
 use std::io;
+use std::mem;
+use std::str;
 
-use byteorder::{ReadBytesExt, LittleEndian};
+use byteorder::{ByteOrder, LittleEndian};
 use error_chain::bail;
 use log::debug;
-use serde::de::{self, Deserialize, DeserializeOwned, DeserializeSeed, Visitor};
-
-use crate::error::{self, DeErrorKind, DeSerdeType};
-use crate::identifiable::{BOOL_FALSE_ID, BOOL_TRUE_ID};
+use serde::de::{self, Deserialize, DeserializeOwned, DeserializeSeed, IntoDeserializer, Visitor};
+use serde::forward_to_deserialize_any;
+
+use crate::error::{self, DeErrorContext, DeErrorKind, DeSerdeType};
+use crate::flags::{FlagsLayoutEntry, FLAGS_STRUCT_NAME};
+use crate::identifiable::{BOOL_FALSE_ID, BOOL_TRUE_ID, DOUBLE_ID, INT_ID, LONG_ID, STRING_ID, VECTOR_ID};
+use crate::registry::{Constructor, Field, Registry, TlType};
+use crate::wrappers::{SKIPPED_WITH_SIZE_STRUCT_NAME, WITH_SIZE_STRUCT_NAME};
+#[cfg(feature = "gzip")]
+use crate::identifiable::GZIP_PACKED_ID;
+#[cfg(feature = "gzip")]
+use crate::wrappers::{gzip_unpack_truncated, GZIP_PACKED_STRUCT_NAME};
 use crate::utils::{i128_from_parts, safe_float_cast, safe_int_cast, safe_uint_cast, u128_from_parts};
 
 
+/// A source of MTProto binary data for [`Deserializer`].
+///
+/// It is implemented for every `io::Read` and for [`SliceRead`] which is
+/// able to lend strings and byte sequences without copying them.
+pub trait Read<'de> {
+    /// Read the exact number of bytes required to fill `buf`.
+    fn read_exact(&mut self, buf: &mut [u8]) -> io::Result<()>;
+
+    /// Read all bytes until EOF, placing them into `buf`.
+    fn read_to_end(&mut self, buf: &mut Vec<u8>) -> io::Result<usize>;
+
+    /// Consume `len` bytes and return them borrowed from the underlying data.
+    ///
+    /// Returns `Ok(None)` without consuming anything if the data doesn't
+    /// live for `'de`.
+    fn borrow_bytes(&mut self, len: usize) -> io::Result<Option<&'de [u8]>>;
+}
+
+impl<'de, R: io::Read> Read<'de> for R {
+    fn read_exact(&mut self, buf: &mut [u8]) -> io::Result<()> {
+        io::Read::read_exact(self, buf)
+    }
+
+    fn read_to_end(&mut self, buf: &mut Vec<u8>) -> io::Result<usize> {
+        io::Read::read_to_end(self, buf)
+    }
+
+    fn borrow_bytes(&mut self, _len: usize) -> io::Result<Option<&'de [u8]>> {
+        Ok(None)
+    }
+}
+
+/// A byte slice reader which allows deserializing borrowed `&'de str` and
+/// `&'de [u8]` values.
+///
+/// [`from_bytes`] and its siblings use this reader.
+#[derive(Debug)]
+pub struct SliceRead<'de> {
+    slice: &'de [u8],
+}
+
+impl<'de> SliceRead<'de> {
+    /// Create a reader from a byte slice.
+    pub fn new(slice: &'de [u8]) -> SliceRead<'de> {
+        SliceRead { slice }
+    }
+
+    /// Return the unprocessed part of the slice.
+    pub fn into_inner(self) -> &'de [u8] {
+        self.slice
+    }
+}
+
+impl<'de> Read<'de> for SliceRead<'de> {
+    fn read_exact(&mut self, buf: &mut [u8]) -> io::Result<()> {
+        io::Read::read_exact(&mut self.slice, buf)
+    }
+
+    fn read_to_end(&mut self, buf: &mut Vec<u8>) -> io::Result<usize> {
+        io::Read::read_to_end(&mut self.slice, buf)
+    }
+
+    fn borrow_bytes(&mut self, len: usize) -> io::Result<Option<&'de [u8]>> {
+        if len > self.slice.len() {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "failed to fill whole buffer"));
+        }
+
+        let (bytes, rest) = self.slice.split_at(len);
+        self.slice = rest;
+
+        Ok(Some(bytes))
+    }
+}
+
+
+/// Bytes which are either borrowed from the input or copied out of it.
+enum MaybeBorrowed<'de> {
+    Borrowed(&'de [u8]),
+    Owned(Vec<u8>),
+}
+
+/// Fail on invalid UTF-8 in borrowed bytes with the same `FromUtf8Error` as
+/// for an owned string. Bytes are only copied once they turn out invalid.
+fn from_utf8_error(bytes: &[u8]) -> error::Error {
+    String::from_utf8(bytes.to_vec())
+        .err()
+        .unwrap_or_else(|| unreachable!("bytes must be invalid UTF-8"))
+        .into()
+}
+
+
+/// Limits on resources a [`Deserializer`] may spend on untrusted data.
+///
+/// By default nothing is limited.
+///
+/// # Examples
+///
+/// ```
+/// use serde::Deserialize;
+/// use serde_mtproto::de::{Deserializer, DeserializerOptions};
+///
+/// # fn run() -> serde_mtproto::Result<()> {
+/// let options = DeserializerOptions::new()
+///     .max_total_bytes(1024)
+///     .max_vector_len(16)
+///     .max_string_len(256)
+///     .max_depth(8);
+///
+/// let bytes = [0, 0, 0, 1];  // a vector with 16777216 elements
+/// let mut de = Deserializer::with_options(&bytes[..], &[], options);
+///
+/// assert!(Vec::<u32>::deserialize(&mut de).is_err());
+/// #     Ok(())
+/// # }
+///
+/// # fn main() { run().unwrap(); }
+/// ```
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct DeserializerOptions {
+    max_total_bytes: Option<u64>,
+    max_vector_len: Option<u32>,
+    max_string_len: Option<usize>,
+    max_depth: Option<u32>,
+}
+
+impl DeserializerOptions {
+    /// Create options without any limits.
+    pub fn new() -> DeserializerOptions {
+        DeserializerOptions::default()
+    }
+
+    /// Limit the number of bytes read from the input in total.
+    pub fn max_total_bytes(self, max_total_bytes: u64) -> DeserializerOptions {
+        DeserializerOptions { max_total_bytes: Some(max_total_bytes), ..self }
+    }
+
+    /// Limit the number of elements in sequences and maps.
+    pub fn max_vector_len(self, max_vector_len: u32) -> DeserializerOptions {
+        DeserializerOptions { max_vector_len: Some(max_vector_len), ..self }
+    }
+
+    /// Limit the length of strings and byte sequences in bytes.
+    pub fn max_string_len(self, max_string_len: usize) -> DeserializerOptions {
+        DeserializerOptions { max_string_len: Some(max_string_len), ..self }
+    }
+
+    /// Limit how deep sequences, maps, structs and enums can be nested in
+    /// each other.
+    pub fn max_depth(self, max_depth: u32) -> DeserializerOptions {
+        DeserializerOptions { max_depth: Some(max_depth), ..self }
+    }
+}
+
+
 /// A structure that deserializes  MTProto binary representation into Rust values.
 #[derive(Debug)]
-pub struct Deserializer<'ids, R: io::Read> {
+pub struct Deserializer<'ids, R> {
     reader: R,
+    options: DeserializerOptions,
+    bytes_read: u64,
+    unpacked_bytes_read: u64,
+    depth: u32,
     enum_variant_ids: &'ids [&'static str],
+    enum_variants: Option<&'static [&'static str]>,
+    last_variant_id: Option<&'static str>,
+    flags_bit: Option<bool>,
+    read_flags: Option<(u32, &'static [&'static str])>,
+    path: Vec<PathSegment<'ids>>,
+    size_bound: Option<u64>,
+    registry: Option<&'ids Registry>,
+}
+
+/// A component of the path to the value being deserialized.
+#[derive(Clone, Copy, Debug)]
+enum PathSegment<'ids> {
+    Name(&'ids str),
+    Index(u32),
 }
 
-impl<'ids, R: io::Read> Deserializer<'ids, R> {
-    /// Create a MTProto deserializer from an `io::Read` and enum variant hint.
+/// Names of wrapper structs which don't show up in error paths.
+const TRANSPARENT_STRUCT_NAMES: &[&str] = &["Boxed", "GzipPacked", "SkippedWithSize", "WithSize"];
+
+impl<'ids, R> Deserializer<'ids, R> {
+    /// Create a MTProto deserializer from an `io::Read` (or any other
+    /// [`Read`]) and enum variant hint.
+    ///
+    /// The hint lists variant names of enums in the order they are
+    /// deserialized. Enums wrapped in [`Boxed`](../wrappers/struct.Boxed.html)
+    /// don't need it since their variants are selected using the type id
+    /// from the data, so the hint can be empty if there are no bare enums.
     pub fn new(reader: R, enum_variant_ids: &'ids [&'static str]) -> Deserializer<'ids, R> {
-        Deserializer { reader, enum_variant_ids }
+        Deserializer::with_options(reader, enum_variant_ids, DeserializerOptions::new())
+    }
+
+    /// Create a MTProto deserializer which enforces resource limits from
+    /// `options`.
+    pub fn with_options(reader: R,
+                        enum_variant_ids: &'ids [&'static str],
+                        options: DeserializerOptions)
+                       -> Deserializer<'ids, R> {
+        Deserializer {
+            reader,
+            options,
+            bytes_read: 0,
+            unpacked_bytes_read: 0,
+            depth: 0,
+            enum_variant_ids,
+            enum_variants: None,
+            last_variant_id: None,
+            flags_bit: None,
+            read_flags: None,
+            path: Vec::new(),
+            size_bound: None,
+            registry: None,
+        }
+    }
+
+    /// Use `registry` to deserialize boxed values of registered types via
+    /// `deserialize_any` and `deserialize_ignored_any`.
+    ///
+    /// See the [`registry`](../registry/index.html) module for details.
+    pub fn set_registry(&mut self, registry: &'ids Registry) {
+        self.registry = Some(registry);
     }
 
     /// Unwraps the `Deserializer` and returns the underlying `io::Read`.
@@ -30,6 +252,46 @@ impl<'ids, R: io::Read> Deserializer<'ids, R> {
         self.reader
     }
 
+    /// Attach the current offset and path to `error` unless it already
+    /// carries a more precise location.
+    fn contextualize(&self, error: error::Error) -> error::Error {
+        if error.de_context().is_some() {
+            return error;
+        }
+
+        let mut path = String::new();
+        for segment in &self.path {
+            match *segment {
+                PathSegment::Name(name) => {
+                    if !path.is_empty() {
+                        path.push('.');
+                    }
+                    path.push_str(name);
+                },
+                PathSegment::Index(index) => {
+                    path.push_str(&format!("[{}]", index));
+                },
+            }
+        }
+
+        error.with_de_context(DeErrorContext::new(self.bytes_read, path))
+    }
+}
+
+macro_rules! impl_read_fixed {
+    ($($read:ident -> $type:ty, $size:expr;)*) => {
+        $(
+            fn $read(&mut self) -> error::Result<$type> {
+                let mut buf = [0; $size];
+                self.read_exact(&mut buf)?;
+
+                Ok(LittleEndian::$read(&buf))
+            }
+        )*
+    };
+}
+
+impl<'de, 'ids, R: Read<'de>> Deserializer<'ids, R> {
     /// Consumes the `Deserializer` and returns remaining unprocessed bytes.
     pub fn remaining_bytes(mut self) -> error::Result<Vec<u8>> {
         let mut buf = Vec::new();
@@ -40,8 +302,206 @@ impl<'ids, R: io::Read> Deserializer<'ids, R> {
         Ok(buf)
     }
 
+    /// Check that `len` more bytes can be read without exceeding the limit.
+    fn check_consume(&self, len: usize) -> error::Result<u64> {
+        let bytes_read = self.bytes_read.saturating_add(safe_uint_cast::<usize, u64>(len)?);
+        self.check_total_bytes(bytes_read.saturating_add(self.unpacked_bytes_read))?;
+
+        if let Some(size_bound) = self.size_bound {
+            if bytes_read > size_bound {
+                bail!(DeErrorKind::SizeBoundExceeded(size_bound));
+            }
+        }
+
+        Ok(bytes_read)
+    }
+
+    /// Fail if `total_bytes` read from the input and from data unpacked out
+    /// of it exceed the limit.
+    fn check_total_bytes(&self, total_bytes: u64) -> error::Result<()> {
+        match self.options.max_total_bytes {
+            Some(max_total_bytes) if total_bytes > max_total_bytes => {
+                bail!(DeErrorKind::TotalBytesLimitExceeded(max_total_bytes));
+            },
+            _ => Ok(()),
+        }
+    }
+
+    /// Read and discard `len` bytes.
+    fn skip_bytes(&mut self, len: u64) -> error::Result<()> {
+        let mut buf = [0; 256];
+        let mut remaining = len;
+
+        while remaining > 0 {
+            let chunk_len = safe_uint_cast::<u64, usize>(remaining)
+                .map(|remaining| remaining.min(buf.len()))
+                .unwrap_or(buf.len());
+            let chunk = buf.get_mut(..chunk_len)
+                .unwrap_or_else(|| unreachable!("chunk length must not exceed the buffer length"));
+
+            self.read_exact(chunk)?;
+            remaining -= safe_uint_cast::<usize, u64>(chunk_len)?;
+        }
+
+        Ok(())
+    }
+
+    /// Run `f` which must consume exactly `size` bytes.
+    fn bounded<T, F>(&mut self, size: u32, f: F) -> error::Result<T>
+        where F: FnOnce(&mut Deserializer<'ids, R>) -> error::Result<T>
+    {
+        let start = self.bytes_read;
+        let end = start.saturating_add(u64::from(size));
+
+        if let Some(size_bound) = self.size_bound {
+            if end > size_bound {
+                bail!(DeErrorKind::SizeBoundExceeded(size_bound));
+            }
+        }
+
+        let outer_size_bound = self.size_bound.take();
+        self.size_bound = Some(end);
+        let result = f(self);
+        self.size_bound = outer_size_bound;
+        let value = result?;
+
+        if self.bytes_read != end {
+            let consumed = safe_uint_cast::<u64, u32>(self.bytes_read - start)?;
+            bail!(DeErrorKind::SizeMismatch(size, consumed));
+        }
+
+        Ok(value)
+    }
+
+    /// Give a (type id, data) pair to `visitor`, unpacking the data first if
+    /// it's `gzip_packed`.
+    ///
+    /// Packed data is deserialized with the same options, registry, path
+    /// and enum variant hint. Unpacked bytes count toward the total bytes
+    /// limit, and offsets in errors from them continue from the end of the
+    /// packed data as if they followed it.
+    #[cfg(feature = "gzip")]
+    fn deserialize_gzip_packed<V>(&mut self, max_unpacked_len: usize, visitor: V) -> error::Result<V::Value>
+        where V: Visitor<'de>
+    {
+        let type_id = self.read_u32()?;
+        if type_id != GZIP_PACKED_ID {
+            return visitor.visit_seq(TypeIdAccess::new(self, type_id));
+        }
+
+        let unpacked_data = self.read_gzip_packed(max_unpacked_len)?;
+        debug!("Deserializing gzip-packed data of unpacked length {}", unpacked_data.len());
+
+        let mut unpacked_de = Deserializer {
+            reader: unpacked_data.as_slice(),
+            options: self.options,
+            bytes_read: self.bytes_read,
+            unpacked_bytes_read: self.unpacked_bytes_read,
+            depth: self.depth,
+            enum_variant_ids: self.enum_variant_ids,
+            enum_variants: None,
+            last_variant_id: None,
+            flags_bit: None,
+            read_flags: None,
+            path: self.path.clone(),
+            size_bound: None,
+            registry: self.registry,
+        };
+
+        // Packed data may be packed once more, which is caught by depth limit
+        let value = de::Deserializer::deserialize_tuple_struct(
+            &mut unpacked_de, GZIP_PACKED_STRUCT_NAME, max_unpacked_len, visitor)?;
+        self.enum_variant_ids = unpacked_de.enum_variant_ids;
+        self.unpacked_bytes_read = unpacked_de.unpacked_bytes_read
+            .saturating_add(unpacked_de.bytes_read - self.bytes_read);
+
+        Ok(value)
+    }
+
+    /// Read the data of a `gzip_packed` value following its type id and
+    /// unpack it, failing if it's longer than `max_unpacked_len` or than
+    /// what's left of the total bytes limit.
+    #[cfg(feature = "gzip")]
+    pub(crate) fn read_gzip_packed(&mut self, max_unpacked_len: usize) -> error::Result<Vec<u8>> {
+        let packed_data = serde_bytes::ByteBuf::deserialize(&mut *self)?;
+        let total_bytes = self.bytes_read.saturating_add(self.unpacked_bytes_read);
+
+        // Don't unpack more than what's left of the total bytes limit
+        let unpack_len = match self.options.max_total_bytes {
+            Some(max_total_bytes) => safe_uint_cast::<u64, usize>(max_total_bytes.saturating_sub(total_bytes))
+                .map_or(max_unpacked_len, |total_bytes_left| total_bytes_left.min(max_unpacked_len)),
+            None => max_unpacked_len,
+        };
+        let unpacked_data = gzip_unpack_truncated(&packed_data, unpack_len)?;
+
+        if unpacked_data.len() > max_unpacked_len {
+            bail!(DeErrorKind::UnpackedLenLimitExceeded(max_unpacked_len));
+        }
+        self.check_total_bytes(total_bytes.saturating_add(safe_uint_cast::<usize, u64>(unpacked_data.len())?))?;
+
+        Ok(unpacked_data)
+    }
+
+    fn read_exact(&mut self, buf: &mut [u8]) -> error::Result<()> {
+        let bytes_read = self.check_consume(buf.len())?;
+        self.reader.read_exact(buf)?;
+        self.bytes_read = bytes_read;
+
+        Ok(())
+    }
+
+    fn check_vector_len(&self, len: u32) -> error::Result<()> {
+        match self.options.max_vector_len {
+            Some(max_vector_len) if len > max_vector_len => {
+                bail!(DeErrorKind::SeqLenLimitExceeded(len, max_vector_len));
+            },
+            _ => Ok(()),
+        }
+    }
+
+    /// Run `f` one nesting level deeper, adding `name` to the path unless
+    /// it's empty or the name of a transparent wrapper.
+    fn nested<T, F>(&mut self, name: &'ids str, f: F) -> error::Result<T>
+        where F: FnOnce(&mut Deserializer<'ids, R>) -> error::Result<T>
+    {
+        let path_len = self.path.len();
+
+        if !name.is_empty() && !TRANSPARENT_STRUCT_NAMES.contains(&name) {
+            self.path.push(PathSegment::Name(name));
+        }
+
+        let result = match self.options.max_depth {
+            Some(max_depth) if self.depth >= max_depth => {
+                Err(DeErrorKind::DepthLimitExceeded(max_depth).into())
+            },
+            _ => {
+                self.depth += 1;
+                let result = f(self);
+                self.depth -= 1;
+
+                result
+            },
+        };
+
+        let result = result.map_err(|e| self.contextualize(e));
+        self.path.truncate(path_len);
+
+        result
+    }
+
+    impl_read_fixed! {
+        read_u24 -> u32, 3;
+        read_i32 -> i32, 4;
+        read_u32 -> u32, 4;
+        read_i64 -> i64, 8;
+        read_u64 -> u64, 8;
+        read_f64 -> f64, 8;
+    }
+
     fn get_str_info(&mut self) -> error::Result<(usize, usize)> {
-        let first_byte = self.reader.read_u8()?;
+        let mut first_byte_buf = [0; 1];
+        self.read_exact(&mut first_byte_buf)?;
+        let [first_byte] = first_byte_buf;
         let len;
         let rem;
 
@@ -51,7 +511,7 @@ impl<'ids, R: io::Read> Deserializer<'ids, R> {
                 rem = (len + 1) % 4;
             },
             254 => {
-                let uncasted = self.reader.read_u24::<LittleEndian>()?;
+                let uncasted = self.read_u24()?;
                 if uncasted <= 253 {
                     bail!(DeErrorKind::BytesLenPrefix254LessThan254(uncasted));
                 }
@@ -68,6 +528,12 @@ impl<'ids, R: io::Read> Deserializer<'ids, R> {
             _ => unreachable!("other match arms should have exhaustively covered every value"),
         }
 
+        if let Some(max_string_len) = self.options.max_string_len {
+            if len > max_string_len {
+                bail!(DeErrorKind::BytesLenLimitExceeded(len, max_string_len));
+            }
+        }
+
         let padding = (4 - rem) % 4;
 
         Ok((len, padding))
@@ -83,19 +549,141 @@ impl<'ids, R: io::Read> Deserializer<'ids, R> {
     fn read_byte_buf(&mut self) -> error::Result<Vec<u8>> {
         let (len, padding) = self.get_str_info()?;
 
+        // Check limits before allocating
+        let bytes_read = self.check_consume(len)?;
         let mut b = vec![0; len];
         self.reader.read_exact(&mut b)?;
+        self.bytes_read = bytes_read;
+        self.read_padding(padding)?;
+
+        Ok(b)
+    }
 
+    /// Read a byte sequence without copying if the reader allows borrowing.
+    fn read_maybe_borrowed_bytes(&mut self) -> error::Result<MaybeBorrowed<'de>> {
+        let (len, padding) = self.get_str_info()?;
+
+        // Check limits before allocating
+        let bytes_read = self.check_consume(len)?;
+
+        let b = match self.reader.borrow_bytes(len)? {
+            Some(b) => MaybeBorrowed::Borrowed(b),
+            None => {
+                let mut b = vec![0; len];
+                self.reader.read_exact(&mut b)?;
+                MaybeBorrowed::Owned(b)
+            },
+        };
+        self.bytes_read = bytes_read;
+
+        self.read_padding(padding)?;
+
+        Ok(b)
+    }
+
+    fn read_padding(&mut self, padding: usize) -> error::Result<()> {
         let mut p = [0; 3];
         let ps = p.get_mut(0..padding)
             .unwrap_or_else(|| unreachable!("padding must be of length 3 or less"));
-        self.reader.read_exact(ps)?;
+        self.read_exact(ps)?;
 
         if ps.iter().any(|b| *b != 0) {
             bail!(DeErrorKind::NonZeroBytesPadding);
         }
 
-        Ok(b)
+        Ok(())
+    }
+
+    fn read_flags(&mut self, layout: &'static [&'static str]) -> error::Result<u32> {
+        let value = self.read_u32()?;
+        debug!("Deserialized flags: {:#x}", value);
+        self.read_flags = Some((value, layout));
+
+        Ok(value)
+    }
+
+    fn registered_constructor(&self, type_id: u32) -> error::Result<&'ids Constructor> {
+        self.registry
+            .and_then(|registry| registry.get(type_id))
+            .ok_or_else(|| error::Error::from(DeErrorKind::UnregisteredTypeId(type_id)))
+    }
+
+    /// Deserialize a value of TL type `ty`, looking up constructor layouts
+    /// in the registry.
+    fn deserialize_tl<V>(&mut self, ty: &'ids TlType, visitor: V) -> error::Result<V::Value>
+        where V: Visitor<'de>
+    {
+        match *ty {
+            TlType::Int => de::Deserializer::deserialize_i32(self, visitor),
+            TlType::Long => de::Deserializer::deserialize_i64(self, visitor),
+            TlType::Double => de::Deserializer::deserialize_f64(self, visitor),
+            TlType::Bool => de::Deserializer::deserialize_bool(self, visitor),
+            TlType::String => de::Deserializer::deserialize_str(self, visitor),
+            TlType::Bytes => de::Deserializer::deserialize_bytes(self, visitor),
+            TlType::Int128 => self.deserialize_tl_chunks(4, visitor),
+            TlType::Int256 => self.deserialize_tl_chunks(8, visitor),
+            TlType::Flags => de::Deserializer::deserialize_u32(self, visitor),
+            TlType::True => visitor.visit_bool(true),
+            TlType::Vector(ref elem_ty) => self.deserialize_tl_vector(elem_ty, visitor),
+            TlType::BoxedVector(ref elem_ty) => {
+                let type_id = self.read_u32()?;
+                if type_id != VECTOR_ID {
+                    bail!(DeErrorKind::TypeIdMismatch(type_id, VECTOR_ID));
+                }
+
+                self.deserialize_tl_vector(elem_ty, visitor)
+            },
+            TlType::Bare(type_id) => {
+                let constructor = self.registered_constructor(type_id)?;
+                self.deserialize_tl_constructor(constructor, visitor)
+            },
+            TlType::Boxed => {
+                let type_id = self.read_u32()?;
+                debug!("Deserialized type id: {:#x}", type_id);
+
+                match type_id {
+                    BOOL_FALSE_ID => visitor.visit_bool(false),
+                    BOOL_TRUE_ID => visitor.visit_bool(true),
+                    INT_ID => de::Deserializer::deserialize_i32(self, visitor),
+                    LONG_ID => de::Deserializer::deserialize_i64(self, visitor),
+                    DOUBLE_ID => de::Deserializer::deserialize_f64(self, visitor),
+                    STRING_ID => de::Deserializer::deserialize_str(self, visitor),
+                    _ => {
+                        let constructor = self.registered_constructor(type_id)?;
+                        self.deserialize_tl_constructor(constructor, visitor)
+                    },
+                }
+            },
+        }
+    }
+
+    /// Deserialize `int128` or `int256` as a sequence of 32-bit chunks.
+    fn deserialize_tl_chunks<V>(&mut self, count: u32, visitor: V) -> error::Result<V::Value>
+        where V: Visitor<'de>
+    {
+        let mut chunks = Vec::new();
+        for _ in 0..count {
+            chunks.push(self.read_u32()?);
+        }
+
+        visitor.visit_seq(de::value::SeqDeserializer::<_, error::Error>::new(chunks.into_iter()))
+    }
+
+    fn deserialize_tl_vector<V>(&mut self, elem_ty: &'ids TlType, visitor: V) -> error::Result<V::Value>
+        where V: Visitor<'de>
+    {
+        let len = self.read_u32()?;
+        debug!("Deserializing vector of len {}", len);
+        self.check_vector_len(len)?;
+
+        self.nested("", |de| visitor.visit_seq(TlSeqAccess::new(de, elem_ty, len)))
+    }
+
+    fn deserialize_tl_constructor<V>(&mut self, constructor: &'ids Constructor, visitor: V) -> error::Result<V::Value>
+        where V: Visitor<'de>
+    {
+        debug!("Deserializing constructor {} ({:#x})", constructor.name, constructor.id);
+        self.nested(&constructor.name, |de| visitor.visit_map(ConstructorAccess::new(de, constructor)))
     }
 }
 
@@ -106,15 +694,22 @@ impl<'ids, 'a> Deserializer<'ids, &'a [u8]> {
     }
 }
 
+impl<'ids, 'a> Deserializer<'ids, SliceRead<'a>> {
+    /// Length of unprocessed data in the byte buffer.
+    pub fn remaining_length(&self) -> usize {
+        self.reader.slice.len()
+    }
+}
+
 
 macro_rules! impl_deserialize_small_int {
     ($small_type:ty, $small_deserialize:ident, $cast:ident,
-     $big_read:ident::<$big_endianness:ident>, $small_visit:ident
+     $big_read:ident, $small_visit:ident
     ) => {
         fn $small_deserialize<V>(self, visitor: V) -> error::Result<V::Value>
             where V: Visitor<'de>
         {
-            let value = self.reader.$big_read::<$big_endianness>()?;
+            let value = self.$big_read()?;
             debug!("Deserialized big int: {:#x}", value);
             let casted = $cast(value)?;
             debug!("Casted to {}: {:#x}", stringify!($small_type), casted);
@@ -125,11 +720,11 @@ macro_rules! impl_deserialize_small_int {
 }
 
 macro_rules! impl_deserialize_big_int {
-    ($type:ty, $deserialize:ident, $read:ident::<$endianness:ident>, $visit:ident) => {
+    ($type:ty, $deserialize:ident, $read:ident, $visit:ident) => {
         fn $deserialize<V>(self, visitor: V) -> error::Result<V::Value>
             where V: Visitor<'de>
         {
-            let value = self.reader.$read::<$endianness>()?;
+            let value = self.$read()?;
             debug!("Deserialized {}: {:#x}", stringify!($type), value);
 
             visitor.$visit(value)
@@ -138,20 +733,30 @@ macro_rules! impl_deserialize_big_int {
 }
 
 impl<'de, 'a, 'ids, R> de::Deserializer<'de> for &'a mut Deserializer<'ids, R>
-    where R: io::Read
+    where R: Read<'de>
 {
     type Error = error::Error;
 
-    fn deserialize_any<V>(self, _visitor: V) -> error::Result<V::Value>
+    fn deserialize_any<V>(self, visitor: V) -> error::Result<V::Value>
         where V: Visitor<'de>
     {
-        bail!(DeErrorKind::UnsupportedSerdeType(DeSerdeType::Any));
+        // Only boxed values of registered types describe themselves
+        match self.registry {
+            Some(_) => self.deserialize_tl(&TlType::Boxed, visitor),
+            None => bail!(DeErrorKind::UnsupportedSerdeType(DeSerdeType::Any)),
+        }
     }
 
     fn deserialize_bool<V>(self, visitor: V) -> error::Result<V::Value>
         where V: Visitor<'de>
     {
-        let id_value = self.reader.read_u32::<LittleEndian>()?;
+        if let Some(bit_value) = self.flags_bit.take() {
+            // `flags.N?true` fields are represented by their flags bit only
+            debug!("Deserialized bool from a flags bit: {}", bit_value);
+            return visitor.visit_bool(bit_value);
+        }
+
+        let id_value = self.read_u32()?;
 
         let value = match id_value {
             BOOL_FALSE_ID => false,
@@ -168,32 +773,32 @@ impl<'de, 'a, 'ids, R> de::Deserializer<'de> for &'a mut Deserializer<'ids, R>
         visitor.visit_bool(value)
     }
 
-    impl_deserialize_small_int!(i8,  deserialize_i8,  safe_int_cast, read_i32::<LittleEndian>, visit_i8);
-    impl_deserialize_small_int!(i16, deserialize_i16, safe_int_cast, read_i32::<LittleEndian>, visit_i16);
-    impl_deserialize_big_int!(i32, deserialize_i32, read_i32::<LittleEndian>, visit_i32);
-    impl_deserialize_big_int!(i64, deserialize_i64, read_i64::<LittleEndian>, visit_i64);
+    impl_deserialize_small_int!(i8,  deserialize_i8,  safe_int_cast, read_i32, visit_i8);
+    impl_deserialize_small_int!(i16, deserialize_i16, safe_int_cast, read_i32, visit_i16);
+    impl_deserialize_big_int!(i32, deserialize_i32, read_i32, visit_i32);
+    impl_deserialize_big_int!(i64, deserialize_i64, read_i64, visit_i64);
 
     fn deserialize_i128<V>(self, visitor: V) -> error::Result<V::Value>
         where V: Visitor<'de>
     {
-        let lo = self.reader.read_u64::<LittleEndian>()?;
-        let hi = self.reader.read_i64::<LittleEndian>()?;
+        let lo = self.read_u64()?;
+        let hi = self.read_i64()?;
         let value = i128_from_parts(hi, lo);
         debug!("Deserialized i128: {:#x}", value);
 
         visitor.visit_i128(value)
     }
 
-    impl_deserialize_small_int!(u8,  deserialize_u8,  safe_uint_cast, read_u32::<LittleEndian>, visit_u8);
-    impl_deserialize_small_int!(u16, deserialize_u16, safe_uint_cast, read_u32::<LittleEndian>, visit_u16);
-    impl_deserialize_big_int!(u32, deserialize_u32, read_u32::<LittleEndian>, visit_u32);
-    impl_deserialize_big_int!(u64, deserialize_u64, read_u64::<LittleEndian>, visit_u64);
+    impl_deserialize_small_int!(u8,  deserialize_u8,  safe_uint_cast, read_u32, visit_u8);
+    impl_deserialize_small_int!(u16, deserialize_u16, safe_uint_cast, read_u32, visit_u16);
+    impl_deserialize_big_int!(u32, deserialize_u32, read_u32, visit_u32);
+    impl_deserialize_big_int!(u64, deserialize_u64, read_u64, visit_u64);
 
     fn deserialize_u128<V>(self, visitor: V) -> error::Result<V::Value>
         where V: Visitor<'de>
     {
-        let lo = self.reader.read_u64::<LittleEndian>()?;
-        let hi = self.reader.read_u64::<LittleEndian>()?;
+        let lo = self.read_u64()?;
+        let hi = self.read_u64()?;
         let value = u128_from_parts(hi, lo);
         debug!("Deserialized u128: {:#x}", value);
 
@@ -203,7 +808,7 @@ impl<'de, 'a, 'ids, R> de::Deserializer<'de> for &'a mut Deserializer<'ids, R>
     fn deserialize_f32<V>(self, visitor: V) -> error::Result<V::Value>
         where V: Visitor<'de>
     {
-        let value = self.reader.read_f64::<LittleEndian>()?;
+        let value = self.read_f64()?;
         debug!("Deserialized big float: {}", value);
 
         let casted = safe_float_cast(value)?;
@@ -215,7 +820,7 @@ impl<'de, 'a, 'ids, R> de::Deserializer<'de> for &'a mut Deserializer<'ids, R>
     fn deserialize_f64<V>(self, visitor: V) -> error::Result<V::Value>
         where V: Visitor<'de>
     {
-        let value = self.reader.read_f64::<LittleEndian>()?;
+        let value = self.read_f64()?;
         debug!("Deserialized f64: {}", value);
 
         visitor.visit_f64(value)
@@ -230,9 +835,18 @@ impl<'de, 'a, 'ids, R> de::Deserializer<'de> for &'a mut Deserializer<'ids, R>
     fn deserialize_str<V>(self, visitor: V) -> error::Result<V::Value>
         where V: Visitor<'de>
     {
-        let s = self.read_string()?;
-        debug!("Deserialized str: {:?}", s);
-        visitor.visit_str(&s)
+        match self.read_maybe_borrowed_bytes()? {
+            MaybeBorrowed::Borrowed(b) => {
+                let s = str::from_utf8(b).map_err(|_| from_utf8_error(b))?;
+                debug!("Deserialized borrowed str: {:?}", s);
+                visitor.visit_borrowed_str(s)
+            },
+            MaybeBorrowed::Owned(b) => {
+                let s = String::from_utf8(b)?;
+                debug!("Deserialized str: {:?}", s);
+                visitor.visit_str(&s)
+            },
+        }
     }
 
     fn deserialize_string<V>(self, visitor: V) -> error::Result<V::Value>
@@ -246,9 +860,16 @@ impl<'de, 'a, 'ids, R> de::Deserializer<'de> for &'a mut Deserializer<'ids, R>
     fn deserialize_bytes<V>(self, visitor: V) -> error::Result<V::Value>
         where V: Visitor<'de>
     {
-        let b = self.read_byte_buf()?;
-        debug!("Deserialized bytes: {:?}", b);
-        visitor.visit_bytes(&b)
+        match self.read_maybe_borrowed_bytes()? {
+            MaybeBorrowed::Borrowed(b) => {
+                debug!("Deserialized borrowed bytes: {:?}", b);
+                visitor.visit_borrowed_bytes(b)
+            },
+            MaybeBorrowed::Owned(b) => {
+                debug!("Deserialized bytes: {:?}", b);
+                visitor.visit_bytes(&b)
+            },
+        }
     }
 
     fn deserialize_byte_buf<V>(self, visitor: V) -> error::Result<V::Value>
@@ -259,10 +880,20 @@ impl<'de, 'a, 'ids, R> de::Deserializer<'de> for &'a mut Deserializer<'ids, R>
         visitor.visit_byte_buf(b)
     }
 
-    fn deserialize_option<V>(self, _visitor: V) -> error::Result<V::Value>
+    fn deserialize_option<V>(self, visitor: V) -> error::Result<V::Value>
         where V: Visitor<'de>
     {
-        bail!(DeErrorKind::UnsupportedSerdeType(DeSerdeType::Option));
+        match self.flags_bit.take() {
+            Some(true) => {
+                debug!("Deserializing some from a set flags bit");
+                visitor.visit_some(self)
+            },
+            Some(false) => {
+                debug!("Deserialized none from an unset flags bit");
+                visitor.visit_none()
+            },
+            None => bail!(DeErrorKind::UnsupportedSerdeType(DeSerdeType::Option)),
+        }
     }
 
     fn deserialize_unit<V>(self, _visitor: V) -> error::Result<V::Value>
@@ -288,85 +919,185 @@ impl<'de, 'a, 'ids, R> de::Deserializer<'de> for &'a mut Deserializer<'ids, R>
     fn deserialize_seq<V>(self, visitor: V) -> error::Result<V::Value>
         where V: Visitor<'de>
     {
-        let len = self.reader.read_u32::<LittleEndian>()?;
+        let len = self.read_u32()?;
         debug!("Deserializing seq of len {}", len);
+        self.check_vector_len(len)?;
 
-        visitor.visit_seq(SeqAccess::new(self, len))
+        self.nested("", |de| visitor.visit_seq(SeqAccess::new(de, len)))
     }
 
     fn deserialize_tuple<V>(self, len: usize, visitor: V) -> error::Result<V::Value>
         where V: Visitor<'de>
     {
         debug!("Deserializing tuple of len {}", len);
-        visitor.visit_seq(SeqAccess::new(self, safe_uint_cast(len)?))
+        let len = safe_uint_cast(len)?;
+        self.nested("", |de| visitor.visit_seq(SeqAccess::new(de, len)))
     }
 
     fn deserialize_tuple_struct<V>(self, name: &'static str, len: usize, visitor: V) -> error::Result<V::Value>
         where V: Visitor<'de>
     {
+        #[cfg(feature = "gzip")]
+        {
+            if name == GZIP_PACKED_STRUCT_NAME {
+                debug!("Deserializing possibly gzip-packed struct {}", name);
+                return self.nested(name, |de| de.deserialize_gzip_packed(len, visitor));
+            }
+        }
+
         debug!("Deserializing tuple struct {} of len {}", name, len);
-        visitor.visit_seq(SeqAccess::new(self, safe_uint_cast(len)?))
+        let len = safe_uint_cast(len)?;
+        self.nested(name, |de| visitor.visit_seq(SeqAccess::new(de, len)))
     }
 
     fn deserialize_map<V>(self, visitor: V) -> error::Result<V::Value>
         where V: Visitor<'de>
     {
-        let len = self.reader.read_u32::<LittleEndian>()?;
+        let len = self.read_u32()?;
         debug!("Deserializing map of len {}", len);
+        self.check_vector_len(len)?;
 
-        visitor.visit_map(MapAccess::new(self, len))
+        self.nested("", |de| visitor.visit_map(MapAccess::new(de, len)))
     }
 
     fn deserialize_struct<V>(self, name: &'static str, fields: &'static [&'static str], visitor: V) -> error::Result<V::Value>
         where V: Visitor<'de>
     {
+        if name == FLAGS_STRUCT_NAME {
+            let value = self.read_flags(fields)?;
+            return visitor.visit_u32(value);
+        }
+
+        if name == WITH_SIZE_STRUCT_NAME && fields.len() == 2 {
+            debug!("Deserializing sized struct {} with fields {:?}", name, fields);
+            let size = self.read_u32()?;
+            return self.nested(name, |de| visitor.visit_seq(SizedSeqAccess::new(de, size)));
+        }
+
+        if name == SKIPPED_WITH_SIZE_STRUCT_NAME && fields.len() == 2 {
+            debug!("Skipping sized struct {} with fields {:?}", name, fields);
+            let size = self.read_u32()?;
+            return self.nested(name, |de| visitor.visit_seq(SizedSeqAccess::skipping(de, size)));
+        }
+
         debug!("Deserializing struct {} with fields {:?}", name, fields);
-        visitor.visit_seq(SeqAccess::new(self, safe_uint_cast(fields.len())?))
+        self.nested(name, |de| {
+            let seq = SeqAccess::with_fields(de, fields, None)?;
+            visitor.visit_seq(SeqAccess { transparent: TRANSPARENT_STRUCT_NAMES.contains(&name), ..seq })
+        })
     }
 
     fn deserialize_enum<V>(self, name: &'static str, variants: &'static [&'static str], visitor: V) -> error::Result<V::Value>
         where V: Visitor<'de>
     {
         debug!("Deserializing enum {} with variants {:?}", name, variants);
-        visitor.visit_enum(EnumVariantAccess::new(self))
+        self.enum_variants = Some(variants);
+        self.nested(name, |de| visitor.visit_enum(EnumVariantAccess::new(de)))
     }
 
     fn deserialize_identifier<V>(self, visitor: V) -> error::Result<V::Value>
         where V: Visitor<'de>
     {
         debug!("Deserializing identifier");
-        let (variant_id, rest) = self.enum_variant_ids.split_first()
-            .ok_or_else(|| error::Error::from(DeErrorKind::NoEnumVariantId))?;
+        let variant_id = match self.enum_variants.take() {
+            // The variant is already known, e.g. from the type id of a `Boxed` enum.
+            Some(&[only_variant]) => {
+                // Skip a hint for this variant to stay compatible with exhaustive hint lists
+                if let Some((&hinted_variant_id, rest)) = self.enum_variant_ids.split_first() {
+                    if hinted_variant_id == only_variant {
+                        self.enum_variant_ids = rest;
+                    }
+                }
+
+                only_variant
+            },
+            _ => {
+                let (&variant_id, rest) = self.enum_variant_ids.split_first()
+                    .ok_or_else(|| error::Error::from(DeErrorKind::NoEnumVariantId))?;
+
+                self.enum_variant_ids = rest;
+                variant_id
+            },
+        };
 
         debug!("Deserialized variant_id {}", variant_id);
-        self.enum_variant_ids = rest;
+        self.last_variant_id = Some(variant_id);
 
         visitor.visit_str(variant_id)
     }
 
-    fn deserialize_ignored_any<V>(self, _visitor: V) -> error::Result<V::Value>
+    fn deserialize_ignored_any<V>(self, visitor: V) -> error::Result<V::Value>
         where V: Visitor<'de>
     {
-        bail!(DeErrorKind::UnsupportedSerdeType(DeSerdeType::IgnoredAny));
+        // Only a boxed value of a registered type can be skipped since the
+        // data itself doesn't tell how long a value is. Sized values are
+        // skipped as a whole with `SkippedWithSize`.
+        match self.registry {
+            Some(_) => {
+                debug!("Skipping a registered boxed value");
+                let de::IgnoredAny = self.deserialize_tl(&TlType::Boxed, de::IgnoredAny)?;
+                visitor.visit_unit()
+            },
+            None => bail!(DeErrorKind::UnsupportedSerdeType(DeSerdeType::IgnoredAny)),
+        }
     }
 }
 
 
 #[derive(Debug)]
-struct SeqAccess<'a, 'ids: 'a, R: io::Read> {
+struct SeqAccess<'a, 'ids, R> {
     de: &'a mut Deserializer<'ids, R>,
     len: u32,
     next_index: u32,
+    fields: &'static [&'static str],
+    variant: Option<&'static str>,
+    flags_bits: Vec<(&'static str, bool)>,
+    transparent: bool,
 }
 
-impl<'a, 'ids, R: io::Read> SeqAccess<'a, 'ids, R> {
+impl<'a, 'ids, R> SeqAccess<'a, 'ids, R> {
     fn new(de: &'a mut Deserializer<'ids, R>, len: u32) -> SeqAccess<'a, 'ids, R> {
-        SeqAccess { de, len, next_index: 0 }
+        SeqAccess {
+            de,
+            len,
+            next_index: 0,
+            fields: &[],
+            variant: None,
+            flags_bits: Vec::new(),
+            transparent: false,
+        }
+    }
+
+    fn with_fields(de: &'a mut Deserializer<'ids, R>,
+                   fields: &'static [&'static str],
+                   variant: Option<&'static str>)
+                  -> error::Result<SeqAccess<'a, 'ids, R>> {
+        let len = safe_uint_cast(fields.len())?;
+
+        Ok(SeqAccess { fields, variant, ..SeqAccess::new(de, len) })
+    }
+
+    fn bind_flags(&mut self, flags_field: &'static str, value: u32, layout: &'static [&'static str])
+        -> error::Result<()>
+    {
+        for &entry in layout {
+            let parsed = FlagsLayoutEntry::parse(entry)
+                .ok_or_else(|| error::Error::from(DeErrorKind::InvalidFlagsLayoutEntry(entry)))?;
+
+            if parsed.is_bound_to(self.variant, flags_field) {
+                let bit_value = value & (1 << parsed.bit) != 0;
+                debug!("Bound field {} to flags bit {}.{} => {}",
+                    parsed.field, flags_field, parsed.bit, bit_value);
+                self.flags_bits.push((parsed.field, bit_value));
+            }
+        }
+
+        Ok(())
     }
 }
 
 impl<'de, 'a, 'ids, R> de::SeqAccess<'de> for SeqAccess<'a, 'ids, R>
-    where R: 'a + io::Read
+    where R: 'a + Read<'de>
 {
     type Error = error::Error;
 
@@ -380,8 +1111,46 @@ impl<'de, 'a, 'ids, R> de::SeqAccess<'de> for SeqAccess<'a, 'ids, R>
             return Ok(None);
         }
 
-        debug!("Deserializing sequence element");
-        seed.deserialize(&mut *self.de).map(Some)
+        let field = safe_uint_cast::<u32, usize>(self.next_index - 1).ok()
+            .and_then(|index| self.fields.get(index))
+            .cloned();
+
+        let path_len = self.de.path.len();
+
+        let flags_bit = field.and_then(|field| {
+            self.flags_bits.iter()
+                .find(|&&(bound_field, _)| bound_field == field)
+                .map(|&(_, bit_value)| bit_value)
+        });
+
+        // Values nested in a conditional field of another type must not
+        // consume its flags bit, which is then reported as not optional.
+        let outer_flags_bit = mem::replace(&mut self.de.flags_bit, flags_bit);
+
+        if let Some(field) = field {
+            debug!("Deserializing field {}", field);
+
+            if !self.transparent {
+                self.de.path.push(PathSegment::Name(field));
+            }
+        } else {
+            debug!("Deserializing sequence element");
+            self.de.path.push(PathSegment::Index(self.next_index - 1));
+        }
+
+        // On error the path is truncated by the enclosing `Deserializer::nested()`
+        let value = seed.deserialize(&mut *self.de)?;
+        self.de.path.truncate(path_len);
+
+        if mem::replace(&mut self.de.flags_bit, outer_flags_bit).is_some() {
+            bail!(DeErrorKind::FlaggedFieldNotOptional(field.unwrap_or("")));
+        }
+
+        if let (Some((flags_value, layout)), Some(field)) = (self.de.read_flags.take(), field) {
+            self.bind_flags(field, flags_value, layout)?;
+        }
+
+        Ok(Some(value))
     }
 
     fn size_hint(&self) -> Option<usize> {
@@ -390,21 +1159,134 @@ impl<'de, 'a, 'ids, R> de::SeqAccess<'de> for SeqAccess<'a, 'ids, R>
 }
 
 
+/// Gives the size read beforehand as the first element and bounds the
+/// second one by that size, or skips that many bytes in place of the second
+/// one for a `SkippedWithSize`.
+#[derive(Debug)]
+struct SizedSeqAccess<'a, 'ids, R> {
+    de: &'a mut Deserializer<'ids, R>,
+    size: u32,
+    next_index: u32,
+    skip: bool,
+}
+
+impl<'a, 'ids, R> SizedSeqAccess<'a, 'ids, R> {
+    fn new(de: &'a mut Deserializer<'ids, R>, size: u32) -> SizedSeqAccess<'a, 'ids, R> {
+        SizedSeqAccess { de, size, next_index: 0, skip: false }
+    }
+
+    fn skipping(de: &'a mut Deserializer<'ids, R>, size: u32) -> SizedSeqAccess<'a, 'ids, R> {
+        SizedSeqAccess { de, size, next_index: 0, skip: true }
+    }
+}
+
+impl<'de, 'a, 'ids, R> de::SeqAccess<'de> for SizedSeqAccess<'a, 'ids, R>
+    where R: 'a + Read<'de>
+{
+    type Error = error::Error;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> error::Result<Option<T::Value>>
+        where T: DeserializeSeed<'de>
+    {
+        self.next_index += 1;
+
+        match self.next_index {
+            1 => seed.deserialize(self.size.into_deserializer()).map(Some),
+            2 if self.skip => {
+                debug!("Skipping value of size {}", self.size);
+                self.de.skip_bytes(u64::from(self.size))?;
+                seed.deserialize(().into_deserializer()).map(Some)
+            },
+            2 => {
+                debug!("Deserializing value bounded by size {}", self.size);
+                let flags_bit = self.de.flags_bit.take();
+                let value = self.de.bounded(self.size, |de| seed.deserialize(de))?;
+                self.de.flags_bit = flags_bit;
+
+                Ok(Some(value))
+            },
+            _ => {
+                debug!("SizedSeqAccess::next_element_seed() is called when no elements is left to deserialize");
+                self.next_index = 3;
+                Ok(None)
+            },
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        match self.next_index {
+            0 => Some(2),
+            1 => Some(1),
+            _ => Some(0),
+        }
+    }
+}
+
+
+/// Gives the type id read beforehand as the first element and
+/// deserializes the second one in place.
+#[cfg(feature = "gzip")]
 #[derive(Debug)]
-struct MapAccess<'a, 'ids: 'a, R: io::Read> {
+struct TypeIdAccess<'a, 'ids, R> {
+    de: &'a mut Deserializer<'ids, R>,
+    type_id: u32,
+    next_index: u32,
+}
+
+#[cfg(feature = "gzip")]
+impl<'a, 'ids, R> TypeIdAccess<'a, 'ids, R> {
+    fn new(de: &'a mut Deserializer<'ids, R>, type_id: u32) -> TypeIdAccess<'a, 'ids, R> {
+        TypeIdAccess { de, type_id, next_index: 0 }
+    }
+}
+
+#[cfg(feature = "gzip")]
+impl<'de, 'a, 'ids, R> de::SeqAccess<'de> for TypeIdAccess<'a, 'ids, R>
+    where R: 'a + Read<'de>
+{
+    type Error = error::Error;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> error::Result<Option<T::Value>>
+        where T: DeserializeSeed<'de>
+    {
+        self.next_index += 1;
+
+        match self.next_index {
+            1 => seed.deserialize(self.type_id.into_deserializer()).map(Some),
+            2 => seed.deserialize(&mut *self.de).map(Some),
+            _ => {
+                debug!("TypeIdAccess::next_element_seed() is called when no elements is left to deserialize");
+                self.next_index = 3;
+                Ok(None)
+            },
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        match self.next_index {
+            0 => Some(2),
+            1 => Some(1),
+            _ => Some(0),
+        }
+    }
+}
+
+
+#[derive(Debug)]
+struct MapAccess<'a, 'ids, R> {
     de: &'a mut Deserializer<'ids, R>,
     len: u32,
     next_index: u32,
 }
 
-impl<'a, 'ids, R: io::Read> MapAccess<'a, 'ids, R> {
+impl<'a, 'ids, R> MapAccess<'a, 'ids, R> {
     fn new(de: &'a mut Deserializer<'ids, R>, len: u32) -> MapAccess<'a, 'ids, R> {
         MapAccess { de, len, next_index: 0 }
     }
 }
 
 impl<'de, 'a, 'ids, R> de::MapAccess<'de> for MapAccess<'a, 'ids, R>
-    where R: 'a + io::Read
+    where R: 'a + Read<'de>
 {
     type Error = error::Error;
 
@@ -419,14 +1301,28 @@ impl<'de, 'a, 'ids, R> de::MapAccess<'de> for MapAccess<'a, 'ids, R>
         }
 
         debug!("Deserializing map key");
-        seed.deserialize(&mut *self.de).map(Some)
+        let path_len = self.de.path.len();
+        self.de.path.push(PathSegment::Index(self.next_index - 1));
+        let flags_bit = self.de.flags_bit.take();
+        let key = seed.deserialize(&mut *self.de)?;
+        self.de.flags_bit = flags_bit;
+        self.de.path.truncate(path_len);
+
+        Ok(Some(key))
     }
 
     fn next_value_seed<V>(&mut self, seed: V) -> error::Result<V::Value>
         where V: DeserializeSeed<'de>
     {
         debug!("Deserializing map value");
-        seed.deserialize(&mut *self.de)
+        let path_len = self.de.path.len();
+        self.de.path.push(PathSegment::Index(self.next_index - 1));
+        let flags_bit = self.de.flags_bit.take();
+        let value = seed.deserialize(&mut *self.de)?;
+        self.de.flags_bit = flags_bit;
+        self.de.path.truncate(path_len);
+
+        Ok(value)
     }
 
     fn size_hint(&self) -> Option<usize> {
@@ -435,35 +1331,213 @@ impl<'de, 'a, 'ids, R> de::MapAccess<'de> for MapAccess<'a, 'ids, R>
 }
 
 
+/// Name of the key which holds the constructor name in maps given by
+/// `deserialize_any`.
+const CONSTRUCTOR_NAME_KEY: &str = "_";
+
+/// Gives the constructor name and then fields of a registered constructor
+/// as map entries.
 #[derive(Debug)]
-struct EnumVariantAccess<'a, 'ids: 'a, R: io::Read> {
+struct ConstructorAccess<'a, 'ids, R> {
     de: &'a mut Deserializer<'ids, R>,
+    constructor: &'ids Constructor,
+    next_index: usize,
+    flags: Vec<(&'ids str, u32)>,
 }
 
-impl<'a, 'ids, R: io::Read> EnumVariantAccess<'a, 'ids, R> {
+impl<'a, 'ids, R> ConstructorAccess<'a, 'ids, R> {
+    fn new(de: &'a mut Deserializer<'ids, R>, constructor: &'ids Constructor) -> ConstructorAccess<'a, 'ids, R> {
+        ConstructorAccess { de, constructor, next_index: 0, flags: Vec::new() }
+    }
+
+    fn flags_bit(&self, field: &'ids Field) -> error::Result<Option<bool>> {
+        let condition = match field.condition {
+            Some(ref condition) => condition,
+            None => return Ok(None),
+        };
+
+        let flags_value = self.flags.iter()
+            .find(|&&(flags_field, _)| flags_field == condition.flags_field)
+            .map(|&(_, flags_value)| flags_value)
+            .ok_or_else(|| error::Error::from(DeErrorKind::UnknownFlagsField(condition.flags_field.clone())))?;
+
+        Ok(Some(flags_value.checked_shr(condition.bit).unwrap_or(0) & 1 != 0))
+    }
+}
+
+impl<'de, 'a, 'ids, R> de::MapAccess<'de> for ConstructorAccess<'a, 'ids, R>
+    where R: 'a + Read<'de>
+{
+    type Error = error::Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> error::Result<Option<K::Value>>
+        where K: DeserializeSeed<'de>
+    {
+        let key = match self.next_index.checked_sub(1) {
+            None => CONSTRUCTOR_NAME_KEY,
+            Some(index) => match self.constructor.fields.get(index) {
+                Some(field) => &field.name,
+                None => {
+                    debug!("ConstructorAccess::next_key_seed() is called when no fields is left to deserialize");
+                    return Ok(None);
+                },
+            },
+        };
+
+        self.next_index += 1;
+        seed.deserialize(key.into_deserializer()).map(Some)
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> error::Result<V::Value>
+        where V: DeserializeSeed<'de>
+    {
+        let constructor = self.constructor;
+        let field = match self.next_index.checked_sub(2) {
+            None => return seed.deserialize(constructor.name.as_str().into_deserializer()),
+            Some(index) => constructor.fields.get(index)
+                .unwrap_or_else(|| unreachable!("a value must follow its key")),
+        };
+
+        debug!("Deserializing field {}", field.name);
+        let path_len = self.de.path.len();
+        self.de.path.push(PathSegment::Name(&field.name));
+
+        // On error the path is truncated by the enclosing `Deserializer::nested()`
+        let value = if field.ty == TlType::Flags {
+            let flags_value = self.de.read_u32()?;
+            debug!("Deserialized flags: {:#x}", flags_value);
+            self.flags.push((&field.name, flags_value));
+
+            seed.deserialize(IntoDeserializer::<error::Error>::into_deserializer(flags_value))?
+        } else {
+            let flags_bit = self.flags_bit(field)?;
+            seed.deserialize(TlValueDeserializer { de: &mut *self.de, ty: &field.ty, flags_bit })?
+        };
+        self.de.path.truncate(path_len);
+
+        Ok(value)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.constructor.fields.len() + 1 - self.next_index)
+    }
+}
+
+
+/// Gives elements of a vector whose element type is known from the registry.
+#[derive(Debug)]
+struct TlSeqAccess<'a, 'ids, R> {
+    de: &'a mut Deserializer<'ids, R>,
+    ty: &'ids TlType,
+    len: u32,
+    next_index: u32,
+}
+
+impl<'a, 'ids, R> TlSeqAccess<'a, 'ids, R> {
+    fn new(de: &'a mut Deserializer<'ids, R>, ty: &'ids TlType, len: u32) -> TlSeqAccess<'a, 'ids, R> {
+        TlSeqAccess { de, ty, len, next_index: 0 }
+    }
+}
+
+impl<'de, 'a, 'ids, R> de::SeqAccess<'de> for TlSeqAccess<'a, 'ids, R>
+    where R: 'a + Read<'de>
+{
+    type Error = error::Error;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> error::Result<Option<T::Value>>
+        where T: DeserializeSeed<'de>
+    {
+        if self.next_index < self.len {
+            self.next_index += 1;
+        } else {
+            debug!("TlSeqAccess::next_element_seed() is called when no elements is left to deserialize");
+            return Ok(None);
+        }
+
+        let path_len = self.de.path.len();
+        self.de.path.push(PathSegment::Index(self.next_index - 1));
+        let value = seed.deserialize(TlValueDeserializer { de: &mut *self.de, ty: self.ty, flags_bit: None })?;
+        self.de.path.truncate(path_len);
+
+        Ok(Some(value))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        safe_uint_cast(self.len - self.next_index).ok()
+    }
+}
+
+
+/// Deserializes a value of a TL type known from the registry which is
+/// absent if it's bound to an unset flags bit.
+#[derive(Debug)]
+struct TlValueDeserializer<'a, 'ids, R> {
+    de: &'a mut Deserializer<'ids, R>,
+    ty: &'ids TlType,
+    flags_bit: Option<bool>,
+}
+
+impl<'de, 'a, 'ids, R> de::Deserializer<'de> for TlValueDeserializer<'a, 'ids, R>
+    where R: 'a + Read<'de>
+{
+    type Error = error::Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> error::Result<V::Value>
+        where V: Visitor<'de>
+    {
+        match (self.flags_bit, self.ty) {
+            // `flags.N?true` fields are represented by their flags bit only
+            (Some(bit_value), &TlType::True) => visitor.visit_bool(bit_value),
+            (Some(false), _) => visitor.visit_none(),
+            (Some(true), ty) => visitor.visit_some(TlValueDeserializer { de: self.de, ty, flags_bit: None }),
+            (None, ty) => self.de.deserialize_tl(ty, visitor),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 u8 u16 u32 u64 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+
+#[derive(Debug)]
+struct EnumVariantAccess<'a, 'ids, R> {
+    de: &'a mut Deserializer<'ids, R>,
+    variant: Option<&'static str>,
+}
+
+impl<'a, 'ids, R> EnumVariantAccess<'a, 'ids, R> {
     fn new(de: &'a mut Deserializer<'ids, R>) -> EnumVariantAccess<'a, 'ids, R> {
-        EnumVariantAccess { de }
+        EnumVariantAccess { de, variant: None }
     }
 }
 
 impl<'de, 'a, 'ids, R> de::EnumAccess<'de> for EnumVariantAccess<'a, 'ids, R>
-    where R: 'a + io::Read
+    where R: 'a + Read<'de>
 {
     type Error = error::Error;
     type Variant = Self;
 
-    fn variant_seed<V>(self, seed: V) -> error::Result<(V::Value, Self::Variant)>
+    fn variant_seed<V>(mut self, seed: V) -> error::Result<(V::Value, Self::Variant)>
         where V: DeserializeSeed<'de>
     {
         debug!("Deserializing enum variant");
         let value = seed.deserialize(&mut *self.de)?;
+        self.variant = self.de.last_variant_id.take();
+
+        // Popped by the enclosing `Deserializer::nested()`
+        if let Some(variant) = self.variant {
+            self.de.path.push(PathSegment::Name(variant));
+        }
 
         Ok((value, self))
     }
 }
 
 impl<'de, 'a, 'ids, R> de::VariantAccess<'de> for EnumVariantAccess<'a, 'ids, R>
-    where R: 'a + io::Read
+    where R: 'a + Read<'de>
 {
     type Error = error::Error;
 
@@ -490,7 +1564,7 @@ impl<'de, 'a, 'ids, R> de::VariantAccess<'de> for EnumVariantAccess<'a, 'ids, R>
         where V: Visitor<'de>
     {
         debug!("Deserializing struct variant");
-        de::Deserializer::deserialize_struct(self.de, "", fields, visitor)
+        visitor.visit_seq(SeqAccess::with_fields(self.de, fields, self.variant)?)
     }
 }
 
@@ -499,8 +1573,8 @@ impl<'de, 'a, 'ids, R> de::VariantAccess<'de> for EnumVariantAccess<'a, 'ids, R>
 pub fn from_bytes<'de, T>(bytes: &'de [u8], enum_variant_ids: &[&'static str]) -> error::Result<T>
     where T: Deserialize<'de>
 {
-    let mut de = Deserializer::new(bytes, enum_variant_ids);
-    let value: T = Deserialize::deserialize(&mut de)?;
+    let mut de = Deserializer::new(SliceRead::new(bytes), enum_variant_ids);
+    let value: T = Deserialize::deserialize(&mut de).map_err(|e| de.contextualize(e))?;
 
     Ok(value)
 }
@@ -511,10 +1585,10 @@ pub fn from_bytes_reuse<'de, T>(bytes: &'de [u8],
                                -> error::Result<(T, &'de [u8])>
     where T: Deserialize<'de>
 {
-    let mut de = Deserializer::new(bytes, enum_variant_ids);
-    let value: T = Deserialize::deserialize(&mut de)?;
+    let mut de = Deserializer::new(SliceRead::new(bytes), enum_variant_ids);
+    let value: T = Deserialize::deserialize(&mut de).map_err(|e| de.contextualize(e))?;
 
-    Ok((value, de.reader))
+    Ok((value, de.reader.into_inner()))
 }
 
 /// Deserialize an instance of type `T` from bytes of binary MTProto using a seed.
@@ -525,8 +1599,24 @@ pub fn from_bytes_seed<'de, S, T>(
 ) -> error::Result<T>
     where S: DeserializeSeed<'de, Value = T>
 {
-    let mut de = Deserializer::new(bytes, enum_variant_ids);
-    let value: T = DeserializeSeed::deserialize(seed, &mut de)?;
+    let mut de = Deserializer::new(SliceRead::new(bytes), enum_variant_ids);
+    let value: T = DeserializeSeed::deserialize(seed, &mut de).map_err(|e| de.contextualize(e))?;
+
+    Ok(value)
+}
+
+/// Deserialize an instance of type `T` from bytes of binary MTProto using a
+/// seed and resource limits from `options`.
+pub(crate) fn from_bytes_seed_with_options<'de, S, T>(
+    seed: S,
+    bytes: &'de [u8],
+    enum_variant_ids: &[&'static str],
+    options: DeserializerOptions,
+) -> error::Result<T>
+    where S: DeserializeSeed<'de, Value = T>
+{
+    let mut de = Deserializer::with_options(SliceRead::new(bytes), enum_variant_ids, options);
+    let value: T = DeserializeSeed::deserialize(seed, &mut de).map_err(|e| de.contextualize(e))?;
 
     Ok(value)
 }
@@ -537,7 +1627,7 @@ pub fn from_reader<R, T>(reader: R, enum_variant_ids: &[&'static str]) -> error:
           T: DeserializeOwned,
 {
     let mut de = Deserializer::new(reader, enum_variant_ids);
-    let value: T = Deserialize::deserialize(&mut de)?;
+    let value: T = Deserialize::deserialize(&mut de).map_err(|e| de.contextualize(e))?;
 
     Ok(value)
 }
@@ -551,7 +1641,7 @@ pub fn from_reader_reuse<R, T>(reader: R,
           T: DeserializeOwned,
 {
     let mut de = Deserializer::new(reader, enum_variant_ids);
-    let value: T = Deserialize::deserialize(&mut de)?;
+    let value: T = Deserialize::deserialize(&mut de).map_err(|e| de.contextualize(e))?;
 
     Ok((value, de.reader))
 }
@@ -566,7 +1656,7 @@ pub fn from_reader_seed<R, S, T>(
           R: io::Read,
 {
     let mut de = Deserializer::new(reader, enum_variant_ids);
-    let value: T = DeserializeSeed::deserialize(seed, &mut de)?;
+    let value: T = DeserializeSeed::deserialize(seed, &mut de).map_err(|e| de.contextualize(e))?;
 
     Ok(value)
 }
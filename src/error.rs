@@ -29,6 +29,36 @@ error_chain! {
             display("deserialization error in serde_mtproto: {}", kind)
         }
 
+        /// An error while framing or unframing a transport packet.
+        Transport(kind: TransportErrorKind) {
+            description("transport error in serde_mtproto")
+            display("transport error in serde_mtproto: {}", kind)
+        }
+
+        /// An error while encrypting or decrypting a message.
+        Crypto(kind: CryptoErrorKind) {
+            description("crypto error in serde_mtproto")
+            display("crypto error in serde_mtproto: {}", kind)
+        }
+
+        /// An error while creating an authorization key.
+        Handshake(kind: HandshakeErrorKind) {
+            description("handshake error in serde_mtproto")
+            display("handshake error in serde_mtproto: {}", kind)
+        }
+
+        /// An error while parsing an RSA key or using it for RSA_PAD.
+        Rsa(kind: RsaErrorKind) {
+            description("RSA error in serde_mtproto")
+            display("RSA error in serde_mtproto: {}", kind)
+        }
+
+        /// A message id which is malformed or out of the allowed time window.
+        MessageId(kind: MessageIdErrorKind) {
+            description("message id error in serde_mtproto")
+            display("message id error in serde_mtproto: {}", kind)
+        }
+
         /// Error while casting a signed integer.
         SignedIntegerCast(num: crate::utils::IntMax) {
             description("error while casting a signed integer")
@@ -75,6 +105,12 @@ pub enum SerErrorKind {
     Msg(String),
     /// Excess elements found, stores the needed count.
     ExcessElements(u32),
+    /// A field bound to a flags bit is neither an `Option` nor a `bool`.
+    FlaggedFieldNotOptional(&'static str),
+    /// A malformed entry returned by `FlagsLayout::flags_layout()`.
+    InvalidFlagsLayoutEntry(&'static str),
+    /// A message id which is not valid for any side, stores the id.
+    InvalidMessageId(i64),
     /// Cannot serialize maps with unknown length.
     MapsWithUnknownLengthUnsupported,
     /// Not enough elements, stores the actual and needed count.
@@ -96,6 +132,15 @@ impl fmt::Display for SerErrorKind {
             SerErrorKind::ExcessElements(len) => {
                 write!(f, "excess elements, need no more than {}", len)
             },
+            SerErrorKind::FlaggedFieldNotOptional(field) => {
+                write!(f, "field {:?} is bound to a flags bit, but is neither an option nor a bool", field)
+            },
+            SerErrorKind::InvalidFlagsLayoutEntry(entry) => {
+                write!(f, "invalid flags layout entry {:?}", entry)
+            },
+            SerErrorKind::InvalidMessageId(message_id) => {
+                write!(f, "invalid message id {:#x}", message_id)
+            },
             SerErrorKind::MapsWithUnknownLengthUnsupported => {
                 write!(f, "maps with ahead-of-time unknown length are not supported")
             },
@@ -171,6 +216,32 @@ pub enum DeErrorKind {
     NoEnumVariantId,
     /// The deserialized size and the predicted one aren't the same.
     SizeMismatch(u32, u32),
+    /// A field bound to a flags bit is neither an `Option` nor a `bool`.
+    FlaggedFieldNotOptional(&'static str),
+    /// A malformed entry returned by `FlagsLayout::flags_layout()`.
+    InvalidFlagsLayoutEntry(&'static str),
+    /// Deserialization would read more bytes in total than allowed, stores the limit.
+    TotalBytesLimitExceeded(u64),
+    /// A sequence or a map is longer than allowed, stores the length and the limit.
+    SeqLenLimitExceeded(u32, u32),
+    /// A string or a byte sequence is longer than allowed, stores the length and the limit.
+    BytesLenLimitExceeded(usize, usize),
+    /// Values are nested deeper than allowed, stores the limit.
+    DepthLimitExceeded(u32),
+    /// Packed data is longer than allowed after decompression, stores the limit.
+    UnpackedLenLimitExceeded(usize),
+    /// A value reads past the end of the enclosing sized value, stores the
+    /// offset of that end.
+    SizeBoundExceeded(u64),
+    /// A boxed value has a type id which is not in the registry.
+    UnregisteredTypeId(u32),
+    /// A `flags.N?T` field of a registered constructor refers to a flags
+    /// field which wasn't deserialized before it.
+    UnknownFlagsField(String),
+    /// An unencrypted message has a non-zero auth key id, stores the id.
+    NonZeroAuthKeyId(u64),
+    /// A message id which is not valid for any side, stores the id.
+    InvalidMessageId(i64),
 }
 
 impl fmt::Display for DeErrorKind {
@@ -208,6 +279,42 @@ impl fmt::Display for DeErrorKind {
                 write!(f, "size mismatch: deserialized {}, predicted {}",
                     deserialized_size, static_size_hint)
             },
+            DeErrorKind::FlaggedFieldNotOptional(field) => {
+                write!(f, "field {:?} is bound to a flags bit, but is neither an option nor a bool", field)
+            },
+            DeErrorKind::InvalidFlagsLayoutEntry(entry) => {
+                write!(f, "invalid flags layout entry {:?}", entry)
+            },
+            DeErrorKind::TotalBytesLimitExceeded(limit) => {
+                write!(f, "total bytes limit exceeded: can read at most {} bytes", limit)
+            },
+            DeErrorKind::SeqLenLimitExceeded(len, limit) => {
+                write!(f, "sequence length limit exceeded: have {}, at most {} allowed", len, limit)
+            },
+            DeErrorKind::BytesLenLimitExceeded(len, limit) => {
+                write!(f, "byte sequence length limit exceeded: have {}, at most {} allowed", len, limit)
+            },
+            DeErrorKind::DepthLimitExceeded(limit) => {
+                write!(f, "nesting depth limit exceeded: at most {} levels allowed", limit)
+            },
+            DeErrorKind::UnpackedLenLimitExceeded(limit) => {
+                write!(f, "unpacked data length limit exceeded: at most {} bytes allowed", limit)
+            },
+            DeErrorKind::SizeBoundExceeded(offset) => {
+                write!(f, "value doesn't fit in its declared size ending at offset {:#x}", offset)
+            },
+            DeErrorKind::UnregisteredTypeId(type_id) => {
+                write!(f, "type id {:#x} is not registered", type_id)
+            },
+            DeErrorKind::UnknownFlagsField(ref field) => {
+                write!(f, "unknown flags field {:?}", field)
+            },
+            DeErrorKind::NonZeroAuthKeyId(auth_key_id) => {
+                write!(f, "unencrypted message has a non-zero auth key id {:#018x}", auth_key_id)
+            },
+            DeErrorKind::InvalidMessageId(message_id) => {
+                write!(f, "invalid message id {:#x}", message_id)
+            },
         }
     }
 }
@@ -241,6 +348,88 @@ impl fmt::Display for DeSerdeType {
     }
 }
 
+/// Location in the input where a deserialization error happened.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct DeErrorContext {
+    offset: u64,
+    path: String,
+}
+
+impl DeErrorContext {
+    pub(crate) fn new(offset: u64, path: String) -> DeErrorContext {
+        DeErrorContext { offset, path }
+    }
+
+    /// Number of bytes successfully consumed before the error.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Dot-separated path of struct, enum, variant and field names to the
+    /// erroneous value, with sequence indices in brackets (e.g.
+    /// `Message.entities[2].MessageEntity.Link.url`). Empty if the error
+    /// happened at the top level.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+}
+
+impl fmt::Display for DeErrorContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "at offset {:#x}", self.offset)?;
+
+        if !self.path.is_empty() {
+            write!(f, ", in {}", self.path)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Keeps the location of a deserialization error in the error chain, so that
+/// the error kind stays the same.
+#[derive(Debug)]
+struct DeContextLink {
+    context: DeErrorContext,
+    next_error: Option<Box<dyn std::error::Error + Send>>,
+}
+
+impl fmt::Display for DeContextLink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.context, f)
+    }
+}
+
+impl std::error::Error for DeContextLink {
+    fn description(&self) -> &str {
+        "location of a deserialization error"
+    }
+
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self.next_error {
+            Some(ref next_error) => Some(&**next_error),
+            None => None,
+        }
+    }
+}
+
+impl Error {
+    /// Location of a deserialization error, if known.
+    pub fn de_context(&self) -> Option<&DeErrorContext> {
+        self.1.next_error.as_ref()
+            .and_then(|next_error| next_error.downcast_ref::<DeContextLink>())
+            .map(|link| &link.context)
+    }
+
+    pub(crate) fn with_de_context(self, context: DeErrorContext) -> Error {
+        let Error(kind, mut state) = self;
+        let next_error = state.next_error.take();
+        state.next_error = Some(Box::new(DeContextLink { context, next_error }));
+
+        Error(kind, state)
+    }
+}
+
 impl From<DeErrorKind> for Error {
     fn from(kind: DeErrorKind) -> Error {
         ErrorKind::De(kind).into()
@@ -248,6 +437,289 @@ impl From<DeErrorKind> for Error {
 }
 
 
+
+/// Transport framing error kinds.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum TransportErrorKind {
+    /// The payload length is not divisible by 4, stores the length.
+    UnalignedPayload(usize),
+    /// The payload is longer than allowed, stores the length and the limit.
+    FrameTooLong(usize, usize),
+    /// The length field of a frame has an invalid value.
+    InvalidFrameLen(u32),
+    /// The frame checksum is wrong, stores the computed and the received one.
+    Crc32Mismatch(u32, u32),
+    /// The frame is out of order, stores the expected and the received
+    /// sequence number.
+    SeqNoMismatch(u32, u32),
+    /// The server responded with a transport error code instead of a
+    /// payload (e.g. -404 or -429).
+    ServerError(i32),
+    /// The transport has no tag, so it can't be obfuscated.
+    NoTag,
+    /// The obfuscated init header carries an unknown transport tag.
+    UnknownTag(u32),
+}
+
+impl fmt::Display for TransportErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            TransportErrorKind::UnalignedPayload(len) => {
+                write!(f, "payload length {} is not divisible by 4", len)
+            },
+            TransportErrorKind::FrameTooLong(len, limit) => {
+                write!(f, "frame is too long: have {} bytes, at most {} allowed", len, limit)
+            },
+            TransportErrorKind::InvalidFrameLen(len) => {
+                write!(f, "invalid frame length {}", len)
+            },
+            TransportErrorKind::Crc32Mismatch(computed, received) => {
+                write!(f, "CRC32 mismatch: computed {:#010x}, received {:#010x}", computed, received)
+            },
+            TransportErrorKind::SeqNoMismatch(expected, received) => {
+                write!(f, "sequence number mismatch: expected {}, received {}", expected, received)
+            },
+            TransportErrorKind::ServerError(code) => {
+                write!(f, "server responded with transport error code {}", code)
+            },
+            TransportErrorKind::NoTag => {
+                write!(f, "transport has no tag and can't be obfuscated")
+            },
+            TransportErrorKind::UnknownTag(tag) => {
+                write!(f, "unknown transport tag {:#010x}", tag)
+            },
+        }
+    }
+}
+
+impl From<TransportErrorKind> for Error {
+    fn from(kind: TransportErrorKind) -> Error {
+        ErrorKind::Transport(kind).into()
+    }
+}
+
+
+/// Message encryption error kinds.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum CryptoErrorKind {
+    /// An authorization key of a wrong length, stores the length.
+    InvalidAuthKeyLen(usize),
+    /// The message is encrypted with another authorization key, stores the
+    /// expected and the received key id.
+    AuthKeyIdMismatch(u64, u64),
+    /// The encrypted data is either too short or not divisible by the
+    /// block size, stores the length (of the whole envelope if it's too
+    /// short to contain the header).
+    InvalidEncryptedDataLen(usize),
+    /// `msg_key` doesn't match the decrypted data.
+    MsgKeyMismatch,
+    /// `message_data_length` is out of bounds of the decrypted data or not
+    /// divisible by 4, stores the length.
+    InvalidMessageDataLen(u32),
+    /// Padding is shorter than 12 or longer than 1024 bytes, stores the
+    /// length.
+    InvalidPaddingLen(usize),
+}
+
+impl fmt::Display for CryptoErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            CryptoErrorKind::InvalidAuthKeyLen(len) => {
+                write!(f, "authorization key must be 256 bytes long, have {}", len)
+            },
+            CryptoErrorKind::AuthKeyIdMismatch(expected, received) => {
+                write!(f, "auth_key_id mismatch: expected {:#018x}, received {:#018x}", expected, received)
+            },
+            CryptoErrorKind::InvalidEncryptedDataLen(len) => {
+                write!(f, "invalid encrypted data length {}", len)
+            },
+            CryptoErrorKind::MsgKeyMismatch => {
+                write!(f, "msg_key doesn't match the decrypted data")
+            },
+            CryptoErrorKind::InvalidMessageDataLen(len) => {
+                write!(f, "invalid message_data_length {}", len)
+            },
+            CryptoErrorKind::InvalidPaddingLen(len) => {
+                write!(f, "padding of length {} is not in 12..=1024 range", len)
+            },
+        }
+    }
+}
+
+impl From<CryptoErrorKind> for Error {
+    fn from(kind: CryptoErrorKind) -> Error {
+        ErrorKind::Crypto(kind).into()
+    }
+}
+
+
+/// Message id error kinds.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum MessageIdErrorKind {
+    /// A message id which is not positive or yields 2 modulo 4, stores the
+    /// id.
+    Invalid(i64),
+    /// A message id too far in the past, stores the id and how many seconds
+    /// it is behind the current time.
+    TooOld(i64, i64),
+    /// A message id too far in the future, stores the id and how many
+    /// seconds it is ahead of the current time.
+    TooNew(i64, i64),
+}
+
+impl fmt::Display for MessageIdErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            MessageIdErrorKind::Invalid(message_id) => {
+                write!(f, "invalid message id {:#x}", message_id)
+            },
+            MessageIdErrorKind::TooOld(message_id, secs) => {
+                write!(f, "message id {:#x} is {} seconds in the past", message_id, secs)
+            },
+            MessageIdErrorKind::TooNew(message_id, secs) => {
+                write!(f, "message id {:#x} is {} seconds in the future", message_id, secs)
+            },
+        }
+    }
+}
+
+impl From<MessageIdErrorKind> for Error {
+    fn from(kind: MessageIdErrorKind) -> Error {
+        ErrorKind::MessageId(kind).into()
+    }
+}
+
+
+/// Authorization key creation error kinds.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum HandshakeErrorKind {
+    /// The handshake is not started, already finished or failed.
+    UnexpectedMessage,
+    /// A response carries a nonce of another handshake.
+    NonceMismatch,
+    /// None of the known RSA keys match the server fingerprints, stores
+    /// the fingerprints.
+    UnknownFingerprints(Vec<i64>),
+    /// `pq` is not a product of two primes, stores its value.
+    InvalidPq(u64),
+    /// The server failed to decrypt the DH parameters request.
+    ServerDhParamsFail,
+    /// The encrypted answer is malformed or its SHA-1 doesn't match.
+    InvalidEncryptedAnswer,
+    /// The DH prime is not a safe 2048-bit prime.
+    InvalidDhPrime,
+    /// The DH generator is not valid for the prime, stores its value.
+    InvalidG(i32),
+    /// `g_a` is out of the allowed range.
+    InvalidGA,
+    /// `new_nonce_hash` doesn't match the created key.
+    NewNonceHashMismatch,
+    /// The server failed to create the key.
+    DhGenFail,
+}
+
+impl fmt::Display for HandshakeErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            HandshakeErrorKind::UnexpectedMessage => {
+                write!(f, "handshake doesn't expect a message")
+            },
+            HandshakeErrorKind::NonceMismatch => {
+                write!(f, "nonce mismatch")
+            },
+            HandshakeErrorKind::UnknownFingerprints(ref fingerprints) => {
+                write!(f, "no RSA key for server fingerprints {:x?}", fingerprints)
+            },
+            HandshakeErrorKind::InvalidPq(pq) => {
+                write!(f, "pq {:#x} is not a product of two primes", pq)
+            },
+            HandshakeErrorKind::ServerDhParamsFail => {
+                write!(f, "server failed to decrypt DH parameters request")
+            },
+            HandshakeErrorKind::InvalidEncryptedAnswer => {
+                write!(f, "invalid encrypted answer")
+            },
+            HandshakeErrorKind::InvalidDhPrime => {
+                write!(f, "DH prime is not a safe 2048-bit prime")
+            },
+            HandshakeErrorKind::InvalidG(g) => {
+                write!(f, "invalid DH generator {}", g)
+            },
+            HandshakeErrorKind::InvalidGA => {
+                write!(f, "g_a is out of the allowed range")
+            },
+            HandshakeErrorKind::NewNonceHashMismatch => {
+                write!(f, "new_nonce_hash mismatch")
+            },
+            HandshakeErrorKind::DhGenFail => {
+                write!(f, "server failed to create the authorization key")
+            },
+        }
+    }
+}
+
+impl From<HandshakeErrorKind> for Error {
+    fn from(kind: HandshakeErrorKind) -> Error {
+        ErrorKind::Handshake(kind).into()
+    }
+}
+
+
+/// RSA key and `RSA_PAD` error kinds.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum RsaErrorKind {
+    /// The input is not a well-formed PEM document.
+    InvalidPem,
+    /// A PEM document of an unsupported kind, stores its label.
+    UnexpectedPemLabel(String),
+    /// The DER-encoded key is malformed.
+    InvalidDer,
+    /// The key is not an `rsaEncryption` key.
+    UnsupportedAlgorithm,
+    /// An RSA key which is not 2048 bits long, stores its length in bits.
+    InvalidKeyLength(usize),
+    /// Data to be encrypted with `RSA_PAD` is too long, stores the length.
+    DataTooLong(usize),
+    /// Data encrypted with `RSA_PAD` is malformed or its SHA-256 doesn't
+    /// match.
+    InvalidEncryptedData,
+}
+
+impl fmt::Display for RsaErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            RsaErrorKind::InvalidPem => {
+                write!(f, "invalid PEM document")
+            },
+            RsaErrorKind::UnexpectedPemLabel(ref label) => {
+                write!(f, "unexpected PEM label {:?}", label)
+            },
+            RsaErrorKind::InvalidDer => {
+                write!(f, "invalid DER-encoded key")
+            },
+            RsaErrorKind::UnsupportedAlgorithm => {
+                write!(f, "key algorithm is not rsaEncryption")
+            },
+            RsaErrorKind::InvalidKeyLength(bits) => {
+                write!(f, "RSA key must be 2048 bits long, have {}", bits)
+            },
+            RsaErrorKind::DataTooLong(len) => {
+                write!(f, "data for RSA_PAD must be at most 144 bytes long, have {}", len)
+            },
+            RsaErrorKind::InvalidEncryptedData => {
+                write!(f, "invalid RSA_PAD encrypted data")
+            },
+        }
+    }
+}
+
+impl From<RsaErrorKind> for Error {
+    fn from(kind: RsaErrorKind) -> Error {
+        ErrorKind::Rsa(kind).into()
+    }
+}
+
+
 impl ser::Error for Error {
     fn custom<T: fmt::Display>(msg: T) -> Error {
         SerErrorKind::Msg(msg.to_string()).into()
@@ -0,0 +1,269 @@
+//! Support for conditional fields (`flags.N?Type`) of TL combinators.
+//!
+//! A TL combinator like
+//!
+//! ```text
+//! channel#8e87ccd8 flags:# broadcast:flags.5?true id:int title:string username:flags.6?string = Chat;
+//! ```
+//!
+//! contains a `flags:#` field which is a bitmask telling which of the
+//! following conditional fields are present in the serialized data.
+//!
+//! In Rust such a field is represented by the [`Flags`] marker type, and
+//! fields bound to its bits are marked with
+//! `#[mtproto_flags(bit = N)]`:
+//!
+//! * `Option<T>` fields correspond to `flags.N?T` and are only serialized
+//!   when they are `Some`;
+//! * `bool` fields correspond to `flags.N?true` and are never serialized
+//!   themselves, only their bit is.
+//!
+//! The bitmask is computed from the values of conditional fields during
+//! serialization and is used to decide which fields to read during
+//! deserialization.
+//! `#[derive(MtProtoIdentifiable)]` generates the [`FlagsLayout`]
+//! implementation which ties bits to fields and `#[derive(MtProtoSized)]`
+//! takes absent fields into account.
+//!
+//! # Examples
+//!
+//! ```
+//! use serde_derive::{Serialize, Deserialize};
+//! use serde_mtproto::Flags;
+//! use serde_mtproto_derive::{MtProtoIdentifiable, MtProtoSized};
+//!
+//! #[derive(Serialize, Deserialize, MtProtoIdentifiable, MtProtoSized)]
+//! #[mtproto_identifiable(id = "0x8e87ccd8")]
+//! struct Channel {
+//!     flags: Flags<Channel>,
+//!     #[mtproto_flags(bit = 5)]
+//!     broadcast: bool,
+//!     id: i32,
+//!     title: String,
+//!     #[mtproto_flags(bit = 6)]
+//!     username: Option<String>,
+//! }
+//!
+//! # fn run() -> serde_mtproto::Result<()> {
+//! let channel = Channel {
+//!     flags: Flags::new(),
+//!     broadcast: true,
+//!     id: 1,
+//!     title: "news".to_owned(),
+//!     username: None,
+//! };
+//!
+//! let bytes = serde_mtproto::to_bytes(&channel)?;
+//!
+//! assert_eq!(bytes, vec![
+//!     0x20, 0, 0, 0,              // flags: only bit 5 is set
+//!     1, 0, 0, 0,                 // id
+//!     4, b'n', b'e', b'w', b's',  // title
+//!     0, 0, 0,
+//! ]);
+//! #     Ok(())
+//! # }
+//!
+//! # fn main() { run().unwrap(); }
+//! ```
+//!
+//! When a combinator has several flags fields (like `flags2:#` in recent
+//! layers), the one a conditional field refers to is chosen with
+//! `#[mtproto_flags(field = "flags2", bit = N)]`.
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+
+#[cfg(feature = "quickcheck")]
+use quickcheck::{Arbitrary, Gen};
+use serde::de::{self, Deserialize, Deserializer, IgnoredAny, MapAccess, Visitor};
+use serde::ser::{Serialize, Serializer, SerializeStruct};
+
+use crate::error;
+use crate::sized::{MtProtoSized, INT_SIZE};
+
+
+/// Serde struct name which `serde_mtproto` serializer and deserializer use to
+/// recognize [`Flags`] values.
+pub(crate) const FLAGS_STRUCT_NAME: &str = "\u{0}serde_mtproto::Flags";
+
+
+/// A trait for a Rust data structure which has conditional fields bound to
+/// bits of its [`Flags`] fields.
+///
+/// Usually this trait is implemented by `#[derive(MtProtoIdentifiable)]`.
+pub trait FlagsLayout {
+    /// Get entries that describe which bit of which flags field each
+    /// conditional field is bound to.
+    ///
+    /// Each entry has the form `"field:flags.N"` for structs and
+    /// `"Variant::field:flags.N"` for enum struct variants where `field` and
+    /// `flags` are Serde names of the conditional field and the flags field
+    /// respectively, and `N` is a bit index in `0..32`.
+    fn flags_layout() -> &'static [&'static str];
+}
+
+
+/// A marker for a `flags:#` field of a TL combinator.
+///
+/// Its value is computed from conditional fields of `T` during
+/// serialization, so it doesn't store anything by itself. See the
+/// [module-level documentation](index.html) for details.
+pub struct Flags<T> {
+    phantom: PhantomData<fn() -> T>,
+}
+
+impl<T> Flags<T> {
+    /// Create a flags marker.
+    pub fn new() -> Flags<T> {
+        Flags { phantom: PhantomData }
+    }
+}
+
+// Implemented manually because derived impls would require `T` to implement
+// respective traits, which in turn would require them from the type containing
+// a `Flags<T>` field.
+
+impl<T> Clone for Flags<T> {
+    fn clone(&self) -> Flags<T> {
+        *self
+    }
+}
+
+impl<T> Copy for Flags<T> {}
+
+impl<T> fmt::Debug for Flags<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Flags")
+    }
+}
+
+impl<T> Default for Flags<T> {
+    fn default() -> Flags<T> {
+        Flags::new()
+    }
+}
+
+impl<T> PartialEq for Flags<T> {
+    fn eq(&self, _other: &Flags<T>) -> bool {
+        true
+    }
+}
+
+impl<T> Eq for Flags<T> {}
+
+impl<T> PartialOrd for Flags<T> {
+    fn partial_cmp(&self, other: &Flags<T>) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T> Ord for Flags<T> {
+    fn cmp(&self, _other: &Flags<T>) -> Ordering {
+        Ordering::Equal
+    }
+}
+
+impl<T> Hash for Flags<T> {
+    fn hash<H: Hasher>(&self, _state: &mut H) {}
+}
+
+impl<T: FlagsLayout> Serialize for Flags<T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer
+    {
+        // `serde_mtproto` serializer uses field names to learn the layout and computes the
+        // bitmask itself.
+        let layout = T::flags_layout();
+        let mut ser = serializer.serialize_struct(FLAGS_STRUCT_NAME, layout.len())?;
+        for entry in layout {
+            ser.serialize_field(entry, &())?;
+        }
+        ser.end()
+    }
+}
+
+impl<'de, T: FlagsLayout> Deserialize<'de> for Flags<T> {
+    fn deserialize<D>(deserializer: D) -> Result<Flags<T>, D::Error>
+        where D: Deserializer<'de>
+    {
+        struct FlagsVisitor<T>(PhantomData<fn() -> T>);
+
+        impl<'de, T> Visitor<'de> for FlagsVisitor<T> {
+            type Value = Flags<T>;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a flags bitmask")
+            }
+
+            fn visit_u32<E>(self, _value: u32) -> Result<Flags<T>, E>
+                where E: de::Error
+            {
+                Ok(Flags::new())
+            }
+
+            // For formats other than MTProto which serialize `Flags` as a struct
+            fn visit_map<A>(self, mut map: A) -> Result<Flags<T>, A::Error>
+                where A: MapAccess<'de>
+            {
+                while map.next_entry::<IgnoredAny, IgnoredAny>()?.is_some() {}
+
+                Ok(Flags::new())
+            }
+        }
+
+        deserializer.deserialize_struct(FLAGS_STRUCT_NAME, T::flags_layout(), FlagsVisitor(PhantomData))
+    }
+}
+
+impl<T> MtProtoSized for Flags<T> {
+    fn size_hint(&self) -> error::Result<usize> {
+        Ok(INT_SIZE)
+    }
+}
+
+#[cfg(feature = "quickcheck")]
+impl<T: 'static> Arbitrary for Flags<T> {
+    fn arbitrary<G: Gen>(_g: &mut G) -> Flags<T> {
+        Flags::new()
+    }
+}
+
+
+/// A parsed entry of `FlagsLayout::flags_layout()`.
+#[derive(Debug)]
+pub(crate) struct FlagsLayoutEntry {
+    pub(crate) variant: Option<&'static str>,
+    pub(crate) field: &'static str,
+    pub(crate) flags_field: &'static str,
+    pub(crate) bit: u32,
+}
+
+impl FlagsLayoutEntry {
+    pub(crate) fn parse(entry: &'static str) -> Option<FlagsLayoutEntry> {
+        let (variant, rest) = match entry.find("::") {
+            Some(pos) => (Some(entry.get(..pos)?), entry.get(pos + 2..)?),
+            None => (None, entry),
+        };
+
+        let colon = rest.find(':')?;
+        let (field, cond) = (rest.get(..colon)?, rest.get(colon + 1..)?);
+        let dot = cond.rfind('.')?;
+        let (flags_field, bit_str) = (cond.get(..dot)?, cond.get(dot + 1..)?);
+
+        let bit = bit_str.parse::<u32>().ok()?;
+        if field.is_empty() || flags_field.is_empty() || bit >= 32 {
+            return None;
+        }
+
+        Some(FlagsLayoutEntry { variant, field, flags_field, bit })
+    }
+
+    /// Whether this entry belongs to the flags field `flags_field` of a
+    /// struct or an enum variant `variant`.
+    pub(crate) fn is_bound_to(&self, variant: Option<&str>, flags_field: &str) -> bool {
+        self.variant == variant && self.flags_field == flags_field
+    }
+}
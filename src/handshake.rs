@@ -0,0 +1,997 @@
+//! Authorization key creation.
+//!
+//! Before exchanging encrypted messages a client creates an authorization
+//! key together with a server using the Diffie-Hellman key exchange:
+//!
+//! 1. `req_pq_multi` is answered with `resPQ` carrying a number `pq` to
+//!    factorize and fingerprints of the server RSA keys;
+//! 2. `req_DH_params` with `p_q_inner_data_dc` encrypted by [`RSA_PAD`] using
+//!    one of those keys is answered with `server_DH_params_ok` carrying the
+//!    DH prime, the generator and `g_a` encrypted with a key derived from
+//!    the nonces;
+//! 3. `set_client_DH_params` with `g_b` is answered with `dh_gen_ok`, after
+//!    which both sides know the authorization key.
+//!
+//! All messages are sent as [`UnencryptedMessage`]s.
+//! [`Handshake`] drives the exchange without doing any I/O: it returns
+//! payloads to send and is fed with received payloads, so it can be used
+//! with any transport and I/O model.
+//!
+//! See <https://core.telegram.org/mtproto/auth_key> for details.
+//!
+//! [`RSA_PAD`]: ../rsa/index.html
+//! [`UnencryptedMessage`]: ../struct.UnencryptedMessage.html
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use std::io::Write;
+//! use std::net::TcpStream;
+//!
+//! use serde_mtproto::handshake::{Handshake, Step};
+//! use serde_mtproto::rsa::RsaPublicKey;
+//! use serde_mtproto::transport::{Intermediate, Transport};
+//!
+//! # fn run() -> serde_mtproto::Result<()> {
+//! # let (n, e) = (&[0xc1; 256][..], &[0x01, 0x00, 0x01][..]);
+//! let server_key = RsaPublicKey::new(n, e)?;
+//! let mut handshake = Handshake::new(vec![server_key], 2);
+//!
+//! let mut stream = TcpStream::connect("149.154.167.51:443")?;
+//! let mut transport = Intermediate::new();
+//! stream.write_all(transport.tag())?;
+//!
+//! let mut request = handshake.start()?;
+//! let authorization = loop {
+//!     transport.write_frame(&mut stream, &request)?;
+//!
+//!     match handshake.handle(&transport.read_frame(&mut stream)?)? {
+//!         Step::Send(bytes) => request = bytes,
+//!         Step::Done(authorization) => break authorization,
+//!     }
+//! };
+//!
+//! println!("created key {:#x}", authorization.auth_key.id());
+//! #     Ok(())
+//! # }
+//! #
+//! # fn main() { run().unwrap(); }
+//! ```
+
+#![cfg_attr(feature = "cargo-clippy", allow(clippy::unreadable_literal))]  // To match the look & feel from TL schema
+
+use std::cmp;
+use std::fmt;
+use std::mem;
+
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+use error_chain::bail;
+use num_bigint::BigUint;
+use num_traits::{One, ToPrimitive};
+use num_traits::cast::cast;
+use rand::{CryptoRng, Rng, RngCore};
+use serde::de::DeserializeOwned;
+use serde::ser::Serialize;
+use serde_bytes::ByteBuf;
+use serde_derive::{Serialize, Deserialize};
+use sha1::Sha1;
+use sha2::Digest;
+
+use crate::crypto::{self, AuthKey};
+use crate::de::{from_bytes, from_bytes_reuse};
+use crate::error::{self, HandshakeErrorKind};
+use crate::identifiable::Identifiable;
+use crate::ints::{Int128, Int256};
+use crate::message_id::{Clock, MessageIdGenerator, MessageIdKind, SystemClock};
+use crate::rsa::{self, RsaPublicKey, to_be_bytes_padded};
+use crate::ser::to_bytes;
+use crate::sized::MtProtoSized;
+use crate::wrappers::{Boxed, UnencryptedMessage};
+
+
+/// Type id of the `req_pq_multi` function.
+pub const REQ_PQ_MULTI_ID: u32 = 0xbe7e8ef1;
+/// Type id of the `resPQ` constructor.
+pub const RES_PQ_ID: u32 = 0x05162463;
+/// Type id of the `p_q_inner_data_dc` constructor.
+pub const P_Q_INNER_DATA_DC_ID: u32 = 0xa9f55f95;
+/// Type id of the `req_DH_params` function.
+pub const REQ_DH_PARAMS_ID: u32 = 0xd712e4be;
+/// Type id of the `server_DH_params_fail` constructor.
+pub const SERVER_DH_PARAMS_FAIL_ID: u32 = 0x79cb045d;
+/// Type id of the `server_DH_params_ok` constructor.
+pub const SERVER_DH_PARAMS_OK_ID: u32 = 0xd0e8075c;
+/// Type id of the `server_DH_inner_data` constructor.
+pub const SERVER_DH_INNER_DATA_ID: u32 = 0xb5890dba;
+/// Type id of the `client_DH_inner_data` constructor.
+pub const CLIENT_DH_INNER_DATA_ID: u32 = 0x6643b654;
+/// Type id of the `set_client_DH_params` function.
+pub const SET_CLIENT_DH_PARAMS_ID: u32 = 0xf5045f1f;
+/// Type id of the `dh_gen_ok` constructor.
+pub const DH_GEN_OK_ID: u32 = 0x3bcbf734;
+/// Type id of the `dh_gen_retry` constructor.
+pub const DH_GEN_RETRY_ID: u32 = 0x46dc1fb9;
+/// Type id of the `dh_gen_fail` constructor.
+pub const DH_GEN_FAIL_ID: u32 = 0xa69dae02;
+
+/// Length of DH primes in bits.
+const KEY_BITS: usize = 2048;
+
+/// The DH prime used by Telegram servers, which is known to be safe and
+/// doesn't need a primality test.
+const KNOWN_DH_PRIME: &[u8] = b"\
+    c71caeb9c6b1c9048e6c522f70f13f73980d40238e3e21c14934d037563d930f\
+    48198a0aa7c14058229493d22530f4dbfa336f6e0ac925139543aed44cce7c37\
+    20fd51f69458705ac68cd4fe6b6b13abdc9746512969328454f18faf8c595f64\
+    2477fe96bb2a941d5bcd1d4ac8cc49880708fa9b378e3c4f3a9060bee67cf9a4\
+    a4a695811051907e162753b56b0f6b410dba74d8a84b2a14b3144e0ef1284754\
+    fd17ed950d5965b4b9dd46582db1178d169c6bc465b0d6ff9ca3928fef5b9ae4\
+    e418fc15e83ebea0f87fa9ff5eed70050ded2849f47bf959d956850ce929851f\
+    0d8115f635b105ee2e4e15d04b2454bf6f4fadf034b10403119cd8e3b92fcc5b";
+/// Number of Miller-Rabin rounds for DH primes other than the known one.
+const MILLER_RABIN_ROUNDS: usize = 32;
+
+/// Number of attempts to factorize `pq` with different polynomials.
+const PQ_FACTORIZATION_ATTEMPTS: u64 = 8;
+/// Number of Pollard's rho iterations per attempt, enough for 32-bit
+/// factors.
+const PQ_FACTORIZATION_ITERATIONS: usize = 1 << 20;
+
+
+/// `req_pq_multi#be7e8ef1 nonce:int128 = ResPQ;`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ReqPqMulti {
+    /// Random number chosen by the client.
+    pub nonce: Int128,
+}
+
+/// `resPQ#05162463 nonce:int128 server_nonce:int128 pq:string
+/// server_public_key_fingerprints:Vector<long> = ResPQ;`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ResPq {
+    /// `nonce` from `req_pq_multi`.
+    pub nonce: Int128,
+    /// Random number chosen by the server.
+    pub server_nonce: Int128,
+    /// Big-endian product of two primes.
+    pub pq: ByteBuf,
+    /// Fingerprints of RSA keys the server has private keys for.
+    pub server_public_key_fingerprints: Boxed<Vec<i64>>,
+}
+
+/// `p_q_inner_data_dc#a9f55f95 pq:string p:string q:string nonce:int128
+/// server_nonce:int128 new_nonce:int256 dc:int = P_Q_inner_data;`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PQInnerDataDc {
+    /// `pq` from `resPQ`.
+    pub pq: ByteBuf,
+    /// Big-endian lesser factor of `pq`.
+    pub p: ByteBuf,
+    /// Big-endian greater factor of `pq`.
+    pub q: ByteBuf,
+    /// `nonce` from `req_pq_multi`.
+    pub nonce: Int128,
+    /// `server_nonce` from `resPQ`.
+    pub server_nonce: Int128,
+    /// Random number chosen by the client and never sent unencrypted.
+    pub new_nonce: Int256,
+    /// DC id, offset by 10000 for test servers and negative for media DCs.
+    pub dc: i32,
+}
+
+/// `req_DH_params#d712e4be nonce:int128 server_nonce:int128 p:string
+/// q:string public_key_fingerprint:long encrypted_data:string =
+/// Server_DH_Params;`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ReqDhParams {
+    /// `nonce` from `req_pq_multi`.
+    pub nonce: Int128,
+    /// `server_nonce` from `resPQ`.
+    pub server_nonce: Int128,
+    /// Big-endian lesser factor of `pq`.
+    pub p: ByteBuf,
+    /// Big-endian greater factor of `pq`.
+    pub q: ByteBuf,
+    /// Fingerprint of the RSA key used to encrypt the data.
+    pub public_key_fingerprint: i64,
+    /// Boxed `p_q_inner_data_dc` encrypted with `RSA_PAD`.
+    pub encrypted_data: ByteBuf,
+}
+
+/// `Server_DH_Params` type.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ServerDhParams {
+    /// `server_DH_params_fail#79cb045d nonce:int128 server_nonce:int128
+    /// new_nonce_hash:int128 = Server_DH_Params;`
+    Fail {
+        /// `nonce` from `req_pq_multi`.
+        nonce: Int128,
+        /// `server_nonce` from `resPQ`.
+        server_nonce: Int128,
+        /// 128 lower-order bits of SHA-1 of `new_nonce`.
+        new_nonce_hash: Int128,
+    },
+    /// `server_DH_params_ok#d0e8075c nonce:int128 server_nonce:int128
+    /// encrypted_answer:string = Server_DH_Params;`
+    Ok {
+        /// `nonce` from `req_pq_multi`.
+        nonce: Int128,
+        /// `server_nonce` from `resPQ`.
+        server_nonce: Int128,
+        /// Boxed `server_DH_inner_data` encrypted with a key derived from
+        /// the nonces.
+        encrypted_answer: ByteBuf,
+    },
+}
+
+/// `server_DH_inner_data#b5890dba nonce:int128 server_nonce:int128 g:int
+/// dh_prime:string g_a:string server_time:int = Server_DH_inner_data;`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ServerDhInnerData {
+    /// `nonce` from `req_pq_multi`.
+    pub nonce: Int128,
+    /// `server_nonce` from `resPQ`.
+    pub server_nonce: Int128,
+    /// DH generator.
+    pub g: i32,
+    /// Big-endian safe 2048-bit DH prime.
+    pub dh_prime: ByteBuf,
+    /// Big-endian `g ^ a mod dh_prime` for a secret `a` of the server.
+    pub g_a: ByteBuf,
+    /// Current server time.
+    pub server_time: i32,
+}
+
+/// `client_DH_inner_data#6643b654 nonce:int128 server_nonce:int128
+/// retry_id:long g_b:string = Client_DH_Inner_Data;`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ClientDhInnerData {
+    /// `nonce` from `req_pq_multi`.
+    pub nonce: Int128,
+    /// `server_nonce` from `resPQ`.
+    pub server_nonce: Int128,
+    /// 0 for the first attempt, `auth_key_aux_hash` of the previous
+    /// attempt otherwise.
+    pub retry_id: i64,
+    /// Big-endian `g ^ b mod dh_prime` for a secret `b` of the client.
+    pub g_b: ByteBuf,
+}
+
+/// `set_client_DH_params#f5045f1f nonce:int128 server_nonce:int128
+/// encrypted_data:string = Set_client_DH_params_answer;`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SetClientDhParams {
+    /// `nonce` from `req_pq_multi`.
+    pub nonce: Int128,
+    /// `server_nonce` from `resPQ`.
+    pub server_nonce: Int128,
+    /// Boxed `client_DH_inner_data` encrypted with a key derived from the
+    /// nonces.
+    pub encrypted_data: ByteBuf,
+}
+
+/// `Set_client_DH_params_answer` type.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum SetClientDhParamsAnswer {
+    /// `dh_gen_ok#3bcbf734 nonce:int128 server_nonce:int128
+    /// new_nonce_hash1:int128 = Set_client_DH_params_answer;`
+    Ok {
+        /// `nonce` from `req_pq_multi`.
+        nonce: Int128,
+        /// `server_nonce` from `resPQ`.
+        server_nonce: Int128,
+        /// See [`new_nonce_hash`].
+        new_nonce_hash1: Int128,
+    },
+    /// `dh_gen_retry#46dc1fb9 nonce:int128 server_nonce:int128
+    /// new_nonce_hash2:int128 = Set_client_DH_params_answer;`
+    Retry {
+        /// `nonce` from `req_pq_multi`.
+        nonce: Int128,
+        /// `server_nonce` from `resPQ`.
+        server_nonce: Int128,
+        /// See [`new_nonce_hash`].
+        new_nonce_hash2: Int128,
+    },
+    /// `dh_gen_fail#a69dae02 nonce:int128 server_nonce:int128
+    /// new_nonce_hash3:int128 = Set_client_DH_params_answer;`
+    Fail {
+        /// `nonce` from `req_pq_multi`.
+        nonce: Int128,
+        /// `server_nonce` from `resPQ`.
+        server_nonce: Int128,
+        /// See [`new_nonce_hash`].
+        new_nonce_hash3: Int128,
+    },
+}
+
+
+impl_identifiable_and_sized! {
+    ReqPqMulti        => (REQ_PQ_MULTI_ID, [nonce]),
+    ResPq             => (RES_PQ_ID, [nonce, server_nonce, pq, server_public_key_fingerprints]),
+    PQInnerDataDc     => (P_Q_INNER_DATA_DC_ID, [pq, p, q, nonce, server_nonce, new_nonce, dc]),
+    ReqDhParams       => (REQ_DH_PARAMS_ID, [nonce, server_nonce, p, q, public_key_fingerprint, encrypted_data]),
+    ServerDhInnerData => (SERVER_DH_INNER_DATA_ID, [nonce, server_nonce, g, dh_prime, g_a, server_time]),
+    ClientDhInnerData => (CLIENT_DH_INNER_DATA_ID, [nonce, server_nonce, retry_id, g_b]),
+    SetClientDhParams => (SET_CLIENT_DH_PARAMS_ID, [nonce, server_nonce, encrypted_data]),
+}
+
+impl Identifiable for ServerDhParams {
+    fn all_type_ids() -> &'static [u32] {
+        &[SERVER_DH_PARAMS_FAIL_ID, SERVER_DH_PARAMS_OK_ID]
+    }
+
+    fn all_enum_variant_names() -> Option<&'static [&'static str]> {
+        Some(&["Fail", "Ok"])
+    }
+
+    fn type_id(&self) -> u32 {
+        match *self {
+            ServerDhParams::Fail { .. } => SERVER_DH_PARAMS_FAIL_ID,
+            ServerDhParams::Ok { .. } => SERVER_DH_PARAMS_OK_ID,
+        }
+    }
+
+    fn enum_variant_id(&self) -> Option<&'static str> {
+        match *self {
+            ServerDhParams::Fail { .. } => Some("Fail"),
+            ServerDhParams::Ok { .. } => Some("Ok"),
+        }
+    }
+}
+
+impl MtProtoSized for ServerDhParams {
+    fn size_hint(&self) -> error::Result<usize> {
+        let (nonce, server_nonce, rest_size) = match *self {
+            ServerDhParams::Fail { ref nonce, ref server_nonce, ref new_nonce_hash } => {
+                (nonce, server_nonce, new_nonce_hash.size_hint()?)
+            },
+            ServerDhParams::Ok { ref nonce, ref server_nonce, ref encrypted_answer } => {
+                (nonce, server_nonce, encrypted_answer.size_hint()?)
+            },
+        };
+
+        Ok(nonce.size_hint()? + server_nonce.size_hint()? + rest_size)
+    }
+}
+
+impl Identifiable for SetClientDhParamsAnswer {
+    fn all_type_ids() -> &'static [u32] {
+        &[DH_GEN_OK_ID, DH_GEN_RETRY_ID, DH_GEN_FAIL_ID]
+    }
+
+    fn all_enum_variant_names() -> Option<&'static [&'static str]> {
+        Some(&["Ok", "Retry", "Fail"])
+    }
+
+    fn type_id(&self) -> u32 {
+        match *self {
+            SetClientDhParamsAnswer::Ok { .. } => DH_GEN_OK_ID,
+            SetClientDhParamsAnswer::Retry { .. } => DH_GEN_RETRY_ID,
+            SetClientDhParamsAnswer::Fail { .. } => DH_GEN_FAIL_ID,
+        }
+    }
+
+    fn enum_variant_id(&self) -> Option<&'static str> {
+        match *self {
+            SetClientDhParamsAnswer::Ok { .. } => Some("Ok"),
+            SetClientDhParamsAnswer::Retry { .. } => Some("Retry"),
+            SetClientDhParamsAnswer::Fail { .. } => Some("Fail"),
+        }
+    }
+}
+
+impl MtProtoSized for SetClientDhParamsAnswer {
+    fn size_hint(&self) -> error::Result<usize> {
+        // All variants have the same layout
+        let (nonce, server_nonce, new_nonce_hash) = self.fields();
+
+        Ok(nonce.size_hint()? + server_nonce.size_hint()? + new_nonce_hash.size_hint()?)
+    }
+}
+
+impl SetClientDhParamsAnswer {
+    fn fields(&self) -> (&Int128, &Int128, &Int128) {
+        match *self {
+            SetClientDhParamsAnswer::Ok { ref nonce, ref server_nonce, new_nonce_hash1: ref hash } |
+            SetClientDhParamsAnswer::Retry { ref nonce, ref server_nonce, new_nonce_hash2: ref hash } |
+            SetClientDhParamsAnswer::Fail { ref nonce, ref server_nonce, new_nonce_hash3: ref hash } => {
+                (nonce, server_nonce, hash)
+            },
+        }
+    }
+}
+
+
+/// An authorization key created by a [`Handshake`].
+#[derive(Clone, Debug)]
+pub struct Authorization {
+    /// The authorization key.
+    pub auth_key: AuthKey,
+    /// Server salt for the first encrypted messages.
+    pub server_salt: i64,
+    /// Difference between the server and the local time in seconds.
+    pub time_offset: i64,
+}
+
+/// Result of feeding a response to a [`Handshake`].
+#[derive(Debug)]
+pub enum Step {
+    /// Send these bytes to the server and feed the response to the
+    /// handshake.
+    Send(Vec<u8>),
+    /// The authorization key is created.
+    Done(Authorization),
+}
+
+/// A client-side authorization key exchange.
+///
+/// [`start`](#method.start) returns the first request, and every response
+/// of the server fed to [`handle`](#method.handle) yields either the next
+/// request or the created key. An error aborts the exchange, after which it
+/// can be restarted with `start`.
+pub struct Handshake<C = SystemClock> {
+    server_keys: Vec<RsaPublicKey>,
+    dc_id: i32,
+    message_ids: MessageIdGenerator<C>,
+    state: State,
+}
+
+enum State {
+    Idle,
+    ResPq {
+        nonce: Int128,
+    },
+    ServerDhParams {
+        nonces: Nonces,
+    },
+    SetClientDhParamsAnswer {
+        nonces: Nonces,
+        dh_params: DhParams,
+        auth_key: AuthKey,
+    },
+    Done,
+}
+
+/// Nonces of a handshake after `resPQ` is received.
+#[derive(Clone, Copy)]
+struct Nonces {
+    nonce: Int128,
+    server_nonce: Int128,
+    new_nonce: Int256,
+}
+
+impl Nonces {
+    fn check(&self, nonce: &Int128, server_nonce: &Int128) -> error::Result<()> {
+        check_nonce(&self.nonce, nonce)?;
+        check_nonce(&self.server_nonce, server_nonce)
+    }
+}
+
+impl Handshake<SystemClock> {
+    /// Create a handshake with a server which has private keys for some of
+    /// `server_keys` in a DC with id `dc_id`, using the system clock for
+    /// message ids.
+    pub fn new(server_keys: Vec<RsaPublicKey>, dc_id: i32) -> Handshake<SystemClock> {
+        Handshake::with_clock(server_keys, dc_id, SystemClock)
+    }
+}
+
+impl<C: Clock> Handshake<C> {
+    /// Create a handshake with a server which has private keys for some of
+    /// `server_keys` in a DC with id `dc_id`, using `clock` for message
+    /// ids.
+    pub fn with_clock(server_keys: Vec<RsaPublicKey>, dc_id: i32, clock: C) -> Handshake<C> {
+        Handshake {
+            server_keys,
+            dc_id,
+            message_ids: MessageIdGenerator::with_clock(clock),
+            state: State::Idle,
+        }
+    }
+
+    /// Start or restart the exchange and return the first request.
+    ///
+    /// Uses `rand::thread_rng()` for random values.
+    pub fn start(&mut self) -> error::Result<Vec<u8>> {
+        self.start_with_rng(&mut rand::thread_rng())
+    }
+
+    /// Start or restart the exchange using `rng` for random values and
+    /// return the first request.
+    pub fn start_with_rng<R>(&mut self, rng: &mut R) -> error::Result<Vec<u8>>
+        where R: RngCore + CryptoRng
+    {
+        let nonce = Int128::new(rng.gen());
+        let request = self.message(&ReqPqMulti { nonce })?;
+        self.state = State::ResPq { nonce };
+
+        Ok(request)
+    }
+
+    /// Handle a response of the server and return the next step.
+    ///
+    /// Uses `rand::thread_rng()` for random values.
+    pub fn handle(&mut self, response: &[u8]) -> error::Result<Step> {
+        self.handle_with_rng(response, &mut rand::thread_rng())
+    }
+
+    /// Handle a response of the server using `rng` for random values and
+    /// return the next step.
+    pub fn handle_with_rng<R>(&mut self, response: &[u8], rng: &mut R) -> error::Result<Step>
+        where R: RngCore + CryptoRng
+    {
+        // Any error leaves the handshake in the `Done` state
+        match mem::replace(&mut self.state, State::Done) {
+            State::ResPq { nonce } => {
+                self.handle_res_pq(nonce, response, rng)
+            },
+            State::ServerDhParams { nonces } => {
+                self.handle_server_dh_params(nonces, response, rng)
+            },
+            State::SetClientDhParamsAnswer { nonces, dh_params, auth_key } => {
+                self.handle_set_client_dh_params_answer(nonces, dh_params, &auth_key, response, rng)
+            },
+            State::Idle | State::Done => bail!(HandshakeErrorKind::UnexpectedMessage),
+        }
+    }
+
+    fn handle_res_pq<R>(&mut self, nonce: Int128, response: &[u8], rng: &mut R) -> error::Result<Step>
+        where R: RngCore + CryptoRng
+    {
+        let res_pq = from_bytes::<UnencryptedMessage<ResPq>>(response, &[])?.into_inner();
+        check_nonce(&nonce, &res_pq.nonce)?;
+
+        let fingerprints = res_pq.server_public_key_fingerprints.into_inner();
+        let server_key = match self.server_keys.iter().find(|key| fingerprints.contains(&key.fingerprint())) {
+            Some(server_key) => server_key,
+            None => bail!(HandshakeErrorKind::UnknownFingerprints(fingerprints)),
+        };
+
+        let pq = match pq_from_bytes(&res_pq.pq) {
+            Some(pq) => pq,
+            None => bail!(HandshakeErrorKind::InvalidPq(0)),
+        };
+        let (p, q) = match factorize(pq) {
+            Some(factors) => factors,
+            None => bail!(HandshakeErrorKind::InvalidPq(pq)),
+        };
+
+        let p = ByteBuf::from(u64_to_be_bytes(p));
+        let q = ByteBuf::from(u64_to_be_bytes(q));
+        let nonces = Nonces {
+            nonce,
+            server_nonce: res_pq.server_nonce,
+            new_nonce: Int256::new(rng.gen()),
+        };
+
+        let inner_data = PQInnerDataDc {
+            pq: res_pq.pq,
+            p: p.clone(),
+            q: q.clone(),
+            nonce,
+            server_nonce: nonces.server_nonce,
+            new_nonce: nonces.new_nonce,
+            dc: self.dc_id,
+        };
+        let encrypted_data = rsa::encrypt_pad_with_rng(server_key, &to_bytes(&Boxed::new(&inner_data))?, rng)?;
+
+        let request = self.message(&ReqDhParams {
+            nonce,
+            server_nonce: nonces.server_nonce,
+            p,
+            q,
+            public_key_fingerprint: server_key.fingerprint(),
+            encrypted_data: ByteBuf::from(encrypted_data),
+        })?;
+        self.state = State::ServerDhParams { nonces };
+
+        Ok(Step::Send(request))
+    }
+
+    fn handle_server_dh_params<R>(&mut self, nonces: Nonces, response: &[u8], rng: &mut R) -> error::Result<Step>
+        where R: RngCore + CryptoRng
+    {
+        let encrypted_answer = match from_bytes::<UnencryptedMessage<ServerDhParams>>(response, &[])?.into_inner() {
+            ServerDhParams::Fail { nonce, server_nonce, new_nonce_hash } => {
+                nonces.check(&nonce, &server_nonce)?;
+
+                let mut expected_hash = [0; 16];
+                #[cfg_attr(feature = "cargo-clippy", allow(clippy::indexing_slicing))]
+                expected_hash.copy_from_slice(&Sha1::digest(nonces.new_nonce.as_bytes())[4..20]);
+                if new_nonce_hash != Int128::new(expected_hash) {
+                    bail!(HandshakeErrorKind::NewNonceHashMismatch);
+                }
+
+                bail!(HandshakeErrorKind::ServerDhParamsFail);
+            },
+            ServerDhParams::Ok { nonce, server_nonce, encrypted_answer } => {
+                nonces.check(&nonce, &server_nonce)?;
+                encrypted_answer
+            },
+        };
+
+        let (tmp_aes_key, tmp_aes_iv) = tmp_aes_key_iv(&nonces.server_nonce, &nonces.new_nonce);
+        let answer: ServerDhInnerData = decrypt_with_hash(&tmp_aes_key, &tmp_aes_iv, &encrypted_answer)?;
+        nonces.check(&answer.nonce, &answer.server_nonce)?;
+
+        let dh_params = DhParams::new(answer.g, &answer.dh_prime, &answer.g_a, rng)?;
+        self.message_ids.correct_time_offset(i64::from(answer.server_time) << 32);
+
+        self.set_client_dh_params(nonces, dh_params, 0, rng)
+    }
+
+    fn handle_set_client_dh_params_answer<R>(&mut self,
+                                             nonces: Nonces,
+                                             dh_params: DhParams,
+                                             auth_key: &AuthKey,
+                                             response: &[u8],
+                                             rng: &mut R)
+                                            -> error::Result<Step>
+        where R: RngCore + CryptoRng
+    {
+        let answer = from_bytes::<UnencryptedMessage<SetClientDhParamsAnswer>>(response, &[])?.into_inner();
+        let (nonce, server_nonce, received_hash) = answer.fields();
+        nonces.check(nonce, server_nonce)?;
+
+        let number = match answer {
+            SetClientDhParamsAnswer::Ok { .. } => 1,
+            SetClientDhParamsAnswer::Retry { .. } => 2,
+            SetClientDhParamsAnswer::Fail { .. } => 3,
+        };
+        if *received_hash != new_nonce_hash(&nonces.new_nonce, number, auth_key) {
+            bail!(HandshakeErrorKind::NewNonceHashMismatch);
+        }
+
+        match answer {
+            SetClientDhParamsAnswer::Ok { .. } => Ok(Step::Done(Authorization {
+                auth_key: auth_key.clone(),
+                server_salt: server_salt(&nonces.server_nonce, &nonces.new_nonce),
+                time_offset: self.message_ids.time_offset(),
+            })),
+            SetClientDhParamsAnswer::Retry { .. } => {
+                let retry_id = LittleEndian::read_i64(&auth_key_aux_hash(auth_key));
+                self.set_client_dh_params(nonces, dh_params, retry_id, rng)
+            },
+            SetClientDhParamsAnswer::Fail { .. } => bail!(HandshakeErrorKind::DhGenFail),
+        }
+    }
+
+    fn set_client_dh_params<R>(&mut self,
+                               nonces: Nonces,
+                               dh_params: DhParams,
+                               retry_id: i64,
+                               rng: &mut R)
+                              -> error::Result<Step>
+        where R: RngCore + CryptoRng
+    {
+        let (b, g_b) = loop {
+            let b = random_biguint(rng);
+            let g_b = dh_params.g.modpow(&b, &dh_params.prime);
+
+            if dh_params.is_in_range(&g_b) {
+                break (b, g_b);
+            }
+        };
+
+        let auth_key = AuthKey::from_slice(&to_be_bytes_padded(&dh_params.g_a.modpow(&b, &dh_params.prime)))?;
+
+        let inner_data = ClientDhInnerData {
+            nonce: nonces.nonce,
+            server_nonce: nonces.server_nonce,
+            retry_id,
+            g_b: ByteBuf::from(g_b.to_bytes_be()),
+        };
+        let (tmp_aes_key, tmp_aes_iv) = tmp_aes_key_iv(&nonces.server_nonce, &nonces.new_nonce);
+        let encrypted_data = encrypt_with_hash(&tmp_aes_key, &tmp_aes_iv, &to_bytes(&Boxed::new(&inner_data))?, rng);
+
+        let request = self.message(&SetClientDhParams {
+            nonce: nonces.nonce,
+            server_nonce: nonces.server_nonce,
+            encrypted_data: ByteBuf::from(encrypted_data),
+        })?;
+        self.state = State::SetClientDhParamsAnswer { nonces, dh_params, auth_key };
+
+        Ok(Step::Send(request))
+    }
+
+    fn message<T>(&mut self, body: &T) -> error::Result<Vec<u8>>
+        where T: Serialize + Identifiable + MtProtoSized
+    {
+        let message_id = self.message_ids.next_id(MessageIdKind::Client);
+
+        to_bytes(&UnencryptedMessage::new(message_id, body))
+    }
+}
+
+// Don't leak nonces and keys into logs
+impl<C: fmt::Debug> fmt::Debug for Handshake<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = match self.state {
+            State::Idle => "Idle",
+            State::ResPq { .. } => "ResPq",
+            State::ServerDhParams { .. } => "ServerDhParams",
+            State::SetClientDhParamsAnswer { .. } => "SetClientDhParamsAnswer",
+            State::Done => "Done",
+        };
+
+        f.debug_struct("Handshake")
+            .field("server_keys", &self.server_keys)
+            .field("dc_id", &self.dc_id)
+            .field("message_ids", &self.message_ids)
+            .field("state", &state)
+            .finish()
+    }
+}
+
+
+/// Derive the AES key and IV which encrypt `server_DH_inner_data` and
+/// `client_DH_inner_data`.
+#[cfg_attr(feature = "cargo-clippy", allow(clippy::indexing_slicing))]
+pub fn tmp_aes_key_iv(server_nonce: &Int128, new_nonce: &Int256) -> ([u8; 32], [u8; 32]) {
+    let new_server = Sha1::new().chain(new_nonce.as_bytes()).chain(server_nonce.as_bytes()).result();
+    let server_new = Sha1::new().chain(server_nonce.as_bytes()).chain(new_nonce.as_bytes()).result();
+    let new_new = Sha1::new().chain(new_nonce.as_bytes()).chain(new_nonce.as_bytes()).result();
+
+    let mut key = [0; 32];
+    key[0..20].copy_from_slice(&new_server);
+    key[20..32].copy_from_slice(&server_new[0..12]);
+
+    let mut iv = [0; 32];
+    iv[0..8].copy_from_slice(&server_new[12..20]);
+    iv[8..28].copy_from_slice(&new_new);
+    iv[28..32].copy_from_slice(&new_nonce.as_bytes()[0..4]);
+
+    (key, iv)
+}
+
+/// Compute `new_nonce_hash1`, `new_nonce_hash2` or `new_nonce_hash3`
+/// depending on `number`: the 128 lower-order bits of SHA-1 of `new_nonce`,
+/// `number` and the 64 higher-order bits of SHA-1 of `auth_key`.
+#[cfg_attr(feature = "cargo-clippy", allow(clippy::indexing_slicing))]
+pub fn new_nonce_hash(new_nonce: &Int256, number: u8, auth_key: &AuthKey) -> Int128 {
+    let hash = Sha1::new()
+        .chain(new_nonce.as_bytes())
+        .chain([number])
+        .chain(auth_key_aux_hash(auth_key))
+        .result();
+
+    let mut bytes = [0; 16];
+    bytes.copy_from_slice(&hash[4..20]);
+
+    Int128::new(bytes)
+}
+
+/// Compute the first server salt, which is `new_nonce` XOR `server_nonce`
+/// truncated to 64 bits.
+pub fn server_salt(server_nonce: &Int128, new_nonce: &Int256) -> i64 {
+    LittleEndian::read_i64(&new_nonce.as_bytes()[0..8]) ^ LittleEndian::read_i64(&server_nonce.as_bytes()[0..8])
+}
+
+
+/// DH parameters received from the server.
+struct DhParams {
+    prime: BigUint,
+    g: BigUint,
+    g_a: BigUint,
+}
+
+impl DhParams {
+    fn new<R: RngCore>(g: i32, prime: &[u8], g_a: &[u8], rng: &mut R) -> error::Result<DhParams> {
+        let prime = BigUint::from_bytes_be(prime);
+        if prime.bits() != KEY_BITS || !is_safe_prime(&prime, rng) {
+            bail!(HandshakeErrorKind::InvalidDhPrime);
+        }
+
+        // `g` must generate a cyclic subgroup of prime order (p - 1) / 2,
+        // i.e. be a quadratic residue modulo p
+        let is_valid_g = match g {
+            2 => rem_u32(&prime, 8) == 7,
+            3 => rem_u32(&prime, 3) == 2,
+            4 => true,
+            5 => [1, 4].contains(&rem_u32(&prime, 5)),
+            6 => [19, 23].contains(&rem_u32(&prime, 24)),
+            7 => [3, 5, 6].contains(&rem_u32(&prime, 7)),
+            _ => false,
+        };
+        if !is_valid_g {
+            bail!(HandshakeErrorKind::InvalidG(g));
+        }
+
+        let dh_params = DhParams {
+            prime,
+            g: BigUint::from(cast::<i32, u32>(g).unwrap_or(0)),
+            g_a: BigUint::from_bytes_be(g_a),
+        };
+        if !dh_params.is_in_range(&dh_params.g_a) {
+            bail!(HandshakeErrorKind::InvalidGA);
+        }
+
+        Ok(dh_params)
+    }
+
+    /// Check that `2^(2048 - 64) <= value <= prime - 2^(2048 - 64)`, which
+    /// also implies `1 < value < prime - 1`.
+    fn is_in_range(&self, value: &BigUint) -> bool {
+        let margin = BigUint::one() << (KEY_BITS - 64);
+
+        *value >= margin && self.prime >= margin && *value <= &self.prime - &margin
+    }
+}
+
+fn check_nonce(expected: &Int128, received: &Int128) -> error::Result<()> {
+    if expected != received {
+        bail!(HandshakeErrorKind::NonceMismatch);
+    }
+
+    Ok(())
+}
+
+/// The 64 higher-order bits of SHA-1 of the authorization key.
+#[cfg_attr(feature = "cargo-clippy", allow(clippy::indexing_slicing))]
+fn auth_key_aux_hash(auth_key: &AuthKey) -> [u8; 8] {
+    let mut aux_hash = [0; 8];
+    aux_hash.copy_from_slice(&Sha1::digest(auth_key.as_bytes())[0..8]);
+
+    aux_hash
+}
+
+/// Prepend SHA-1 of `data` and append random padding up to the AES block
+/// size, then encrypt with AES-256-IGE.
+#[cfg_attr(feature = "cargo-clippy", allow(clippy::indexing_slicing))]
+fn encrypt_with_hash<R: RngCore>(key: &[u8; 32], iv: &[u8; 32], data: &[u8], rng: &mut R) -> Vec<u8> {
+    let mut buf = Sha1::digest(data).to_vec();
+    buf.extend_from_slice(data);
+
+    let mut padding = [0; 15];
+    rng.fill_bytes(&mut padding);
+    let padding_len = (16 - buf.len() % 16) % 16;
+    buf.extend_from_slice(&padding[..padding_len]);
+
+    crypto::aes_ige_encrypt(key, iv, &mut buf);
+    buf
+}
+
+/// Decrypt with AES-256-IGE, then deserialize a boxed value checking the
+/// prepended SHA-1 and the padding.
+#[cfg_attr(feature = "cargo-clippy", allow(clippy::indexing_slicing))]
+fn decrypt_with_hash<T>(key: &[u8; 32], iv: &[u8; 32], encrypted: &[u8]) -> error::Result<T>
+    where T: DeserializeOwned + Identifiable
+{
+    if encrypted.len() < 20 || encrypted.len() % 16 != 0 {
+        bail!(HandshakeErrorKind::InvalidEncryptedAnswer);
+    }
+
+    let mut buf = encrypted.to_vec();
+    crypto::aes_ige_decrypt(key, iv, &mut buf);
+
+    let (hash, data) = buf.split_at(20);
+    let (value, padding) = from_bytes_reuse::<Boxed<T>>(data, &[])?;
+    let data_len = data.len() - padding.len();
+
+    if padding.len() >= 16 || *hash != *Sha1::digest(&data[..data_len]) {
+        bail!(HandshakeErrorKind::InvalidEncryptedAnswer);
+    }
+
+    Ok(value.into_inner())
+}
+
+fn random_biguint<R: RngCore>(rng: &mut R) -> BigUint {
+    let mut bytes = [0; KEY_BITS / 8];
+    rng.fill_bytes(&mut bytes);
+
+    BigUint::from_bytes_be(&bytes)
+}
+
+fn rem_u32(value: &BigUint, modulus: u32) -> u32 {
+    (value % modulus).to_u32().unwrap_or_else(|| unreachable!("remainder must be less than modulus"))
+}
+
+fn is_safe_prime<R: RngCore>(prime: &BigUint, rng: &mut R) -> bool {
+    let known_prime = BigUint::parse_bytes(KNOWN_DH_PRIME, 16);
+    if known_prime.as_ref() == Some(prime) {
+        return true;
+    }
+
+    is_probable_prime(prime, rng) && is_probable_prime(&((prime - 1_u32) >> 1), rng)
+}
+
+/// Miller-Rabin primality test.
+fn is_probable_prime<R: RngCore>(n: &BigUint, rng: &mut R) -> bool {
+    let one = BigUint::one();
+    let two = BigUint::from(2_u32);
+
+    if *n <= BigUint::from(3_u32) {
+        return *n > one;
+    }
+    if rem_u32(n, 2) == 0 {
+        return false;
+    }
+
+    let n_minus_one = n - &one;
+    let mut d = n_minus_one.clone();
+    let mut s = 0;
+    while rem_u32(&d, 2) == 0 {
+        d >>= 1;
+        s += 1;
+    }
+
+    'witnesses: for _ in 0..MILLER_RABIN_ROUNDS {
+        // A witness in 2..=n-2
+        let a = random_biguint(rng) % (n - 3_u32) + &two;
+        let mut x = a.modpow(&d, n);
+
+        if x == one || x == n_minus_one {
+            continue;
+        }
+
+        for _ in 1..s {
+            x = x.modpow(&two, n);
+
+            if x == n_minus_one {
+                continue 'witnesses;
+            }
+        }
+
+        return false;
+    }
+
+    true
+}
+
+fn pq_from_bytes(bytes: &[u8]) -> Option<u64> {
+    if bytes.is_empty() || bytes.len() > 8 {
+        return None;
+    }
+
+    Some(BigEndian::read_uint(bytes, bytes.len()))
+}
+
+fn u64_to_be_bytes(value: u64) -> Vec<u8> {
+    let mut bytes = [0; 8];
+    BigEndian::write_u64(&mut bytes, value);
+    bytes.iter().skip_while(|&&byte| byte == 0).cloned().collect()
+}
+
+/// Find factors `p < q` of `pq` using Pollard's rho algorithm.
+fn factorize(pq: u64) -> Option<(u64, u64)> {
+    if pq < 4 {
+        return None;
+    }
+    if pq % 2 == 0 {
+        return Some((2, pq / 2));
+    }
+
+    for c in 1..=PQ_FACTORIZATION_ATTEMPTS {
+        let f = |x: u64| -> u64 {
+            let value = (u128::from(x) * u128::from(x) + u128::from(c)) % u128::from(pq);
+            cast(value).unwrap_or_else(|| unreachable!("remainder must be less than modulus"))
+        };
+        let (mut x, mut y) = (2, 2);
+
+        for _ in 0..PQ_FACTORIZATION_ITERATIONS {
+            x = f(x);
+            y = f(f(y));
+
+            let d = gcd(cmp::max(x, y) - cmp::min(x, y), pq);
+            if d == pq {
+                break;
+            }
+            if d != 1 {
+                return Some((cmp::min(d, pq / d), cmp::max(d, pq / d)));
+            }
+        }
+    }
+
+    None
+}
+
+fn gcd(mut a: u64, mut b: u64) -> u64 {
+    while b != 0 {
+        let r = a % b;
+        a = b;
+        b = r;
+    }
+
+    a
+}
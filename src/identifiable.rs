@@ -17,6 +17,8 @@ pub const DOUBLE_ID: u32 = 0x2210c154;
 pub const STRING_ID: u32 = 0xb5286e24;
 /// Type id of the vector type.
 pub const VECTOR_ID: u32 = 0x1cb5c415;
+/// Type id of the `gzip_packed` constructor.
+pub const GZIP_PACKED_ID: u32 = 0x3072cfa1;
 
 
 const BOOL_IDS: &[u32] = &[BOOL_TRUE_ID, BOOL_FALSE_ID];
@@ -64,8 +66,8 @@ pub trait Identifiable {
     /// Get all enum variant names of an identifiable type.
     ///
     /// For structs this method must return `None` and for enums it must return
-    /// `Some` with stringified variant names in the same order as the variants
-    /// themselves.
+    /// `Some` with variant names, as Serde sees them, in the same order as the
+    /// variants themselves.
     ///
     /// # Compatibility note
     ///
@@ -224,6 +226,24 @@ impl<'a> Identifiable for &'a str {
     }
 }
 
+impl<T> Identifiable for &[T] {
+    fn all_type_ids() -> &'static [u32] {
+        VECTOR_IDS
+    }
+
+    fn all_enum_variant_names() -> Option<&'static [&'static str]> {
+        None
+    }
+
+    fn type_id(&self) -> u32 {
+        VECTOR_ID
+    }
+
+    fn enum_variant_id(&self) -> Option<&'static str> {
+        None
+    }
+}
+
 impl<T> Identifiable for Vec<T> {
     fn all_type_ids() -> &'static [u32] {
         VECTOR_IDS
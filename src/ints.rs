@@ -0,0 +1,208 @@
+//! Fixed-width `int128` and `int256` TL types.
+//!
+//! Unlike Rust `i128` and `u128` which are serialized as a pair of `long`
+//! values, these types are defined by TL as `int128 4*[ int ] = Int128` and
+//! `int256 8*[ int ] = Int256` and are mostly used as opaque random
+//! values, e.g. nonces during the authorization key exchange. Hence they
+//! store the serialized bytes as is.
+
+use std::fmt;
+use std::mem;
+
+use byteorder::{ByteOrder, LittleEndian};
+#[cfg(feature = "quickcheck")]
+use quickcheck::{Arbitrary, Gen};
+use serde::de::{self, Deserialize, Deserializer, Error as DeError, Visitor};
+use serde::ser::{Serialize, Serializer, SerializeTupleStruct};
+
+use crate::error::{self, DeErrorKind};
+use crate::sized::MtProtoSized;
+use crate::utils::{i128_from_parts, i128_to_parts, safe_uint_cast, u128_from_parts, u128_to_parts};
+
+
+const CHUNK_SIZE: usize = mem::size_of::<u32>() / mem::size_of::<u8>();
+
+
+macro_rules! impl_fixed_width_int {
+    ($(
+        $(#[$attr:meta])*
+        $name:ident: $len:expr;
+    )*) => {
+        $(
+            $(#[$attr])*
+            #[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+            pub struct $name {
+                bytes: [u8; $len],
+            }
+
+            impl $name {
+                /// Create a value from its serialized representation.
+                pub fn new(bytes: [u8; $len]) -> $name {
+                    $name { bytes }
+                }
+
+                /// Return an immutable reference to the serialized representation.
+                pub fn as_bytes(&self) -> &[u8; $len] {
+                    &self.bytes
+                }
+
+                /// Return a mutable reference to the serialized representation.
+                pub fn as_bytes_mut(&mut self) -> &mut [u8; $len] {
+                    &mut self.bytes
+                }
+
+                /// Consume the value and return its serialized representation.
+                pub fn into_bytes(self) -> [u8; $len] {
+                    self.bytes
+                }
+            }
+
+            impl From<[u8; $len]> for $name {
+                fn from(bytes: [u8; $len]) -> $name {
+                    $name::new(bytes)
+                }
+            }
+
+            impl From<$name> for [u8; $len] {
+                fn from(value: $name) -> [u8; $len] {
+                    value.into_bytes()
+                }
+            }
+
+            impl AsRef<[u8]> for $name {
+                fn as_ref(&self) -> &[u8] {
+                    &self.bytes
+                }
+            }
+
+            impl AsMut<[u8]> for $name {
+                fn as_mut(&mut self) -> &mut [u8] {
+                    &mut self.bytes
+                }
+            }
+
+            impl Serialize for $name {
+                fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+                    where S: Serializer
+                {
+                    let mut ser = serializer.serialize_tuple_struct(stringify!($name), $len / CHUNK_SIZE)?;
+
+                    for chunk_u32 in self.bytes.chunks(CHUNK_SIZE).map(LittleEndian::read_u32) {
+                        ser.serialize_field(&chunk_u32)?;
+                    }
+
+                    ser.end()
+                }
+            }
+
+            impl<'de> Deserialize<'de> for $name {
+                fn deserialize<D>(deserializer: D) -> Result<$name, D::Error>
+                    where D: Deserializer<'de>
+                {
+                    struct IntVisitor;
+
+                    impl<'de> Visitor<'de> for IntVisitor {
+                        type Value = $name;
+
+                        fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                            write!(f, "{} 32-bit chunks of {}", $len / CHUNK_SIZE, stringify!($name))
+                        }
+
+                        fn visit_seq<A>(self, mut seq: A) -> Result<$name, A::Error>
+                            where A: de::SeqAccess<'de>
+                        {
+                            let mut bytes = [0; $len];
+                            let chunks_count = safe_uint_cast::<usize, u32>($len / CHUNK_SIZE)
+                                .map_err(A::Error::custom)?;
+
+                            for (i, chunk_mut) in (0..).zip(bytes.chunks_mut(CHUNK_SIZE)) {
+                                let chunk_u32 = seq.next_element()?.ok_or_else(|| {
+                                    A::Error::custom(error::Error::from(
+                                        DeErrorKind::NotEnoughElements(i, chunks_count)))
+                                })?;
+
+                                LittleEndian::write_u32(chunk_mut, chunk_u32);
+                            }
+
+                            Ok($name { bytes })
+                        }
+                    }
+
+                    deserializer.deserialize_tuple_struct(stringify!($name), $len / CHUNK_SIZE, IntVisitor)
+                }
+            }
+
+            impl MtProtoSized for $name {
+                fn size_hint(&self) -> error::Result<usize> {
+                    Ok($len)
+                }
+            }
+
+            #[cfg(feature = "quickcheck")]
+            impl Arbitrary for $name {
+                fn arbitrary<G: Gen>(g: &mut G) -> $name {
+                    let mut bytes = [0; $len];
+                    g.fill_bytes(&mut bytes);
+
+                    $name { bytes }
+                }
+            }
+        )*
+    };
+}
+
+impl_fixed_width_int! {
+    /// The TL `int128` type, 16 bytes laid out as four little-endian
+    /// 32-bit `int` values.
+    Int128: 16;
+
+    /// The TL `int256` type, 32 bytes laid out as eight little-endian
+    /// 32-bit `int` values.
+    Int256: 32;
+}
+
+impl From<u128> for Int128 {
+    fn from(value: u128) -> Int128 {
+        let (hi, lo) = u128_to_parts(value);
+        let mut bytes = [0; 16];
+
+        {
+            let (lo_bytes, hi_bytes) = bytes.split_at_mut(8);
+            LittleEndian::write_u64(lo_bytes, lo);
+            LittleEndian::write_u64(hi_bytes, hi);
+        }
+
+        Int128 { bytes }
+    }
+}
+
+impl From<Int128> for u128 {
+    fn from(value: Int128) -> u128 {
+        let (lo_bytes, hi_bytes) = value.bytes.split_at(8);
+
+        u128_from_parts(LittleEndian::read_u64(hi_bytes), LittleEndian::read_u64(lo_bytes))
+    }
+}
+
+impl From<i128> for Int128 {
+    fn from(value: i128) -> Int128 {
+        let (hi, lo) = i128_to_parts(value);
+        let mut bytes = [0; 16];
+
+        {
+            let (lo_bytes, hi_bytes) = bytes.split_at_mut(8);
+            LittleEndian::write_u64(lo_bytes, lo);
+            LittleEndian::write_i64(hi_bytes, hi);
+        }
+
+        Int128 { bytes }
+    }
+}
+
+impl From<Int128> for i128 {
+    fn from(value: Int128) -> i128 {
+        let (lo_bytes, hi_bytes) = value.bytes.split_at(8);
+
+        i128_from_parts(LittleEndian::read_i64(hi_bytes), LittleEndian::read_u64(lo_bytes))
+    }
+}
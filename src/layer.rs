@@ -0,0 +1,230 @@
+//! Support for talking to servers on different API layers.
+//!
+//! Each API layer is a version of the TL schema, and the same logical
+//! constructor can have different ids (and layouts) on different layers.
+//! Types can be annotated with the range of layers they are valid for using
+//! `#[mtproto_identifiable(layers = "...")]` along with the id, which
+//! `#[derive(MtProtoDescribed)]` puts into their
+//! [`Constructor`](../registry/struct.Constructor.html) layouts. A
+//! [`Registry`](../registry/struct.Registry.html) then resolves which
+//! constructor to use for a chosen layer.
+//!
+//! The layer is chosen by wrapping the first query of a connection in
+//! [`InvokeWithLayer`] and [`InitConnection`]. Their queries are boxed, so
+//! they compose with the [`Boxed`](../struct.Boxed.html) wrapper, and they
+//! return whatever the wrapped query returns.
+//!
+//! # Examples
+//!
+//! ```
+//! use serde_mtproto::Boxed;
+//! use serde_mtproto::layer::{InitConnection, InvokeWithLayer};
+//! use serde_mtproto::rpc::call_to_bytes;
+//! use serde_mtproto::service::Ping;
+//!
+//! # fn run() -> serde_mtproto::Result<()> {
+//! let init_connection: InitConnection<_> = InitConnection::new(12345, Ping { ping_id: 1 });
+//! let query = InvokeWithLayer::new(100, init_connection);
+//!
+//! let bytes = call_to_bytes(&query)?;
+//! assert_eq!(&bytes[..8], &[0x0d, 0x0d, 0x9b, 0xda, 100, 0, 0, 0]);
+//! assert_eq!(&bytes[8..12], &[0xa9, 0x5e, 0xcd, 0xc1]);
+//! #     Ok(())
+//! # }
+//! #
+//! # fn main() { run().unwrap(); }
+//! ```
+
+#![cfg_attr(feature = "cargo-clippy", allow(clippy::unreadable_literal))]  // To match the look & feel from TL schema
+
+use std::fmt;
+
+use serde_derive::{Serialize, Deserialize};
+
+use crate::flags::{Flags, FlagsLayout};
+use crate::identifiable::Identifiable;
+use crate::rpc::RemoteCall;
+use crate::wrappers::Boxed;
+
+
+/// Type id of the `invokeWithLayer` function.
+pub const INVOKE_WITH_LAYER_ID: u32 = 0xda9b0d0d;
+/// Type id of the `initConnection` function.
+pub const INIT_CONNECTION_ID: u32 = 0xc1cd5ea9;
+/// Type id of the `inputClientProxy` constructor.
+pub const INPUT_CLIENT_PROXY_ID: u32 = 0x75588b3f;
+
+
+/// An inclusive range of API layers, possibly unbounded from either side.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct LayerRange {
+    start: Option<i32>,
+    end: Option<i32>,
+}
+
+impl LayerRange {
+    /// Create a range of layers from `start` to `end` inclusively, where
+    /// `None` means no bound.
+    pub fn new(start: Option<i32>, end: Option<i32>) -> LayerRange {
+        LayerRange { start, end }
+    }
+
+    /// Create a range of all layers.
+    pub fn all() -> LayerRange {
+        LayerRange::new(None, None)
+    }
+
+    /// Get the first layer of the range, if it's bounded.
+    pub fn start(&self) -> Option<i32> {
+        self.start
+    }
+
+    /// Get the last layer of the range, if it's bounded.
+    pub fn end(&self) -> Option<i32> {
+        self.end
+    }
+
+    /// Check whether the range contains `layer`.
+    pub fn contains(&self, layer: i32) -> bool {
+        let after_start = match self.start {
+            Some(start) => start <= layer,
+            None => true,
+        };
+        let before_end = match self.end {
+            Some(end) => layer <= end,
+            None => true,
+        };
+
+        after_start && before_end
+    }
+}
+
+impl Default for LayerRange {
+    fn default() -> LayerRange {
+        LayerRange::all()
+    }
+}
+
+/// Formats the range the same way it's written in
+/// `#[mtproto_identifiable(layers = "...")]`, e.g. `23..=44` or `45..`.
+impl fmt::Display for LayerRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(start) = self.start {
+            write!(f, "{}", start)?;
+        }
+
+        match self.end {
+            Some(end) => write!(f, "..={}", end),
+            None => f.write_str(".."),
+        }
+    }
+}
+
+
+/// `invokeWithLayer#da9b0d0d {X:Type} layer:int query:!X = X;`
+///
+/// Makes the server use the given layer for the query and the rest of the
+/// connection.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct InvokeWithLayer<X> {
+    /// The layer to use.
+    pub layer: i32,
+    /// The query, usually a `Boxed` function.
+    pub query: X,
+}
+
+impl<F: Identifiable> InvokeWithLayer<Boxed<F>> {
+    /// Wrap a function call to invoke it with `layer`.
+    pub fn new(layer: i32, query: F) -> InvokeWithLayer<Boxed<F>> {
+        InvokeWithLayer { layer, query: Boxed::new(query) }
+    }
+}
+
+/// `initConnection#c1cd5ea9 {X:Type} flags:# api_id:int device_model:string
+/// system_version:string app_version:string system_lang_code:string
+/// lang_pack:string lang_code:string proxy:flags.0?InputClientProxy
+/// params:flags.1?JSONValue query:!X = X;`
+///
+/// Initializes the connection with client information, which must be done
+/// with the first query of a connection.
+///
+/// `params` are of the boxed `JSONValue` type from the API schema, which is
+/// a type parameter since it's not a part of this crate.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct InitConnection<X, P = ()> {
+    /// Flags of `proxy` and `params`.
+    pub flags: Flags<InitConnection<X, P>>,
+    /// Application identifier.
+    pub api_id: i32,
+    /// Device model.
+    pub device_model: String,
+    /// Operating system version.
+    pub system_version: String,
+    /// Application version.
+    pub app_version: String,
+    /// Code of the operating system language.
+    pub system_lang_code: String,
+    /// Language pack to use.
+    pub lang_pack: String,
+    /// Code of the language to use.
+    pub lang_code: String,
+    /// Information about the proxy in use.
+    pub proxy: Option<Boxed<InputClientProxy>>,
+    /// Additional parameters.
+    pub params: Option<P>,
+    /// The query, usually a `Boxed` function.
+    pub query: X,
+}
+
+impl<F: Identifiable, P> InitConnection<Boxed<F>, P> {
+    /// Wrap a function call to initialize the connection with it, leaving
+    /// the client information empty and no proxy or parameters.
+    pub fn new(api_id: i32, query: F) -> InitConnection<Boxed<F>, P> {
+        InitConnection {
+            flags: Flags::new(),
+            api_id,
+            device_model: String::new(),
+            system_version: String::new(),
+            app_version: String::new(),
+            system_lang_code: String::new(),
+            lang_pack: String::new(),
+            lang_code: String::new(),
+            proxy: None,
+            params: None,
+            query: Boxed::new(query),
+        }
+    }
+}
+
+impl<X, P> FlagsLayout for InitConnection<X, P> {
+    fn flags_layout() -> &'static [&'static str] {
+        &["proxy:flags.0", "params:flags.1"]
+    }
+}
+
+/// `inputClientProxy#75588b3f address:string port:int = InputClientProxy;`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct InputClientProxy {
+    /// Proxy address.
+    pub address: String,
+    /// Proxy port.
+    pub port: i32,
+}
+
+
+impl_identifiable_and_sized! {
+    InvokeWithLayer<X>   => (INVOKE_WITH_LAYER_ID, [layer, query]),
+    InitConnection<X, P> => (INIT_CONNECTION_ID, [
+        flags, api_id, device_model, system_version, app_version, system_lang_code, lang_pack, lang_code, proxy,
+        params, query
+    ]),
+    InputClientProxy     => (INPUT_CLIENT_PROXY_ID, [address, port]),
+}
+
+impl<X: RemoteCall> RemoteCall for InvokeWithLayer<X> {
+    type Return = X::Return;
+}
+
+impl<X: RemoteCall, P> RemoteCall for InitConnection<X, P> {
+    type Return = X::Return;
+}
@@ -6,7 +6,7 @@
 
 
 // For `error_chain!` macro used in `error` module
-#![recursion_limit = "66"]
+#![recursion_limit = "128"]
 
 #![cfg_attr(feature = "test-nightly-regressions", feature(nll))]
 
@@ -71,14 +71,30 @@
 extern crate serde;
 
 
+#[macro_use]
 mod utils;
 
+#[cfg(feature = "crypto")]
+pub mod crypto;
 pub mod de;
 pub mod error;
+pub mod flags;
+#[cfg(feature = "crypto")]
+pub mod handshake;
 pub mod helpers;
 pub mod identifiable;
+pub mod ints;
+pub mod layer;
+pub mod message_id;
+pub mod registry;
+#[cfg(feature = "crypto")]
+pub mod rsa;
+pub mod rpc;
 pub mod ser;
+pub mod service;
+pub mod session;
 pub mod sized;
+pub mod transport;
 pub mod wrappers;
 
 
@@ -112,8 +128,13 @@ doc_inline! {
     pub use crate::error::{Error, ErrorKind, Result, ResultExt};
 
     // Other items generally useful for MTProto [de]serialization
+    pub use crate::flags::{Flags, FlagsLayout};
     pub use crate::helpers::{UnsizedByteBuf, UnsizedByteBufSeed};
     pub use crate::identifiable::Identifiable;
+    pub use crate::ints::{Int128, Int256};
+    pub use crate::rpc::RemoteCall;
     pub use crate::sized::{MtProtoSized, size_hint_from_byte_seq_len};
-    pub use crate::wrappers::{Boxed, WithId, WithSize};
+    pub use crate::wrappers::{Boxed, SkippedWithSize, UnencryptedMessage, WithId, WithSize};
+    #[cfg(feature = "gzip")]
+    pub use crate::wrappers::{GzipPacked, GzipPackedSeed};
 }
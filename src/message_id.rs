@@ -0,0 +1,287 @@
+//! Generation and validation of message ids.
+//!
+//! A message id approximately equals Unix time multiplied by 2^32, so the
+//! upper 32 bits hold seconds and the lower ones hold a fraction of a
+//! second. Ids of messages sent by a client are divisible by 4, ids of
+//! messages sent by a server yield 1 modulo 4 for responses and 3 for other
+//! messages. Ids must increase monotonically within a session.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::time::Duration;
+//!
+//! use serde_mtproto::UnencryptedMessage;
+//! use serde_mtproto::message_id::{MessageIdGenerator, MessageIdKind, MessageIdValidator};
+//!
+//! # fn run() -> serde_mtproto::Result<()> {
+//! let clock = || Duration::from_secs(1_500_000_000);
+//! let mut generator = MessageIdGenerator::with_clock(clock);
+//!
+//! let first_id = generator.next_id(MessageIdKind::Client);
+//! let second_id = generator.next_id(MessageIdKind::Client);
+//! assert_eq!(first_id, 1_500_000_000 << 32);
+//! assert_eq!(second_id, first_id + 4);
+//!
+//! let message = UnencryptedMessage::new(second_id, 0_i32);
+//! let validator = MessageIdValidator::new();
+//! assert_eq!(validator.validate(message.message_id(), generator.now())?, MessageIdKind::Client);
+//! #     Ok(())
+//! # }
+//! #
+//! # fn main() { run().unwrap(); }
+//! ```
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use error_chain::bail;
+use num_traits::cast::cast;
+
+use crate::error::{self, MessageIdErrorKind};
+use crate::service::BadMsgNotification;
+use crate::utils::safe_int_cast;
+
+
+/// Default for how many seconds a message id may be in the past.
+pub const DEFAULT_MAX_PAST_SECS: u64 = 300;
+/// Default for how many seconds a message id may be in the future.
+pub const DEFAULT_MAX_FUTURE_SECS: u64 = 30;
+
+/// `bad_msg_notification` error code for a message id which is too low.
+const MSG_ID_TOO_LOW: i32 = 16;
+/// `bad_msg_notification` error code for a message id which is too high.
+const MSG_ID_TOO_HIGH: i32 = 17;
+
+
+/// A source of the current time.
+pub trait Clock {
+    /// Return the time elapsed since the Unix epoch.
+    fn unix_time(&self) -> Duration;
+}
+
+/// The system clock.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn unix_time(&self) -> Duration {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_else(|_| Duration::from_secs(0))
+    }
+}
+
+impl<F: Fn() -> Duration> Clock for F {
+    fn unix_time(&self) -> Duration {
+        self()
+    }
+}
+
+
+/// The sender of a message and its purpose, encoded in the two lowest bits
+/// of its id.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum MessageIdKind {
+    /// A message sent by a client, the id is divisible by 4.
+    Client,
+    /// A response sent by a server, the id yields 1 modulo 4.
+    Response,
+    /// Any other message sent by a server, the id yields 3 modulo 4.
+    Notification,
+}
+
+impl MessageIdKind {
+    /// Get the kind of a message id, or `None` if the id is not valid for
+    /// any of them.
+    pub fn of(message_id: i64) -> Option<MessageIdKind> {
+        if message_id <= 0 {
+            return None;
+        }
+
+        match message_id & 3 {
+            0 => Some(MessageIdKind::Client),
+            1 => Some(MessageIdKind::Response),
+            3 => Some(MessageIdKind::Notification),
+            _ => None,
+        }
+    }
+
+    fn remainder(self) -> i64 {
+        match self {
+            MessageIdKind::Client => 0,
+            MessageIdKind::Response => 1,
+            MessageIdKind::Notification => 3,
+        }
+    }
+}
+
+
+/// A generator of monotonically increasing message ids.
+///
+/// The time is taken from a [`Clock`] and corrected by an offset to match
+/// the server time.
+#[derive(Clone, Debug)]
+pub struct MessageIdGenerator<C = SystemClock> {
+    clock: C,
+    time_offset: i64,
+    last_id: i64,
+}
+
+impl MessageIdGenerator<SystemClock> {
+    /// Create a generator using the system clock.
+    pub fn new() -> MessageIdGenerator<SystemClock> {
+        MessageIdGenerator::with_clock(SystemClock)
+    }
+}
+
+impl Default for MessageIdGenerator<SystemClock> {
+    fn default() -> MessageIdGenerator<SystemClock> {
+        MessageIdGenerator::new()
+    }
+}
+
+impl<C: Clock> MessageIdGenerator<C> {
+    /// Create a generator using `clock`.
+    pub fn with_clock(clock: C) -> MessageIdGenerator<C> {
+        MessageIdGenerator { clock, time_offset: 0, last_id: 0 }
+    }
+
+    /// Return the current time corrected by the time offset.
+    pub fn now(&self) -> Duration {
+        let unix_time = self.clock.unix_time();
+        let offset = Duration::from_secs(cast(self.time_offset.wrapping_abs()).unwrap_or(0));
+
+        if self.time_offset >= 0 {
+            unix_time.checked_add(offset).unwrap_or(unix_time)
+        } else {
+            unix_time.checked_sub(offset).unwrap_or_default()
+        }
+    }
+
+    /// Return the number of seconds added to the clock time.
+    pub fn time_offset(&self) -> i64 {
+        self.time_offset
+    }
+
+    /// Set the number of seconds added to the clock time.
+    pub fn set_time_offset(&mut self, time_offset: i64) {
+        self.time_offset = time_offset;
+    }
+
+    /// Set the time offset so that the current time matches the time of a
+    /// message id generated by the server.
+    ///
+    /// Ids generated afterwards are based on the corrected time, but still
+    /// greater than the ones generated before, so moving the time backwards
+    /// only has effect once it catches up with them.
+    pub fn correct_time_offset(&mut self, server_message_id: i64) {
+        let clock_secs = i128::from(self.clock.unix_time().as_secs());
+        let offset = i128::from(server_message_id >> 32) - clock_secs;
+
+        self.time_offset = safe_int_cast(offset).unwrap_or(0);
+    }
+
+    /// Correct the time offset if `notification` reports that a message id
+    /// is too low or too high, using the id of the message which
+    /// contained the notification.
+    ///
+    /// If the id is too high, ids generated afterwards follow the corrected
+    /// time right away, even though they are lower than the ones generated
+    /// before, which the server rejects anyway.
+    ///
+    /// Returns `true` if the time offset was corrected.
+    pub fn handle_bad_msg_notification(&mut self,
+                                       notification: &BadMsgNotification,
+                                       server_message_id: i64)
+                                      -> bool
+    {
+        match notification.error_code {
+            MSG_ID_TOO_LOW => {
+                self.correct_time_offset(server_message_id);
+                true
+            },
+            MSG_ID_TOO_HIGH => {
+                self.correct_time_offset(server_message_id);
+                self.last_id = 0;
+                true
+            },
+            _ => false,
+        }
+    }
+
+    /// Generate a message id of the given kind which is greater than all
+    /// previously generated ones, unless the server has reported them too
+    /// high since.
+    ///
+    /// Time-based ids fit in `i64` until 2038.
+    pub fn next_id(&mut self, kind: MessageIdKind) -> i64 {
+        let now = self.now();
+        let secs = i128::from(now.as_secs());
+        let fraction = (i128::from(now.subsec_nanos()) << 32) / 1_000_000_000;
+        let time_id = safe_int_cast::<i128, i64>((secs << 32) | fraction).unwrap_or(i64::MAX);
+
+        let mut id = (time_id & !3) | kind.remainder();
+        if id <= self.last_id {
+            id = (self.last_id & !3).saturating_add(4) | kind.remainder();
+        }
+
+        self.last_id = id;
+        id
+    }
+}
+
+
+/// A validator of received message ids.
+///
+/// Rejects ids which are malformed or too far in the past or in the future
+/// (by default more than 300 and 30 seconds respectively).
+#[derive(Clone, Debug)]
+pub struct MessageIdValidator {
+    max_past_secs: u64,
+    max_future_secs: u64,
+}
+
+impl MessageIdValidator {
+    /// Create a validator with the default time window.
+    pub fn new() -> MessageIdValidator {
+        MessageIdValidator {
+            max_past_secs: DEFAULT_MAX_PAST_SECS,
+            max_future_secs: DEFAULT_MAX_FUTURE_SECS,
+        }
+    }
+
+    /// Set how many seconds a message id may be in the past.
+    pub fn max_past_secs(self, max_past_secs: u64) -> MessageIdValidator {
+        MessageIdValidator { max_past_secs, ..self }
+    }
+
+    /// Set how many seconds a message id may be in the future.
+    pub fn max_future_secs(self, max_future_secs: u64) -> MessageIdValidator {
+        MessageIdValidator { max_future_secs, ..self }
+    }
+
+    /// Check a message id against the current time `now` and return its
+    /// kind.
+    pub fn validate(&self, message_id: i64, now: Duration) -> error::Result<MessageIdKind> {
+        let kind = match MessageIdKind::of(message_id) {
+            Some(kind) => kind,
+            None => bail!(MessageIdErrorKind::Invalid(message_id)),
+        };
+
+        let ahead_secs = i128::from(message_id >> 32) - i128::from(now.as_secs());
+
+        if -ahead_secs > i128::from(self.max_past_secs) {
+            bail!(MessageIdErrorKind::TooOld(message_id, safe_int_cast(-ahead_secs)?));
+        }
+
+        if ahead_secs > i128::from(self.max_future_secs) {
+            bail!(MessageIdErrorKind::TooNew(message_id, safe_int_cast(ahead_secs)?));
+        }
+
+        Ok(kind)
+    }
+}
+
+impl Default for MessageIdValidator {
+    fn default() -> MessageIdValidator {
+        MessageIdValidator::new()
+    }
+}
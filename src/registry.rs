@@ -0,0 +1,319 @@
+//! Constructor layouts which make MTProto data self-describing.
+//!
+//! MTProto binary representation doesn't carry field names or types, so
+//! `Deserializer::deserialize_any` and `Deserializer::deserialize_ignored_any`
+//! are only supported when the deserializer has a [`Registry`] to look up
+//! the layout of a boxed value by its constructor id. This allows to
+//! deserialize into self-describing data structures like `serde_json::Value`,
+//! use `#[serde(untagged)]` enums or skip values of registered types.
+//!
+//! The registry can be populated manually (e.g. from a TL schema) or from
+//! types implementing [`TlDescribed`] which can be derived using
+//! `#[derive(MtProtoDescribed)]`.
+//!
+//! Constructors are given to the visitor as maps whose first entry `_`
+//! holds the constructor name and the rest are the fields. Fields bound to
+//! an unset flags bit are given as `none`, `flags.N?true` fields are given
+//! as `bool` values.
+//!
+//! ```
+//! # use serde_mtproto::layer::LayerRange;
+//! # use serde_mtproto::registry::{Constructor, Field, Registry, TlType};
+//! let mut registry = Registry::new();
+//! registry.insert(Constructor {
+//!     name: "inputPeerUser".to_owned(),
+//!     id: 0x7b8e7de6,
+//!     fields: vec![
+//!         Field::new("user_id", TlType::Int),
+//!         Field::new("access_hash", TlType::Long),
+//!     ],
+//!     layers: LayerRange::all(),
+//! });
+//!
+//! let bytes = [0xe6, 0x7d, 0x8e, 0x7b, 1, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0];
+//! let mut de = serde_mtproto::Deserializer::new(&bytes[..], &[]);
+//! de.set_registry(&registry);
+//!
+//! let value: serde_json::Value = serde::Deserialize::deserialize(&mut de).unwrap();
+//! assert_eq!(value, serde_json::json!({
+//!     "_": "inputPeerUser",
+//!     "user_id": 1,
+//!     "access_hash": 2,
+//! }));
+//! ```
+//!
+//! A registry may hold constructors of several API layers at once. Each
+//! constructor knows the [`LayerRange`](../layer/struct.LayerRange.html) it is
+//! valid for, so the one to use for a layer can be resolved by name with
+//! [`Registry::resolve`], and [`Registry::for_layer`] leaves only those
+//! valid for a layer to deserialize data of that layer.
+//!
+//! ```
+//! # use serde_mtproto::layer::LayerRange;
+//! # use serde_mtproto::registry::{Constructor, Field, Registry, TlType};
+//! let mut registry = Registry::new();
+//! registry.insert(Constructor {
+//!     name: "userEmpty".to_owned(),
+//!     id: 0x200250ba,
+//!     fields: vec![Field::new("id", TlType::Int)],
+//!     layers: LayerRange::new(None, Some(132)),
+//! });
+//! registry.insert(Constructor {
+//!     name: "userEmpty".to_owned(),
+//!     id: 0xd3bc4b7a,
+//!     fields: vec![Field::new("id", TlType::Long)],
+//!     layers: LayerRange::new(Some(133), None),
+//! });
+//!
+//! assert_eq!(registry.resolve("userEmpty", 100).map(|c| c.id), Some(0x200250ba));
+//! assert_eq!(registry.resolve("userEmpty", 133).map(|c| c.id), Some(0xd3bc4b7a));
+//! assert!(registry.for_layer(133).get(0x200250ba).is_none());
+//! ```
+
+#![cfg_attr(feature = "cargo-clippy", allow(clippy::unreadable_literal))]  // To match the look & feel from TL schema
+
+use std::collections::HashMap;
+
+use serde_bytes::ByteBuf;
+
+use crate::flags::Flags;
+use crate::ints::{Int128, Int256};
+use crate::layer::LayerRange;
+use crate::wrappers::Boxed;
+
+
+/// A TL type of a constructor field.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum TlType {
+    /// `int`, a 32-bit integer.
+    Int,
+    /// `long`, a 64-bit integer.
+    Long,
+    /// `double`, a 64-bit floating-point number.
+    Double,
+    /// `Bool`, which is boxed with either of `boolTrue` and `boolFalse`
+    /// constructors.
+    Bool,
+    /// `string`, a UTF-8 string.
+    String,
+    /// `bytes`, a byte sequence.
+    Bytes,
+    /// `int128`, 16 opaque bytes.
+    Int128,
+    /// `int256`, 32 opaque bytes.
+    Int256,
+    /// `#`, a flags field for `flags.N?T` fields of the same constructor.
+    Flags,
+    /// `true` which is only represented by a flags bit.
+    True,
+    /// Bare `vector<T>`.
+    Vector(Box<TlType>),
+    /// Boxed `Vector<T>`.
+    BoxedVector(Box<TlType>),
+    /// A bare constructor with the given id.
+    Bare(u32),
+    /// Any boxed type whose constructor id is read from data.
+    Boxed,
+}
+
+/// A `flags.N?T` field condition.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct FieldCondition {
+    /// Name of the flags field of the same constructor.
+    pub flags_field: String,
+    /// Bit of the flags field which tells if the field is present.
+    pub bit: u32,
+}
+
+/// A field of a constructor.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Field {
+    /// Field name.
+    pub name: String,
+    /// Field type.
+    pub ty: TlType,
+    /// Flags bit the field is bound to, if any.
+    pub condition: Option<FieldCondition>,
+}
+
+impl Field {
+    /// Create an unconditional field.
+    pub fn new(name: &str, ty: TlType) -> Field {
+        Field { name: name.to_owned(), ty, condition: None }
+    }
+
+    /// Create a `flags.N?T` field.
+    pub fn conditional(name: &str, ty: TlType, flags_field: &str, bit: u32) -> Field {
+        let condition = FieldCondition { flags_field: flags_field.to_owned(), bit };
+
+        Field { name: name.to_owned(), ty, condition: Some(condition) }
+    }
+}
+
+/// Layout of a constructor.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Constructor {
+    /// Constructor name.
+    pub name: String,
+    /// Constructor id.
+    pub id: u32,
+    /// Fields in the order they are serialized.
+    pub fields: Vec<Field>,
+    /// API layers the constructor is valid for.
+    pub layers: LayerRange,
+}
+
+/// A collection of constructor layouts indexed by their ids.
+#[derive(Clone, Debug, Default)]
+pub struct Registry {
+    constructors: HashMap<u32, Constructor>,
+}
+
+impl Registry {
+    /// Create an empty registry.
+    pub fn new() -> Registry {
+        Registry { constructors: HashMap::new() }
+    }
+
+    /// Add a constructor layout, returning the previous one with the same id.
+    pub fn insert(&mut self, constructor: Constructor) -> Option<Constructor> {
+        self.constructors.insert(constructor.id, constructor)
+    }
+
+    /// Add constructor layouts of `T`.
+    ///
+    /// Types of `T` fields are not registered, this should be done
+    /// separately.
+    pub fn register<T: TlDescribed>(&mut self) {
+        for constructor in T::tl_constructors() {
+            let _ = self.insert(constructor);
+        }
+    }
+
+    /// Get a constructor layout by its id.
+    pub fn get(&self, id: u32) -> Option<&Constructor> {
+        self.constructors.get(&id)
+    }
+
+    /// Iterate over all registered constructor layouts in arbitrary order.
+    pub fn constructors(&self) -> impl Iterator<Item = &Constructor> {
+        self.constructors.values()
+    }
+
+    /// Get the layout of the constructor named `name` (e.g. `userEmpty`)
+    /// which is valid for `layer`.
+    ///
+    /// If several constructors match, any of them is returned.
+    pub fn resolve(&self, name: &str, layer: i32) -> Option<&Constructor> {
+        self.constructors().find(|c| c.name == name && c.layers.contains(layer))
+    }
+
+    /// Create a registry with only those constructor layouts which are
+    /// valid for `layer`.
+    pub fn for_layer(&self, layer: i32) -> Registry {
+        let constructors = self.constructors.iter()
+            .filter(|&(_, c)| c.layers.contains(layer))
+            .map(|(&id, c)| (id, c.clone()))
+            .collect();
+
+        Registry { constructors }
+    }
+}
+
+
+/// A trait for a Rust data structure that can describe its TL layout.
+pub trait TlDescribed {
+    /// The TL type of the value when it is a field of some constructor.
+    fn tl_type() -> TlType;
+
+    /// Constructor layouts of this type if it is a TL type with
+    /// constructors, i.e. not a builtin one.
+    fn tl_constructors() -> Vec<Constructor> {
+        Vec::new()
+    }
+}
+
+macro_rules! impl_tl_described_for_builtin_types {
+    ($($type:ty => $tl_type:expr,)*) => {
+        $(
+            impl TlDescribed for $type {
+                fn tl_type() -> TlType {
+                    $tl_type
+                }
+            }
+        )*
+    };
+}
+
+impl_tl_described_for_builtin_types! {
+    bool => TlType::Bool,
+
+    i8  => TlType::Int,
+    i16 => TlType::Int,
+    i32 => TlType::Int,
+    i64 => TlType::Long,
+
+    u8  => TlType::Int,
+    u16 => TlType::Int,
+    u32 => TlType::Int,
+    u64 => TlType::Long,
+
+    f32 => TlType::Double,
+    f64 => TlType::Double,
+
+    String => TlType::String,
+    ByteBuf => TlType::Bytes,
+
+    Int128 => TlType::Int128,
+    Int256 => TlType::Int256,
+}
+
+impl TlDescribed for &str {
+    fn tl_type() -> TlType {
+        TlType::String
+    }
+}
+
+impl<T: TlDescribed> TlDescribed for Vec<T> {
+    fn tl_type() -> TlType {
+        TlType::Vector(Box::new(T::tl_type()))
+    }
+}
+
+impl<T: TlDescribed> TlDescribed for Box<T> {
+    fn tl_type() -> TlType {
+        T::tl_type()
+    }
+
+    fn tl_constructors() -> Vec<Constructor> {
+        T::tl_constructors()
+    }
+}
+
+/// Only makes sense for `flags.N?T` fields where the condition is specified
+/// separately.
+impl<T: TlDescribed> TlDescribed for Option<T> {
+    fn tl_type() -> TlType {
+        T::tl_type()
+    }
+}
+
+impl<T> TlDescribed for Flags<T> {
+    fn tl_type() -> TlType {
+        TlType::Flags
+    }
+}
+
+impl<T: TlDescribed> TlDescribed for Boxed<T> {
+    fn tl_type() -> TlType {
+        match T::tl_type() {
+            TlType::Vector(elem_type) => TlType::BoxedVector(elem_type),
+            TlType::Bool => TlType::Bool,
+            _ => TlType::Boxed,
+        }
+    }
+
+    fn tl_constructors() -> Vec<Constructor> {
+        T::tl_constructors()
+    }
+}
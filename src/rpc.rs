@@ -0,0 +1,82 @@
+//! `RemoteCall` trait for TL functions which ties a request to the type of
+//! its result.
+//!
+//! A TL function like `ping#7abe77ec ping_id:long = Pong;` is serialized as
+//! a boxed value of its struct, and the `rpc_result` it is answered with
+//! contains a value of its result type. Use [`call_to_bytes`] and
+//! [`return_from_bytes`] to do both without spelling out the result type
+//! or enum variant hints.
+//!
+//! # Examples
+//!
+//! ```
+//! use serde_derive::{Serialize, Deserialize};
+//! use serde_mtproto::Boxed;
+//! use serde_mtproto::rpc::{call_to_bytes, return_from_bytes};
+//! use serde_mtproto_derive::{MtProtoIdentifiable, MtProtoRemoteCall};
+//!
+//! #[derive(Serialize, MtProtoIdentifiable, MtProtoRemoteCall)]
+//! #[mtproto_identifiable(id = "0x0f6c8f1a")]
+//! #[mtproto_remote_call(returns = "Boxed<Vec<Boxed<i32>>>")]
+//! struct GetNumbers {
+//!     count: i32,
+//! }
+//!
+//! # fn run() -> serde_mtproto::Result<()> {
+//! let request = call_to_bytes(&GetNumbers { count: 2 })?;
+//! assert_eq!(request, [0x1a, 0x8f, 0x6c, 0x0f, 0x02, 0x00, 0x00, 0x00]);
+//!
+//! let result_bytes = serde_mtproto::to_bytes(&Boxed::new(vec![Boxed::new(4), Boxed::new(2)]))?;
+//! let numbers = return_from_bytes::<GetNumbers>(&result_bytes)?;
+//! assert_eq!(numbers, Boxed::new(vec![Boxed::new(4), Boxed::new(2)]));
+//! #     Ok(())
+//! # }
+//! #
+//! # fn main() { run().unwrap(); }
+//! ```
+
+use serde::de::DeserializeOwned;
+use serde::ser::Serialize;
+
+use crate::error;
+use crate::identifiable::Identifiable;
+use crate::wrappers::Boxed;
+
+
+/// A TL function which is answered with a value of type `Return`.
+///
+/// Can be derived with `#[derive(MtProtoRemoteCall)]` and
+/// `#[mtproto_remote_call(returns = "...")]` attribute from
+/// `serde_mtproto_derive`. Type parameters used as `X::Return` in the
+/// attribute get a `RemoteCall` bound, which is handy for wrappers like
+/// `invokeWithLayer {X:Type} layer:int query:!X = X`.
+pub trait RemoteCall {
+    /// Type of the value the function returns, as it is found in the
+    /// `rpc_result`.
+    ///
+    /// Results are always boxed, so this is either an enum for a boxed type
+    /// or a `Boxed` wrapper, e.g. `Boxed<Pong>` for `Pong` or
+    /// `Boxed<Vec<Boxed<User>>>` for `Vector<User>`.
+    type Return: DeserializeOwned;
+}
+
+impl<F: RemoteCall> RemoteCall for Boxed<F> {
+    type Return = F::Return;
+}
+
+
+/// Serialize a function call as a byte vector of binary MTProto, i.e. as a
+/// boxed value.
+pub fn call_to_bytes<F>(call: &F) -> error::Result<Vec<u8>>
+    where F: RemoteCall + Identifiable + Serialize
+{
+    crate::ser::to_bytes(&Boxed::new(call))
+}
+
+/// Deserialize the result of a function call from bytes of binary MTProto,
+/// e.g. from `rpc_result`.
+pub fn return_from_bytes<F>(bytes: &[u8]) -> error::Result<F::Return>
+    where F: RemoteCall
+{
+    crate::de::from_bytes(bytes, &[])
+}
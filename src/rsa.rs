@@ -0,0 +1,460 @@
+//! RSA keys of servers and the `RSA_PAD` encryption scheme.
+//!
+//! Servers announce their RSA keys by 64-bit fingerprints: the lower-order
+//! bits of SHA-1 over the key serialized as
+//!
+//! ```text
+//! rsa_public_key n:bytes e:bytes = RSAPublicKey;
+//! ```
+//!
+//! A client encrypts `p_q_inner_data` with the matching key using `RSA_PAD`:
+//! the data is padded with random bytes to 192 bytes, reversed and appended
+//! with a SHA-256 hash, then encrypted with AES-256-IGE under a random key,
+//! which is in turn masked with SHA-256 of the ciphertext. The resulting 256
+//! bytes are encrypted with RSA.
+//!
+//! Decryption is provided for test servers.
+//!
+//! See <https://core.telegram.org/mtproto/auth_key> for details.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use std::fs;
+//!
+//! use serde_mtproto::rsa::{self, RsaPrivateKey};
+//!
+//! # fn run() -> serde_mtproto::Result<()> {
+//! let private_key = RsaPrivateKey::from_pem(&fs::read_to_string("server_key.pem")?)?;
+//! let public_key = private_key.public_key();
+//! println!("fingerprint {:#x}", public_key.fingerprint());
+//!
+//! let encrypted = rsa::encrypt_pad(public_key, b"p_q_inner_data")?;
+//! let decrypted = rsa::decrypt_pad(&private_key, &encrypted)?;
+//!
+//! // The data is returned with random padding
+//! assert_eq!(decrypted.len(), 192);
+//! assert_eq!(&decrypted[..14], b"p_q_inner_data");
+//! #     Ok(())
+//! # }
+//! #
+//! # fn main() { run().unwrap(); }
+//! ```
+
+
+use std::fmt;
+
+use byteorder::{ByteOrder, LittleEndian};
+use error_chain::bail;
+use num_bigint::BigUint;
+use rand::{CryptoRng, RngCore};
+use serde_bytes::ByteBuf;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+
+use crate::crypto;
+use crate::error::{self, RsaErrorKind};
+use crate::ser::to_bytes;
+
+
+/// Length of RSA keys in bits.
+pub const KEY_BITS: usize = 2048;
+/// Length of RSA keys and encrypted data in bytes.
+const KEY_LEN: usize = KEY_BITS / 8;
+/// Length of the data with random padding encrypted by `RSA_PAD`.
+pub const PAD_DATA_LEN: usize = 192;
+/// Maximal length of the data encrypted by `RSA_PAD`.
+pub const PAD_MAX_DATA_LEN: usize = 144;
+
+/// Length of the SHA-256 hash and the temporary AES key.
+const HASH_LEN: usize = 32;
+
+/// DER encoding of the `rsaEncryption` object identifier,
+/// 1.2.840.113549.1.1.1.
+const RSA_ENCRYPTION_OID: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x01];
+
+const DER_INTEGER: u8 = 0x02;
+const DER_BIT_STRING: u8 = 0x03;
+const DER_OCTET_STRING: u8 = 0x04;
+const DER_NULL: u8 = 0x05;
+const DER_OBJECT_IDENTIFIER: u8 = 0x06;
+const DER_SEQUENCE: u8 = 0x30;
+
+
+/// An RSA public key of a server.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RsaPublicKey {
+    n: BigUint,
+    e: BigUint,
+    fingerprint: i64,
+}
+
+impl RsaPublicKey {
+    /// Create a key from the big-endian bytes of its modulus and exponent.
+    ///
+    /// The modulus must be 2048 bits long.
+    pub fn new(n: &[u8], e: &[u8]) -> error::Result<RsaPublicKey> {
+        RsaPublicKey::from_biguints(BigUint::from_bytes_be(n), BigUint::from_bytes_be(e))
+    }
+
+    /// Parse a PEM-encoded key, either PKCS#1 (`RSA PUBLIC KEY`, the format
+    /// in which Telegram publishes its keys) or X.509 `SubjectPublicKeyInfo`
+    /// (`PUBLIC KEY`).
+    pub fn from_pem(pem: &str) -> error::Result<RsaPublicKey> {
+        let (label, der) = parse_pem(pem)?;
+
+        match label {
+            "RSA PUBLIC KEY" => RsaPublicKey::from_pkcs1_der(&der),
+            "PUBLIC KEY" => {
+                let mut key_info = DerReader::new(&der).read_sequence()?;
+                read_rsa_algorithm(&mut key_info.read_sequence()?)?;
+
+                let key = key_info.read(DER_BIT_STRING)?;
+                key_info.finish()?;
+
+                // The key occupies whole bytes, so no bits are unused
+                match key.split_first() {
+                    Some((&0, key)) => RsaPublicKey::from_pkcs1_der(key),
+                    _ => bail!(RsaErrorKind::InvalidDer),
+                }
+            },
+            _ => bail!(RsaErrorKind::UnexpectedPemLabel(label.to_owned())),
+        }
+    }
+
+    /// The fingerprint which identifies the key in `resPQ`.
+    pub fn fingerprint(&self) -> i64 {
+        self.fingerprint
+    }
+
+    fn from_biguints(n: BigUint, e: BigUint) -> error::Result<RsaPublicKey> {
+        if n.bits() != KEY_BITS {
+            bail!(RsaErrorKind::InvalidKeyLength(n.bits()));
+        }
+
+        // The fingerprint is the 64 lower-order bits of SHA-1 of the key
+        // serialized as a bare `rsa_public_key`
+        let serialized = to_bytes(&(ByteBuf::from(n.to_bytes_be()), ByteBuf::from(e.to_bytes_be())))?;
+        #[cfg_attr(feature = "cargo-clippy", allow(clippy::indexing_slicing))]
+        let fingerprint = LittleEndian::read_i64(&Sha1::digest(&serialized)[12..20]);
+
+        Ok(RsaPublicKey { n, e, fingerprint })
+    }
+
+    /// `RSAPublicKey ::= SEQUENCE { modulus INTEGER, publicExponent INTEGER }`
+    fn from_pkcs1_der(der: &[u8]) -> error::Result<RsaPublicKey> {
+        let mut key = DerReader::new(der).read_sequence()?;
+        let n = key.read_integer()?;
+        let e = key.read_integer()?;
+        key.finish()?;
+
+        RsaPublicKey::from_biguints(n, e)
+    }
+}
+
+
+/// An RSA private key, which a server uses to decrypt `RSA_PAD` data.
+#[derive(Clone, Eq, PartialEq)]
+pub struct RsaPrivateKey {
+    public_key: RsaPublicKey,
+    d: BigUint,
+}
+
+impl RsaPrivateKey {
+    /// Create a key from the big-endian bytes of its modulus, public
+    /// exponent and private exponent.
+    ///
+    /// The modulus must be 2048 bits long.
+    pub fn new(n: &[u8], e: &[u8], d: &[u8]) -> error::Result<RsaPrivateKey> {
+        Ok(RsaPrivateKey {
+            public_key: RsaPublicKey::new(n, e)?,
+            d: BigUint::from_bytes_be(d),
+        })
+    }
+
+    /// Parse a PEM-encoded key, either PKCS#1 (`RSA PRIVATE KEY`) or PKCS#8
+    /// (`PRIVATE KEY`).
+    pub fn from_pem(pem: &str) -> error::Result<RsaPrivateKey> {
+        let (label, der) = parse_pem(pem)?;
+
+        match label {
+            "RSA PRIVATE KEY" => RsaPrivateKey::from_pkcs1_der(&der),
+            "PRIVATE KEY" => {
+                let mut key_info = DerReader::new(&der).read_sequence()?;
+                let _version = key_info.read_integer()?;
+                read_rsa_algorithm(&mut key_info.read_sequence()?)?;
+
+                // Trailing optional attributes are ignored
+                let key = key_info.read(DER_OCTET_STRING)?;
+                RsaPrivateKey::from_pkcs1_der(key)
+            },
+            _ => bail!(RsaErrorKind::UnexpectedPemLabel(label.to_owned())),
+        }
+    }
+
+    /// The public part of the key.
+    pub fn public_key(&self) -> &RsaPublicKey {
+        &self.public_key
+    }
+
+    /// `RSAPrivateKey ::= SEQUENCE { version INTEGER, modulus INTEGER,
+    /// publicExponent INTEGER, privateExponent INTEGER, ... }`
+    ///
+    /// The remaining CRT values are not used.
+    fn from_pkcs1_der(der: &[u8]) -> error::Result<RsaPrivateKey> {
+        let mut key = DerReader::new(der).read_sequence()?;
+        let _version = key.read_integer()?;
+        let n = key.read_integer()?;
+        let e = key.read_integer()?;
+        let d = key.read_integer()?;
+
+        Ok(RsaPrivateKey {
+            public_key: RsaPublicKey::from_biguints(n, e)?,
+            d,
+        })
+    }
+}
+
+impl fmt::Debug for RsaPrivateKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Don't leak the private exponent into logs
+        f.debug_struct("RsaPrivateKey")
+            .field("public_key", &self.public_key)
+            .finish()
+    }
+}
+
+
+/// Encrypt `data` of at most 144 bytes with `RSA_PAD` and return 256 bytes.
+///
+/// Uses `rand::thread_rng()` for padding and the temporary key.
+pub fn encrypt_pad(key: &RsaPublicKey, data: &[u8]) -> error::Result<Vec<u8>> {
+    encrypt_pad_with_rng(key, data, &mut rand::thread_rng())
+}
+
+/// Encrypt `data` of at most 144 bytes with `RSA_PAD` and return 256 bytes,
+/// using `rng` for padding and the temporary key.
+#[cfg_attr(feature = "cargo-clippy", allow(clippy::indexing_slicing))]
+pub fn encrypt_pad_with_rng<R>(key: &RsaPublicKey, data: &[u8], rng: &mut R) -> error::Result<Vec<u8>>
+    where R: RngCore + CryptoRng
+{
+    if data.len() > PAD_MAX_DATA_LEN {
+        bail!(RsaErrorKind::DataTooLong(data.len()));
+    }
+
+    let mut data_with_padding = [0; PAD_DATA_LEN];
+    data_with_padding[..data.len()].copy_from_slice(data);
+    rng.fill_bytes(&mut data_with_padding[data.len()..]);
+
+    let mut data_pad_reversed = data_with_padding;
+    data_pad_reversed.reverse();
+
+    loop {
+        let mut temp_key = [0; HASH_LEN];
+        rng.fill_bytes(&mut temp_key);
+
+        let mut data_with_hash = [0; PAD_DATA_LEN + HASH_LEN];
+        data_with_hash[..PAD_DATA_LEN].copy_from_slice(&data_pad_reversed);
+        data_with_hash[PAD_DATA_LEN..].copy_from_slice(
+            &Sha256::new().chain(temp_key).chain(&data_with_padding[..]).result());
+        crypto::aes_ige_encrypt(&temp_key, &[0; 32], &mut data_with_hash);
+
+        let mut key_aes_encrypted = Vec::with_capacity(KEY_LEN);
+        key_aes_encrypted.extend(temp_key.iter().zip(Sha256::digest(&data_with_hash)).map(|(k, h)| k ^ h));
+        key_aes_encrypted.extend_from_slice(&data_with_hash);
+
+        // Retry with another key if the value doesn't fit the modulus
+        let value = BigUint::from_bytes_be(&key_aes_encrypted);
+        if value < key.n {
+            return Ok(to_be_bytes_padded(&value.modpow(&key.e, &key.n)));
+        }
+    }
+}
+
+/// Decrypt 256 bytes encrypted with `RSA_PAD` and return the data with its
+/// random padding, 192 bytes in total.
+pub fn decrypt_pad(key: &RsaPrivateKey, encrypted: &[u8]) -> error::Result<Vec<u8>> {
+    let public_key = &key.public_key;
+    let value = BigUint::from_bytes_be(encrypted);
+
+    if encrypted.len() != KEY_LEN || value >= public_key.n {
+        bail!(RsaErrorKind::InvalidEncryptedData);
+    }
+
+    let key_aes_encrypted = to_be_bytes_padded(&value.modpow(&key.d, &public_key.n));
+    let (temp_key_xor, aes_encrypted) = key_aes_encrypted.split_at(HASH_LEN);
+
+    let mut temp_key = [0; HASH_LEN];
+    for ((k, x), h) in temp_key.iter_mut().zip(temp_key_xor).zip(Sha256::digest(aes_encrypted)) {
+        *k = x ^ h;
+    }
+
+    let mut data_with_hash = aes_encrypted.to_vec();
+    crypto::aes_ige_decrypt(&temp_key, &[0; 32], &mut data_with_hash);
+
+    let (data_pad_reversed, hash) = data_with_hash.split_at(PAD_DATA_LEN);
+    let mut data_with_padding = data_pad_reversed.to_vec();
+    data_with_padding.reverse();
+
+    if *hash != *Sha256::new().chain(temp_key).chain(&data_with_padding).result() {
+        bail!(RsaErrorKind::InvalidEncryptedData);
+    }
+
+    Ok(data_with_padding)
+}
+
+/// Big-endian bytes of `value` padded with zeros to 256 bytes.
+pub(crate) fn to_be_bytes_padded(value: &BigUint) -> Vec<u8> {
+    let bytes = value.to_bytes_be();
+    let mut padded = vec![0; KEY_LEN.saturating_sub(bytes.len())];
+    padded.extend_from_slice(&bytes);
+
+    padded
+}
+
+
+/// Split a PEM document into its label and decoded contents.
+fn parse_pem(pem: &str) -> error::Result<(&str, Vec<u8>)> {
+    const BEGIN: &str = "-----BEGIN ";
+    const DASHES: &str = "-----";
+
+    let pem = pem.trim();
+    if !pem.starts_with(BEGIN) {
+        bail!(RsaErrorKind::InvalidPem);
+    }
+
+    let rest = &pem[BEGIN.len()..];
+    let (label, rest) = match rest.find(DASHES) {
+        Some(index) => (&rest[..index], &rest[index + DASHES.len()..]),
+        None => bail!(RsaErrorKind::InvalidPem),
+    };
+
+    let end = format!("-----END {}-----", label);
+    if !rest.ends_with(&end) {
+        bail!(RsaErrorKind::InvalidPem);
+    }
+
+    Ok((label, decode_base64(&rest[..rest.len() - end.len()])?))
+}
+
+/// Decode standard Base64 ignoring whitespace.
+fn decode_base64(input: &str) -> error::Result<Vec<u8>> {
+    let mut output = Vec::with_capacity(input.len() * 3 / 4);
+    let mut buffer = 0_u32;
+    let mut buffered_bits = 0;
+    let mut padding = 0;
+
+    for byte in input.bytes().filter(|b| !b.is_ascii_whitespace()) {
+        let value = match byte {
+            b'A'..=b'Z' => byte - b'A',
+            b'a'..=b'z' => byte - b'a' + 26,
+            b'0'..=b'9' => byte - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            b'=' => {
+                padding += 1;
+                continue;
+            },
+            _ => bail!(RsaErrorKind::InvalidPem),
+        };
+
+        // Padding may only appear at the end
+        if padding > 0 {
+            bail!(RsaErrorKind::InvalidPem);
+        }
+
+        buffer = (buffer << 6) | u32::from(value);
+        buffered_bits += 6;
+
+        if buffered_bits >= 8 {
+            buffered_bits -= 8;
+            output.push((buffer >> buffered_bits & 0xff) as u8);
+            buffer &= (1 << buffered_bits) - 1;
+        }
+    }
+
+    if padding > 2 || buffer != 0 {
+        bail!(RsaErrorKind::InvalidPem);
+    }
+
+    Ok(output)
+}
+
+
+/// Reads the DER subset used by RSA keys.
+struct DerReader<'a> {
+    input: &'a [u8],
+}
+
+impl<'a> DerReader<'a> {
+    fn new(input: &'a [u8]) -> DerReader<'a> {
+        DerReader { input }
+    }
+
+    /// Read an element with the given tag and return its contents.
+    fn read(&mut self, tag: u8) -> error::Result<&'a [u8]> {
+        let (&actual_tag, rest) = self.input.split_first().ok_or(RsaErrorKind::InvalidDer)?;
+        let (&first_len_byte, mut rest) = rest.split_first().ok_or(RsaErrorKind::InvalidDer)?;
+
+        if actual_tag != tag {
+            bail!(RsaErrorKind::InvalidDer);
+        }
+
+        let len = if first_len_byte < 0x80 {
+            usize::from(first_len_byte)
+        } else {
+            // The long form with up to 4 bytes, more is never needed for keys
+            let len_len = usize::from(first_len_byte & 0x7f);
+            if len_len == 0 || len_len > 4 || len_len > rest.len() {
+                bail!(RsaErrorKind::InvalidDer);
+            }
+
+            let (len_bytes, after_len) = rest.split_at(len_len);
+            rest = after_len;
+            len_bytes.iter().fold(0, |len, &byte| (len << 8) | usize::from(byte))
+        };
+
+        if len > rest.len() {
+            bail!(RsaErrorKind::InvalidDer);
+        }
+
+        let (contents, rest) = rest.split_at(len);
+        self.input = rest;
+
+        Ok(contents)
+    }
+
+    fn read_sequence(&mut self) -> error::Result<DerReader<'a>> {
+        self.read(DER_SEQUENCE).map(DerReader::new)
+    }
+
+    /// Read a non-negative integer.
+    fn read_integer(&mut self) -> error::Result<BigUint> {
+        let bytes = self.read(DER_INTEGER)?;
+
+        match bytes.first() {
+            Some(&first) if first < 0x80 => Ok(BigUint::from_bytes_be(bytes)),
+            _ => bail!(RsaErrorKind::InvalidDer),
+        }
+    }
+
+    /// Check that all elements have been read.
+    fn finish(&self) -> error::Result<()> {
+        if !self.input.is_empty() {
+            bail!(RsaErrorKind::InvalidDer);
+        }
+
+        Ok(())
+    }
+}
+
+/// `AlgorithmIdentifier ::= SEQUENCE { algorithm OBJECT IDENTIFIER,
+/// parameters ANY }` with `rsaEncryption` and `NULL` parameters.
+fn read_rsa_algorithm(algorithm: &mut DerReader<'_>) -> error::Result<()> {
+    if algorithm.read(DER_OBJECT_IDENTIFIER)? != RSA_ENCRYPTION_OID {
+        bail!(RsaErrorKind::UnsupportedAlgorithm);
+    }
+
+    let _parameters = algorithm.read(DER_NULL)?;
+    algorithm.finish()
+}
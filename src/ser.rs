@@ -1,6 +1,7 @@
 //! Serialize a Rust data structure into its MTProto binary representation.
 
 use std::io;
+use std::mem;
 
 use byteorder::{WriteBytesExt, LittleEndian};
 use error_chain::bail;
@@ -8,6 +9,7 @@ use log::debug;
 use serde::ser::{self, Serialize};
 
 use crate::error::{self, SerErrorKind, SerSerdeType};
+use crate::flags::{FlagsLayoutEntry, FLAGS_STRUCT_NAME};
 use crate::identifiable::Identifiable;
 use crate::utils::{i128_to_parts, safe_uint_cast, u128_to_parts};
 
@@ -16,12 +18,29 @@ use crate::utils::{i128_to_parts, safe_uint_cast, u128_to_parts};
 #[derive(Debug)]
 pub struct Serializer<W: io::Write> {
     writer: W,
+    conditional: Conditional,
+    captured_flags_layout: Option<Vec<&'static str>>,
+}
+
+/// State of a field which is bound to a flags bit.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Conditional {
+    /// The value being serialized is not bound to any flags bit.
+    Inactive,
+    /// The value being serialized is bound to a flags bit which is yet to be determined.
+    Expected,
+    /// The flags bit has been determined from the serialized value.
+    Determined(bool),
 }
 
 impl<W: io::Write> Serializer<W> {
     /// Create a MTProto serializer from an `io::Write`.
     pub fn new(writer: W) -> Serializer<W> {
-        Serializer { writer }
+        Serializer {
+            writer,
+            conditional: Conditional::Inactive,
+            captured_flags_layout: None,
+        }
     }
 
     /// Unwraps the `Serializer` and returns the underlying `io::Write`.
@@ -110,6 +129,13 @@ impl<'a, W> ser::Serializer for &'a mut Serializer<W>
 
 
     fn serialize_bool(self, value: bool) -> error::Result<()> {
+        if self.conditional == Conditional::Expected {
+            // `flags.N?true` fields are represented by their flags bit only
+            self.conditional = Conditional::Determined(value);
+            debug!("Serialized bool as a flags bit: {}", value);
+            return Ok(());
+        }
+
         self.writer.write_u32::<LittleEndian>(value.type_id())?;
         debug!("Serialized bool: {} => {:#x}", value, value.type_id());
         Ok(())
@@ -171,13 +197,27 @@ impl<'a, W> ser::Serializer for &'a mut Serializer<W>
     }
 
     fn serialize_none(self) -> error::Result<()> {
-        bail!(SerErrorKind::UnsupportedSerdeType(SerSerdeType::None));
+        if self.conditional != Conditional::Expected {
+            bail!(SerErrorKind::UnsupportedSerdeType(SerSerdeType::None));
+        }
+
+        self.conditional = Conditional::Determined(false);
+        debug!("Serialized none as an unset flags bit");
+        Ok(())
     }
 
-    fn serialize_some<T>(self, _value: &T) -> error::Result<()>
+    fn serialize_some<T>(self, value: &T) -> error::Result<()>
         where T: ?Sized + Serialize
     {
-        bail!(SerErrorKind::UnsupportedSerdeType(SerSerdeType::Some));
+        if self.conditional != Conditional::Expected {
+            bail!(SerErrorKind::UnsupportedSerdeType(SerSerdeType::Some));
+        }
+
+        debug!("Serializing some as a set flags bit");
+        self.conditional = Conditional::Inactive;
+        value.serialize(&mut *self)?;
+        self.conditional = Conditional::Determined(true);
+        Ok(())
     }
 
     fn serialize_unit(self) -> error::Result<()> {
@@ -260,6 +300,12 @@ impl<'a, W> ser::Serializer for &'a mut Serializer<W>
     }
 
     fn serialize_struct(self, name: &'static str, len: usize) -> error::Result<Self::SerializeStruct> {
+        if name == FLAGS_STRUCT_NAME {
+            debug!("Capturing flags layout of len {}", len);
+            self.captured_flags_layout = Some(Vec::with_capacity(len));
+            return Ok(SerializeFixedLengthSeq::capturing_flags_layout(self, safe_uint_cast(len)?));
+        }
+
         debug!("Serializing struct {} of len {}", name, len);
         Ok(SerializeFixedLengthSeq::new(self, safe_uint_cast(len)?))
     }
@@ -272,7 +318,7 @@ impl<'a, W> ser::Serializer for &'a mut Serializer<W>
                                -> error::Result<Self::SerializeStructVariant> {
         debug!("Serializing struct variant {}::{} (variant index {}) of len {}",
             name, variant, variant_index, len);
-        Ok(SerializeFixedLengthSeq::new(self, safe_uint_cast(len)?))
+        Ok(SerializeFixedLengthSeq::new(self, safe_uint_cast(len)?).with_variant(variant))
     }
 }
 
@@ -283,11 +329,48 @@ pub struct SerializeFixedLengthSeq<'a, W: io::Write> {
     ser: &'a mut Serializer<W>,
     len: u32,
     next_index: u32,
+    variant: Option<&'static str>,
+    capturing_flags_layout: bool,
+    flags: Option<FlagsState>,
+}
+
+/// Flags fields which have already been serialized along with conditional
+/// fields bound to them.
+///
+/// Values of all fields that follow the first flags field are buffered
+/// because bitmasks can only be written after all conditional fields are
+/// known.
+#[derive(Debug)]
+struct FlagsState {
+    entries: Vec<FlagsLayoutEntry>,
+    segments: Vec<FlagsSegment>,
+}
+
+#[derive(Debug)]
+struct FlagsSegment {
+    flags_field: &'static str,
+    mask: u32,
+    buffer: Vec<u8>,
 }
 
 impl<'a, W: io::Write> SerializeFixedLengthSeq<'a, W> {
     fn new(ser: &'a mut Serializer<W>, len: u32) -> SerializeFixedLengthSeq<'a, W> {
-        SerializeFixedLengthSeq { ser, len, next_index: 0 }
+        SerializeFixedLengthSeq {
+            ser,
+            len,
+            next_index: 0,
+            variant: None,
+            capturing_flags_layout: false,
+            flags: None,
+        }
+    }
+
+    fn capturing_flags_layout(ser: &'a mut Serializer<W>, len: u32) -> SerializeFixedLengthSeq<'a, W> {
+        SerializeFixedLengthSeq { capturing_flags_layout: true, ..SerializeFixedLengthSeq::new(ser, len) }
+    }
+
+    fn with_variant(self, variant: &'static str) -> SerializeFixedLengthSeq<'a, W> {
+        SerializeFixedLengthSeq { variant: Some(variant), ..self }
     }
 
     fn with_serialize_len(ser: &'a mut Serializer<W>, len: u32) -> error::Result<SerializeFixedLengthSeq<'a, W>> {
@@ -312,13 +395,69 @@ impl<'a, W: io::Write> SerializeFixedLengthSeq<'a, W> {
             bail!(SerErrorKind::ExcessElements(self.len));
         }
 
+        if self.capturing_flags_layout {
+            if let (Some(entry), Some(layout)) = (key, self.ser.captured_flags_layout.as_mut()) {
+                debug!("Captured flags layout entry {}", entry);
+                layout.push(entry);
+            }
+
+            return Ok(());
+        }
+
         if let Some(key) = key {
             debug!("Serializing field {}", key);
         } else {
             debug!("Serializing element");
         }
 
-        value.serialize(&mut *self.ser)
+        let captured_flags_layout = match self.flags {
+            None => serialize_field_value(&mut *self.ser, key, value, None)?.1,
+            Some(ref mut flags) => {
+                // Once a flags field is met, all subsequent values are buffered
+                let entry_index = key.and_then(|key| flags.entries.iter().position(|e| e.field == key));
+                let mut buffer_ser = Serializer::new(Vec::new());
+                let (bit_value, layout) = serialize_field_value(&mut buffer_ser, key, value, entry_index)?;
+
+                if let (Some(true), Some(index)) = (bit_value, entry_index) {
+                    flags.set_bit(index);
+                }
+
+                flags.segments.last_mut()
+                    .unwrap_or_else(|| unreachable!("flags state is only created along with a segment"))
+                    .buffer.extend(buffer_ser.writer);
+
+                layout
+            },
+        };
+
+        if let Some(layout) = captured_flags_layout {
+            let flags_field = key.ok_or(SerErrorKind::InvalidFlagsLayoutEntry(""))?;
+            self.start_flags_segment(flags_field, &layout)?;
+        }
+
+        Ok(())
+    }
+
+    fn start_flags_segment(&mut self, flags_field: &'static str, layout: &[&'static str]) -> error::Result<()> {
+        debug!("Starting flags segment for field {}", flags_field);
+
+        let flags = self.flags.get_or_insert_with(|| FlagsState {
+            entries: Vec::new(),
+            segments: Vec::new(),
+        });
+
+        for &entry in layout {
+            let parsed = FlagsLayoutEntry::parse(entry)
+                .ok_or(SerErrorKind::InvalidFlagsLayoutEntry(entry))?;
+
+            if parsed.is_bound_to(self.variant, flags_field) {
+                flags.entries.push(parsed);
+            }
+        }
+
+        flags.segments.push(FlagsSegment { flags_field, mask: 0, buffer: Vec::new() });
+
+        Ok(())
     }
 
     fn impl_serialize_end(self, data_type: &'static str) -> error::Result<()> {
@@ -329,12 +468,64 @@ impl<'a, W: io::Write> SerializeFixedLengthSeq<'a, W> {
         // `self.index > self.len` here is a programming error
         assert_eq!(self.next_index, self.len);
 
+        if let Some(flags) = self.flags {
+            for segment in flags.segments {
+                debug!("Writing flags {} => {:#x} with {} buffered bytes",
+                    segment.flags_field, segment.mask, segment.buffer.len());
+                self.ser.writer.write_u32::<LittleEndian>(segment.mask)?;
+                self.ser.writer.write_all(&segment.buffer)?;
+            }
+        }
+
         debug!("Finished serializing {}", data_type);
 
         Ok(())
     }
 }
 
+impl FlagsState {
+    fn set_bit(&mut self, entry_index: usize) {
+        if let Some(entry) = self.entries.get(entry_index) {
+            let segment = self.segments.iter_mut().find(|s| s.flags_field == entry.flags_field);
+
+            if let Some(segment) = segment {
+                segment.mask |= 1 << entry.bit;
+            }
+        }
+    }
+}
+
+/// Serialize a value of a struct field or a sequence element.
+///
+/// Returns the flags bit determined from the value if it is a conditional
+/// field and the flags layout if it is a flags field.
+fn serialize_field_value<X, T>(ser: &mut Serializer<X>,
+                               key: Option<&'static str>,
+                               value: &T,
+                               entry_index: Option<usize>)
+                              -> error::Result<(Option<bool>, Option<Vec<&'static str>>)>
+    where X: io::Write,
+          T: ?Sized + Serialize,
+{
+    let conditional = match entry_index {
+        Some(_) => Conditional::Expected,
+        None => Conditional::Inactive,
+    };
+
+    // Values nested in a conditional field of another type must not
+    // determine its flags bit, which is then reported as not optional.
+    let outer_conditional = mem::replace(&mut ser.conditional, conditional);
+    value.serialize(&mut *ser)?;
+
+    let bit_value = match mem::replace(&mut ser.conditional, outer_conditional) {
+        Conditional::Determined(bit_value) => Some(bit_value),
+        Conditional::Expected => bail!(SerErrorKind::FlaggedFieldNotOptional(key.unwrap_or(""))),
+        Conditional::Inactive => None,
+    };
+
+    Ok((bit_value, ser.captured_flags_layout.take()))
+}
+
 impl<'a, W> ser::SerializeSeq for SerializeFixedLengthSeq<'a, W>
     where W: 'a + io::Write
 {
@@ -474,14 +665,22 @@ impl<'a, W> ser::SerializeMap for SerializeFixedLengthMap<'a, W>
         }
 
         debug!("Serializing key");
-        key.serialize(&mut *self.ser)
+        let conditional = mem::replace(&mut self.ser.conditional, Conditional::Inactive);
+        key.serialize(&mut *self.ser)?;
+        self.ser.conditional = conditional;
+
+        Ok(())
     }
 
     fn serialize_value<T>(&mut self, value: &T) -> error::Result<()>
         where T: ?Sized + Serialize
     {
         debug!("Serializing value");
-        value.serialize(&mut *self.ser)
+        let conditional = mem::replace(&mut self.ser.conditional, Conditional::Inactive);
+        value.serialize(&mut *self.ser)?;
+        self.ser.conditional = conditional;
+
+        Ok(())
     }
 
     fn end(self) -> error::Result<()> {
@@ -0,0 +1,550 @@
+//! Service messages of the MTProto protocol.
+//!
+//! These types are part of the protocol itself rather than of an API layer,
+//! see <https://core.telegram.org/mtproto/service_messages> and
+//! <https://core.telegram.org/mtproto/service_messages_about_messages>.
+//!
+//! Constructors with an `Object` field (`message` and `rpc_result`) are
+//! generic over it. Such fields are usually [`Boxed`](../struct.Boxed.html)
+//! values, and to put messages of different types in one [`MsgContainer`]
+//! use an enum with a `#[derive(MtProtoIdentifiable)]` variant for each of
+//! them.
+//!
+//! The type of an `rpc_result` value depends on the query it answers, so
+//! use [`PendingRequests`] to decode it with a decoder registered for each
+//! sent query. Functions which are answered here, `ping` and
+//! `get_future_salts`, implement [`RemoteCall`](../rpc/trait.RemoteCall.html)
+//! to register them with [`PendingRequests::insert_call`].
+//!
+//! # Examples
+//!
+//! ```
+//! use serde_mtproto::{Boxed, from_bytes, to_bytes};
+//! use serde_mtproto::service::{Message, MsgContainer, MsgsAck, Pong};
+//!
+//! # fn run() -> serde_mtproto::Result<()> {
+//! let container = MsgContainer {
+//!     messages: vec![
+//!         Message {
+//!             msg_id: 0x5e0b_8000_0000_0001,
+//!             seqno: 1,
+//!             body: Boxed::new(Pong { msg_id: 0x5e0b_7fff_0000_0004, ping_id: 42 }),
+//!         },
+//!     ],
+//! };
+//!
+//! let bytes = to_bytes(&Boxed::new(&container))?;
+//! let deserialized: Boxed<MsgContainer<Boxed<Pong>>> = from_bytes(&bytes, &[])?;
+//!
+//! assert_eq!(deserialized.into_inner(), container);
+//!
+//! let ack = MsgsAck { msg_ids: Boxed::new(vec![0x5e0b_8000_0000_0001]) };
+//! assert_eq!(to_bytes(&Boxed::new(ack))?.len(), 4 + 4 + 4 + 8);
+//! #     Ok(())
+//! # }
+//! #
+//! # fn main() { run().unwrap(); }
+//! ```
+
+#![cfg_attr(feature = "cargo-clippy", allow(clippy::unreadable_literal))]  // To match the look & feel from TL schema
+
+#[cfg(feature = "gzip")]
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+use std::marker::PhantomData;
+
+use byteorder::{ByteOrder, LittleEndian};
+use error_chain::bail;
+use serde::de::{Deserialize, DeserializeOwned, DeserializeSeed, Deserializer};
+use serde::ser::{Error as SerError, Serialize, Serializer, SerializeStruct};
+use serde_derive::{Serialize, Deserialize};
+
+use crate::de::DeserializerOptions;
+use crate::error::{self, DeErrorKind};
+use crate::identifiable::Identifiable;
+#[cfg(feature = "gzip")]
+use crate::identifiable::GZIP_PACKED_ID;
+use crate::rpc::RemoteCall;
+use crate::sized::MtProtoSized;
+use crate::wrappers::{Boxed, WithSize};
+#[cfg(feature = "gzip")]
+use crate::wrappers::DEFAULT_MAX_UNPACKED_LEN;
+
+
+/// Type id of the `msg_container` constructor.
+pub const MSG_CONTAINER_ID: u32 = 0x73f1f8dc;
+/// Type id of the `message` constructor.
+pub const MESSAGE_ID: u32 = 0x5bb8e511;
+/// Type id of the `rpc_result` constructor.
+pub const RPC_RESULT_ID: u32 = 0xf35c6d01;
+/// Type id of the `rpc_error` constructor.
+pub const RPC_ERROR_ID: u32 = 0x2144ca19;
+/// Type id of the `msgs_ack` constructor.
+pub const MSGS_ACK_ID: u32 = 0x62d6b459;
+/// Type id of the `bad_msg_notification` constructor.
+pub const BAD_MSG_NOTIFICATION_ID: u32 = 0xa7eff811;
+/// Type id of the `bad_server_salt` constructor.
+pub const BAD_SERVER_SALT_ID: u32 = 0xedab447b;
+/// Type id of the `new_session_created` constructor.
+pub const NEW_SESSION_CREATED_ID: u32 = 0x9ec20908;
+/// Type id of the `pong` constructor.
+pub const PONG_ID: u32 = 0x347773c5;
+/// Type id of the `future_salts` constructor.
+pub const FUTURE_SALTS_ID: u32 = 0xae500895;
+/// Type id of the `future_salt` constructor.
+pub const FUTURE_SALT_ID: u32 = 0x0949d9dc;
+/// Type id of the `ping` function.
+pub const PING_ID: u32 = 0x7abe77ec;
+/// Type id of the `get_future_salts` function.
+pub const GET_FUTURE_SALTS_ID: u32 = 0xb921bd04;
+
+
+/// `msg_container#73f1f8dc messages:vector<%Message> = MessageContainer;`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(bound(
+    serialize = "T: Serialize + MtProtoSized",
+    deserialize = "T: Deserialize<'de> + MtProtoSized",
+))]
+pub struct MsgContainer<T> {
+    /// Messages packed in the container, a bare vector of bare messages.
+    pub messages: Vec<Message<T>>,
+}
+
+/// `message msg_id:long seqno:int bytes:int body:Object = Message;`
+///
+/// The `bytes` field is computed from the body when serializing and checked
+/// against it when deserializing.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Message<T> {
+    /// Message identifier.
+    pub msg_id: i64,
+    /// Message sequence number.
+    pub seqno: i32,
+    /// Message body, usually a boxed value.
+    pub body: T,
+}
+
+/// `rpc_result#f35c6d01 req_msg_id:long result:Object = RpcResult;`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RpcResult<T> {
+    /// Identifier of the message with the query.
+    pub req_msg_id: i64,
+    /// Query result, usually a boxed value.
+    pub result: T,
+}
+
+/// `rpc_error#2144ca19 error_code:int error_message:string = RpcError;`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RpcError {
+    /// Error code, similar to HTTP status codes.
+    pub error_code: i32,
+    /// Error type, e.g. `FLOOD_WAIT_30`.
+    pub error_message: String,
+}
+
+/// `msgs_ack#62d6b459 msg_ids:Vector<long> = MsgsAck;`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MsgsAck {
+    /// Identifiers of acknowledged messages.
+    pub msg_ids: Boxed<Vec<i64>>,
+}
+
+/// `bad_msg_notification#a7eff811 bad_msg_id:long bad_msg_seqno:int
+/// error_code:int = BadMsgNotification;`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BadMsgNotification {
+    /// Identifier of the rejected message.
+    pub bad_msg_id: i64,
+    /// Sequence number of the rejected message.
+    pub bad_msg_seqno: i32,
+    /// Reason why the message was rejected.
+    pub error_code: i32,
+}
+
+/// `bad_server_salt#edab447b bad_msg_id:long bad_msg_seqno:int
+/// error_code:int new_server_salt:long = BadMsgNotification;`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BadServerSalt {
+    /// Identifier of the rejected message.
+    pub bad_msg_id: i64,
+    /// Sequence number of the rejected message.
+    pub bad_msg_seqno: i32,
+    /// Always 48 (incorrect server salt).
+    pub error_code: i32,
+    /// Server salt to resend the message with.
+    pub new_server_salt: i64,
+}
+
+/// `new_session_created#9ec20908 first_msg_id:long unique_id:long
+/// server_salt:long = NewSession;`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct NewSessionCreated {
+    /// Identifier of the first message of the session.
+    pub first_msg_id: i64,
+    /// Unique identifier of the session generated by the server.
+    pub unique_id: i64,
+    /// Current server salt.
+    pub server_salt: i64,
+}
+
+/// `pong#347773c5 msg_id:long ping_id:long = Pong;`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Pong {
+    /// Identifier of the message with the ping.
+    pub msg_id: i64,
+    /// `ping_id` from the ping.
+    pub ping_id: i64,
+}
+
+/// `future_salts#ae500895 req_msg_id:long now:int salts:vector<future_salt>
+/// = FutureSalts;`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FutureSalts {
+    /// Identifier of the message with the query.
+    pub req_msg_id: i64,
+    /// Current server time.
+    pub now: i32,
+    /// Server salts, a bare vector of bare salts.
+    pub salts: Vec<FutureSalt>,
+}
+
+/// `future_salt#0949d9dc valid_since:int valid_until:int salt:long =
+/// FutureSalt;`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FutureSalt {
+    /// Unix time since which the salt is valid.
+    pub valid_since: i32,
+    /// Unix time until which the salt is valid.
+    pub valid_until: i32,
+    /// Server salt.
+    pub salt: i64,
+}
+
+/// `ping#7abe77ec ping_id:long = Pong;`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Ping {
+    /// Arbitrary identifier which is sent back in the pong.
+    pub ping_id: i64,
+}
+
+/// `get_future_salts#b921bd04 num:int = FutureSalts;`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GetFutureSalts {
+    /// Number of salts to request.
+    pub num: i32,
+}
+
+
+impl<T> Serialize for Message<T>
+    where T: Serialize + MtProtoSized
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer,
+    {
+        let body = WithSize::new(&self.body).map_err(S::Error::custom)?;
+
+        let mut ser = serializer.serialize_struct("Message", 3)?;
+        ser.serialize_field("msg_id", &self.msg_id)?;
+        ser.serialize_field("seqno", &self.seqno)?;
+        ser.serialize_field("body", &body)?;
+        ser.end()
+    }
+}
+
+impl<'de, T> Deserialize<'de> for Message<T>
+    where T: Deserialize<'de> + MtProtoSized
+{
+    fn deserialize<D>(deserializer: D) -> Result<Message<T>, D::Error>
+        where D: Deserializer<'de>
+    {
+        // `WithSize` reads and checks the `bytes` field
+        #[derive(Deserialize)]
+        #[serde(rename = "Message", bound = "T: Deserialize<'de> + MtProtoSized")]
+        struct MessageHelper<T> {
+            msg_id: i64,
+            seqno: i32,
+            body: WithSize<T>,
+        }
+
+        let helper = MessageHelper::<T>::deserialize(deserializer)?;
+
+        Ok(Message {
+            msg_id: helper.msg_id,
+            seqno: helper.seqno,
+            body: helper.body.into_inner(),
+        })
+    }
+}
+
+impl<T: MtProtoSized> MtProtoSized for Message<T> {
+    fn size_hint(&self) -> error::Result<usize> {
+        let mut result = 0;
+
+        result += self.msg_id.size_hint()?;
+        result += self.seqno.size_hint()?;
+        result += WithSize::new(&self.body)?.size_hint()?;
+
+        Ok(result)
+    }
+}
+
+
+impl_identifiable_and_sized! {
+    MsgContainer<T>    => (MSG_CONTAINER_ID, [messages]),
+    RpcResult<T>       => (RPC_RESULT_ID, [req_msg_id, result]),
+    RpcError           => (RPC_ERROR_ID, [error_code, error_message]),
+    MsgsAck            => (MSGS_ACK_ID, [msg_ids]),
+    BadMsgNotification => (BAD_MSG_NOTIFICATION_ID, [bad_msg_id, bad_msg_seqno, error_code]),
+    BadServerSalt      => (BAD_SERVER_SALT_ID, [bad_msg_id, bad_msg_seqno, error_code, new_server_salt]),
+    NewSessionCreated  => (NEW_SESSION_CREATED_ID, [first_msg_id, unique_id, server_salt]),
+    Pong               => (PONG_ID, [msg_id, ping_id]),
+    FutureSalts        => (FUTURE_SALTS_ID, [req_msg_id, now, salts]),
+    FutureSalt         => (FUTURE_SALT_ID, [valid_since, valid_until, salt]),
+    Ping               => (PING_ID, [ping_id]),
+    GetFutureSalts     => (GET_FUTURE_SALTS_ID, [num]),
+}
+
+impl RemoteCall for Ping {
+    type Return = Boxed<Pong>;
+}
+
+impl RemoteCall for GetFutureSalts {
+    type Return = Boxed<FutureSalts>;
+}
+
+impl<T> Identifiable for Message<T> {
+    fn all_type_ids() -> &'static [u32] {
+        &[MESSAGE_ID]
+    }
+
+    fn all_enum_variant_names() -> Option<&'static [&'static str]> {
+        None
+    }
+
+    fn type_id(&self) -> u32 {
+        MESSAGE_ID
+    }
+
+    fn enum_variant_id(&self) -> Option<&'static str> {
+        None
+    }
+}
+
+
+// ========== PENDING REQUESTS ========== //
+
+/// Result of an `rpc_result` decoded by [`PendingRequests`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum RpcResponse<R> {
+    /// The result decoded by the decoder registered for the query.
+    Decoded(R),
+    /// The query failed with an `rpc_error`.
+    Error(RpcError),
+    /// The raw result bytes since no decoder is registered for the query.
+    Raw(Vec<u8>),
+}
+
+/// A table of sent queries which decodes `rpc_result` values into
+/// responses of type `R`.
+///
+/// Each query is registered by its message id along with a decoder, either
+/// a type (see [`insert`](#method.insert)) or a `DeserializeSeed` (see
+/// [`insert_seed`](#method.insert_seed)), which is used once when the
+/// result arrives. Results are decoded without enum variant hints, so
+/// enums must be boxed, and with resource limits given to
+/// [`with_options`](#method.with_options). With the `gzip` feature enabled,
+/// results sent inside `gzip_packed` are unpacked before decoding, up to
+/// `DeserializerOptions::max_total_bytes` and `DEFAULT_MAX_UNPACKED_LEN`
+/// bytes.
+///
+/// # Examples
+///
+/// ```
+/// use serde_mtproto::{Boxed, to_bytes};
+/// use serde_mtproto::service::{PendingRequests, Pong, RpcResponse, RpcResult};
+///
+/// # fn run() -> serde_mtproto::Result<()> {
+/// let mut pending = PendingRequests::<Boxed<Pong>>::new();
+/// pending.insert::<Boxed<Pong>>(0x5e0b_8000_0000_0004);
+///
+/// let pong = Boxed::new(Pong { msg_id: 0x5e0b_8000_0000_0004, ping_id: 42 });
+/// let bytes = to_bytes(&Boxed::new(RpcResult { req_msg_id: 0x5e0b_8000_0000_0004, result: &pong }))?;
+///
+/// let rpc_result = pending.decode(&bytes)?;
+/// assert_eq!(rpc_result.result, RpcResponse::Decoded(pong));
+/// assert!(pending.is_empty());
+/// #     Ok(())
+/// # }
+/// #
+/// # fn main() { run().unwrap(); }
+/// ```
+pub struct PendingRequests<R> {
+    decoders: HashMap<i64, Box<dyn Decoder<R>>>,
+    options: DeserializerOptions,
+}
+
+impl<R> PendingRequests<R> {
+    /// Create an empty table.
+    pub fn new() -> PendingRequests<R> {
+        PendingRequests::with_options(DeserializerOptions::new())
+    }
+
+    /// Create an empty table which enforces resource limits from `options`
+    /// when decoding results.
+    pub fn with_options(options: DeserializerOptions) -> PendingRequests<R> {
+        PendingRequests { decoders: HashMap::new(), options }
+    }
+
+    /// Register a query whose result is decoded as `T` and converted into
+    /// `R`.
+    ///
+    /// Returns `true` if a query with this id was already pending.
+    pub fn insert<T>(&mut self, req_msg_id: i64) -> bool
+        where T: DeserializeOwned + 'static,
+              R: From<T>,
+    {
+        self.insert_decoder(req_msg_id, TypedDecoder(PhantomData::<T>))
+    }
+
+    /// Register a function call whose result is decoded as its
+    /// [`RemoteCall::Return`](../rpc/trait.RemoteCall.html#associatedtype.Return)
+    /// type and converted into `R`.
+    ///
+    /// Returns `true` if a query with this id was already pending.
+    pub fn insert_call<F>(&mut self, req_msg_id: i64) -> bool
+        where F: RemoteCall,
+              F::Return: 'static,
+              R: From<F::Return>,
+    {
+        self.insert::<F::Return>(req_msg_id)
+    }
+
+    /// Register a query whose result is decoded using `seed`.
+    ///
+    /// Returns `true` if a query with this id was already pending.
+    pub fn insert_seed<S>(&mut self, req_msg_id: i64, seed: S) -> bool
+        where S: for<'de> DeserializeSeed<'de, Value = R> + 'static,
+    {
+        self.insert_decoder(req_msg_id, SeedDecoder(seed))
+    }
+
+    fn insert_decoder<D: Decoder<R> + 'static>(&mut self, req_msg_id: i64, decoder: D) -> bool {
+        self.decoders.insert(req_msg_id, Box::new(decoder)).is_some()
+    }
+
+    /// Stop waiting for the result of a query, e.g. after it is cancelled.
+    ///
+    /// Returns `true` if the query was pending.
+    pub fn remove(&mut self, req_msg_id: i64) -> bool {
+        self.decoders.remove(&req_msg_id).is_some()
+    }
+
+    /// Check whether a query is pending.
+    pub fn contains(&self, req_msg_id: i64) -> bool {
+        self.decoders.contains_key(&req_msg_id)
+    }
+
+    /// Return the number of pending queries.
+    pub fn len(&self) -> usize {
+        self.decoders.len()
+    }
+
+    /// Check whether no queries are pending.
+    pub fn is_empty(&self) -> bool {
+        self.decoders.is_empty()
+    }
+
+    /// Decode a boxed `rpc_result` value.
+    ///
+    /// The query it answers is no longer pending afterwards, unless the
+    /// `rpc_result` itself is malformed. Decoders are used up by decoding,
+    /// so this also holds if the result fails to decode: the error is
+    /// returned and the query should be considered failed.
+    ///
+    /// An `rpc_error` result is decoded regardless of the registered
+    /// decoder, and the result of a query which is not pending is returned
+    /// as raw (unpacked if `gzip_packed`) bytes.
+    pub fn decode(&mut self, bytes: &[u8]) -> error::Result<RpcResult<RpcResponse<R>>> {
+        let ((type_id, req_msg_id), raw_result): ((u32, i64), _) = crate::de::from_bytes_reuse(bytes, &[])?;
+        if type_id != RPC_RESULT_ID {
+            bail!(DeErrorKind::InvalidTypeId(type_id, RpcResult::<()>::all_type_ids()));
+        }
+
+        #[cfg(feature = "gzip")]
+        let unpacked_result = unpack_result(raw_result, self.options)?;
+        #[cfg(feature = "gzip")]
+        let result_bytes = &*unpacked_result;
+        #[cfg(not(feature = "gzip"))]
+        let result_bytes = raw_result;
+
+        let result = match self.decoders.remove(&req_msg_id) {
+            Some(_) if result_bytes.get(..4).map(LittleEndian::read_u32) == Some(RPC_ERROR_ID) => {
+                let rpc_error: Boxed<RpcError> =
+                    crate::de::from_bytes_seed_with_options(PhantomData, result_bytes, &[], self.options)?;
+                RpcResponse::Error(rpc_error.into_inner())
+            },
+            Some(decoder) => RpcResponse::Decoded(decoder.decode(result_bytes, self.options)?),
+            None => RpcResponse::Raw(result_bytes.to_vec()),
+        };
+
+        Ok(RpcResult { req_msg_id, result })
+    }
+}
+
+/// Unpack a result sent inside `gzip_packed`, or return it as is.
+#[cfg(feature = "gzip")]
+fn unpack_result(result_bytes: &[u8], options: DeserializerOptions) -> error::Result<Cow<'_, [u8]>> {
+    if result_bytes.get(..4).map(LittleEndian::read_u32) != Some(GZIP_PACKED_ID) {
+        return Ok(Cow::Borrowed(result_bytes));
+    }
+
+    let mut de = crate::de::Deserializer::with_options(crate::de::SliceRead::new(result_bytes), &[], options);
+    let _type_id = u32::deserialize(&mut de)?;
+
+    de.read_gzip_packed(DEFAULT_MAX_UNPACKED_LEN).map(Cow::Owned)
+}
+
+impl<R> Default for PendingRequests<R> {
+    fn default() -> PendingRequests<R> {
+        PendingRequests::new()
+    }
+}
+
+impl<R> fmt::Debug for PendingRequests<R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PendingRequests")
+            .field("req_msg_ids", &self.decoders.keys().collect::<Vec<_>>())
+            .field("options", &self.options)
+            .finish()
+    }
+}
+
+/// A one-shot decoder of a query result.
+///
+/// Takes `Box<Self>` because calling a boxed `FnOnce` requires a newer
+/// compiler.
+trait Decoder<R> {
+    fn decode(self: Box<Self>, bytes: &[u8], options: DeserializerOptions) -> error::Result<R>;
+}
+
+struct TypedDecoder<T>(PhantomData<T>);
+
+impl<T, R> Decoder<R> for TypedDecoder<T>
+    where T: DeserializeOwned,
+          R: From<T>,
+{
+    fn decode(self: Box<Self>, bytes: &[u8], options: DeserializerOptions) -> error::Result<R> {
+        crate::de::from_bytes_seed_with_options(PhantomData::<T>, bytes, &[], options).map(R::from)
+    }
+}
+
+struct SeedDecoder<S>(S);
+
+impl<S, R> Decoder<R> for SeedDecoder<S>
+    where S: for<'de> DeserializeSeed<'de, Value = R>,
+{
+    fn decode(self: Box<Self>, bytes: &[u8], options: DeserializerOptions) -> error::Result<R> {
+        crate::de::from_bytes_seed_with_options(self.0, bytes, &[], options)
+    }
+}
@@ -0,0 +1,240 @@
+//! Sequence numbers and acknowledgments within a session.
+//!
+//! Messages are either content-related, which require an acknowledgment,
+//! or not: only containers and acknowledgments themselves belong to the
+//! latter among the built-in service messages. The sequence number of a
+//! message is twice the number of content-related messages sent before it,
+//! plus one if the message is content-related itself.
+//!
+//! [`Session`] assigns message ids and sequence numbers to outgoing
+//! messages, remembers which of them are awaiting acknowledgments and
+//! collects ids of received content-related messages to acknowledge them
+//! with `msgs_ack`.
+//!
+//! See <https://core.telegram.org/mtproto/description#message-sequence-number-msg-seqno>
+//! and <https://core.telegram.org/mtproto/service_messages_about_messages>
+//! for details.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::time::Duration;
+//!
+//! use serde_mtproto::Boxed;
+//! use serde_mtproto::service::{MsgsAck, Pong};
+//! use serde_mtproto::session::Session;
+//!
+//! let clock = || Duration::from_secs(1_500_000_000);
+//! let mut session = Session::with_clock(0x1234, clock);
+//!
+//! let pong = session.pack(Boxed::new(Pong { msg_id: 0x5e0b_8000_0000_0004, ping_id: 42 }));
+//! assert_eq!(pong.seqno, 1);
+//! assert!(session.is_awaiting_ack(pong.msg_id));
+//!
+//! // A received content-related message has an odd sequence number
+//! session.receive(0x5e0b_8000_0000_0001, 1);
+//! let ack = session.take_ack().unwrap();
+//! assert_eq!(*ack.msg_ids.inner(), vec![0x5e0b_8000_0000_0001]);
+//!
+//! let ack = session.pack(Boxed::new(ack));
+//! assert_eq!(ack.seqno, 2);
+//!
+//! session.handle_ack(&MsgsAck { msg_ids: Boxed::new(vec![pong.msg_id]) });
+//! assert!(!session.is_awaiting_ack(pong.msg_id));
+//! ```
+
+use std::collections::BTreeSet;
+
+use crate::identifiable::Identifiable;
+use crate::message_id::{Clock, MessageIdGenerator, MessageIdKind, SystemClock};
+use crate::service::{Message, MsgsAck, MSG_CONTAINER_ID, MSGS_ACK_ID};
+use crate::wrappers::Boxed;
+
+#[cfg(feature = "crypto")]
+use crate::crypto::PlainMessage;
+
+
+/// Type ids of the built-in service constructors which are not
+/// content-related.
+pub const NON_CONTENT_RELATED_IDS: &[u32] = &[MSG_CONTAINER_ID, MSGS_ACK_ID];
+
+
+/// Check whether a message with a body of the given type id requires an
+/// acknowledgment.
+///
+/// All constructors are content-related except the built-in ones listed in
+/// [`NON_CONTENT_RELATED_IDS`].
+///
+/// [`NON_CONTENT_RELATED_IDS`]: constant.NON_CONTENT_RELATED_IDS.html
+pub fn is_content_related(type_id: u32) -> bool {
+    !NON_CONTENT_RELATED_IDS.contains(&type_id)
+}
+
+
+/// The client side of a session.
+#[derive(Clone, Debug)]
+pub struct Session<C = SystemClock> {
+    session_id: i64,
+    message_ids: MessageIdGenerator<C>,
+    content_related_count: i32,
+    awaiting_ack: BTreeSet<i64>,
+    pending_acks: BTreeSet<i64>,
+}
+
+impl Session<SystemClock> {
+    /// Create a session with the given id using the system clock for
+    /// message ids.
+    pub fn new(session_id: i64) -> Session<SystemClock> {
+        Session::with_clock(session_id, SystemClock)
+    }
+}
+
+impl<C: Clock> Session<C> {
+    /// Create a session with the given id using `clock` for message ids.
+    pub fn with_clock(session_id: i64, clock: C) -> Session<C> {
+        Session::with_message_ids(session_id, MessageIdGenerator::with_clock(clock))
+    }
+
+    /// Create a session with the given id using `message_ids` for message
+    /// ids, e.g. one with the time offset already corrected.
+    pub fn with_message_ids(session_id: i64, message_ids: MessageIdGenerator<C>) -> Session<C> {
+        Session {
+            session_id,
+            message_ids,
+            content_related_count: 0,
+            awaiting_ack: BTreeSet::new(),
+            pending_acks: BTreeSet::new(),
+        }
+    }
+
+    /// Return the session id.
+    pub fn session_id(&self) -> i64 {
+        self.session_id
+    }
+
+    /// Return the message id generator.
+    pub fn message_ids(&self) -> &MessageIdGenerator<C> {
+        &self.message_ids
+    }
+
+    /// Return the message id generator mutably, e.g. to handle a
+    /// `bad_msg_notification`.
+    pub fn message_ids_mut(&mut self) -> &mut MessageIdGenerator<C> {
+        &mut self.message_ids
+    }
+
+    /// Start a new session with the given id.
+    ///
+    /// Sequence numbers start over and no messages are awaiting
+    /// acknowledgments anymore.
+    pub fn reset(&mut self, session_id: i64) {
+        self.session_id = session_id;
+        self.content_related_count = 0;
+        self.awaiting_ack.clear();
+        self.pending_acks.clear();
+    }
+
+    /// Return the sequence number for the next outgoing message.
+    pub fn next_seqno(&mut self, content_related: bool) -> i32 {
+        let seqno = self.content_related_count.wrapping_mul(2);
+
+        if content_related {
+            self.content_related_count = self.content_related_count.wrapping_add(1);
+            seqno.wrapping_add(1)
+        } else {
+            seqno
+        }
+    }
+
+    /// Wrap `body` into a message with a new message id and the sequence
+    /// number for its type id.
+    ///
+    /// If the message is content-related, it's awaiting an acknowledgment
+    /// afterwards.
+    pub fn pack<T: Identifiable>(&mut self, body: T) -> Message<T> {
+        let content_related = is_content_related(body.type_id());
+        self.pack_as(body, content_related)
+    }
+
+    /// Like [`pack`](#method.pack), but with content-relatedness given
+    /// explicitly for constructors which are not known to this crate.
+    pub fn pack_as<T>(&mut self, body: T, content_related: bool) -> Message<T> {
+        let msg_id = self.message_ids.next_id(MessageIdKind::Client);
+        let seqno = self.next_seqno(content_related);
+
+        if content_related {
+            let _ = self.awaiting_ack.insert(msg_id);
+        }
+
+        Message { msg_id, seqno, body }
+    }
+
+    /// Like [`pack`](#method.pack), but for encryption with the given
+    /// server salt.
+    #[cfg(feature = "crypto")]
+    pub fn pack_plain<T: Identifiable>(&mut self, salt: i64, body: T) -> PlainMessage<T> {
+        let message = self.pack(body);
+
+        PlainMessage {
+            salt,
+            session_id: self.session_id,
+            message_id: message.msg_id,
+            seq_no: message.seqno,
+            body: message.body,
+        }
+    }
+
+    /// Check whether an outgoing message is awaiting an acknowledgment.
+    pub fn is_awaiting_ack(&self, msg_id: i64) -> bool {
+        self.awaiting_ack.contains(&msg_id)
+    }
+
+    /// Return ids of outgoing messages awaiting acknowledgments in
+    /// ascending order, e.g. to resend them.
+    pub fn awaiting_ack(&self) -> Vec<i64> {
+        self.awaiting_ack.iter().cloned().collect()
+    }
+
+    /// Mark an outgoing message as acknowledged, either explicitly or by a
+    /// response to it.
+    ///
+    /// Returns `true` if the message was awaiting an acknowledgment.
+    pub fn acknowledge(&mut self, msg_id: i64) -> bool {
+        self.awaiting_ack.remove(&msg_id)
+    }
+
+    /// Mark all outgoing messages in `msgs_ack` as acknowledged.
+    pub fn handle_ack(&mut self, ack: &MsgsAck) {
+        for &msg_id in ack.msg_ids.inner() {
+            let _ = self.acknowledge(msg_id);
+        }
+    }
+
+    /// Register a received message by its id and sequence number.
+    ///
+    /// Content-related messages, which have odd sequence numbers, are
+    /// acknowledged by the next [`take_ack`](#method.take_ack).
+    pub fn receive(&mut self, msg_id: i64, seqno: i32) {
+        if seqno & 1 == 1 {
+            let _ = self.pending_acks.insert(msg_id);
+        }
+    }
+
+    /// Return the number of received messages not acknowledged yet.
+    pub fn pending_ack_count(&self) -> usize {
+        self.pending_acks.len()
+    }
+
+    /// Return `msgs_ack` for all received content-related messages since
+    /// the last call, if there are any.
+    pub fn take_ack(&mut self) -> Option<MsgsAck> {
+        if self.pending_acks.is_empty() {
+            return None;
+        }
+
+        let msg_ids = self.pending_acks.iter().cloned().collect();
+        self.pending_acks.clear();
+
+        Some(MsgsAck { msg_ids: Boxed::new(msg_ids) })
+    }
+}
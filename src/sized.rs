@@ -249,6 +249,17 @@ impl<K, V> MtProtoSized for BTreeMap<K, V>
     }
 }
 
+/// Only used for conditional fields bound to a flags bit where `None` is
+/// not serialized at all.
+impl<T: MtProtoSized> MtProtoSized for Option<T> {
+    fn size_hint(&self) -> error::Result<usize> {
+        match *self {
+            Some(ref value) => value.size_hint(),
+            None => Ok(0),
+        }
+    }
+}
+
 impl MtProtoSized for () {
     fn size_hint(&self) -> error::Result<usize> {
         Ok(0)
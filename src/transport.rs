@@ -0,0 +1,806 @@
+//! MTProto transport framing.
+//!
+//! Serialized MTProto messages are sent over TCP using one of the
+//! following transports which prepend a length to every payload:
+//!
+//! | Transport              | Tag          | Frame layout                                 |
+//! |------------------------|--------------|----------------------------------------------|
+//! | [`Abridged`]           | `0xef`       | `len / 4` in 1 or 4 bytes, payload           |
+//! | [`Intermediate`]       | `0xeeeeeeee` | `len` in 4 bytes, payload                    |
+//! | [`PaddedIntermediate`] | `0xdddddddd` | `len` in 4 bytes, payload, 0-15 random bytes |
+//! | [`Full`]               | none         | `len` in 4 bytes, seqno, payload, CRC32      |
+//!
+//! The tag is sent once at the start of a connection to select the
+//! transport and is available via [`Transport::tag`].
+//!
+//! Transports work over in-memory buffers with [`Transport::encode_frame`]
+//! and [`Transport::decode_frame`], which makes them usable with any I/O
+//! model, as well as directly over `io::Write` and `io::Read` with
+//! [`Transport::write_frame`] and [`Transport::read_frame`].
+//!
+//! [`PaddedIntermediate`] is behind the `rand` feature (which `crypto`
+//! enables too) to generate padding.
+//!
+//! [`Obfuscated`] (behind the `crypto` feature) wraps a stream to hide
+//! the transport tag and frames behind AES-256-CTR, with any transport
+//! except [`Full`] on top of it.
+//!
+//! A 4-byte payload carrying a negative number is a transport error sent
+//! by the server, so it's reported as
+//! [`TransportErrorKind::ServerError`](../error/enum.TransportErrorKind.html).
+//!
+//! # Examples
+//!
+//! ```
+//! use serde_mtproto::transport::{Decoded, Full, Transport};
+//!
+//! # fn run() -> serde_mtproto::Result<()> {
+//! let mut client = Full::new();
+//! let mut server = Full::new();
+//!
+//! let mut buf = Vec::new();
+//! client.encode_frame(&[1, 2, 3, 4], &mut buf)?;
+//!
+//! match server.decode_frame(&buf)? {
+//!     Decoded::Frame { payload, consumed } => {
+//!         assert_eq!(payload, [1, 2, 3, 4]);
+//!         assert_eq!(consumed, buf.len());
+//!     },
+//!     Decoded::Incomplete { .. } => unreachable!(),
+//! }
+//! #     Ok(())
+//! # }
+//! #
+//! # fn main() { run().unwrap(); }
+//! ```
+
+#![cfg_attr(feature = "cargo-clippy", allow(clippy::unreadable_literal))]  // To match the look & feel from MTProto docs
+
+#[cfg(feature = "crypto")]
+use std::fmt;
+use std::io;
+
+use byteorder::{ByteOrder, LittleEndian};
+use error_chain::bail;
+#[cfg(feature = "crypto")]
+use rand::CryptoRng;
+#[cfg(feature = "rand")]
+use rand::RngCore;
+#[cfg(feature = "rand")]
+use rand::rngs::ThreadRng;
+#[cfg(feature = "crypto")]
+use sha2::{Digest, Sha256};
+
+#[cfg(feature = "crypto")]
+use crate::crypto::AesCtr;
+use crate::error::{self, TransportErrorKind};
+use crate::utils::safe_uint_cast;
+
+
+/// Default limit on the payload length of a received frame.
+pub const DEFAULT_MAX_FRAME_LEN: usize = 16 * 1024 * 1024;
+
+const ABRIDGED_TAG: &[u8] = &[0xef];
+const INTERMEDIATE_TAG: &[u8] = &[0xee, 0xee, 0xee, 0xee];
+#[cfg(feature = "rand")]
+const PADDED_INTERMEDIATE_TAG: &[u8] = &[0xdd, 0xdd, 0xdd, 0xdd];
+
+/// Length of the length, seqno and CRC32 fields of a full transport frame.
+const FULL_OVERHEAD_LEN: usize = 12;
+
+/// Length of the init header of an obfuscated connection.
+#[cfg(feature = "crypto")]
+const OBFUSCATED_HEADER_LEN: usize = 64;
+
+/// Starts of an obfuscated init header which would make a connection look
+/// like HTTP, TLS or an unobfuscated transport.
+#[cfg(feature = "crypto")]
+const FORBIDDEN_HEADER_STARTS: &[u32] = &[
+    0x44414548,  // "HEAD"
+    0x54534f50,  // "POST"
+    0x20544547,  // "GET "
+    0x4954504f,  // "OPTI"
+    0x02010316,  // TLS handshake
+    0xdddddddd,
+    0xeeeeeeee,
+];
+
+
+/// Result of decoding a frame from a buffer.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum Decoded {
+    /// A complete frame at the start of the buffer.
+    Frame {
+        /// The unframed payload.
+        payload: Vec<u8>,
+        /// Number of bytes of the buffer the frame takes.
+        consumed: usize,
+    },
+    /// The buffer doesn't contain a complete frame yet.
+    Incomplete {
+        /// Minimal number of bytes the buffer must contain to make progress.
+        needed: usize,
+    },
+}
+
+/// An MTProto transport which frames and unframes payloads.
+pub trait Transport {
+    /// Bytes sent once at the start of a connection to select this
+    /// transport.
+    fn tag(&self) -> &'static [u8];
+
+    /// Append a frame containing `payload` to `buf`.
+    fn encode_frame(&mut self, payload: &[u8], buf: &mut Vec<u8>) -> error::Result<()>;
+
+    /// Decode a frame from the start of `buf`.
+    ///
+    /// The transport state is only updated if a complete frame is decoded.
+    fn decode_frame(&mut self, buf: &[u8]) -> error::Result<Decoded>;
+
+    /// Write a frame containing `payload` to `writer`.
+    fn write_frame<W: io::Write>(&mut self, writer: &mut W, payload: &[u8]) -> error::Result<()> {
+        let mut buf = Vec::new();
+        self.encode_frame(payload, &mut buf)?;
+        writer.write_all(&buf)?;
+
+        Ok(())
+    }
+
+    /// Read a frame from `reader` and return its payload.
+    ///
+    /// Doesn't read past the end of the frame.
+    fn read_frame<R: io::Read>(&mut self, reader: &mut R) -> error::Result<Vec<u8>> {
+        let mut buf = Vec::new();
+
+        loop {
+            match self.decode_frame(&buf)? {
+                Decoded::Frame { payload, .. } => return Ok(payload),
+                Decoded::Incomplete { needed } => {
+                    let start = buf.len();
+                    buf.resize(needed, 0);
+                    reader.read_exact(buf.split_at_mut(start).1)?;
+                },
+            }
+        }
+    }
+}
+
+
+/// The abridged transport: `0xef` tag, payload length divided by 4 in
+/// 1 byte if it's less than 127 and in 4 bytes starting with `0x7f`
+/// otherwise.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Abridged {
+    max_frame_len: usize,
+}
+
+impl Abridged {
+    /// Create an abridged transport with the default frame length limit.
+    pub fn new() -> Abridged {
+        Abridged::with_max_frame_len(DEFAULT_MAX_FRAME_LEN)
+    }
+
+    /// Create an abridged transport which rejects received frames longer
+    /// than `max_frame_len`.
+    pub fn with_max_frame_len(max_frame_len: usize) -> Abridged {
+        Abridged { max_frame_len }
+    }
+}
+
+impl Default for Abridged {
+    fn default() -> Abridged {
+        Abridged::new()
+    }
+}
+
+impl Transport for Abridged {
+    fn tag(&self) -> &'static [u8] {
+        ABRIDGED_TAG
+    }
+
+    fn encode_frame(&mut self, payload: &[u8], buf: &mut Vec<u8>) -> error::Result<()> {
+        if payload.len() % 4 != 0 {
+            bail!(TransportErrorKind::UnalignedPayload(payload.len()));
+        }
+
+        let words = payload.len() / 4;
+
+        if words < 0x7f {
+            buf.push(safe_uint_cast(words)?);
+        } else if words < 1 << 24 {
+            let mut header = [0x7f, 0, 0, 0];
+            LittleEndian::write_u24(header.split_at_mut(1).1, safe_uint_cast(words)?);
+            buf.extend_from_slice(&header);
+        } else {
+            bail!(TransportErrorKind::FrameTooLong(payload.len(), ((1 << 24) - 1) * 4));
+        }
+
+        buf.extend_from_slice(payload);
+
+        Ok(())
+    }
+
+    fn decode_frame(&mut self, buf: &[u8]) -> error::Result<Decoded> {
+        let (header_len, words) = match buf.first() {
+            None => return Ok(Decoded::Incomplete { needed: 1 }),
+            Some(&0x7f) => match buf.get(1..4) {
+                Some(len_bytes) => (4, LittleEndian::read_u24(len_bytes)),
+                None => return Ok(Decoded::Incomplete { needed: 4 }),
+            },
+            Some(&words) => (1, u32::from(words)),
+        };
+
+        let len = safe_uint_cast::<u32, usize>(words)?.saturating_mul(4);
+        decode_payload(buf, header_len, len, self.max_frame_len)
+    }
+}
+
+
+/// The intermediate transport: `0xeeeeeeee` tag, payload length in 4 bytes.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Intermediate {
+    max_frame_len: usize,
+}
+
+impl Intermediate {
+    /// Create an intermediate transport with the default frame length limit.
+    pub fn new() -> Intermediate {
+        Intermediate::with_max_frame_len(DEFAULT_MAX_FRAME_LEN)
+    }
+
+    /// Create an intermediate transport which rejects received frames
+    /// longer than `max_frame_len`.
+    pub fn with_max_frame_len(max_frame_len: usize) -> Intermediate {
+        Intermediate { max_frame_len }
+    }
+}
+
+impl Default for Intermediate {
+    fn default() -> Intermediate {
+        Intermediate::new()
+    }
+}
+
+impl Transport for Intermediate {
+    fn tag(&self) -> &'static [u8] {
+        INTERMEDIATE_TAG
+    }
+
+    fn encode_frame(&mut self, payload: &[u8], buf: &mut Vec<u8>) -> error::Result<()> {
+        encode_intermediate(payload, &[], buf)
+    }
+
+    fn decode_frame(&mut self, buf: &[u8]) -> error::Result<Decoded> {
+        decode_intermediate(buf, self.max_frame_len)
+    }
+}
+
+
+/// The padded intermediate transport: `0xdddddddd` tag, the length in
+/// 4 bytes of the payload followed by 0 to 15 random padding bytes.
+///
+/// Padding is meant to hide payload lengths and can't be told apart from
+/// the payload, so received payloads include it. Both encrypted and
+/// unencrypted MTProto messages carry their own length, so the trailing
+/// padding can be ignored when deserializing them.
+///
+/// Padding is generated by `R`, which doesn't have to be cryptographically
+/// secure.
+#[cfg(feature = "rand")]
+#[derive(Clone, Debug)]
+pub struct PaddedIntermediate<R = ThreadRng> {
+    max_frame_len: usize,
+    rng: R,
+}
+
+#[cfg(feature = "rand")]
+impl PaddedIntermediate {
+    /// Create a padded intermediate transport with the default frame length
+    /// limit.
+    ///
+    /// Uses `rand::thread_rng()` to generate padding.
+    pub fn new() -> PaddedIntermediate {
+        PaddedIntermediate::with_max_frame_len(DEFAULT_MAX_FRAME_LEN)
+    }
+
+    /// Create a padded intermediate transport which rejects received frames
+    /// longer than `max_frame_len`.
+    ///
+    /// Uses `rand::thread_rng()` to generate padding.
+    pub fn with_max_frame_len(max_frame_len: usize) -> PaddedIntermediate {
+        PaddedIntermediate::with_max_frame_len_and_rng(max_frame_len, rand::thread_rng())
+    }
+}
+
+#[cfg(feature = "rand")]
+impl<R: RngCore> PaddedIntermediate<R> {
+    /// Create a padded intermediate transport with the default frame length
+    /// limit which generates padding using `rng`.
+    pub fn with_rng(rng: R) -> PaddedIntermediate<R> {
+        PaddedIntermediate::with_max_frame_len_and_rng(DEFAULT_MAX_FRAME_LEN, rng)
+    }
+
+    /// Create a padded intermediate transport which rejects received frames
+    /// longer than `max_frame_len` and generates padding using `rng`.
+    pub fn with_max_frame_len_and_rng(max_frame_len: usize, rng: R) -> PaddedIntermediate<R> {
+        PaddedIntermediate { max_frame_len, rng }
+    }
+}
+
+#[cfg(feature = "rand")]
+impl Default for PaddedIntermediate {
+    fn default() -> PaddedIntermediate {
+        PaddedIntermediate::new()
+    }
+}
+
+#[cfg(feature = "rand")]
+impl<R: RngCore> Transport for PaddedIntermediate<R> {
+    fn tag(&self) -> &'static [u8] {
+        PADDED_INTERMEDIATE_TAG
+    }
+
+    fn encode_frame(&mut self, payload: &[u8], buf: &mut Vec<u8>) -> error::Result<()> {
+        let mut padding = [0; 16];
+        self.rng.fill_bytes(&mut padding);
+        let (&padding_len, padding) = padding.split_first()
+            .unwrap_or_else(|| unreachable!("padding buffer must not be empty"));
+        let padding = padding.get(..usize::from(padding_len % 16))
+            .unwrap_or_else(|| unreachable!("padding must be of length 15 or less"));
+
+        encode_intermediate(payload, padding, buf)
+    }
+
+    fn decode_frame(&mut self, buf: &[u8]) -> error::Result<Decoded> {
+        decode_intermediate(buf, self.max_frame_len)
+    }
+}
+
+
+/// The full transport: no tag, the total frame length and the sequence
+/// number of the frame in 4 bytes each, payload and CRC32 of all that.
+///
+/// Sequence numbers are counted from 0 separately for sent and received
+/// frames, so a single `Full` value must be used for a connection.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Full {
+    max_frame_len: usize,
+    send_seq_no: u32,
+    recv_seq_no: u32,
+}
+
+impl Full {
+    /// Create a full transport with the default frame length limit.
+    pub fn new() -> Full {
+        Full::with_max_frame_len(DEFAULT_MAX_FRAME_LEN)
+    }
+
+    /// Create a full transport which rejects received frames longer than
+    /// `max_frame_len`.
+    pub fn with_max_frame_len(max_frame_len: usize) -> Full {
+        Full { max_frame_len, send_seq_no: 0, recv_seq_no: 0 }
+    }
+
+    /// Sequence number of the next sent frame.
+    pub fn send_seq_no(&self) -> u32 {
+        self.send_seq_no
+    }
+
+    /// Sequence number expected for the next received frame.
+    pub fn recv_seq_no(&self) -> u32 {
+        self.recv_seq_no
+    }
+}
+
+impl Default for Full {
+    fn default() -> Full {
+        Full::new()
+    }
+}
+
+impl Transport for Full {
+    fn tag(&self) -> &'static [u8] {
+        &[]
+    }
+
+    fn encode_frame(&mut self, payload: &[u8], buf: &mut Vec<u8>) -> error::Result<()> {
+        let len = safe_uint_cast::<usize, u32>(payload.len().saturating_add(FULL_OVERHEAD_LEN))?;
+        let start = buf.len();
+
+        let mut header = [0; 8];
+        {
+            let (len_bytes, seq_no_bytes) = header.split_at_mut(4);
+            LittleEndian::write_u32(len_bytes, len);
+            LittleEndian::write_u32(seq_no_bytes, self.send_seq_no);
+        }
+        buf.extend_from_slice(&header);
+        buf.extend_from_slice(payload);
+
+        let mut crc = [0; 4];
+        LittleEndian::write_u32(&mut crc, crc32fast::hash(buf.split_at(start).1));
+        buf.extend_from_slice(&crc);
+
+        self.send_seq_no = self.send_seq_no.wrapping_add(1);
+
+        Ok(())
+    }
+
+    fn decode_frame(&mut self, buf: &[u8]) -> error::Result<Decoded> {
+        let len = match buf.get(0..4) {
+            Some(len_bytes) => LittleEndian::read_u32(len_bytes),
+            None => return Ok(Decoded::Incomplete { needed: 4 }),
+        };
+
+        let frame_len = safe_uint_cast::<u32, usize>(len)?;
+        if frame_len < FULL_OVERHEAD_LEN || frame_len % 4 != 0 {
+            bail!(TransportErrorKind::InvalidFrameLen(len));
+        }
+
+        let payload_len = frame_len - FULL_OVERHEAD_LEN;
+        if payload_len > self.max_frame_len {
+            bail!(TransportErrorKind::FrameTooLong(payload_len, self.max_frame_len));
+        }
+
+        let frame = match buf.get(..frame_len) {
+            Some(frame) => frame,
+            None => return Ok(Decoded::Incomplete { needed: frame_len }),
+        };
+
+        let (checked, crc_bytes) = frame.split_at(frame_len - 4);
+        let computed_crc = crc32fast::hash(checked);
+        let received_crc = LittleEndian::read_u32(crc_bytes);
+        if computed_crc != received_crc {
+            bail!(TransportErrorKind::Crc32Mismatch(computed_crc, received_crc));
+        }
+
+        let (header, payload) = checked.split_at(8);
+        let seq_no = LittleEndian::read_u32(header.split_at(4).1);
+        if seq_no != self.recv_seq_no {
+            bail!(TransportErrorKind::SeqNoMismatch(self.recv_seq_no, seq_no));
+        }
+
+        self.recv_seq_no = self.recv_seq_no.wrapping_add(1);
+        check_server_error(payload)?;
+
+        Ok(Decoded::Frame { payload: payload.to_vec(), consumed: frame_len })
+    }
+}
+
+
+/// Parameters of an obfuscated connection which must match on both sides.
+#[cfg(feature = "crypto")]
+#[derive(Clone, Default, Eq, Hash, PartialEq)]
+pub struct ObfuscatedConfig {
+    dc_id: i16,
+    secret: Option<[u8; 16]>,
+}
+
+#[cfg(feature = "crypto")]
+impl ObfuscatedConfig {
+    /// Create a configuration with DC id 0 and without a proxy secret.
+    pub fn new() -> ObfuscatedConfig {
+        ObfuscatedConfig { dc_id: 0, secret: None }
+    }
+
+    /// Set the DC id sent in the init header, negative for media DCs.
+    pub fn dc_id(self, dc_id: i16) -> ObfuscatedConfig {
+        ObfuscatedConfig { dc_id, ..self }
+    }
+
+    /// Set the proxy secret mixed into the AES keys.
+    pub fn secret(self, secret: [u8; 16]) -> ObfuscatedConfig {
+        ObfuscatedConfig { secret: Some(secret), ..self }
+    }
+}
+
+// Don't leak the secret into logs
+#[cfg(feature = "crypto")]
+impl fmt::Debug for ObfuscatedConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ObfuscatedConfig")
+            .field("dc_id", &self.dc_id)
+            .field("has_secret", &self.secret.is_some())
+            .finish()
+    }
+}
+
+
+/// The obfuscated transport: a stream adapter which starts a connection
+/// with a random 64-byte init header and encrypts everything after it with
+/// AES-256-CTR.
+///
+/// The init header carries the tag of the transport used on top of this
+/// adapter and the DC id, both encrypted, while the rest of it seeds the
+/// keys and IVs for both directions. Bytes are encrypted as they are
+/// written and decrypted as they are read, so an I/O error leaves the
+/// connection unusable.
+///
+/// # Examples
+///
+/// ```
+/// use std::io::Cursor;
+///
+/// use serde_mtproto::transport::{Intermediate, Obfuscated, ObfuscatedConfig, Transport};
+///
+/// # fn run() -> serde_mtproto::Result<()> {
+/// let config = ObfuscatedConfig::new().dc_id(2);
+///
+/// let mut client = Obfuscated::connect(Vec::new(), &Intermediate::new(), &config)?;
+/// Intermediate::new().write_frame(&mut client, &[1, 2, 3, 4])?;
+///
+/// let mut server = Obfuscated::accept(Cursor::new(client.into_inner()), &config)?;
+/// assert_eq!(server.transport_tag(), Intermediate::new().tag());
+/// assert_eq!(server.dc_id(), 2);
+/// assert_eq!(Intermediate::new().read_frame(&mut server)?, [1, 2, 3, 4]);
+/// #     Ok(())
+/// # }
+/// #
+/// # fn main() { run().unwrap(); }
+/// ```
+#[cfg(feature = "crypto")]
+pub struct Obfuscated<S> {
+    stream: S,
+    encryptor: AesCtr,
+    decryptor: AesCtr,
+    transport_tag: &'static [u8],
+    dc_id: i16,
+}
+
+#[cfg(feature = "crypto")]
+impl<S: io::Write> Obfuscated<S> {
+    /// Write an init header for `transport` to `stream` and obfuscate
+    /// everything sent and received afterwards.
+    ///
+    /// Uses `rand::thread_rng()` to generate the header.
+    pub fn connect<T>(stream: S, transport: &T, config: &ObfuscatedConfig) -> error::Result<Obfuscated<S>>
+        where T: Transport
+    {
+        Obfuscated::connect_with_rng(stream, transport, config, &mut rand::thread_rng())
+    }
+
+    /// Write an init header for `transport` generated using `rng` to
+    /// `stream` and obfuscate everything sent and received afterwards.
+    pub fn connect_with_rng<T, R>(
+        mut stream: S,
+        transport: &T,
+        config: &ObfuscatedConfig,
+        rng: &mut R,
+    ) -> error::Result<Obfuscated<S>>
+        where T: Transport,
+              R: RngCore + CryptoRng,
+    {
+        let transport_tag = transport.tag();
+        let tag = match obfuscated_tag(transport_tag) {
+            Some(tag) => tag,
+            None => bail!(TransportErrorKind::NoTag),
+        };
+
+        let mut header = [0; OBFUSCATED_HEADER_LEN];
+        loop {
+            rng.fill_bytes(&mut header);
+
+            if is_valid_header(&header) {
+                break;
+            }
+        }
+
+        header[56..60].copy_from_slice(&tag);
+        LittleEndian::write_i16(&mut header[60..62], config.dc_id);
+
+        let (mut encryptor, decryptor) = header_ciphers(&header, config.secret.as_ref());
+
+        // Only the tag and the DC id are sent encrypted, the rest of the
+        // header must stay intact for the server to derive the same keys
+        let mut encrypted_header = header;
+        encryptor.apply_keystream(&mut encrypted_header);
+        header[56..64].copy_from_slice(&encrypted_header[56..64]);
+        stream.write_all(&header)?;
+
+        Ok(Obfuscated { stream, encryptor, decryptor, transport_tag, dc_id: config.dc_id })
+    }
+}
+
+#[cfg(feature = "crypto")]
+impl<S: io::Read> Obfuscated<S> {
+    /// Read an init header from `stream` and obfuscate everything sent and
+    /// received afterwards.
+    pub fn accept(mut stream: S, config: &ObfuscatedConfig) -> error::Result<Obfuscated<S>> {
+        let mut header = [0; OBFUSCATED_HEADER_LEN];
+        stream.read_exact(&mut header)?;
+
+        let (mut decryptor, encryptor) = header_ciphers(&header, config.secret.as_ref());
+        decryptor.apply_keystream(&mut header);
+
+        let mut tag = [0; 4];
+        tag.copy_from_slice(&header[56..60]);
+        let transport_tag = match unobfuscated_tag(tag) {
+            Some(transport_tag) => transport_tag,
+            None => bail!(TransportErrorKind::UnknownTag(LittleEndian::read_u32(&tag))),
+        };
+        let dc_id = LittleEndian::read_i16(&header[60..62]);
+
+        Ok(Obfuscated { stream, encryptor, decryptor, transport_tag, dc_id })
+    }
+}
+
+#[cfg(feature = "crypto")]
+impl<S> Obfuscated<S> {
+    /// Tag of the transport used on top of this connection, as returned
+    /// by [`Transport::tag`].
+    pub fn transport_tag(&self) -> &'static [u8] {
+        self.transport_tag
+    }
+
+    /// DC id sent in the init header.
+    pub fn dc_id(&self) -> i16 {
+        self.dc_id
+    }
+
+    /// Get a reference to the underlying stream.
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    /// Get a mutable reference to the underlying stream.
+    ///
+    /// Reading from or writing to it directly breaks the obfuscation.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.stream
+    }
+
+    /// Unwrap the underlying stream.
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+}
+
+#[cfg(feature = "crypto")]
+impl<S: io::Read> io::Read for Obfuscated<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.stream.read(buf)?;
+        #[cfg_attr(feature = "cargo-clippy", allow(clippy::indexing_slicing))]
+        self.decryptor.apply_keystream(&mut buf[..read]);
+
+        Ok(read)
+    }
+}
+
+#[cfg(feature = "crypto")]
+impl<S: io::Write> io::Write for Obfuscated<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // The keystream can't be rewound, so the whole buffer must be
+        // written at once
+        let mut encrypted = buf.to_vec();
+        self.encryptor.apply_keystream(&mut encrypted);
+        self.stream.write_all(&encrypted)?;
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+// Don't leak the keys into logs
+#[cfg(feature = "crypto")]
+impl<S: fmt::Debug> fmt::Debug for Obfuscated<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Obfuscated")
+            .field("stream", &self.stream)
+            .field("transport_tag", &self.transport_tag)
+            .field("dc_id", &self.dc_id)
+            .finish()
+    }
+}
+
+
+fn encode_intermediate(payload: &[u8], padding: &[u8], buf: &mut Vec<u8>) -> error::Result<()> {
+    let len = safe_uint_cast::<usize, u32>(payload.len().saturating_add(padding.len()))?;
+
+    let mut header = [0; 4];
+    LittleEndian::write_u32(&mut header, len);
+    buf.extend_from_slice(&header);
+    buf.extend_from_slice(payload);
+    buf.extend_from_slice(padding);
+
+    Ok(())
+}
+
+fn decode_intermediate(buf: &[u8], max_frame_len: usize) -> error::Result<Decoded> {
+    let len = match buf.get(0..4) {
+        Some(len_bytes) => LittleEndian::read_u32(len_bytes),
+        None => return Ok(Decoded::Incomplete { needed: 4 }),
+    };
+
+    decode_payload(buf, 4, safe_uint_cast(len)?, max_frame_len)
+}
+
+fn decode_payload(buf: &[u8], header_len: usize, len: usize, max_frame_len: usize) -> error::Result<Decoded> {
+    if len > max_frame_len {
+        bail!(TransportErrorKind::FrameTooLong(len, max_frame_len));
+    }
+
+    let frame_len = header_len.saturating_add(len);
+    let payload = match buf.get(header_len..frame_len) {
+        Some(payload) => payload,
+        None => return Ok(Decoded::Incomplete { needed: frame_len }),
+    };
+
+    check_server_error(payload)?;
+
+    Ok(Decoded::Frame { payload: payload.to_vec(), consumed: frame_len })
+}
+
+/// Report a 4-byte payload with a negative error code as an error.
+fn check_server_error(payload: &[u8]) -> error::Result<()> {
+    if payload.len() == 4 {
+        let code = LittleEndian::read_i32(payload);
+
+        if code < 0 {
+            bail!(TransportErrorKind::ServerError(code));
+        }
+    }
+
+    Ok(())
+}
+
+/// Tag of a transport as sent in an obfuscated init header.
+#[cfg(feature = "crypto")]
+fn obfuscated_tag(tag: &[u8]) -> Option<[u8; 4]> {
+    match *tag {
+        [byte] => Some([byte; 4]),
+        [a, b, c, d] => Some([a, b, c, d]),
+        _ => None,
+    }
+}
+
+/// Tag of a transport for its tag sent in an obfuscated init header.
+#[cfg(feature = "crypto")]
+fn unobfuscated_tag(tag: [u8; 4]) -> Option<&'static [u8]> {
+    [ABRIDGED_TAG, INTERMEDIATE_TAG, PADDED_INTERMEDIATE_TAG].iter()
+        .cloned()
+        .find(|&transport_tag| obfuscated_tag(transport_tag) == Some(tag))
+}
+
+#[cfg(feature = "crypto")]
+fn is_valid_header(header: &[u8; OBFUSCATED_HEADER_LEN]) -> bool {
+    ABRIDGED_TAG.first() != Some(&header[0])
+        && !FORBIDDEN_HEADER_STARTS.contains(&LittleEndian::read_u32(&header[0..4]))
+        && LittleEndian::read_u32(&header[4..8]) != 0
+}
+
+/// Ciphers for the direction from the sender of an obfuscated init header
+/// and for the reverse one.
+#[cfg(feature = "crypto")]
+fn header_ciphers(header: &[u8; OBFUSCATED_HEADER_LEN], secret: Option<&[u8; 16]>) -> (AesCtr, AesCtr) {
+    let mut key_iv = [0; 48];
+    key_iv.copy_from_slice(&header[8..56]);
+    let forward = header_cipher(&key_iv, secret);
+
+    key_iv.reverse();
+    let backward = header_cipher(&key_iv, secret);
+
+    (forward, backward)
+}
+
+#[cfg(feature = "crypto")]
+fn header_cipher(key_iv: &[u8; 48], secret: Option<&[u8; 16]>) -> AesCtr {
+    let (key_bytes, iv_bytes) = key_iv.split_at(32);
+
+    let mut key = [0; 32];
+    match secret {
+        Some(secret) => {
+            let mut hasher = Sha256::new();
+            hasher.input(key_bytes);
+            hasher.input(secret);
+            key.copy_from_slice(&hasher.result());
+        },
+        None => key.copy_from_slice(key_bytes),
+    }
+
+    let mut iv = [0; 16];
+    iv.copy_from_slice(iv_bytes);
+
+    AesCtr::new(&key, &iv)
+}
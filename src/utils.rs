@@ -86,6 +86,42 @@ pub(crate) fn u128_to_parts(n: u128) -> (u64, u64) {
 }
 
 
+/// Implement `Identifiable` and `MtProtoSized` for structs of a single
+/// constructor, where the size is the sum of sizes of the listed fields.
+macro_rules! impl_identifiable_and_sized {
+    ($($type:ident $(<$($param:ident),*>)* => ($id:expr, [$($field:ident),*]),)*) => {
+        $(
+            impl$(<$($param),*>)* $crate::identifiable::Identifiable for $type$(<$($param),*>)* {
+                fn all_type_ids() -> &'static [u32] {
+                    &[$id]
+                }
+
+                fn all_enum_variant_names() -> Option<&'static [&'static str]> {
+                    None
+                }
+
+                fn type_id(&self) -> u32 {
+                    $id
+                }
+
+                fn enum_variant_id(&self) -> Option<&'static str> {
+                    None
+                }
+            }
+
+            impl$(<$($param: $crate::sized::MtProtoSized),*>)* $crate::sized::MtProtoSized for $type$(<$($param),*>)* {
+                fn size_hint(&self) -> $crate::error::Result<usize> {
+                    let mut result = 0;
+                    $(result += $crate::sized::MtProtoSized::size_hint(&self.$field)?;)*
+
+                    Ok(result)
+                }
+            }
+        )*
+    };
+}
+
+
 #[cfg(test)]
 mod tests {
     const I128_PARTS: &[(i128, (i64, u64))] = &[
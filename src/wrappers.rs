@@ -3,10 +3,16 @@
 //!
 //! ## Data and metadata layout
 //!
-//! | Wrapper type | Layout       |
-//! |--------------|--------------|
-//! | [`Boxed`]    | (id, data)   |
-//! | [`WithSize`] | (size, data) |
+//! | Wrapper type           | Layout                          |
+//! |------------------------|---------------------------------|
+//! | [`Boxed`]              | (id, data)                      |
+//! | [`WithSize`]           | (size, data)                    |
+//! | [`UnencryptedMessage`] | (0, message id, size, id, data) |
+//!
+//! With the `gzip` feature enabled, there is also [`GzipPacked`] which is
+//! laid out either as (id, data) like [`Boxed`] or as
+//! (`0x3072cfa1`, packed data) where the packed data is a byte sequence
+//! with gzip-compressed (id, data).
 //!
 //! ## How does `Boxed<WithSize<T>>` differ from `WithSize<Boxed<T>>`?
 //!
@@ -32,20 +38,47 @@
 //!
 //! This crate uses `Boxed` as the main naming scheme, whereas `WithId`
 //! is a type alias.
+//!
+//! ## Vectors
+//!
+//! TL distinguishes the boxed `Vector<T>` type, which is prefixed with
+//! the `vector#1cb5c415` constructor id, from the bare `vector<T>` one.
+//! Elements of either of them can be bare or boxed too:
+//!
+//! | TL type            | Rust type                | Layout                           |
+//! |--------------------|--------------------------|----------------------------------|
+//! | `vector<%T>`       | `Vec<T>`                 | (len, data...)                   |
+//! | `vector<T>`        | `Vec<Boxed<T>>`          | (len, (id, data)...)             |
+//! | `Vector<%T>`       | `Boxed<Vec<T>>`          | (0x1cb5c415, len, data...)       |
+//! | `Vector<T>`        | `Boxed<Vec<Boxed<T>>>`   | (0x1cb5c415, len, (id, data)...) |
+//!
+//! Builtin types are usually sent bare inside vectors, e.g. `Vector<long>`
+//! is `Boxed<Vec<i64>>`, while values of polymorphic types must be boxed
+//! to know their constructor, e.g. `Vector<Message>` is
+//! `Boxed<Vec<Boxed<Message>>>`.
 
 use std::fmt;
+#[cfg(feature = "gzip")]
+use std::io::{Read, Write};
 use std::marker::PhantomData;
 
 use error_chain::bail;
+#[cfg(feature = "gzip")]
+use flate2::{Compression, read::GzDecoder, write::GzEncoder};
 #[cfg(feature = "quickcheck")]
 use quickcheck::{Arbitrary, Gen};
 use serde::de::{Deserialize, DeserializeSeed, Deserializer,
-                Error as DeError, MapAccess, SeqAccess, Visitor};
+                Error as DeError, IgnoredAny, MapAccess, SeqAccess, Visitor};
+#[cfg(feature = "gzip")]
+use serde::de::DeserializeOwned;
 use serde::ser::{Error as SerError, Serialize, Serializer, SerializeStruct};
 use serde_derive::Deserialize;
 
-use crate::error::{self, DeErrorKind};
+use crate::error::{self, DeErrorKind, SerErrorKind};
+#[cfg(feature = "gzip")]
+use crate::identifiable::GZIP_PACKED_ID;
 use crate::identifiable::Identifiable;
+use crate::message_id::MessageIdKind;
 use crate::sized::MtProtoSized;
 use crate::utils::{safe_uint_cast, safe_uint_eq};
 
@@ -120,7 +153,13 @@ impl<'de, T> Deserialize<'de> for Boxed<T>
                 let type_id = next_seq_element(&mut seq, 0, 2)?;
                 check_type_id::<T>(type_id).map_err(A::Error::custom)?;
 
-                let value = next_seq_element(&mut seq, 1, 2)?;
+                // Formats that represent structs as sequences (like MTProto) don't carry enum
+                // variant names, so the variant is selected using the type id instead.
+                let value = match enum_variant_name::<T>(type_id) {
+                    Some(variant) => next_seq_element_seed(&mut seq, EnumVariantSeed::new(variant), 1, 2)?,
+                    None => next_seq_element(&mut seq, 1, 2)?,
+                };
+
                 checked_boxed_value::<T>(type_id, value).map_err(A::Error::custom)
             }
 
@@ -189,9 +228,22 @@ impl<T> Arbitrary for Boxed<T>
 }
 
 
+/// Name of structs which MTProto deserializer treats as (size, data)
+/// pairs, reading exactly `size` bytes for data.
+pub(crate) const WITH_SIZE_STRUCT_NAME: &str = "WithSize";
+
+/// Name of structs which MTProto deserializer treats as (size, data)
+/// pairs, skipping `size` bytes of data.
+pub(crate) const SKIPPED_WITH_SIZE_STRUCT_NAME: &str = "SkippedWithSize";
+
 /// A struct that wraps a [`MtProtoSized`] type value to serialize and
 /// deserialize as a MTProto data type with the size of its serialized
 /// value.
+///
+/// When deserializing MTProto, the inner value must consume exactly the
+/// declared number of bytes, otherwise deserialization fails as soon as the
+/// value reads past them. Use [`SkippedWithSize`] to skip such a value
+/// without knowing its type.
 #[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub struct WithSize<T> {
     inner: T,
@@ -228,7 +280,7 @@ impl<T> Serialize for WithSize<T>
         let size_usize = self.inner.size_hint().map_err(S::Error::custom)?;
         let size_u32 = safe_uint_cast::<usize, u32>(size_usize).map_err(S::Error::custom)?;
 
-        let mut ser = serializer.serialize_struct("WithSize", 2)?;
+        let mut ser = serializer.serialize_struct(WITH_SIZE_STRUCT_NAME, 2)?;
         ser.serialize_field("size", &size_u32)?;
         ser.serialize_field("inner", &self.inner)?;
         ser.end()
@@ -244,10 +296,10 @@ impl<'de, T> Deserialize<'de> for WithSize<T>
         where D: Deserializer<'de>
     {
         // Here we only implement through a helper struct because fully manual implementation
-        // (like what is present for `Boxed`) won't provide us eny benefits over this solution - we
-        // can obtain a deserialized size beforehand, but we can't apply it since neither Serde
-        // deserializable types, nor Serde deserializers in general have any means to limit the
-        // amount of raw data to be processed.
+        // (like what is present for `Boxed`) won't provide us eny benefits over this solution -
+        // MTProto deserializer bounds the `inner` field by the `size` one by itself since Serde
+        // deserializable types have no means to limit the amount of raw data to be processed.
+        // The size hint check below is still useful for other formats.
         #[derive(Deserialize)]
         #[serde(rename = "WithSize")]
         struct WithSizeHelper<T> {
@@ -326,6 +378,548 @@ fn check_type_id<T: Identifiable>(type_id: u32) -> error::Result<()> {
     Ok(())
 }
 
+/// Get the name of an enum variant which has the id `type_id` if `T` is an
+/// enum.
+fn enum_variant_name<T: Identifiable>(type_id: u32) -> Option<&'static str> {
+    let names = T::all_enum_variant_names()?;
+    let index = T::all_type_ids().iter().position(|&id| id == type_id)?;
+
+    names.get(index).cloned()
+}
+
+
+/// A [`WithSize`] value which is skipped when deserialized, keeping only
+/// its size.
+///
+/// This allows to get past a sized value whose type is unknown, e.g. a
+/// newer constructor. Only supports deserialization because the skipped
+/// data isn't stored.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct SkippedWithSize {
+    size: u32,
+}
+
+impl SkippedWithSize {
+    /// Return the size of the skipped value.
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+}
+
+impl<'de> Deserialize<'de> for SkippedWithSize {
+    fn deserialize<D>(deserializer: D) -> Result<SkippedWithSize, D::Error>
+        where D: Deserializer<'de>
+    {
+        struct SkippedWithSizeVisitor;
+
+        impl<'de> Visitor<'de> for SkippedWithSizeVisitor {
+            type Value = SkippedWithSize;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("size and a value to skip")
+            }
+
+            fn visit_seq<A>(self, mut seq: A) -> Result<SkippedWithSize, A::Error>
+                where A: SeqAccess<'de>
+            {
+                let size = next_seq_element(&mut seq, 0, 2)?;
+                let IgnoredAny = next_seq_element(&mut seq, 1, 2)?;
+
+                Ok(SkippedWithSize { size })
+            }
+
+            fn visit_map<A>(self, mut map: A) -> Result<SkippedWithSize, A::Error>
+                where A: MapAccess<'de>
+            {
+                let size = next_struct_element(&mut map, "size", 0, 2)?;
+                let IgnoredAny = next_struct_element(&mut map, "inner", 1, 2)?;
+
+                Ok(SkippedWithSize { size })
+            }
+        }
+
+        deserializer.deserialize_struct(SKIPPED_WITH_SIZE_STRUCT_NAME, &["size", "inner"], SkippedWithSizeVisitor)
+    }
+}
+
+impl MtProtoSized for SkippedWithSize {
+    fn size_hint(&self) -> error::Result<usize> {
+        // Just an u32 value to use for `<u32 as MtProtoSized>::size_hint`
+        let size_size_hint = 0_u32.size_hint()?;
+        let inner_size_hint = safe_uint_cast::<u32, usize>(self.size)?;
+
+        Ok(size_size_hint + inner_size_hint)
+    }
+}
+
+
+/// A struct that wraps an [`Identifiable`] type value to serialize and
+/// deserialize as an unencrypted MTProto message.
+///
+/// Messages are sent unencrypted only before an authorization key is
+/// generated and are laid out as
+/// (`auth_key_id` = 0, `message_id`, `message_data_length`, id, data), i.e.
+/// like a [`WithSize`]`<`[`Boxed`]`<T>>` prefixed with two `long` values.
+///
+/// When deserializing, `auth_key_id` must be zero, `message_data_length`
+/// must match the size of the boxed value and `message_id` must be valid
+/// (see [`UnencryptedMessage::new`]).
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct UnencryptedMessage<T> {
+    message_id: i64,
+    inner: T,
+}
+
+impl<T: Identifiable + MtProtoSized> UnencryptedMessage<T> {
+    /// Wrap a value along with its message id.
+    ///
+    /// The message id must have a [`MessageIdKind`], otherwise
+    /// serialization fails. Use a [`MessageIdGenerator`] to get one.
+    ///
+    /// [`MessageIdKind`]: ../message_id/enum.MessageIdKind.html
+    /// [`MessageIdGenerator`]: ../message_id/struct.MessageIdGenerator.html
+    pub fn new(message_id: i64, inner: T) -> UnencryptedMessage<T> {
+        UnencryptedMessage { message_id, inner }
+    }
+
+    /// Return the message id.
+    pub fn message_id(&self) -> i64 {
+        self.message_id
+    }
+
+    /// Return an immutable reference to the underlying data.
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    /// Return a mutable reference to the underlying data.
+    pub fn inner_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    /// Unwrap the message and return the wrapped value.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T> Serialize for UnencryptedMessage<T>
+    where T: Serialize + Identifiable + MtProtoSized
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer,
+    {
+        if !is_valid_message_id(self.message_id) {
+            return Err(S::Error::custom(error::Error::from(SerErrorKind::InvalidMessageId(self.message_id))));
+        }
+
+        let message_data = WithSize::new(Boxed::new(&self.inner)).map_err(S::Error::custom)?;
+
+        let mut ser = serializer.serialize_struct("UnencryptedMessage", 3)?;
+        ser.serialize_field("auth_key_id", &0_u64)?;
+        ser.serialize_field("message_id", &self.message_id)?;
+        ser.serialize_field("message_data", &message_data)?;
+        ser.end()
+    }
+}
+
+impl<'de, T> Deserialize<'de> for UnencryptedMessage<T>
+    where T: Deserialize<'de> + Identifiable + MtProtoSized
+{
+    fn deserialize<D>(deserializer: D) -> Result<UnencryptedMessage<T>, D::Error>
+        where D: Deserializer<'de>
+    {
+        struct UnencryptedMessageVisitor<T>(PhantomData<T>);
+
+        impl<'de, T> Visitor<'de> for UnencryptedMessageVisitor<T>
+            where T: Deserialize<'de> + Identifiable + MtProtoSized
+        {
+            type Value = UnencryptedMessage<T>;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("zero auth key id, message id and a sized boxed value")
+            }
+
+            fn visit_seq<A>(self, mut seq: A) -> Result<UnencryptedMessage<T>, A::Error>
+                where A: SeqAccess<'de>
+            {
+                let auth_key_id = next_seq_element(&mut seq, 0, 3)?;
+                let message_id = next_seq_element(&mut seq, 1, 3)?;
+                check_unencrypted_header(auth_key_id, message_id).map_err(A::Error::custom)?;
+
+                let message_data: WithSize<Boxed<T>> = next_seq_element(&mut seq, 2, 3)?;
+                Ok(UnencryptedMessage::new(message_id, message_data.into_inner().into_inner()))
+            }
+
+            fn visit_map<A>(self, mut map: A) -> Result<UnencryptedMessage<T>, A::Error>
+                where A: MapAccess<'de>
+            {
+                let auth_key_id = next_struct_element(&mut map, "auth_key_id", 0, 3)?;
+                let message_id = next_struct_element(&mut map, "message_id", 1, 3)?;
+                check_unencrypted_header(auth_key_id, message_id).map_err(A::Error::custom)?;
+
+                let message_data: WithSize<Boxed<T>> = next_struct_element(&mut map, "message_data", 2, 3)?;
+                Ok(UnencryptedMessage::new(message_id, message_data.into_inner().into_inner()))
+            }
+        }
+
+        fn check_unencrypted_header(auth_key_id: u64, message_id: i64) -> error::Result<()> {
+            if auth_key_id != 0 {
+                bail!(DeErrorKind::NonZeroAuthKeyId(auth_key_id));
+            }
+
+            if !is_valid_message_id(message_id) {
+                bail!(DeErrorKind::InvalidMessageId(message_id));
+            }
+
+            Ok(())
+        }
+
+        deserializer.deserialize_struct(
+            "UnencryptedMessage",
+            &["auth_key_id", "message_id", "message_data"],
+            UnencryptedMessageVisitor(PhantomData),
+        )
+    }
+}
+
+impl<T: Identifiable + MtProtoSized> MtProtoSized for UnencryptedMessage<T> {
+    fn size_hint(&self) -> error::Result<usize> {
+        // Just an u64 value to use for `<u64 as MtProtoSized>::size_hint`
+        let auth_key_id_size_hint = 0_u64.size_hint()?;
+        let message_id_size_hint = self.message_id.size_hint()?;
+        let message_data_size_hint = WithSize::new(Boxed::new(&self.inner))?.size_hint()?;
+
+        Ok(auth_key_id_size_hint + message_id_size_hint + message_data_size_hint)
+    }
+}
+
+fn is_valid_message_id(message_id: i64) -> bool {
+    MessageIdKind::of(message_id).is_some()
+}
+
+/// Default limit for [`GzipPacked`] data size after decompression.
+#[cfg(feature = "gzip")]
+pub const DEFAULT_MAX_UNPACKED_LEN: usize = 16 * 1024 * 1024;
+
+/// Name of structs which MTProto deserializer treats as (id, data) pairs,
+/// unpacking data first if the id is `gzip_packed#3072cfa1`.
+#[cfg(feature = "gzip")]
+pub(crate) const GZIP_PACKED_STRUCT_NAME: &str = "GzipPacked";
+
+/// A struct that wraps an [`Identifiable`] type value which may be sent
+/// compressed inside a `gzip_packed#3072cfa1 packed_data:string = Object`
+/// constructor.
+///
+/// When deserializing, both the packed and the plain boxed representations
+/// are accepted. MTProto deserializer reads the packed data with the same
+/// options, registry and enum variant hint as the rest of the data.
+///
+/// When serializing, the value is only packed if it was wrapped with a
+/// compression threshold and the boxed value is larger than it. The value
+/// is compressed once when wrapped, so it can't be modified in place.
+#[cfg(feature = "gzip")]
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct GzipPacked<T> {
+    inner: T,
+    packed_data: Option<Vec<u8>>,
+}
+
+#[cfg(feature = "gzip")]
+impl<T: Identifiable> GzipPacked<T> {
+    /// Wrap a value which is never compressed when serialized.
+    pub fn new(inner: T) -> GzipPacked<T> {
+        GzipPacked { inner, packed_data: None }
+    }
+
+    /// Return an immutable reference to the underlying data.
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    /// Unwrap the value.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+#[cfg(feature = "gzip")]
+impl<T> GzipPacked<T>
+    where T: Serialize + Identifiable
+{
+    /// Wrap a value which is compressed if its boxed representation is
+    /// longer than `threshold` bytes.
+    pub fn with_compression_threshold(inner: T, threshold: usize) -> error::Result<GzipPacked<T>> {
+        let boxed = crate::ser::to_bytes(&Boxed::new(&inner))?;
+        if boxed.len() <= threshold {
+            return Ok(GzipPacked::new(inner));
+        }
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&boxed)?;
+
+        Ok(GzipPacked { inner, packed_data: Some(encoder.finish()?) })
+    }
+}
+
+#[cfg(feature = "gzip")]
+impl<T> Serialize for GzipPacked<T>
+    where T: Serialize + Identifiable
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer,
+    {
+        let mut ser = serializer.serialize_struct(GZIP_PACKED_STRUCT_NAME, 2)?;
+        match self.packed_data {
+            Some(ref packed_data) => {
+                ser.serialize_field("id", &GZIP_PACKED_ID)?;
+                ser.serialize_field("inner", &serde_bytes::Bytes::new(packed_data))?;
+            },
+            None => {
+                ser.serialize_field("id", &self.inner.type_id())?;
+                ser.serialize_field("inner", &self.inner)?;
+            },
+        }
+        ser.end()
+    }
+}
+
+#[cfg(feature = "gzip")]
+impl<'de, T> Deserialize<'de> for GzipPacked<T>
+    where T: DeserializeOwned + Identifiable
+{
+    fn deserialize<D>(deserializer: D) -> Result<GzipPacked<T>, D::Error>
+        where D: Deserializer<'de>
+    {
+        GzipPackedSeed::new(DEFAULT_MAX_UNPACKED_LEN).deserialize(deserializer)
+    }
+}
+
+#[cfg(feature = "gzip")]
+impl<T: MtProtoSized> MtProtoSized for GzipPacked<T>
+    where T: Serialize + Identifiable
+{
+    fn size_hint(&self) -> error::Result<usize> {
+        // Just an u32 value to use for `<u32 as MtProtoSized>::size_hint`
+        let id_size_hint = 0_u32.size_hint()?;
+        let inner_size_hint = match self.packed_data {
+            Some(ref packed_data) => serde_bytes::Bytes::new(packed_data).size_hint()?,
+            None => self.inner.size_hint()?,
+        };
+
+        Ok(id_size_hint + inner_size_hint)
+    }
+}
+
+/// A [`GzipPacked`] seed with the maximum length of the data after
+/// decompression.
+#[cfg(feature = "gzip")]
+#[derive(Debug)]
+pub struct GzipPackedSeed<T> {
+    max_unpacked_len: usize,
+    phantom: PhantomData<T>,
+}
+
+#[cfg(feature = "gzip")]
+impl<T> GzipPackedSeed<T> {
+    /// Construct a new seed which fails deserialization if the packed data
+    /// decompresses to more than `max_unpacked_len` bytes.
+    pub fn new(max_unpacked_len: usize) -> GzipPackedSeed<T> {
+        GzipPackedSeed { max_unpacked_len, phantom: PhantomData }
+    }
+}
+
+#[cfg(feature = "gzip")]
+impl<'de, T> DeserializeSeed<'de> for GzipPackedSeed<T>
+    where T: DeserializeOwned + Identifiable
+{
+    type Value = GzipPacked<T>;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<GzipPacked<T>, D::Error>
+        where D: Deserializer<'de>
+    {
+        struct GzipPackedVisitor<T> {
+            max_unpacked_len: usize,
+            phantom: PhantomData<T>,
+        }
+
+        impl<'de, T> Visitor<'de> for GzipPackedVisitor<T>
+            where T: DeserializeOwned + Identifiable
+        {
+            type Value = GzipPacked<T>;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("type id and an `Identifiable` value, possibly gzip-packed")
+            }
+
+            fn visit_seq<A>(self, mut seq: A) -> Result<GzipPacked<T>, A::Error>
+                where A: SeqAccess<'de>
+            {
+                let type_id = next_seq_element(&mut seq, 0, 2)?;
+
+                // MTProto deserializer unpacks the data by itself, so this
+                // is only reached with other formats
+                if type_id == GZIP_PACKED_ID {
+                    let packed_data: serde_bytes::ByteBuf = next_seq_element(&mut seq, 1, 2)?;
+                    let unpacked_data = gzip_unpack(&packed_data, self.max_unpacked_len)
+                        .map_err(A::Error::custom)?;
+                    let boxed: Boxed<T> = crate::de::from_bytes(&unpacked_data, &[])
+                        .map_err(A::Error::custom)?;
+
+                    return Ok(GzipPacked::new(boxed.into_inner()));
+                }
+
+                check_type_id::<T>(type_id).map_err(A::Error::custom)?;
+
+                let value: T = match enum_variant_name::<T>(type_id) {
+                    Some(variant) => next_seq_element_seed(&mut seq, EnumVariantSeed::new(variant), 1, 2)?,
+                    None => next_seq_element(&mut seq, 1, 2)?,
+                };
+
+                if type_id != value.type_id() {
+                    bail!(errconv::<A::Error>(DeErrorKind::TypeIdMismatch(type_id, value.type_id())));
+                }
+
+                Ok(GzipPacked::new(value))
+            }
+        }
+
+        let visitor = GzipPackedVisitor { max_unpacked_len: self.max_unpacked_len, phantom: PhantomData };
+
+        // The length is the only way to pass the limit to MTProto
+        // deserializer, other formats only read the two elements anyway.
+        deserializer.deserialize_tuple_struct(GZIP_PACKED_STRUCT_NAME, self.max_unpacked_len, visitor)
+    }
+}
+
+/// Decompress gzip-packed data, failing if it's longer than
+/// `max_unpacked_len` bytes after decompression.
+#[cfg(feature = "gzip")]
+pub(crate) fn gzip_unpack(packed_data: &[u8], max_unpacked_len: usize) -> error::Result<Vec<u8>> {
+    let unpacked_data = gzip_unpack_truncated(packed_data, max_unpacked_len)?;
+
+    if unpacked_data.len() > max_unpacked_len {
+        bail!(DeErrorKind::UnpackedLenLimitExceeded(max_unpacked_len));
+    }
+
+    Ok(unpacked_data)
+}
+
+/// Decompress at most `max_len + 1` bytes of gzip-packed data, so that the
+/// caller can tell whether it's longer than `max_len`.
+#[cfg(feature = "gzip")]
+pub(crate) fn gzip_unpack_truncated(packed_data: &[u8], max_len: usize) -> error::Result<Vec<u8>> {
+    let mut unpacked_data = Vec::new();
+    let limit = safe_uint_cast::<usize, u64>(max_len)?.saturating_add(1);
+    let _ = GzDecoder::new(packed_data).take(limit).read_to_end(&mut unpacked_data)?;
+
+    Ok(unpacked_data)
+}
+
+
+/// Deserializes an enum restricted to a single variant which is already
+/// known from the type id.
+struct EnumVariantSeed<T> {
+    variant: &'static str,
+    phantom: PhantomData<T>,
+}
+
+impl<T> EnumVariantSeed<T> {
+    fn new(variant: &'static str) -> EnumVariantSeed<T> {
+        EnumVariantSeed { variant, phantom: PhantomData }
+    }
+}
+
+impl<'de, T: Deserialize<'de>> DeserializeSeed<'de> for EnumVariantSeed<T> {
+    type Value = T;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<T, D::Error>
+        where D: Deserializer<'de>
+    {
+        T::deserialize(EnumVariantDeserializer { inner: deserializer, variant: self.variant })
+    }
+}
+
+/// A deserializer which forwards everything to the wrapped one except that
+/// the list of enum variants is narrowed down to the known variant.
+///
+/// MTProto deserializer selects the variant automatically when there is only
+/// one to choose from, other formats ignore the list (except for error
+/// messages) since they store variant names in data.
+struct EnumVariantDeserializer<D> {
+    inner: D,
+    variant: &'static str,
+}
+
+macro_rules! forward_to_inner_deserializer {
+    ($($method:ident($($arg:ident: $arg_ty:ty),*);)*) => {
+        $(
+            fn $method<V>(self, $($arg: $arg_ty,)* visitor: V) -> Result<V::Value, D::Error>
+                where V: Visitor<'de>
+            {
+                self.inner.$method($($arg,)* visitor)
+            }
+        )*
+    };
+}
+
+impl<'de, D: Deserializer<'de>> Deserializer<'de> for EnumVariantDeserializer<D> {
+    type Error = D::Error;
+
+    forward_to_inner_deserializer! {
+        deserialize_any();
+        deserialize_bool();
+        deserialize_i8();
+        deserialize_i16();
+        deserialize_i32();
+        deserialize_i64();
+        deserialize_i128();
+        deserialize_u8();
+        deserialize_u16();
+        deserialize_u32();
+        deserialize_u64();
+        deserialize_u128();
+        deserialize_f32();
+        deserialize_f64();
+        deserialize_char();
+        deserialize_str();
+        deserialize_string();
+        deserialize_bytes();
+        deserialize_byte_buf();
+        deserialize_option();
+        deserialize_unit();
+        deserialize_unit_struct(name: &'static str);
+        deserialize_newtype_struct(name: &'static str);
+        deserialize_seq();
+        deserialize_tuple(len: usize);
+        deserialize_tuple_struct(name: &'static str, len: usize);
+        deserialize_map();
+        deserialize_struct(name: &'static str, fields: &'static [&'static str]);
+        deserialize_identifier();
+        deserialize_ignored_any();
+    }
+
+    fn deserialize_enum<V>(self,
+                           name: &'static str,
+                           variants: &'static [&'static str],
+                           visitor: V)
+                          -> Result<V::Value, D::Error>
+        where V: Visitor<'de>
+    {
+        let variant = self.variant;
+        let narrowed_variants = variants.iter()
+            .position(|v| *v == variant)
+            .and_then(|index| variants.get(index..=index))
+            .unwrap_or(variants);
+
+        self.inner.deserialize_enum(name, narrowed_variants, visitor)
+    }
+
+    fn is_human_readable(&self) -> bool {
+        self.inner.is_human_readable()
+    }
+}
+
 
 fn next_seq_element<'de, T, A>(seq: &mut A,
                                deserialized_count: u32,
@@ -0,0 +1,65 @@
+//! Helpers shared by integration tests.
+
+#![allow(dead_code)]
+
+use std::fmt;
+
+use serde_mtproto::error::{
+    CryptoErrorKind, DeErrorKind, ErrorKind, HandshakeErrorKind, MessageIdErrorKind, RsaErrorKind, SerErrorKind,
+    TransportErrorKind,
+};
+
+
+/// Kind of errors wrapped by one of `ErrorKind` variants.
+pub trait SubErrorKind: fmt::Debug + PartialEq {
+    /// Return the wrapped kind if `kind` is of this type.
+    fn from_error_kind(kind: &ErrorKind) -> Option<&Self>;
+}
+
+macro_rules! impl_sub_error_kind {
+    ($($variant:ident => $kind:ty,)*) => {
+        $(
+            impl SubErrorKind for $kind {
+                fn from_error_kind(kind: &ErrorKind) -> Option<&$kind> {
+                    match *kind {
+                        ErrorKind::$variant(ref kind) => Some(kind),
+                        _ => None,
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_sub_error_kind! {
+    Ser       => SerErrorKind,
+    De        => DeErrorKind,
+    Transport => TransportErrorKind,
+    Crypto    => CryptoErrorKind,
+    Handshake => HandshakeErrorKind,
+    Rsa       => RsaErrorKind,
+    MessageId => MessageIdErrorKind,
+}
+
+
+/// Check that `result` is an error of `expected_kind`.
+pub fn assert_error_kind<T, K>(result: serde_mtproto::Result<T>, expected_kind: K)
+    where T: fmt::Debug,
+          K: SubErrorKind,
+{
+    let error = result.unwrap_err();
+
+    match K::from_error_kind(error.kind()) {
+        Some(kind) => assert_eq!(*kind, expected_kind),
+        None => panic!("unexpected error kind: {:?}", error.kind()),
+    }
+}
+
+/// Check that `result` is an error of `expected_kind` raised by a wrapper,
+/// which reaches the caller through `de::Error::custom`.
+pub fn assert_de_error_msg<T: fmt::Debug>(result: serde_mtproto::Result<T>, expected_kind: DeErrorKind) {
+    match *result.unwrap_err().kind() {
+        ErrorKind::De(DeErrorKind::Msg(ref msg)) => assert!(msg.contains(&expected_kind.to_string())),
+        ref kind => panic!("unexpected error kind: {:?}", kind),
+    }
+}
@@ -0,0 +1,139 @@
+//! Testing MTProto 2.0 message encryption.
+
+mod common;
+
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use serde_derive::{Serialize, Deserialize};
+use serde_mtproto::{Boxed, Int128};
+use serde_mtproto::crypto::{self, AuthKey, PlainMessage, Side};
+use serde_mtproto::error::CryptoErrorKind;
+use serde_mtproto_derive::{MtProtoIdentifiable, MtProtoSized};
+
+use crate::common::assert_error_kind;
+
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, MtProtoIdentifiable, MtProtoSized)]
+#[mtproto_identifiable(id = "0x7abe77ec")]
+struct Ping {
+    ping_id: i64,
+}
+
+#[derive(Debug, Deserialize)]
+struct Envelope {
+    auth_key_id: u64,
+    msg_key: Int128,
+}
+
+
+fn auth_key() -> AuthKey {
+    let mut bytes = [0; 256];
+    for (i, b) in bytes.iter_mut().enumerate() {
+        *b = (i * 7 + 3) as u8;
+    }
+
+    AuthKey::new(bytes)
+}
+
+fn message() -> PlainMessage<Boxed<Ping>> {
+    PlainMessage {
+        salt: 0x0123_4567_89ab_cdef,
+        session_id: -2,
+        message_id: 0x5e0b_8000_0000_0004,
+        seq_no: 3,
+        body: Boxed::new(Ping { ping_id: 0x1122_3344 }),
+    }
+}
+
+fn decrypt(auth_key: &AuthKey, side: Side, envelope: &[u8]) -> serde_mtproto::Result<PlainMessage<Boxed<Ping>>> {
+    crypto::decrypt_message(auth_key, side, envelope, &[])
+}
+
+
+#[test]
+fn test_auth_key_id() {
+    let auth_key = AuthKey::new([0; 256]);
+    // Lower 64 bits of SHA-1 of 256 zero bytes
+    assert_eq!(auth_key.id(), 0x919b_0d57_fd0b_08b1);
+    assert_eq!(auth_key.as_bytes(), &[0; 256][..]);
+
+    assert_error_kind(AuthKey::from_slice(&[0; 255]), CryptoErrorKind::InvalidAuthKeyLen(255));
+    assert_eq!(AuthKey::from_slice(&[0; 256]).unwrap().id(), auth_key.id());
+}
+
+#[test]
+fn test_roundtrip() {
+    let auth_key = auth_key();
+
+    let from_client = crypto::encrypt_message(&auth_key, Side::Client, &message()).unwrap();
+    assert_eq!(decrypt(&auth_key, Side::Server, &from_client).unwrap(), message());
+
+    let from_server = crypto::encrypt_message(&auth_key, Side::Server, &message()).unwrap();
+    assert_eq!(decrypt(&auth_key, Side::Client, &from_server).unwrap(), message());
+}
+
+#[test]
+fn test_envelope_layout() {
+    let auth_key = auth_key();
+    let envelope = crypto::encrypt_message(&auth_key, Side::Client, &message()).unwrap();
+
+    let header: Envelope = serde_mtproto::from_bytes(&envelope, &[]).unwrap();
+    assert_eq!(header.auth_key_id, auth_key.id());
+    assert_ne!(header.msg_key, Int128::from(0_u128));
+
+    // 32 bytes of header, 12 bytes of body and 12..=1024 bytes of padding
+    let encrypted_len = envelope.len() - 24;
+    assert_eq!(encrypted_len % 16, 0);
+    assert!((32 + 12 + 12..=32 + 12 + 1024).contains(&encrypted_len));
+}
+
+#[test]
+fn test_deterministic_with_rng() {
+    let auth_key = auth_key();
+    let encrypt = |seed| {
+        crypto::encrypt_message_with_rng(&auth_key, Side::Client, &message(), &mut StdRng::seed_from_u64(seed))
+            .unwrap()
+    };
+
+    assert_eq!(encrypt(1), encrypt(1));
+    assert_ne!(encrypt(1), encrypt(2));
+}
+
+#[test]
+fn test_wrong_side() {
+    let auth_key = auth_key();
+    let envelope = crypto::encrypt_message(&auth_key, Side::Client, &message()).unwrap();
+
+    assert_error_kind(decrypt(&auth_key, Side::Client, &envelope), CryptoErrorKind::MsgKeyMismatch);
+}
+
+#[test]
+fn test_tampered() {
+    let auth_key = auth_key();
+    let envelope = crypto::encrypt_message(&auth_key, Side::Client, &message()).unwrap();
+
+    let mut bad_msg_key = envelope.clone();
+    bad_msg_key[8] ^= 1;
+    assert_error_kind(decrypt(&auth_key, Side::Server, &bad_msg_key), CryptoErrorKind::MsgKeyMismatch);
+
+    let mut bad_data = envelope.clone();
+    *bad_data.last_mut().unwrap() ^= 1;
+    assert_error_kind(decrypt(&auth_key, Side::Server, &bad_data), CryptoErrorKind::MsgKeyMismatch);
+
+    assert_error_kind(
+        decrypt(&auth_key, Side::Server, &envelope[..envelope.len() - 1]),
+        CryptoErrorKind::InvalidEncryptedDataLen(envelope.len() - 25));
+    assert_error_kind(
+        decrypt(&auth_key, Side::Server, &envelope[..20]),
+        CryptoErrorKind::InvalidEncryptedDataLen(20));
+}
+
+#[test]
+fn test_auth_key_id_mismatch() {
+    let envelope = crypto::encrypt_message(&auth_key(), Side::Client, &message()).unwrap();
+    let other_key = AuthKey::new([0; 256]);
+
+    assert_error_kind(
+        decrypt(&other_key, Side::Server, &envelope),
+        CryptoErrorKind::AuthKeyIdMismatch(other_key.id(), auth_key().id()));
+}
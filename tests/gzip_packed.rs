@@ -0,0 +1,177 @@
+//! Testing `GzipPacked` wrapper.
+
+mod common;
+
+use std::io::Write;
+
+use flate2::{Compression, write::GzEncoder};
+use serde::Deserialize;
+use serde_derive::{Serialize, Deserialize};
+use serde_mtproto::{Boxed, GzipPacked, GzipPackedSeed, MtProtoSized};
+use serde_mtproto::de::{Deserializer, DeserializerOptions};
+use serde_mtproto::error::{DeErrorKind, ErrorKind};
+use serde_mtproto::identifiable::GZIP_PACKED_ID;
+use serde_mtproto_derive::{MtProtoIdentifiable, MtProtoSized};
+
+use crate::common::assert_error_kind;
+
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, MtProtoIdentifiable, MtProtoSized)]
+#[mtproto_identifiable(id = "0x3ded6320")]
+struct Messages {
+    ids: Vec<i32>,
+    text: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, MtProtoIdentifiable, MtProtoSized)]
+enum Update {
+    #[mtproto_identifiable(id = "0x1f2b0afd")]
+    NewMessage {
+        id: i32,
+    },
+    #[mtproto_identifiable(id = "0x1710f156")]
+    Empty,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, MtProtoIdentifiable, MtProtoSized)]
+#[mtproto_identifiable(id = "0x74ae4240")]
+struct Updates {
+    update: Update,
+    date: i32,
+}
+
+
+fn messages() -> Messages {
+    Messages {
+        ids: (0..100).collect(),
+        text: "lorem ipsum ".repeat(20),
+    }
+}
+
+fn pack(boxed: &[u8]) -> Vec<u8> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(boxed).unwrap();
+    let packed_data = encoder.finish().unwrap();
+
+    let mut bytes = serde_mtproto::to_bytes(&GZIP_PACKED_ID).unwrap();
+    bytes.extend(serde_mtproto::to_bytes(&serde_mtproto::ByteBuf::from(packed_data)).unwrap());
+    bytes
+}
+
+
+#[test]
+fn test_gzip_packed_de_packed() {
+    let boxed = serde_mtproto::to_bytes(&Boxed::new(messages())).unwrap();
+    let bytes = pack(&boxed);
+
+    let value: GzipPacked<Messages> = serde_mtproto::from_bytes(&bytes, &[]).unwrap();
+    assert_eq!(value.into_inner(), messages());
+}
+
+#[test]
+fn test_gzip_packed_de_unpacked() {
+    let boxed = serde_mtproto::to_bytes(&Boxed::new(messages())).unwrap();
+
+    let value: GzipPacked<Messages> = serde_mtproto::from_bytes(&boxed, &[]).unwrap();
+    assert_eq!(value.into_inner(), messages());
+}
+
+#[test]
+fn test_gzip_packed_de_enum() {
+    let update = Update::NewMessage { id: 42 };
+    let boxed = serde_mtproto::to_bytes(&Boxed::new(&update)).unwrap();
+
+    let unpacked: GzipPacked<Update> = serde_mtproto::from_bytes(&boxed, &[]).unwrap();
+    assert_eq!(*unpacked.inner(), update);
+
+    let packed: GzipPacked<Update> = serde_mtproto::from_bytes(&pack(&boxed), &[]).unwrap();
+    assert_eq!(packed.into_inner(), update);
+}
+
+#[test]
+fn test_gzip_packed_ser_roundtrip() {
+    let unpacked = GzipPacked::new(messages());
+    let unpacked_bytes = serde_mtproto::to_bytes(&unpacked).unwrap();
+    assert_eq!(unpacked_bytes, serde_mtproto::to_bytes(&Boxed::new(messages())).unwrap());
+    assert_eq!(unpacked.size_hint().unwrap(), unpacked_bytes.len());
+
+    let packed = GzipPacked::with_compression_threshold(messages(), 64).unwrap();
+    let packed_bytes = serde_mtproto::to_bytes(&packed).unwrap();
+    assert_eq!(packed_bytes[0..4], serde_mtproto::to_bytes(&GZIP_PACKED_ID).unwrap()[..]);
+    assert!(packed_bytes.len() < unpacked_bytes.len());
+    assert_eq!(packed.size_hint().unwrap(), packed_bytes.len());
+
+    let value: GzipPacked<Messages> = serde_mtproto::from_bytes(&packed_bytes, &[]).unwrap();
+    assert_eq!(value.into_inner(), messages());
+}
+
+#[test]
+fn test_gzip_packed_ser_below_threshold() {
+    let packed = GzipPacked::with_compression_threshold(Update::Empty, 64).unwrap();
+
+    assert_eq!(
+        serde_mtproto::to_bytes(&packed).unwrap(),
+        serde_mtproto::to_bytes(&Boxed::new(Update::Empty)).unwrap());
+}
+
+#[test]
+fn test_gzip_packed_max_unpacked_len() {
+    let boxed = serde_mtproto::to_bytes(&Boxed::new(messages())).unwrap();
+    let bytes = pack(&boxed);
+
+    let seed = GzipPackedSeed::<Messages>::new(boxed.len());
+    assert!(serde_mtproto::from_bytes_seed(seed, &bytes, &[]).is_ok());
+
+    let seed = GzipPackedSeed::<Messages>::new(boxed.len() - 1);
+    assert_error_kind(
+        serde_mtproto::from_bytes_seed(seed, &bytes, &[]),
+        DeErrorKind::UnpackedLenLimitExceeded(boxed.len() - 1));
+}
+
+#[test]
+fn test_gzip_packed_de_options() {
+    let boxed = serde_mtproto::to_bytes(&Boxed::new(messages())).unwrap();
+    let bytes = pack(&boxed);
+
+    let options = DeserializerOptions::new().max_vector_len(99);
+    let mut de = Deserializer::with_options(&bytes[..], &[], options);
+    let err = GzipPacked::<Messages>::deserialize(&mut de).unwrap_err();
+
+    match *err.kind() {
+        ErrorKind::De(DeErrorKind::SeqLenLimitExceeded(100, 99)) => (),
+        ref kind => panic!("unexpected error kind: {:?}", kind),
+    }
+    assert_eq!(err.de_context().unwrap().path(), "Messages.ids");
+    // Offsets continue past the packed data into the unpacked one
+    assert_eq!(err.de_context().unwrap().offset(), bytes.len() as u64 + 8);
+}
+
+#[test]
+fn test_gzip_packed_de_max_total_bytes() {
+    let boxed = serde_mtproto::to_bytes(&Boxed::new(messages())).unwrap();
+    let bytes = pack(&boxed);
+    assert!(bytes.len() < boxed.len());
+
+    // Both the packed and the unpacked bytes count toward the limit
+    let total_bytes = (bytes.len() + boxed.len()) as u64;
+    let options = DeserializerOptions::new().max_total_bytes(total_bytes);
+    let mut de = Deserializer::with_options(&bytes[..], &[], options);
+    assert_eq!(GzipPacked::<Messages>::deserialize(&mut de).unwrap().into_inner(), messages());
+
+    for &max_total_bytes in &[total_bytes - 1, boxed.len() as u64] {
+        let options = DeserializerOptions::new().max_total_bytes(max_total_bytes);
+        let mut de = Deserializer::with_options(&bytes[..], &[], options);
+        assert_error_kind(
+            GzipPacked::<Messages>::deserialize(&mut de),
+            DeErrorKind::TotalBytesLimitExceeded(max_total_bytes));
+    }
+}
+
+#[test]
+fn test_gzip_packed_de_enum_variant_hint() {
+    let updates = Updates { update: Update::NewMessage { id: 42 }, date: 1 };
+    let boxed = serde_mtproto::to_bytes(&Boxed::new(&updates)).unwrap();
+
+    let value: GzipPacked<Updates> = serde_mtproto::from_bytes(&pack(&boxed), &["NewMessage"]).unwrap();
+    assert_eq!(value.into_inner(), updates);
+}
@@ -0,0 +1,329 @@
+//! Testing the authorization key exchange against a stand-in server.
+
+mod common;
+
+use std::time::Duration;
+
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+use num_bigint::BigUint;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use serde::Serialize;
+use serde_mtproto::{
+    Boxed, ByteBuf, Identifiable, MtProtoSized, UnencryptedMessage, from_bytes, from_bytes_reuse, to_bytes,
+};
+use serde_mtproto::crypto::{AuthKey, aes_ige_decrypt, aes_ige_encrypt};
+use serde_mtproto::error::HandshakeErrorKind;
+use serde_mtproto::handshake::{
+    ClientDhInnerData, Handshake, PQInnerDataDc, ReqDhParams, ReqPqMulti, ResPq,
+    ServerDhInnerData, ServerDhParams, SetClientDhParams, SetClientDhParamsAnswer, Step,
+    new_nonce_hash, server_salt, tmp_aes_key_iv,
+};
+use serde_mtproto::ints::{Int128, Int256};
+use serde_mtproto::message_id::{MessageIdGenerator, MessageIdKind};
+use serde_mtproto::rsa::{self, RsaPrivateKey};
+use sha1::Sha1;
+use sha2::Digest;
+
+use crate::common::assert_error_kind;
+
+
+/// A throwaway RSA key generated for these tests, the same as in `tests/rsa.rs`.
+const TEST_KEY_N: &[u8] = b"\
+    c277b5fc686f15dd34199f0fdcef01bfeca62e9e6a0acca40ebed932d9aefb1e\
+    ec7dcdb0980972307cc6e476ba713c096aa03c5d36c0c0921628f724ad20f6a9\
+    2152250dff849e1a1f339c8bddf68a5e6c3ecb7bde8f8ba11cbfc3413580f715\
+    2f1dc99962775ab1c9b990eff145f2783f30dedabf9f49d3c5f62753c05156b9\
+    f5b8c011ab54b17395390bce609a4169337618fc1e73e1dbe64b3a33f46638b0\
+    c3d69506c3ddfc9a7c748228276a332b31fee258ba9e62eb8376ce855ef680e9\
+    88d0cbd2c91f0eb5485b1ffb3812f47384b7633fbbed6d50885f5b1cef8f0be5\
+    ade4a5d4dc8c738ec93cf599d2bc27941ea159870011f7f08b4a195c6c258c93";
+const TEST_KEY_D: &[u8] = b"\
+    2c8b2624c43919ad7ae7197f95302bdaedd65cdc4d56deebff32a8e6946517c1\
+    4c133edfa1db9c0bce30f298dd78dd731cb8ca4ae1a54216d43b9aec8c397e36\
+    5c5b0800ce53eeb0f60fa8de82bf9b8f284533efd2195836d5b2790381416297\
+    381d1f15e2af8b17292937b136373ae45f390efad524a3d0fdc9b5d561ba5fde\
+    3f2a8fc1c5c3f481328e46cb47eb290c68eafe680f09ce83dddcb424e558c6c5\
+    599bcc50ff9856ae558b591876fa6cd445468105018d42a1e87b80551e78a76e\
+    b960cbc37fe08634d2cf94606726874f897e7201f436e74f59de6abf6639633c\
+    15ac607f3a0b5e8fd6dfe21ac32d9079572bc34f39dbd4df19044a3442158515";
+const TEST_KEY_E: &[u8] = &[0x01, 0x00, 0x01];
+const TEST_KEY_FINGERPRINT: u64 = 0xb8e937276b8afc9e;
+
+const DH_PRIME: &[u8] = b"\
+    c71caeb9c6b1c9048e6c522f70f13f73980d40238e3e21c14934d037563d930f\
+    48198a0aa7c14058229493d22530f4dbfa336f6e0ac925139543aed44cce7c37\
+    20fd51f69458705ac68cd4fe6b6b13abdc9746512969328454f18faf8c595f64\
+    2477fe96bb2a941d5bcd1d4ac8cc49880708fa9b378e3c4f3a9060bee67cf9a4\
+    a4a695811051907e162753b56b0f6b410dba74d8a84b2a14b3144e0ef1284754\
+    fd17ed950d5965b4b9dd46582db1178d169c6bc465b0d6ff9ca3928fef5b9ae4\
+    e418fc15e83ebea0f87fa9ff5eed70050ded2849f47bf959d956850ce929851f\
+    0d8115f635b105ee2e4e15d04b2454bf6f4fadf034b10403119cd8e3b92fcc5b";
+
+/// The example from <https://core.telegram.org/mtproto/samples-auth_key>.
+const PQ: u64 = 0x17ed48941a08f981;
+const P: u64 = 0x494c553b;
+const Q: u64 = 0x53911073;
+
+const CLIENT_TIME: u64 = 1_500_000_000;
+const SERVER_TIME: i32 = 1_500_000_042;
+const DC_ID: i32 = 10_002;
+
+
+fn server_clock() -> Duration {
+    Duration::from_secs(SERVER_TIME as u64)
+}
+
+fn biguint(hex: &[u8]) -> BigUint {
+    BigUint::parse_bytes(hex, 16).unwrap()
+}
+
+fn test_key() -> RsaPrivateKey {
+    RsaPrivateKey::new(&biguint(TEST_KEY_N).to_bytes_be(), TEST_KEY_E, &biguint(TEST_KEY_D).to_bytes_be()).unwrap()
+}
+
+fn new_handshake() -> Handshake<impl Fn() -> Duration> {
+    Handshake::with_clock(vec![test_key().public_key().clone()], DC_ID, || Duration::from_secs(CLIENT_TIME))
+}
+
+fn be_bytes_padded(value: &BigUint) -> Vec<u8> {
+    let bytes = value.to_bytes_be();
+    let mut padded = vec![0; 256 - bytes.len()];
+    padded.extend_from_slice(&bytes);
+    padded
+}
+
+/// The server side of the exchange with the values the client must send.
+struct TestServer {
+    rng: StdRng,
+    message_ids: MessageIdGenerator<fn() -> Duration>,
+    fingerprints: Vec<i64>,
+    g: i32,
+    retries: usize,
+    nonce: Int128,
+    server_nonce: Int128,
+    new_nonce: Int256,
+    a: BigUint,
+    auth_key: Option<AuthKey>,
+}
+
+impl TestServer {
+    fn new() -> TestServer {
+        TestServer {
+            rng: StdRng::seed_from_u64(0x5e4e4),
+            message_ids: MessageIdGenerator::with_clock(server_clock),
+            fingerprints: vec![0x1234, TEST_KEY_FINGERPRINT as i64],
+            g: 3,
+            retries: 0,
+            nonce: Int128::default(),
+            server_nonce: Int128::default(),
+            new_nonce: Int256::default(),
+            a: BigUint::default(),
+            auth_key: None,
+        }
+    }
+
+    fn respond<T>(&mut self, body: T) -> Vec<u8>
+        where T: Serialize + Identifiable + MtProtoSized
+    {
+        let message_id = self.message_ids.next_id(MessageIdKind::Response);
+        to_bytes(&UnencryptedMessage::new(message_id, body)).unwrap()
+    }
+
+    fn handle_req_pq_multi(&mut self, request: &[u8]) -> Vec<u8> {
+        let request: ReqPqMulti = from_bytes::<UnencryptedMessage<_>>(request, &[]).unwrap().into_inner();
+        self.nonce = request.nonce;
+        self.server_nonce = Int128::new(self.rng.gen());
+
+        let mut pq = [0; 8];
+        BigEndian::write_u64(&mut pq, PQ);
+
+        let res_pq = ResPq {
+            nonce: self.nonce,
+            server_nonce: self.server_nonce,
+            pq: ByteBuf::from(pq.to_vec()),
+            server_public_key_fingerprints: Boxed::new(self.fingerprints.clone()),
+        };
+        self.respond(res_pq)
+    }
+
+    fn handle_req_dh_params(&mut self, request: &[u8]) -> Vec<u8> {
+        let request: ReqDhParams = from_bytes::<UnencryptedMessage<_>>(request, &[]).unwrap().into_inner();
+        assert_eq!((request.nonce, request.server_nonce), (self.nonce, self.server_nonce));
+        assert_eq!(request.public_key_fingerprint, TEST_KEY_FINGERPRINT as i64);
+        assert_eq!(BigUint::from_bytes_be(&request.p), BigUint::from(P));
+        assert_eq!(BigUint::from_bytes_be(&request.q), BigUint::from(Q));
+
+        let inner_data = rsa::decrypt_pad(&test_key(), &request.encrypted_data).unwrap();
+        let (inner_data, _) = from_bytes_reuse::<Boxed<PQInnerDataDc>>(&inner_data, &[]).unwrap();
+        let inner_data = inner_data.into_inner();
+        assert_eq!((inner_data.nonce, inner_data.server_nonce), (self.nonce, self.server_nonce));
+        assert_eq!((inner_data.p, inner_data.q), (request.p, request.q));
+        assert_eq!(BigEndian::read_u64(&inner_data.pq), PQ);
+        assert_eq!(inner_data.dc, DC_ID);
+        self.new_nonce = inner_data.new_nonce;
+
+        let mut a = [0; 256];
+        self.rng.fill(&mut a[..]);
+        self.a = BigUint::from_bytes_be(&a);
+        let g_a = BigUint::from(self.g as u32).modpow(&self.a, &biguint(DH_PRIME));
+
+        let answer = ServerDhInnerData {
+            nonce: self.nonce,
+            server_nonce: self.server_nonce,
+            g: self.g,
+            dh_prime: ByteBuf::from(biguint(DH_PRIME).to_bytes_be()),
+            g_a: ByteBuf::from(g_a.to_bytes_be()),
+            server_time: SERVER_TIME,
+        };
+        let answer = to_bytes(&Boxed::new(answer)).unwrap();
+
+        let mut answer_with_hash = Sha1::digest(&answer).to_vec();
+        answer_with_hash.extend_from_slice(&answer);
+        while answer_with_hash.len() % 16 != 0 {
+            answer_with_hash.push(self.rng.gen());
+        }
+        let (tmp_aes_key, tmp_aes_iv) = tmp_aes_key_iv(&self.server_nonce, &self.new_nonce);
+        aes_ige_encrypt(&tmp_aes_key, &tmp_aes_iv, &mut answer_with_hash);
+
+        let server_dh_params = ServerDhParams::Ok {
+            nonce: self.nonce,
+            server_nonce: self.server_nonce,
+            encrypted_answer: ByteBuf::from(answer_with_hash),
+        };
+        self.respond(server_dh_params)
+    }
+
+    fn handle_set_client_dh_params(&mut self, request: &[u8], retry_id: i64) -> Vec<u8> {
+        let request: SetClientDhParams = from_bytes::<UnencryptedMessage<_>>(request, &[]).unwrap().into_inner();
+        assert_eq!((request.nonce, request.server_nonce), (self.nonce, self.server_nonce));
+
+        let mut data_with_hash = request.encrypted_data.to_vec();
+        let (tmp_aes_key, tmp_aes_iv) = tmp_aes_key_iv(&self.server_nonce, &self.new_nonce);
+        aes_ige_decrypt(&tmp_aes_key, &tmp_aes_iv, &mut data_with_hash);
+
+        let (hash, data) = data_with_hash.split_at(20);
+        let (inner_data, padding) = from_bytes_reuse::<Boxed<ClientDhInnerData>>(data, &[]).unwrap();
+        assert_eq!(*hash, *Sha1::digest(&data[..data.len() - padding.len()]));
+
+        let inner_data = inner_data.into_inner();
+        assert_eq!((inner_data.nonce, inner_data.server_nonce), (self.nonce, self.server_nonce));
+        assert_eq!(inner_data.retry_id, retry_id);
+
+        let g_b = BigUint::from_bytes_be(&inner_data.g_b);
+        let auth_key = AuthKey::from_slice(&be_bytes_padded(&g_b.modpow(&self.a, &biguint(DH_PRIME)))).unwrap();
+
+        let answer = if self.retries > 0 {
+            self.retries -= 1;
+
+            SetClientDhParamsAnswer::Retry {
+                nonce: self.nonce,
+                server_nonce: self.server_nonce,
+                new_nonce_hash2: new_nonce_hash(&self.new_nonce, 2, &auth_key),
+            }
+        } else {
+            SetClientDhParamsAnswer::Ok {
+                nonce: self.nonce,
+                server_nonce: self.server_nonce,
+                new_nonce_hash1: new_nonce_hash(&self.new_nonce, 1, &auth_key),
+            }
+        };
+
+        self.auth_key = Some(auth_key);
+        self.respond(answer)
+    }
+}
+
+fn expect_send(step: Step) -> Vec<u8> {
+    match step {
+        Step::Send(request) => request,
+        Step::Done(authorization) => panic!("unexpected end of handshake: {:?}", authorization),
+    }
+}
+
+
+#[test]
+fn test_handshake() {
+    let mut rng = StdRng::seed_from_u64(0xc11e);
+    let mut server = TestServer::new();
+    server.retries = 1;
+    let mut handshake = new_handshake();
+
+    let request = handshake.start_with_rng(&mut rng).unwrap();
+    let response = server.handle_req_pq_multi(&request);
+
+    let request = expect_send(handshake.handle_with_rng(&response, &mut rng).unwrap());
+    let response = server.handle_req_dh_params(&request);
+
+    let request = expect_send(handshake.handle_with_rng(&response, &mut rng).unwrap());
+    let response = server.handle_set_client_dh_params(&request, 0);
+    let retried_auth_key = server.auth_key.clone().unwrap();
+
+    // `dh_gen_retry` makes the client generate another `b`
+    let request = expect_send(handshake.handle_with_rng(&response, &mut rng).unwrap());
+    let response = server.handle_set_client_dh_params(&request, {
+        LittleEndian::read_i64(&Sha1::digest(retried_auth_key.as_bytes())[0..8])
+    });
+
+    let authorization = match handshake.handle_with_rng(&response, &mut rng).unwrap() {
+        Step::Done(authorization) => authorization,
+        Step::Send(_) => panic!("unexpected request"),
+    };
+
+    let server_auth_key = server.auth_key.unwrap();
+    assert_eq!(authorization.auth_key.as_bytes(), server_auth_key.as_bytes());
+    assert_ne!(authorization.auth_key.as_bytes(), retried_auth_key.as_bytes());
+    assert_eq!(authorization.server_salt, server_salt(&server.server_nonce, &server.new_nonce));
+    assert_eq!(authorization.time_offset, 42);
+
+    assert_error_kind(handshake.handle(&response), HandshakeErrorKind::UnexpectedMessage);
+}
+
+#[test]
+fn test_unexpected_message() {
+    let mut server = TestServer::new();
+    let mut handshake = new_handshake();
+
+    let request = ReqPqMulti { nonce: Int128::default() };
+    let response = server.handle_req_pq_multi(&to_bytes(&UnencryptedMessage::new(4, request)).unwrap());
+    assert_error_kind(handshake.handle(&response), HandshakeErrorKind::UnexpectedMessage);
+}
+
+#[test]
+fn test_nonce_mismatch() {
+    let mut server = TestServer::new();
+    let mut handshake = new_handshake();
+
+    let response = server.handle_req_pq_multi(&handshake.start().unwrap());
+    let forged_request = ReqPqMulti { nonce: Int128::new([1; 16]) };
+    let forged_response = server.handle_req_pq_multi(&to_bytes(&UnencryptedMessage::new(4, forged_request)).unwrap());
+    assert_error_kind(handshake.handle(&forged_response), HandshakeErrorKind::NonceMismatch);
+
+    // An error aborts the handshake, but it can be restarted
+    assert_error_kind(handshake.handle(&response), HandshakeErrorKind::UnexpectedMessage);
+    let response = server.handle_req_pq_multi(&handshake.start().unwrap());
+    expect_send(handshake.handle(&response).unwrap());
+}
+
+#[test]
+fn test_unknown_fingerprints() {
+    let mut server = TestServer::new();
+    server.fingerprints = vec![1, 2];
+    let mut handshake = new_handshake();
+
+    let response = server.handle_req_pq_multi(&handshake.start().unwrap());
+    assert_error_kind(handshake.handle(&response), HandshakeErrorKind::UnknownFingerprints(vec![1, 2]));
+}
+
+#[test]
+fn test_invalid_g() {
+    let mut server = TestServer::new();
+    // The DH prime yields 3 modulo 8, so 2 is not a quadratic residue
+    server.g = 2;
+    let mut handshake = new_handshake();
+
+    let response = server.handle_req_pq_multi(&handshake.start().unwrap());
+    let request = expect_send(handshake.handle(&response).unwrap());
+    let response = server.handle_req_dh_params(&request);
+    assert_error_kind(handshake.handle(&response), HandshakeErrorKind::InvalidG(2));
+}
@@ -0,0 +1,89 @@
+//! Testing layer ranges and `invokeWithLayer`/`initConnection` wrappers.
+
+use pretty_assertions::assert_eq;
+use serde_mtproto::{Boxed, Flags, Identifiable, MtProtoSized, from_bytes, to_bytes};
+use serde_mtproto::layer::{InitConnection, InputClientProxy, InvokeWithLayer, LayerRange};
+use serde_mtproto::rpc::{call_to_bytes, return_from_bytes};
+use serde_mtproto::service::{GetFutureSalts, Ping, Pong};
+
+
+#[test]
+fn test_layer_range() {
+    let range = LayerRange::new(Some(23), Some(44));
+    assert!(!range.contains(22));
+    assert!(range.contains(23));
+    assert!(range.contains(44));
+    assert!(!range.contains(45));
+    assert_eq!(range.to_string(), "23..=44");
+
+    assert!(LayerRange::new(Some(45), None).contains(1_000));
+    assert_eq!(LayerRange::new(Some(45), None).to_string(), "45..");
+    assert!(LayerRange::new(None, Some(44)).contains(-1_000));
+    assert_eq!(LayerRange::new(None, Some(44)).to_string(), "..=44");
+
+    assert_eq!(LayerRange::default(), LayerRange::all());
+    assert!(LayerRange::all().contains(0));
+    assert_eq!(LayerRange::all().to_string(), "..");
+}
+
+#[test]
+fn test_invoke_with_layer() {
+    let query = InvokeWithLayer::new(133, Ping { ping_id: 7 });
+    assert_eq!(query.query.inner(), &Ping { ping_id: 7 });
+
+    let bytes = call_to_bytes(&query).unwrap();
+    assert_eq!(bytes, vec![
+        0x0d, 0x0d, 0x9b, 0xda,         // id of invokeWithLayer
+        133, 0, 0, 0,                   // layer
+        0xec, 0x77, 0xbe, 0x7a,         // id of ping
+        7, 0, 0, 0, 0, 0, 0, 0,         // ping_id
+    ]);
+    assert_eq!(bytes.len(), 4 + query.size_hint().unwrap());
+
+    let deserialized: Boxed<InvokeWithLayer<Boxed<Ping>>> = from_bytes(&bytes, &[]).unwrap();
+    assert_eq!(deserialized.into_inner(), query);
+
+    let pong = Boxed::new(Pong { msg_id: 4, ping_id: 7 });
+    let returned = return_from_bytes::<InvokeWithLayer<Boxed<Ping>>>(&to_bytes(&pong).unwrap()).unwrap();
+    assert_eq!(returned, pong);
+}
+
+#[test]
+fn test_init_connection() {
+    // `Boxed<String>` stands for the boxed `JSONValue` from the API schema
+    let mut init_connection: InitConnection<_, Boxed<String>> = InitConnection::new(12345, GetFutureSalts { num: 2 });
+    init_connection.lang_code = "en".to_owned();
+
+    let bytes = to_bytes(&init_connection).unwrap();
+    assert_eq!(bytes, vec![
+        0, 0, 0, 0,                     // flags: no proxy and no params
+        0x39, 0x30, 0, 0,               // api_id
+        0, 0, 0, 0,                     // device_model
+        0, 0, 0, 0,                     // system_version
+        0, 0, 0, 0,                     // app_version
+        0, 0, 0, 0,                     // system_lang_code
+        0, 0, 0, 0,                     // lang_pack
+        2, b'e', b'n', 0,               // lang_code
+        0x04, 0xbd, 0x21, 0xb9,         // id of get_future_salts
+        2, 0, 0, 0,                     // num
+    ]);
+
+    init_connection.proxy = Some(Boxed::new(InputClientProxy { address: "proxy".to_owned(), port: 443 }));
+    init_connection.params = Some(Boxed::new("{}".to_owned()));
+    init_connection.flags = Flags::new();
+
+    let query = InvokeWithLayer::new(133, init_connection);
+    let bytes = call_to_bytes(&query).unwrap();
+    assert_eq!(&bytes[8..16], &[
+        0xa9, 0x5e, 0xcd, 0xc1,         // id of initConnection
+        0b11, 0, 0, 0,                  // flags: proxy and params
+    ]);
+    assert_eq!(bytes.len(), 4 + query.size_hint().unwrap());
+
+    type Query = InvokeWithLayer<Boxed<InitConnection<Boxed<GetFutureSalts>, Boxed<String>>>>;
+    let deserialized: Boxed<Query> = from_bytes(&bytes, &[]).unwrap();
+    assert_eq!(deserialized.into_inner(), query);
+
+    assert_eq!(InitConnection::<Boxed<Ping>>::all_type_ids(), &[0xc1cd5ea9]);
+    assert_eq!(InputClientProxy::all_type_ids(), &[0x75588b3f]);
+}
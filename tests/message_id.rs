@@ -0,0 +1,133 @@
+//! Testing message id generation and validation.
+
+mod common;
+
+use std::cell::Cell;
+use std::rc::Rc;
+use std::time::Duration;
+
+use serde_mtproto::error::MessageIdErrorKind;
+use serde_mtproto::message_id::{Clock, MessageIdGenerator, MessageIdKind, MessageIdValidator};
+use serde_mtproto::service::BadMsgNotification;
+
+use crate::common::assert_error_kind;
+
+
+const NOW_SECS: u64 = 1_500_000_000;
+
+
+fn fake_clock() -> (Rc<Cell<Duration>>, impl Clock) {
+    let time = Rc::new(Cell::new(Duration::from_secs(NOW_SECS)));
+    let clock_time = Rc::clone(&time);
+
+    (time, move || clock_time.get())
+}
+
+
+#[test]
+fn test_message_id_kind() {
+    assert_eq!(MessageIdKind::of(0x5e0b_8000_0000_0004), Some(MessageIdKind::Client));
+    assert_eq!(MessageIdKind::of(0x5e0b_8000_0000_0005), Some(MessageIdKind::Response));
+    assert_eq!(MessageIdKind::of(0x5e0b_8000_0000_0007), Some(MessageIdKind::Notification));
+    assert_eq!(MessageIdKind::of(0x5e0b_8000_0000_0006), None);
+    assert_eq!(MessageIdKind::of(0), None);
+    assert_eq!(MessageIdKind::of(-4), None);
+}
+
+#[test]
+fn test_next_id() {
+    let (time, clock) = fake_clock();
+    let mut generator = MessageIdGenerator::with_clock(clock);
+
+    // Half a second is half of 2^32
+    time.set(Duration::new(NOW_SECS, 500_000_000));
+    let first_id = generator.next_id(MessageIdKind::Client);
+    assert_eq!(first_id, (NOW_SECS as i64) << 32 | 0x8000_0000);
+
+    // Ids increase even if the clock doesn't
+    let second_id = generator.next_id(MessageIdKind::Client);
+    let response_id = generator.next_id(MessageIdKind::Response);
+    assert_eq!(second_id, first_id + 4);
+    assert_eq!(response_id, first_id + 9);
+
+    // ...or goes backwards
+    time.set(Duration::from_secs(NOW_SECS - 1));
+    assert_eq!(generator.next_id(MessageIdKind::Client), first_id + 12);
+
+    time.set(Duration::from_secs(NOW_SECS + 1));
+    assert_eq!(generator.next_id(MessageIdKind::Notification), ((NOW_SECS as i64 + 1) << 32) | 3);
+}
+
+#[test]
+fn test_time_offset() {
+    let (_, clock) = fake_clock();
+    let mut generator = MessageIdGenerator::with_clock(clock);
+
+    generator.set_time_offset(-10);
+    assert_eq!(generator.now(), Duration::from_secs(NOW_SECS - 10));
+    assert_eq!(generator.next_id(MessageIdKind::Client) >> 32, NOW_SECS as i64 - 10);
+
+    // The server is 25 seconds ahead
+    generator.correct_time_offset(((NOW_SECS as i64 + 25) << 32) | 1);
+    assert_eq!(generator.time_offset(), 25);
+    assert_eq!(generator.next_id(MessageIdKind::Client) >> 32, NOW_SECS as i64 + 25);
+}
+
+#[test]
+fn test_handle_bad_msg_notification() {
+    let (_, clock) = fake_clock();
+    let mut generator = MessageIdGenerator::with_clock(clock);
+    let server_message_id = ((NOW_SECS as i64 - 100) << 32) | 3;
+
+    let seqno_too_low = BadMsgNotification { bad_msg_id: 4, bad_msg_seqno: 1, error_code: 32 };
+    assert!(!generator.handle_bad_msg_notification(&seqno_too_low, server_message_id));
+    assert_eq!(generator.time_offset(), 0);
+
+    let msg_id_too_high = BadMsgNotification { bad_msg_id: 4, bad_msg_seqno: 1, error_code: 17 };
+    assert!(generator.handle_bad_msg_notification(&msg_id_too_high, server_message_id));
+    assert_eq!(generator.time_offset(), -100);
+}
+
+#[test]
+fn test_next_id_after_bad_msg_notification() {
+    let (_, clock) = fake_clock();
+    let mut generator = MessageIdGenerator::with_clock(clock);
+    let last_id = generator.next_id(MessageIdKind::Client);
+
+    // Ids keep increasing after they are reported too low
+    let msg_id_too_low = BadMsgNotification { bad_msg_id: last_id, bad_msg_seqno: 1, error_code: 16 };
+    assert!(generator.handle_bad_msg_notification(&msg_id_too_low, ((NOW_SECS as i64) << 32) | 1));
+    assert_eq!(generator.next_id(MessageIdKind::Client), last_id + 4);
+
+    // ...but follow the server time after they are reported too high
+    let msg_id_too_high = BadMsgNotification { bad_msg_id: last_id + 4, bad_msg_seqno: 3, error_code: 17 };
+    assert!(generator.handle_bad_msg_notification(&msg_id_too_high, ((NOW_SECS as i64 - 100) << 32) | 1));
+    assert_eq!(generator.time_offset(), -100);
+    assert_eq!(generator.next_id(MessageIdKind::Client), (NOW_SECS as i64 - 100) << 32);
+    assert_eq!(generator.next_id(MessageIdKind::Notification), ((NOW_SECS as i64 - 100) << 32) | 3);
+}
+
+#[test]
+fn test_validate() {
+    let now = Duration::from_secs(NOW_SECS);
+    let at = |secs: u64, remainder: i64| ((secs as i64) << 32) | remainder;
+    let validator = MessageIdValidator::new();
+
+    assert_eq!(validator.validate(at(NOW_SECS - 300, 0), now).unwrap(), MessageIdKind::Client);
+    assert_eq!(validator.validate(at(NOW_SECS + 30, 1), now).unwrap(), MessageIdKind::Response);
+
+    assert_error_kind(
+        validator.validate(at(NOW_SECS - 301, 0), now),
+        MessageIdErrorKind::TooOld(at(NOW_SECS - 301, 0), 301));
+    assert_error_kind(
+        validator.validate(at(NOW_SECS + 31, 3), now),
+        MessageIdErrorKind::TooNew(at(NOW_SECS + 31, 3), 31));
+    assert_error_kind(
+        validator.validate(at(NOW_SECS, 2), now),
+        MessageIdErrorKind::Invalid(at(NOW_SECS, 2)));
+
+    let strict = MessageIdValidator::new().max_past_secs(10).max_future_secs(0);
+    assert!(strict.validate(at(NOW_SECS - 11, 0), now).is_err());
+    assert!(strict.validate(at(NOW_SECS + 1, 0), now).is_err());
+    assert!(strict.validate(at(NOW_SECS, 0), now).is_ok());
+}
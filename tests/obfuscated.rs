@@ -0,0 +1,164 @@
+//! Testing the obfuscated transport.
+
+mod common;
+
+use std::io::{self, Cursor, Read, Write};
+
+use byteorder::{ByteOrder, LittleEndian};
+use rand::{CryptoRng, RngCore, SeedableRng};
+use rand::rngs::StdRng;
+use serde_mtproto::error::{ErrorKind, TransportErrorKind};
+use serde_mtproto::transport::{
+    Abridged, Full, Intermediate, Obfuscated, ObfuscatedConfig, PaddedIntermediate, Transport,
+};
+
+use crate::common::assert_error_kind;
+
+
+/// One end of an in-memory connection.
+#[derive(Debug, Default)]
+struct Pipe {
+    incoming: Cursor<Vec<u8>>,
+    outgoing: Vec<u8>,
+}
+
+impl Read for Pipe {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.incoming.read(buf)
+    }
+}
+
+impl Write for Pipe {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.outgoing.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Move everything sent by `from` to the incoming bytes of `to`.
+fn transfer(from: &mut Obfuscated<Pipe>, to: &mut Obfuscated<Pipe>) {
+    let sent = from.get_mut().outgoing.drain(..);
+    to.get_mut().incoming.get_mut().extend(sent);
+}
+
+/// Fills buffers with 1, 2, 3 and so on.
+struct CountingRng(u8);
+
+impl RngCore for CountingRng {
+    fn next_u32(&mut self) -> u32 {
+        let mut bytes = [0; 4];
+        self.fill_bytes(&mut bytes);
+        LittleEndian::read_u32(&bytes)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let mut bytes = [0; 8];
+        self.fill_bytes(&mut bytes);
+        LittleEndian::read_u64(&bytes)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for byte in dest {
+            self.0 = self.0.wrapping_add(1);
+            *byte = self.0;
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+impl CryptoRng for CountingRng {}
+
+
+fn check_round_trip<T: Transport>(mut transport: T, config: &ObfuscatedConfig) {
+    let mut client = Obfuscated::connect(Pipe::default(), &transport, config).unwrap();
+    transport.write_frame(&mut client, &[1, 2, 3, 4]).unwrap();
+
+    let sent = client.get_ref().outgoing.clone();
+    let mut server = Obfuscated::accept(Pipe { incoming: Cursor::new(sent), outgoing: Vec::new() }, config).unwrap();
+    assert_eq!(server.transport_tag(), transport.tag());
+    assert_eq!(server.dc_id(), -3);
+    assert_eq!(&transport.read_frame(&mut server).unwrap()[..4], [1, 2, 3, 4]);
+
+    transport.write_frame(&mut server, &[5, 6, 7, 8]).unwrap();
+    transfer(&mut server, &mut client);
+    assert_eq!(&transport.read_frame(&mut client).unwrap()[..4], [5, 6, 7, 8]);
+}
+
+
+#[test]
+fn test_round_trip() {
+    let config = ObfuscatedConfig::new().dc_id(-3);
+
+    check_round_trip(Abridged::new(), &config);
+    check_round_trip(Intermediate::new(), &config);
+    check_round_trip(PaddedIntermediate::new(), &config);
+    check_round_trip(Abridged::new(), &config.secret([0x42; 16]));
+}
+
+#[test]
+fn test_known_header() {
+    let config = ObfuscatedConfig::new().dc_id(2);
+    let mut client = Obfuscated::connect_with_rng(Pipe::default(), &Intermediate::new(), &config, &mut CountingRng(0))
+        .unwrap();
+    Intermediate::new().write_frame(&mut client, &[1, 2, 3, 4]).unwrap();
+
+    let sent = &client.get_ref().outgoing;
+    assert_eq!(sent.len(), 72);
+    assert_eq!(sent[..56], (1..57).collect::<Vec<u8>>()[..]);
+    assert_eq!(sent[56..], [
+        0x33, 0xbd, 0x94, 0x84, 0xf1, 0xfb, 0x4e, 0x3a,
+        0xd9, 0x00, 0x4d, 0x2a, 0x9c, 0x48, 0xed, 0xd8,
+    ]);
+
+    let sent = sent.clone();
+    let mut server = Obfuscated::accept(Pipe { incoming: Cursor::new(sent), outgoing: Vec::new() }, &config).unwrap();
+    assert_eq!(Intermediate::new().read_frame(&mut server).unwrap(), [1, 2, 3, 4]);
+
+    Intermediate::new().write_frame(&mut server, &[5, 6, 7, 8]).unwrap();
+    assert_eq!(server.get_ref().outgoing, [0xfc, 0x8d, 0x18, 0x7a, 0xef, 0x10, 0xc0, 0x47]);
+}
+
+#[test]
+fn test_header_is_obfuscated() {
+    let mut rng = StdRng::seed_from_u64(0x5eed);
+    let forbidden_starts: &[&[u8]] = &[
+        b"HEAD", b"POST", b"GET ", b"OPTI", &[0x16, 0x03, 0x01, 0x02], &[0xdd; 4], &[0xee; 4],
+    ];
+
+    for _ in 0..100 {
+        let client = Obfuscated::connect_with_rng(Vec::new(), &Abridged::new(), &ObfuscatedConfig::new(), &mut rng)
+            .unwrap();
+        let header = client.into_inner();
+
+        assert_eq!(header.len(), 64);
+        assert_ne!(header[0], 0xef);
+        assert!(!forbidden_starts.contains(&&header[0..4]));
+        assert_ne!(header[4..8], [0; 4]);
+        assert_ne!(header[56..60], [0xef; 4]);
+    }
+}
+
+#[test]
+fn test_errors() {
+    let config = ObfuscatedConfig::new();
+    assert_error_kind(Obfuscated::connect(Vec::new(), &Full::new(), &config), TransportErrorKind::NoTag);
+
+    let mut rng = StdRng::seed_from_u64(0x5eed);
+    let client = Obfuscated::connect_with_rng(Vec::new(), &Abridged::new(), &config.clone().secret([1; 16]), &mut rng)
+        .unwrap();
+    let header = client.into_inner();
+
+    match *Obfuscated::accept(Cursor::new(header), &config.clone().secret([2; 16])).unwrap_err().kind() {
+        ErrorKind::Transport(TransportErrorKind::UnknownTag(_)) => (),
+        ref kind => panic!("unexpected error kind: {:?}", kind),
+    }
+
+    assert!(Obfuscated::accept(Cursor::new(vec![0; 63]), &config).is_err());
+}
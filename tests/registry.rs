@@ -0,0 +1,282 @@
+//! Testing schema-driven deserialization using a constructor registry.
+
+mod common;
+
+use serde::de::{Deserialize, IgnoredAny};
+use serde_derive::{Serialize, Deserialize};
+use serde_json::json;
+use serde_mtproto::{Boxed, Deserializer, Flags, Int128};
+use serde_mtproto::error::{DeErrorKind, DeSerdeType};
+use serde_mtproto::layer::LayerRange;
+use serde_mtproto::registry::{Constructor, Field, Registry, TlDescribed, TlType};
+use serde_mtproto_derive::{MtProtoDescribed, MtProtoIdentifiable, MtProtoSized};
+
+use crate::common::assert_error_kind;
+
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, MtProtoIdentifiable, MtProtoSized, MtProtoDescribed)]
+#[mtproto_identifiable(id = "0x3ded6320")]
+struct User {
+    id: i32,
+    first_name: String,
+    phones: Vec<i64>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, MtProtoIdentifiable, MtProtoSized, MtProtoDescribed)]
+#[mtproto_identifiable(id = "0xc4a22e15")]
+struct Channel {
+    flags: Flags<Channel>,
+    #[mtproto_flags(bit = 5)]
+    broadcast: bool,
+    id: i32,
+    #[mtproto_flags(bit = 6)]
+    username: Option<String>,
+    #[serde(rename = "creator")]
+    owner: Boxed<User>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, MtProtoIdentifiable, MtProtoSized, MtProtoDescribed)]
+enum Peer {
+    #[mtproto_identifiable(id = "0x9db1bc6d")]
+    User {
+        user_id: i32,
+    },
+    #[mtproto_identifiable(id = "0xbad0e5bb")]
+    Chat {
+        chat_id: i32,
+    },
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, MtProtoIdentifiable, MtProtoSized, MtProtoDescribed)]
+enum LayeredUser {
+    #[mtproto_identifiable(tl = "userEmpty#200250ba id:int = User", layers = "..=132")]
+    EmptyOld {
+        id: i32,
+    },
+    #[mtproto_identifiable(tl = "userEmpty#d3bc4b7a id:long = User", layers = "133..")]
+    Empty {
+        id: i64,
+    },
+    #[mtproto_identifiable(id = "0xdeadbeef", layers = "100..=110")]
+    Test,
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+#[serde(untagged)]
+enum AnyPeer {
+    User { user_id: i32 },
+    Chat { chat_id: i32 },
+}
+
+
+fn registry() -> Registry {
+    let mut registry = Registry::new();
+    registry.register::<User>();
+    registry.register::<Channel>();
+    registry.register::<Peer>();
+
+    registry
+}
+
+fn user() -> User {
+    User {
+        id: 42,
+        first_name: "Alice".to_owned(),
+        phones: vec![1, 2],
+    }
+}
+
+fn deserialize_any<'de, T: Deserialize<'de>>(bytes: &'de [u8]) -> serde_mtproto::Result<T> {
+    let registry = registry();
+    let mut de = Deserializer::new(bytes, &[]);
+    de.set_registry(&registry);
+
+    T::deserialize(&mut de)
+}
+
+
+#[test]
+fn test_derived_tl_constructors() {
+    assert_eq!(Channel::tl_type(), TlType::Bare(0xc4a22e15));
+    assert_eq!(Peer::tl_type(), TlType::Boxed);
+    assert_eq!(<Boxed<Vec<User>>>::tl_type(), TlType::BoxedVector(Box::new(TlType::Bare(0x3ded6320))));
+
+    assert_eq!(Channel::tl_constructors(), vec![Constructor {
+        name: "Channel".to_owned(),
+        id: 0xc4a22e15,
+        fields: vec![
+            Field::new("flags", TlType::Flags),
+            Field::conditional("broadcast", TlType::True, "flags", 5),
+            Field::new("id", TlType::Int),
+            Field::conditional("username", TlType::String, "flags", 6),
+            Field::new("creator", TlType::Boxed),
+        ],
+        layers: LayerRange::all(),
+    }]);
+
+    let peer_ids = Peer::tl_constructors().iter().map(|c| c.id).collect::<Vec<_>>();
+    assert_eq!(peer_ids, vec![0x9db1bc6d, 0xbad0e5bb]);
+}
+
+#[test]
+fn test_layered_constructors() {
+    let constructors = LayeredUser::tl_constructors();
+    let names_and_layers = constructors.iter().map(|c| (&c.name[..], c.layers)).collect::<Vec<_>>();
+    assert_eq!(names_and_layers, vec![
+        ("userEmpty", LayerRange::new(None, Some(132))),
+        ("userEmpty", LayerRange::new(Some(133), None)),
+        ("Test", LayerRange::new(Some(100), Some(110))),
+    ]);
+    assert_eq!(constructors[2].layers.to_string(), "100..=110");
+
+    let mut registry = Registry::new();
+    registry.register::<LayeredUser>();
+
+    assert_eq!(registry.resolve("userEmpty", 1).map(|c| c.id), Some(0x200250ba));
+    assert_eq!(registry.resolve("userEmpty", 132).map(|c| c.id), Some(0x200250ba));
+    assert_eq!(registry.resolve("userEmpty", 133).map(|c| c.id), Some(0xd3bc4b7a));
+    assert_eq!(registry.resolve("Test", 111), None);
+    assert_eq!(registry.resolve("userFull", 133), None);
+
+    let layer_133 = registry.for_layer(133);
+    assert_eq!(layer_133.constructors().count(), 1);
+
+    let bytes = serde_mtproto::to_bytes(&Boxed::new(LayeredUser::EmptyOld { id: 5 })).unwrap();
+    let mut de = Deserializer::new(&bytes[..], &[]);
+    de.set_registry(&layer_133);
+    assert!(serde_json::Value::deserialize(&mut de).is_err());
+
+    let mut de = Deserializer::new(&bytes[..], &[]);
+    de.set_registry(&registry);
+    assert_eq!(serde_json::Value::deserialize(&mut de).unwrap(), json!({ "_": "userEmpty", "id": 5 }));
+}
+
+#[test]
+fn test_deserialize_any_struct() {
+    let bytes = serde_mtproto::to_bytes(&Boxed::new(user())).unwrap();
+    let value: serde_json::Value = deserialize_any(&bytes).unwrap();
+
+    assert_eq!(value, json!({
+        "_": "User",
+        "id": 42,
+        "first_name": "Alice",
+        "phones": [1, 2],
+    }));
+}
+
+#[test]
+fn test_deserialize_any_flags() {
+    let channel = Channel {
+        flags: Flags::new(),
+        broadcast: true,
+        id: 7,
+        username: None,
+        owner: Boxed::new(user()),
+    };
+    let bytes = serde_mtproto::to_bytes(&Boxed::new(channel)).unwrap();
+    let value: serde_json::Value = deserialize_any(&bytes).unwrap();
+
+    assert_eq!(value, json!({
+        "_": "Channel",
+        "flags": 1 << 5,
+        "broadcast": true,
+        "id": 7,
+        "username": null,
+        "creator": {
+            "_": "User",
+            "id": 42,
+            "first_name": "Alice",
+            "phones": [1, 2],
+        },
+    }));
+}
+
+#[test]
+fn test_deserialize_any_untagged() {
+    let bytes = serde_mtproto::to_bytes(&Boxed::new(Peer::Chat { chat_id: 5 })).unwrap();
+    let peer: AnyPeer = deserialize_any(&bytes).unwrap();
+
+    assert_eq!(peer, AnyPeer::Chat { chat_id: 5 });
+}
+
+#[test]
+fn test_deserialize_ignored_any() {
+    let mut bytes = serde_mtproto::to_bytes(&Boxed::new(user())).unwrap();
+    bytes.extend(serde_mtproto::to_bytes(&Boxed::new(Peer::User { user_id: 3 })).unwrap());
+    bytes.extend(serde_mtproto::to_bytes(&0x1234_i32).unwrap());
+
+    let (_, _, value): (IgnoredAny, IgnoredAny, i32) = deserialize_any(&bytes).unwrap();
+    assert_eq!(value, 0x1234);
+}
+
+#[test]
+fn test_deserialize_ignored_any_with_size() {
+    // Has the same layout as `WithSize<T>` without requiring `T: MtProtoSized`
+    #[derive(Debug, Deserialize)]
+    #[serde(rename = "WithSize")]
+    struct Sized {
+        size: u32,
+        inner: Payload,
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct Payload {
+        _user: IgnoredAny,
+        value: i32,
+    }
+
+    let mut inner = serde_mtproto::to_bytes(&Boxed::new(user())).unwrap();
+    inner.extend(serde_mtproto::to_bytes(&0x1234_i32).unwrap());
+    let mut bytes = serde_mtproto::to_bytes(&(inner.len() as u32)).unwrap();
+    bytes.extend(inner);
+
+    let sized: Sized = deserialize_any(&bytes).unwrap();
+    assert_eq!(sized.size as usize, bytes.len() - 4);
+    assert_eq!(sized.inner.value, 0x1234);
+
+    // The length of an ignored value isn't known without a registry
+    assert_error_kind(
+        serde_mtproto::from_bytes::<Sized>(&bytes, &[]),
+        DeErrorKind::UnsupportedSerdeType(DeSerdeType::IgnoredAny));
+}
+
+#[test]
+fn test_deserialize_any_manual_layout() {
+    let mut registry = Registry::new();
+    let _ = registry.insert(Constructor {
+        name: "resPQ".to_owned(),
+        id: 0x05162463,
+        fields: vec![
+            Field::new("nonce", TlType::Int128),
+            Field::new("fingerprints", TlType::BoxedVector(Box::new(TlType::Long))),
+        ],
+        layers: LayerRange::all(),
+    });
+
+    let mut bytes = serde_mtproto::to_bytes(&0x05162463_u32).unwrap();
+    bytes.extend(serde_mtproto::to_bytes(&Int128::from(1_u128)).unwrap());
+    bytes.extend(serde_mtproto::to_bytes(&Boxed::new(vec![-1_i64])).unwrap());
+
+    let mut de = Deserializer::new(&bytes[..], &[]);
+    de.set_registry(&registry);
+    let value = serde_json::Value::deserialize(&mut de).unwrap();
+
+    assert_eq!(value, json!({
+        "_": "resPQ",
+        "nonce": [1, 0, 0, 0],
+        "fingerprints": [-1],
+    }));
+}
+
+#[test]
+fn test_deserialize_any_unregistered() {
+    let bytes = serde_mtproto::to_bytes(&0xdeadbeef_u32).unwrap();
+    assert_error_kind(deserialize_any::<serde_json::Value>(&bytes), DeErrorKind::UnregisteredTypeId(0xdeadbeef));
+}
+
+#[test]
+fn test_deserialize_any_without_registry() {
+    let bytes = serde_mtproto::to_bytes(&Boxed::new(user())).unwrap();
+
+    assert!(serde_mtproto::from_bytes::<serde_json::Value>(&bytes, &[]).is_err());
+}
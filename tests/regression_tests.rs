@@ -1,20 +1,27 @@
 //! Integration & regression tests.
 
 
+mod common;
+
 use std::collections::BTreeMap;
 
 use derivative::Derivative;
 use lazy_static::lazy_static;
 use maplit::btreemap;
 use pretty_assertions::assert_eq;
-use serde::de::{Deserializer, DeserializeSeed, Error as DeError};
+use serde::de::{Deserialize, Deserializer, DeserializeSeed, Error as DeError};
 use serde_derive::{Serialize, Deserialize};
 use serde_mtproto_derive::{MtProtoIdentifiable, MtProtoSized};
-use serde_bytes::ByteBuf;
+use serde_bytes::{ByteBuf, Bytes};
 use serde_mtproto::{
-    Boxed, MtProtoSized, UnsizedByteBuf, UnsizedByteBufSeed,
-    to_bytes, to_writer, from_bytes, from_reader,
+    Boxed, Flags, FlagsLayout, Identifiable, Int128, Int256, MtProtoSized, SkippedWithSize, UnencryptedMessage, UnsizedByteBuf,
+    UnsizedByteBufSeed, WithSize,
+    to_bytes, to_writer, from_bytes, from_bytes_reuse, from_reader,
 };
+use serde_mtproto::de::DeserializerOptions;
+use serde_mtproto::error::{DeErrorKind, ErrorKind, SerErrorKind};
+
+use crate::common::{assert_de_error_msg, assert_error_kind};
 
 
 #[derive(Debug, Derivative, Serialize, Deserialize, MtProtoIdentifiable, MtProtoSized)]
@@ -40,6 +47,18 @@ struct Message {
     encrypted_data: UnsizedByteBuf,
 }
 
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Envelope {
+    id: u32,
+    letter: Letter,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Letter {
+    pages: u32,
+    seal: Boxed<Nothing>,
+}
+
 fn deserialize_message<'de, D>(deserializer: D) -> Result<UnsizedByteBuf, D::Error>
     where D: Deserializer<'de>
 {
@@ -107,6 +126,110 @@ enum Cafebabe<T> {
     Spam(Boxed<CLike>),
 }
 
+#[derive(Debug, PartialEq, Serialize, Deserialize, MtProtoIdentifiable, MtProtoSized)]
+#[mtproto_identifiable(id = "0xc4a22e15")]
+struct Channel {
+    flags: Flags<Channel>,
+    #[mtproto_flags(bit = 5)]
+    broadcast: bool,
+    id: i32,
+    title: String,
+    #[mtproto_flags(bit = 6)]
+    username: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, MtProtoIdentifiable, MtProtoSized)]
+enum Dialog {
+    #[mtproto_identifiable(id = "0xd1a10900")]
+    Folder {
+        flags: Flags<Dialog>,
+        #[mtproto_flags(field = "flags", bit = 2)]
+        pinned: bool,
+        peer: i64,
+        #[serde(rename = "flags_second")]
+        flags2: Flags<Dialog>,
+        #[mtproto_flags(field = "flags2", bit = 1)]
+        folder_id: Option<i32>,
+        #[mtproto_flags(field = "flags", bit = 0)]
+        unread_count: Option<u32>,
+    },
+    #[mtproto_identifiable(id = "0xd1a10901")]
+    Empty {
+        flags: Flags<Dialog>,
+        #[mtproto_flags(bit = 0)]
+        unread_count: Option<u32>,
+    },
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, MtProtoIdentifiable, MtProtoSized)]
+enum Reaction {
+    #[mtproto_identifiable(id = "0x8935fc73")]
+    #[serde(rename = "reactionCustomEmoji")]
+    CustomEmoji {
+        flags: Flags<Reaction>,
+        #[mtproto_flags(bit = 1)]
+        big: bool,
+        document_id: i64,
+    },
+}
+
+/// Binds a conditional field of a type other than `Option` or `bool`, which
+/// `#[derive(MtProtoIdentifiable)]` would reject.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct FlaggedNonOptional {
+    flags: Flags<FlaggedNonOptional>,
+    inner: NonOptional,
+}
+
+impl FlagsLayout for FlaggedNonOptional {
+    fn flags_layout() -> &'static [&'static str] {
+        &["inner:flags.0"]
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct NonOptional {
+    set: bool,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, MtProtoIdentifiable, MtProtoSized)]
+#[mtproto_identifiable(tl = "channelForbidden#17d493d5 flags:# broadcast:flags.5?true megagroup:flags.8?true \
+                             id:long access_hash:long title:string until_date:flags.16?int = Chat")]
+struct ChannelForbidden {
+    flags: Flags<ChannelForbidden>,
+    #[mtproto_flags(bit = 5)]
+    broadcast: bool,
+    #[mtproto_flags(bit = 8)]
+    megagroup: bool,
+    id: i64,
+    access_hash: i64,
+    title: String,
+    #[mtproto_flags(bit = 16)]
+    until_date: Option<i32>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, MtProtoIdentifiable, MtProtoSized)]
+#[mtproto_identifiable(tl = "fileHash#f39b035c offset:long limit:int hash:bytes = FileHash")]
+struct FileHash {
+    offset: i64,
+    limit: i32,
+    hash: ByteBuf,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, MtProtoIdentifiable, MtProtoSized)]
+enum Peer {
+    #[mtproto_identifiable(tl = "peerUser user_id:long = Peer")]
+    User {
+        user_id: i64,
+    },
+    #[mtproto_identifiable(tl = "peerChat#36c6019a chat_id:long = Peer", id = "0x36c6019a")]
+    Chat {
+        chat_id: i64,
+    },
+    #[mtproto_identifiable(tl = "peerChannel channel_id:long = Peer")]
+    Channel(i64),
+}
+
 
 lazy_static! {
     static ref BUILTIN_I128: i128 = 100000000000000000000000000000000000000;
@@ -277,6 +400,89 @@ lazy_static! {
         0x1e, 0xab, 0x1d, 0xf0,    // id of Cafebabe::Spam in little-endian
         0x1e, 0xab, 0xa1, 0x5c,    // id of CLike::A in little-endian
     ];
+
+    static ref CHANNEL_FULL: Channel = Channel {
+        flags: Flags::new(),
+        broadcast: true,
+        id: 0x1234,
+        title: "ok".to_owned(),
+        username: Some("chan".to_owned()),
+    };
+
+    static ref CHANNEL_FULL_SERIALIZED_BARE: Vec<u8> = vec![
+        0x60, 0, 0, 0,                              // flags with bits 5 and 6 set
+        0x34, 0x12, 0, 0,                           // 0x1234 as little-endian 32-bit int
+        2, 111, 107, 0,                             // string "ok" of length 2 and 1 byte of padding
+        4, 99, 104, 97, 110, 0, 0, 0,               // string "chan" of length 4 and 3 bytes of padding
+    ];
+
+    static ref CHANNEL_EMPTY: Channel = Channel {
+        flags: Flags::new(),
+        broadcast: false,
+        id: -1,
+        title: "".to_owned(),
+        username: None,
+    };
+
+    static ref CHANNEL_EMPTY_SERIALIZED_BOXED: Vec<u8> = vec![
+        0x15, 0x2e, 0xa2, 0xc4,                     // id of Channel in little-endian
+        0, 0, 0, 0,                                 // flags with no bits set
+        255, 255, 255, 255,                         // -1 as little-endian 32-bit int
+        0, 0, 0, 0,                                 // empty string
+    ];
+
+    static ref DIALOG_FOLDER: Dialog = Dialog::Folder {
+        flags: Flags::new(),
+        pinned: true,
+        peer: 7,
+        flags2: Flags::new(),
+        folder_id: Some(1),
+        unread_count: Some(3),
+    };
+
+    static ref DIALOG_FOLDER_SERIALIZED_BOXED: Vec<u8> = vec![
+        0x00, 0x09, 0xa1, 0xd1,                     // id of Dialog::Folder in little-endian
+        0x05, 0, 0, 0,                              // flags with bits 0 and 2 set
+        7, 0, 0, 0, 0, 0, 0, 0,                     // 7 as little-endian 64-bit int
+        0x02, 0, 0, 0,                              // flags2 with bit 1 set
+        1, 0, 0, 0,                                 // 1 as little-endian 32-bit int
+        3, 0, 0, 0,                                 // 3 as little-endian 32-bit int
+    ];
+
+    static ref DIALOG_EMPTY: Dialog = Dialog::Empty {
+        flags: Flags::new(),
+        unread_count: Some(42),
+    };
+
+    static ref DIALOG_EMPTY_SERIALIZED_BOXED: Vec<u8> = vec![
+        0x01, 0x09, 0xa1, 0xd1,                     // id of Dialog::Empty in little-endian
+        0x01, 0, 0, 0,                              // flags with bit 0 set
+        42, 0, 0, 0,                                // 42 as little-endian 32-bit int
+    ];
+
+    static ref VECTOR_OF_LONGS: Vec<i64> = vec![1, -2];
+
+    static ref VECTOR_OF_LONGS_SERIALIZED_BARE: Vec<u8> = vec![
+        2, 0, 0, 0,                                 // vector has 2 elements
+        1, 0, 0, 0, 0, 0, 0, 0,                     // 1 as little-endian 64-bit int
+        254, 255, 255, 255, 255, 255, 255, 255,     // -2 as little-endian 64-bit int
+    ];
+
+    static ref VECTOR_OF_LONGS_SERIALIZED_BOXED: Vec<u8> = vec![
+        0x15, 0xc4, 0xb5, 0x1c,                     // id of vector in little-endian
+        2, 0, 0, 0,                                 // vector has 2 elements
+        1, 0, 0, 0, 0, 0, 0, 0,                     // 1 as little-endian 64-bit int
+        254, 255, 255, 255, 255, 255, 255, 255,     // -2 as little-endian 64-bit int
+    ];
+
+    static ref VECTOR_OF_BOXED_C_LIKE: Vec<Boxed<CLike>> = vec![Boxed::new(CLike::C), Boxed::new(CLike::A)];
+
+    static ref VECTOR_OF_BOXED_C_LIKE_SERIALIZED_BOXED: Vec<u8> = vec![
+        0x15, 0xc4, 0xb5, 0x1c,                     // id of vector in little-endian
+        2, 0, 0, 0,                                 // vector has 2 elements
+        0x11, 0xba, 0x07, 0xf0,                     // id of CLike::C in little-endian
+        0x1e, 0xab, 0xa1, 0x5c,                     // id of CLike::A in little-endian
+    ];
 }
 
 
@@ -512,6 +718,448 @@ test_suite_boxed! {
 }
 
 
+test_suite_bare! {
+    test_struct_with_flags_to_bytes_bare,
+    test_struct_with_flags_to_writer_bare,
+    test_struct_with_flags_from_bytes_bare,
+    test_struct_with_flags_from_reader_bare,
+    test_struct_with_flags_size_prediction_bare =>
+    Channel: (CHANNEL_FULL, CHANNEL_FULL_SERIALIZED_BARE, channel_full_deserialized_bare, &[])
+}
+
+test_suite_boxed! {
+    test_struct_with_unset_flags_to_bytes_boxed,
+    test_struct_with_unset_flags_to_writer_boxed,
+    test_struct_with_unset_flags_from_bytes_boxed,
+    test_struct_with_unset_flags_from_reader_boxed,
+    test_struct_with_unset_flags_size_prediction_boxed =>
+    Channel: (CHANNEL_EMPTY, CHANNEL_EMPTY_SERIALIZED_BOXED, channel_empty_deserialized_boxed, &[])
+}
+
+
+test_suite_boxed! {
+    test_enum_variant_with_two_flags_to_bytes_boxed,
+    test_enum_variant_with_two_flags_to_writer_boxed,
+    test_enum_variant_with_two_flags_from_bytes_boxed,
+    test_enum_variant_with_two_flags_from_reader_boxed,
+    test_enum_variant_with_two_flags_size_prediction_boxed =>
+    Dialog: (DIALOG_FOLDER, DIALOG_FOLDER_SERIALIZED_BOXED, dialog_folder_deserialized_boxed, &["Folder"])
+}
+
+test_suite_boxed! {
+    test_enum_variant_with_flags_to_bytes_boxed,
+    test_enum_variant_with_flags_to_writer_boxed,
+    test_enum_variant_with_flags_from_bytes_boxed,
+    test_enum_variant_with_flags_from_reader_boxed,
+    test_enum_variant_with_flags_size_prediction_boxed =>
+    Dialog: (DIALOG_EMPTY, DIALOG_EMPTY_SERIALIZED_BOXED, dialog_empty_deserialized_boxed, &["Empty"])
+}
+
+test_suite_bare! {
+    test_vector_to_bytes_bare,
+    test_vector_to_writer_bare,
+    test_vector_from_bytes_bare,
+    test_vector_from_reader_bare,
+    test_vector_size_prediction_bare =>
+    Vec<i64>: (VECTOR_OF_LONGS, VECTOR_OF_LONGS_SERIALIZED_BARE, vector_of_longs_deserialized_bare, &[])
+}
+
+test_suite_boxed! {
+    test_vector_to_bytes_boxed,
+    test_vector_to_writer_boxed,
+    test_vector_from_bytes_boxed,
+    test_vector_from_reader_boxed,
+    test_vector_size_prediction_boxed =>
+    Vec<i64>: (VECTOR_OF_LONGS, VECTOR_OF_LONGS_SERIALIZED_BOXED, vector_of_longs_deserialized_boxed, &[])
+}
+
+test_suite_boxed! {
+    test_vector_of_boxed_to_bytes_boxed,
+    test_vector_of_boxed_to_writer_boxed,
+    test_vector_of_boxed_from_bytes_boxed,
+    test_vector_of_boxed_from_reader_boxed,
+    test_vector_of_boxed_size_prediction_boxed =>
+    Vec<Boxed<CLike>>: (VECTOR_OF_BOXED_C_LIKE, VECTOR_OF_BOXED_C_LIKE_SERIALIZED_BOXED,
+                        vector_of_boxed_c_like_deserialized_boxed, &[])
+}
+
+#[test]
+fn test_slice_to_bytes_boxed() {
+    let slice: &[i64] = &VECTOR_OF_LONGS;
+
+    assert_eq!(to_bytes(&Boxed::new(slice)).unwrap(), *VECTOR_OF_LONGS_SERIALIZED_BOXED);
+    assert_eq!(Boxed::new(slice).size_hint().unwrap(), VECTOR_OF_LONGS_SERIALIZED_BOXED.len());
+}
+
+#[test]
+fn test_vector_from_bytes_boxed_invalid_id() {
+    let mut bytes = VECTOR_OF_LONGS_SERIALIZED_BOXED.clone();
+    bytes[0] = 0x16;
+
+    assert!(from_bytes::<Boxed<Vec<i64>>>(&bytes, &[]).is_err());
+}
+
+
+#[test]
+fn test_int128_int256_ser_de() {
+    let nonce_bytes = [
+        0x3e, 0x05, 0x49, 0x82, 0x8c, 0xca, 0x27, 0xe9,
+        0x66, 0xb3, 0x01, 0xa4, 0x8f, 0xec, 0xe2, 0xfc,
+    ];
+    let nonce = Int128::new(nonce_bytes);
+    let mut new_nonce_bytes = [0; 32];
+    new_nonce_bytes[..16].copy_from_slice(&nonce_bytes);
+    let new_nonce = Int256::from(new_nonce_bytes);
+
+    assert_eq!(to_bytes(&nonce).unwrap(), nonce_bytes);
+    assert_eq!(to_bytes(&new_nonce).unwrap(), &new_nonce_bytes[..]);
+    assert_eq!(nonce.size_hint().unwrap(), 16);
+    assert_eq!(new_nonce.size_hint().unwrap(), 32);
+    assert_eq!(from_bytes::<Int128>(&nonce_bytes, &[]).unwrap(), nonce);
+    assert_eq!(from_bytes::<Int256>(&new_nonce_bytes, &[]).unwrap(), new_nonce);
+    assert!(from_bytes::<Int256>(&nonce_bytes, &[]).is_err());
+}
+
+#[test]
+fn test_int128_conversions() {
+    let n = Int128::from(0x0102_0304_0506_0708_090a_0b0c_0d0e_0f10_u128);
+
+    assert_eq!(*n.as_bytes(), [
+        0x10, 0x0f, 0x0e, 0x0d, 0x0c, 0x0b, 0x0a, 0x09,
+        0x08, 0x07, 0x06, 0x05, 0x04, 0x03, 0x02, 0x01,
+    ]);
+    assert_eq!(u128::from(n), 0x0102_0304_0506_0708_090a_0b0c_0d0e_0f10);
+    assert_eq!(i128::from(Int128::from(-2_i128)), -2);
+    assert_eq!(to_bytes(&Int128::from(-2_i128)).unwrap(), to_bytes(&-2_i128).unwrap());
+}
+
+
+/// Variants of boxed enums are selected using the type id from the data.
+#[test]
+fn test_enum_variant_from_bytes_boxed_without_hint() {
+    let c_like_b: Boxed<CLike> = from_bytes(&C_LIKE_B_SERIALIZED_BOXED, &[]).unwrap();
+    assert_eq!(c_like_b.into_inner(), *C_LIKE_B);
+
+    let cafebabe_bar: Boxed<Cafebabe<u32>> = from_bytes(&CAFEBABE_BAR_SERIALIZED_BOXED, &[]).unwrap();
+    assert_eq!(cafebabe_bar.into_inner(), *CAFEBABE_BAR);
+
+    let cafebabe_blob: Boxed<Cafebabe<i16>> = from_reader(CAFEBABE_BLOB_SERIALIZED_BOXED.as_slice(), &[]).unwrap();
+    assert_eq!(cafebabe_blob.into_inner(), *CAFEBABE_BLOB);
+
+    let cafebabe_spam: Boxed<Cafebabe<Vec<String>>> = from_bytes(&CAFEBABE_SPAM_SERIALIZED_BOXED, &[]).unwrap();
+    assert_eq!(cafebabe_spam.into_inner(), *CAFEBABE_SPAM);
+
+    let dialog_folder: Boxed<Dialog> = from_bytes(&DIALOG_FOLDER_SERIALIZED_BOXED, &[]).unwrap();
+    assert_eq!(dialog_folder.into_inner(), *DIALOG_FOLDER);
+}
+
+/// Bare enums nested in boxed ones still need a hint, but only for themselves.
+#[test]
+fn test_enum_variant_from_bytes_boxed_with_partial_hint() {
+    let cafebabe_quux: Boxed<Cafebabe<u16>> = from_bytes(&CAFEBABE_QUUX_SERIALIZED_BOXED, &["C"]).unwrap();
+    assert_eq!(cafebabe_quux.into_inner(), *CAFEBABE_QUUX);
+
+    assert!(from_bytes::<Boxed<Cafebabe<u16>>>(&CAFEBABE_QUUX_SERIALIZED_BOXED, &[]).is_err());
+}
+
+#[test]
+fn test_enum_variant_from_bytes_boxed_unknown_id() {
+    let bytes = [0x01, 0x02, 0x03, 0x04];
+
+    assert!(from_bytes::<Boxed<CLike>>(&bytes, &[]).is_err());
+}
+
+
+#[derive(Debug, PartialEq, Deserialize)]
+struct BorrowedChunk<'a> {
+    name: &'a str,
+    #[serde(borrow)]
+    data: &'a Bytes,
+}
+
+/// Strings and byte sequences are borrowed from the input slice instead of being copied.
+#[test]
+fn test_borrowed_str_and_bytes_from_bytes() {
+    let bytes = [
+        3, 98, 101, 101,                    // string "bee" of length 3 and no padding
+        5, 1, 2, 3, 4, 5, 0, 0,             // 5 bytes and 2 bytes of padding
+        0xff, 0xff, 0xff, 0xff,             // unused data
+    ];
+
+    let (chunk, rest): (BorrowedChunk<'_>, &[u8]) = from_bytes_reuse(&bytes, &[]).unwrap();
+
+    assert_eq!(chunk.name, "bee");
+    assert_eq!(chunk.data, Bytes::new(&[1, 2, 3, 4, 5]));
+    assert_eq!(chunk.name.as_ptr(), bytes[1..].as_ptr());
+    assert_eq!(chunk.data.as_ptr(), bytes[5..].as_ptr());
+    assert_eq!(rest, &[0xff, 0xff, 0xff, 0xff]);
+}
+
+#[test]
+fn test_borrowed_str_invalid_utf8() {
+    let bytes = [2, 0xc3, 0x28, 0];
+
+    // Borrowed and owned strings fail with the same error kind
+    let borrowed_err = from_bytes::<&str>(&bytes, &[]).unwrap_err();
+    let owned_err = from_reader::<_, String>(&bytes[..], &[]).unwrap_err();
+
+    for err in &[borrowed_err, owned_err] {
+        match *err.kind() {
+            ErrorKind::FromUtf8(ref e) => {
+                assert_eq!(e.utf8_error().valid_up_to(), 0);
+                assert_eq!(e.utf8_error().error_len(), Some(1));
+            },
+            ref kind => panic!("unexpected error kind: {:?}", kind),
+        }
+    }
+}
+
+
+fn from_bytes_with_options<'de, T>(bytes: &'de [u8], options: DeserializerOptions) -> serde_mtproto::Result<T>
+    where T: Deserialize<'de>
+{
+    let mut de = serde_mtproto::Deserializer::with_options(bytes, &[], options);
+    T::deserialize(&mut de)
+}
+
+
+#[test]
+fn test_deserializer_options_max_total_bytes() {
+    let options = DeserializerOptions::new().max_total_bytes(16);
+
+    assert!(from_bytes_with_options::<Foo>(&FOO_SERIALIZED_BARE, options.max_total_bytes(20)).is_ok());
+    assert_error_kind(
+        from_bytes_with_options::<Foo>(&FOO_SERIALIZED_BARE, options),
+        DeErrorKind::TotalBytesLimitExceeded(16));
+}
+
+#[test]
+fn test_deserializer_options_max_vector_len() {
+    let options = DeserializerOptions::new().max_vector_len(2);
+    let bytes = [
+        3, 0, 0, 0,                         // vec has 3 elements
+        1, 0, 0, 0, 2, 0, 0, 0, 3, 0, 0, 0,
+    ];
+
+    assert_error_kind(
+        from_bytes_with_options::<Vec<u32>>(&bytes, options),
+        DeErrorKind::SeqLenLimitExceeded(3, 2));
+    assert_error_kind(
+        from_bytes_with_options::<BTreeMap<u32, u32>>(&bytes, options),
+        DeErrorKind::SeqLenLimitExceeded(3, 2));
+}
+
+#[test]
+fn test_deserializer_options_max_string_len() {
+    let options = DeserializerOptions::new().max_string_len(3);
+    // A byte sequence of 0x00ffffff bytes which isn't there
+    let bytes = [0xfe, 0xff, 0xff, 0xff];
+
+    assert!(from_bytes_with_options::<String>(&[3, 98, 101, 101], options).is_ok());
+    assert_error_kind(
+        from_bytes_with_options::<String>(&[4, 98, 101, 101, 114, 0, 0, 0], options),
+        DeErrorKind::BytesLenLimitExceeded(4, 3));
+    assert_error_kind(
+        from_bytes_with_options::<ByteBuf>(&bytes, options),
+        DeErrorKind::BytesLenLimitExceeded(0x00ff_ffff, 3));
+}
+
+#[test]
+fn test_deserializer_options_max_depth() {
+    let options = DeserializerOptions::new().max_depth(2);
+    let bytes = [
+        1, 0, 0, 0,                         // outer vec has 1 element
+        1, 0, 0, 0,                         // middle vec has 1 element
+        0, 0, 0, 0,                         // inner vec is empty
+    ];
+
+    assert!(from_bytes_with_options::<Vec<Vec<u32>>>(&bytes, options).is_ok());
+    assert_error_kind(
+        from_bytes_with_options::<Vec<Vec<Vec<u32>>>>(&bytes, options),
+        DeErrorKind::DepthLimitExceeded(2));
+}
+
+
+#[test]
+fn test_de_error_context_struct_field() {
+    // Cut in the middle of `raw_info` bytes
+    let err = from_bytes::<Foo>(&FOO_SERIALIZED_BARE[..15], &[]).unwrap_err();
+    let context = err.de_context().unwrap();
+
+    assert_eq!(context.offset(), 13);
+    assert_eq!(context.path(), "Foo.raw_info");
+    assert_eq!(context.to_string(), "at offset 0xd, in Foo.raw_info");
+}
+
+#[test]
+fn test_de_error_context_seq_element() {
+    let mut bytes = vec![2, 0, 0, 0];           // vec has 2 elements
+    bytes.extend_from_slice(&FOO_SERIALIZED_BOXED);
+    bytes.extend_from_slice(&[0xff, 0xff, 0xff, 0xff]);  // invalid id of Foo
+
+    let err = from_bytes::<Vec<Boxed<Foo>>>(&bytes, &[]).unwrap_err();
+    let context = err.de_context().unwrap();
+
+    assert_eq!(context.offset(), 0x20);
+    assert_eq!(context.path(), "[1]");
+}
+
+#[test]
+fn test_de_error_context_enum_variant() {
+    let bytes = [
+        0xad, 0xaa, 0xaa, 0xba,             // id of Cafebabe::Baz in little-endian
+        1, 0, 0, 0, 0, 0, 0, 0,             // id
+        2, 0xc3, 0x28, 0,                   // name, which is invalid UTF-8
+    ];
+
+    let err = from_bytes::<Boxed<Cafebabe<u32>>>(&bytes, &[]).unwrap_err();
+    let context = err.de_context().unwrap();
+
+    assert_eq!(context.offset(), 0x10);
+    assert_eq!(context.path(), "Cafebabe.Baz.name");
+    assert_eq!(context.to_string(), "at offset 0x10, in Cafebabe.Baz.name");
+}
+
+#[test]
+fn test_de_error_context_top_level() {
+    let err = from_bytes::<u32>(&[1, 2], &[]).unwrap_err();
+    let context = err.de_context().unwrap();
+
+    assert_eq!(context.offset(), 0);
+    assert_eq!(context.path(), "");
+    assert_eq!(context.to_string(), "at offset 0x0");
+}
+
+#[test]
+fn test_de_error_context_nested_structs() {
+    let bytes = [
+        1, 0, 0, 0,                         // id
+        2, 0, 0, 0,                         // pages
+        0xff, 0xff, 0xff, 0xff,             // invalid id of Nothing
+    ];
+
+    let result = from_bytes::<Envelope>(&bytes, &[]);
+    let context = result.as_ref().unwrap_err().de_context().unwrap().clone();
+
+    assert_eq!(context.offset(), 12);
+    assert_eq!(context.path(), "Envelope.letter.Letter.seal");
+    assert_de_error_msg(result, DeErrorKind::InvalidTypeId(0xffff_ffff, &[0xd15e_a5e0]));
+}
+
+
+fn with_size_bytes(size: u32, inner: &[u8]) -> Vec<u8> {
+    let mut bytes = to_bytes(&size).unwrap();
+    bytes.extend_from_slice(inner);
+    bytes
+}
+
+#[test]
+fn test_with_size_ser_de() {
+    let with_size = WithSize::new(Boxed::new(&*FOO)).unwrap();
+    let bytes = with_size_bytes(24, &FOO_SERIALIZED_BOXED);
+
+    assert_eq!(to_bytes(&with_size).unwrap(), bytes);
+    assert_eq!(with_size.size_hint().unwrap(), bytes.len());
+
+    let with_size: WithSize<Boxed<Foo>> = from_bytes(&bytes, &[]).unwrap();
+    assert_eq!(*with_size.inner().inner(), *FOO);
+}
+
+#[test]
+fn test_with_size_de_under_consumed() {
+    let mut bytes = with_size_bytes(24, &FOO_SERIALIZED_BARE);
+    bytes.extend_from_slice(&[0, 0, 0, 0]);
+
+    assert_error_kind(from_bytes::<WithSize<Foo>>(&bytes, &[]), DeErrorKind::SizeMismatch(24, 20));
+}
+
+#[test]
+fn test_with_size_de_over_consumed() {
+    let bytes = with_size_bytes(8, &FOO_SERIALIZED_BARE);
+    let err = from_bytes::<WithSize<Foo>>(&bytes, &[]).unwrap_err();
+
+    // Fails when reading `size` without reading past the declared size
+    assert_eq!(err.de_context().unwrap().offset(), 8);
+    assert_eq!(err.de_context().unwrap().path(), "Foo.size");
+    assert_error_kind::<(), _>(Err(err), DeErrorKind::SizeBoundExceeded(12));
+}
+
+#[test]
+fn test_with_size_de_skipped() {
+    #[derive(Debug, Deserialize)]
+    struct Envelope {
+        before: i32,
+        payload: SkippedWithSize,
+        after: i32,
+    }
+
+    let mut bytes = to_bytes(&-1_i32).unwrap();
+    bytes.extend(with_size_bytes(24, &FOO_SERIALIZED_BOXED));
+    bytes.extend(to_bytes(&7_i32).unwrap());
+
+    let envelope: Envelope = from_bytes(&bytes, &[]).unwrap();
+    assert_eq!(envelope.before, -1);
+    assert_eq!(envelope.payload.size(), 24);
+    assert_eq!(envelope.payload.size_hint().unwrap(), 28);
+    assert_eq!(envelope.after, 7);
+
+    // Skipping is bounded by the data length
+    assert!(from_bytes::<SkippedWithSize>(&with_size_bytes(28, &FOO_SERIALIZED_BOXED), &[]).is_err());
+}
+
+fn unencrypted_message_bytes(auth_key_id: u64, message_id: i64, size: u32, inner: &[u8]) -> Vec<u8> {
+    let mut bytes = to_bytes(&auth_key_id).unwrap();
+    bytes.extend(to_bytes(&message_id).unwrap());
+    bytes.extend(with_size_bytes(size, inner));
+    bytes
+}
+
+#[test]
+fn test_unencrypted_message_ser_de() {
+    let message = UnencryptedMessage::new(0x5e0b_8000_0000_0004, &*FOO);
+    let bytes = unencrypted_message_bytes(0, 0x5e0b_8000_0000_0004, 24, &FOO_SERIALIZED_BOXED);
+
+    assert_eq!(to_bytes(&message).unwrap(), bytes);
+    assert_eq!(message.size_hint().unwrap(), bytes.len());
+
+    let message: UnencryptedMessage<Foo> = from_bytes(&bytes, &[]).unwrap();
+    assert_eq!(message.message_id(), 0x5e0b_8000_0000_0004);
+    assert_eq!(*message.inner(), *FOO);
+
+    // Enum variants are selected by the type id
+    let bytes = unencrypted_message_bytes(0, 0x5e0b_8000_0000_0001, 4, &C_LIKE_B_SERIALIZED_BOXED);
+    let message: UnencryptedMessage<CLike> = from_bytes(&bytes, &[]).unwrap();
+    assert_eq!(message.into_inner(), CLike::B);
+}
+
+
+#[test]
+fn test_unencrypted_message_de_invalid() {
+    assert_de_error_msg(
+        from_bytes::<UnencryptedMessage<Foo>>(&unencrypted_message_bytes(1, 4, 24, &FOO_SERIALIZED_BOXED), &[]),
+        DeErrorKind::NonZeroAuthKeyId(1));
+    assert_de_error_msg(
+        from_bytes::<UnencryptedMessage<Foo>>(&unencrypted_message_bytes(0, 6, 24, &FOO_SERIALIZED_BOXED), &[]),
+        DeErrorKind::InvalidMessageId(6));
+    assert_de_error_msg(
+        from_bytes::<UnencryptedMessage<Foo>>(&unencrypted_message_bytes(0, -4, 24, &FOO_SERIALIZED_BOXED), &[]),
+        DeErrorKind::InvalidMessageId(-4));
+
+    let mut bytes = unencrypted_message_bytes(0, 4, 28, &FOO_SERIALIZED_BOXED);
+    bytes.extend_from_slice(&[0, 0, 0, 0]);
+    assert_error_kind(from_bytes::<UnencryptedMessage<Foo>>(&bytes, &[]), DeErrorKind::SizeMismatch(28, 24));
+}
+
+#[test]
+fn test_unencrypted_message_ser_invalid_message_id() {
+    match *to_bytes(&UnencryptedMessage::new(0, &*FOO)).unwrap_err().kind() {
+        ErrorKind::Ser(SerErrorKind::Msg(ref msg)) => {
+            assert!(msg.contains(&SerErrorKind::InvalidMessageId(0).to_string()));
+        },
+        ref kind => panic!("unexpected error kind: {:?}", kind),
+    }
+}
+
 /// MTProto-serialized data must be aligned by 4 bytes.
 #[test]
 fn test_serialization_alignment() {
@@ -531,4 +1179,64 @@ fn test_serialization_alignment() {
     assert!(CAFEBABE_BLOB_SERIALIZED_BOXED.len() % 4 == 0);
     assert!(CAFEBABE_QUUX_SERIALIZED_BOXED.len() % 4 == 0);
     assert!(CAFEBABE_SPAM_SERIALIZED_BOXED.len() % 4 == 0);
+    assert!(CHANNEL_FULL_SERIALIZED_BARE.len() % 4 == 0);
+    assert!(CHANNEL_EMPTY_SERIALIZED_BOXED.len() % 4 == 0);
+    assert!(DIALOG_FOLDER_SERIALIZED_BOXED.len() % 4 == 0);
+    assert!(DIALOG_EMPTY_SERIALIZED_BOXED.len() % 4 == 0);
+    assert!(VECTOR_OF_LONGS_SERIALIZED_BARE.len() % 4 == 0);
+    assert!(VECTOR_OF_LONGS_SERIALIZED_BOXED.len() % 4 == 0);
+    assert!(VECTOR_OF_BOXED_C_LIKE_SERIALIZED_BOXED.len() % 4 == 0);
+}
+
+#[test]
+fn test_ids_from_tl_combinators() {
+    assert_eq!(ChannelForbidden::all_type_ids(), &[0x17d493d5]);
+    assert_eq!(FileHash::all_type_ids(), &[0xf39b035c]);
+    assert_eq!(Peer::all_type_ids(), &[0x59511722, 0x36c6019a, 0xa2a5371e]);
+    assert_eq!(Peer::Channel(1).type_id(), 0xa2a5371e);
+
+    let peer = Peer::User { user_id: 1 };
+    let serialized = to_bytes(&Boxed::new(peer)).unwrap();
+    assert_eq!(&serialized[..4], &[0x22, 0x17, 0x51, 0x59]);
+}
+
+#[test]
+fn test_flags_of_renamed_variant() {
+    let reaction = Reaction::CustomEmoji { flags: Flags::new(), big: true, document_id: 5 };
+    let serialized = to_bytes(&reaction).unwrap();
+
+    assert_eq!(serialized, [
+        0x02, 0, 0, 0,                              // flags with bit 1 set
+        5, 0, 0, 0, 0, 0, 0, 0,                     // 5 as little-endian 64-bit int
+    ]);
+    assert_eq!(from_bytes::<Reaction>(&serialized, &["reactionCustomEmoji"]).unwrap(), reaction);
+}
+
+#[test]
+fn test_renamed_enum_variant_from_bytes_boxed() {
+    assert_eq!(Reaction::all_enum_variant_names(), Some(&["reactionCustomEmoji"][..]));
+
+    let reaction = Reaction::CustomEmoji { flags: Flags::new(), big: false, document_id: 7 };
+    assert_eq!(reaction.enum_variant_id(), Some("reactionCustomEmoji"));
+
+    let serialized = to_bytes(&Boxed::new(&reaction)).unwrap();
+    assert_eq!(&serialized[..4], &[0x73, 0xfc, 0x35, 0x89]);
+
+    let deserialized: Boxed<Reaction> = from_bytes(&serialized, &[]).unwrap();
+    assert_eq!(deserialized.into_inner(), reaction);
+}
+
+#[test]
+fn test_flags_of_non_optional_field() {
+    let value = FlaggedNonOptional { flags: Flags::new(), inner: NonOptional { set: true } };
+
+    assert_error_kind(to_bytes(&value), SerErrorKind::FlaggedFieldNotOptional("inner"));
+
+    let bytes = [
+        0x01, 0, 0, 0,                              // flags with bit 0 set
+        0xb5, 0x75, 0x72, 0x99,                     // boolTrue
+    ];
+    assert_error_kind(
+        from_bytes::<FlaggedNonOptional>(&bytes, &[]),
+        DeErrorKind::FlaggedFieldNotOptional("inner"));
 }
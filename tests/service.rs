@@ -0,0 +1,216 @@
+//! Testing built-in service message types.
+
+mod common;
+
+use std::marker::PhantomData;
+
+use serde_derive::{Serialize, Deserialize};
+#[cfg(feature = "gzip")]
+use serde_mtproto::GzipPacked;
+use serde_mtproto::{Boxed, Identifiable, MtProtoSized, from_bytes, to_bytes};
+use serde_mtproto::de::DeserializerOptions;
+use serde_mtproto::error::DeErrorKind;
+use serde_mtproto::rpc::{call_to_bytes, return_from_bytes};
+use serde_mtproto::service::{
+    BadServerSalt, FutureSalt, FutureSalts, GetFutureSalts, Message, MsgContainer, MsgsAck, PendingRequests, Ping,
+    Pong, RpcError, RpcResponse, RpcResult,
+};
+use serde_mtproto_derive::{MtProtoIdentifiable, MtProtoSized};
+
+use crate::common::assert_error_kind;
+
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, MtProtoIdentifiable, MtProtoSized)]
+enum ServiceBody {
+    #[mtproto_identifiable(id = "0x347773c5")]
+    Pong(Pong),
+    #[mtproto_identifiable(id = "0x62d6b459")]
+    MsgsAck(MsgsAck),
+    #[mtproto_identifiable(id = "0xf35c6d01")]
+    RpcResult(RpcResult<Boxed<RpcError>>),
+}
+
+
+fn message<T>(msg_id: i64, seqno: i32, body: T) -> Message<T> {
+    Message { msg_id, seqno, body }
+}
+
+fn assert_ser_de<T>(value: &T)
+    where T: std::fmt::Debug + PartialEq + serde::Serialize + serde::de::DeserializeOwned
+        + Identifiable + MtProtoSized
+{
+    let bytes = to_bytes(&Boxed::new(value)).unwrap();
+    assert_eq!(bytes.len(), 4 + value.size_hint().unwrap());
+
+    let deserialized: Boxed<T> = from_bytes(&bytes, &[]).unwrap();
+    assert_eq!(deserialized.inner(), value);
+}
+
+
+#[test]
+fn test_message_layout() {
+    let pong = Boxed::new(Pong { msg_id: 2, ping_id: 3 });
+    let bytes = to_bytes(&message(0x0102_0304_0506_0708, 5, &pong)).unwrap();
+
+    let mut expected = to_bytes(&0x0102_0304_0506_0708_i64).unwrap();
+    expected.extend(to_bytes(&5_i32).unwrap());
+    expected.extend(to_bytes(&20_u32).unwrap());
+    expected.extend(to_bytes(&pong).unwrap());
+    assert_eq!(bytes, expected);
+
+    // `bytes` must match the body
+    let mut bad_len = expected.clone();
+    bad_len[12] = 24;
+    bad_len.extend(&[0, 0, 0, 0]);
+    assert!(from_bytes::<Message<Boxed<Pong>>>(&bad_len, &[]).is_err());
+}
+
+#[test]
+fn test_heterogeneous_container() {
+    let container = MsgContainer {
+        messages: vec![
+            message(0x5e0b_8000_0000_0001, 1, Boxed::new(ServiceBody::Pong(Pong { msg_id: 4, ping_id: 7 }))),
+            message(0x5e0b_8000_0000_0005, 2, Boxed::new(ServiceBody::MsgsAck(MsgsAck {
+                msg_ids: Boxed::new(vec![8, 12]),
+            }))),
+            message(0x5e0b_8000_0000_0009, 3, Boxed::new(ServiceBody::RpcResult(RpcResult {
+                req_msg_id: 16,
+                result: Boxed::new(RpcError { error_code: 420, error_message: "FLOOD_WAIT_30".to_owned() }),
+            }))),
+        ],
+    };
+
+    assert_ser_de(&container);
+}
+
+#[test]
+fn test_service_types() {
+    assert_ser_de(&BadServerSalt { bad_msg_id: 1, bad_msg_seqno: 2, error_code: 48, new_server_salt: -3 });
+    assert_ser_de(&FutureSalts {
+        req_msg_id: 4,
+        now: 1_500_000_000,
+        salts: vec![
+            FutureSalt { valid_since: 1_500_000_000, valid_until: 1_500_003_600, salt: 5 },
+            FutureSalt { valid_since: 1_500_003_600, valid_until: 1_500_007_200, salt: 6 },
+        ],
+    });
+}
+
+#[test]
+fn test_pending_requests() {
+    #[derive(Debug, PartialEq)]
+    enum Response {
+        Pong(Pong),
+        Salts(FutureSalts),
+    }
+
+    impl From<Boxed<Pong>> for Response {
+        fn from(pong: Boxed<Pong>) -> Response {
+            Response::Pong(pong.into_inner())
+        }
+    }
+
+    impl From<Boxed<FutureSalts>> for Response {
+        fn from(salts: Boxed<FutureSalts>) -> Response {
+            Response::Salts(salts.into_inner())
+        }
+    }
+
+    fn rpc_result<T: serde::Serialize + Identifiable>(req_msg_id: i64, result: T) -> Vec<u8> {
+        to_bytes(&Boxed::new(RpcResult { req_msg_id, result: Boxed::new(result) })).unwrap()
+    }
+
+    let pong = Pong { msg_id: 4, ping_id: 7 };
+    let salts = FutureSalts { req_msg_id: 8, now: 1_500_000_000, salts: vec![] };
+
+    let mut pending = PendingRequests::<Response>::new();
+    assert!(!pending.insert::<Boxed<Pong>>(4));
+    assert!(!pending.insert::<Boxed<FutureSalts>>(8));
+    assert!(!pending.insert::<Boxed<Pong>>(12));
+    assert_eq!(pending.len(), 3);
+
+    // Results may arrive in any order
+    let response = pending.decode(&rpc_result(8, &salts)).unwrap();
+    assert_eq!(response.req_msg_id, 8);
+    assert_eq!(response.result, RpcResponse::Decoded(Response::Salts(salts)));
+
+    let response = pending.decode(&rpc_result(4, &pong)).unwrap();
+    assert_eq!(response.result, RpcResponse::Decoded(Response::Pong(pong.clone())));
+    assert!(!pending.contains(4));
+
+    // Unknown queries are returned raw
+    let response = pending.decode(&rpc_result(4, &pong)).unwrap();
+    assert_eq!(response.result, RpcResponse::Raw(to_bytes(&Boxed::new(&pong)).unwrap()));
+
+    let rpc_error = RpcError { error_code: 400, error_message: "MSG_ID_INVALID".to_owned() };
+    let response = pending.decode(&rpc_result(12, &rpc_error)).unwrap();
+    assert_eq!(response.result, RpcResponse::Error(rpc_error));
+    assert!(pending.is_empty());
+
+    assert!(pending.decode(&to_bytes(&Boxed::new(&pong)).unwrap()).is_err());
+}
+
+#[test]
+fn test_pending_requests_seed() {
+    let mut pending = PendingRequests::new();
+    let _ = pending.insert_seed(4, PhantomData::<Boxed<Pong>>);
+
+    let bytes = to_bytes(&Boxed::new(RpcResult { req_msg_id: 4, result: 0xdead_beef_u32 })).unwrap();
+    assert!(pending.decode(&bytes).is_err());
+    // The decoder is used up even though the result failed to decode
+    assert!(pending.is_empty());
+}
+
+#[cfg(feature = "gzip")]
+#[test]
+fn test_pending_requests_gzip_packed() {
+    let pong = Boxed::new(Pong { msg_id: 4, ping_id: 7 });
+    let packed = GzipPacked::with_compression_threshold(Pong { msg_id: 4, ping_id: 7 }, 0).unwrap();
+    let bytes = to_bytes(&Boxed::new(RpcResult { req_msg_id: 4, result: &packed })).unwrap();
+    assert_ne!(bytes, to_bytes(&Boxed::new(RpcResult { req_msg_id: 4, result: &pong })).unwrap());
+
+    let mut pending = PendingRequests::<Boxed<Pong>>::new();
+    let _ = pending.insert_call::<Ping>(4);
+    assert_eq!(pending.decode(&bytes).unwrap().result, RpcResponse::Decoded(pong.clone()));
+    assert_eq!(pending.decode(&bytes).unwrap().result, RpcResponse::Raw(to_bytes(&pong).unwrap()));
+
+    // Packed data counts toward the total bytes limit before it's unpacked
+    let mut pending = PendingRequests::<Boxed<Pong>>::with_options(DeserializerOptions::new().max_total_bytes(20));
+    let _ = pending.insert_call::<Ping>(4);
+    assert_error_kind(pending.decode(&bytes), DeErrorKind::TotalBytesLimitExceeded(20));
+}
+
+#[test]
+fn test_pending_requests_options() {
+    let numbers = Boxed::new(vec![1, 2, 3]);
+    let bytes = to_bytes(&Boxed::new(RpcResult { req_msg_id: 4, result: &numbers })).unwrap();
+
+    let mut pending = PendingRequests::<Boxed<Vec<i32>>>::new();
+    let _ = pending.insert::<Boxed<Vec<i32>>>(4);
+    assert_eq!(pending.decode(&bytes).unwrap().result, RpcResponse::Decoded(numbers));
+
+    let mut pending = PendingRequests::<Boxed<Vec<i32>>>::with_options(DeserializerOptions::new().max_vector_len(2));
+    let _ = pending.insert::<Boxed<Vec<i32>>>(4);
+    assert_error_kind(pending.decode(&bytes), DeErrorKind::SeqLenLimitExceeded(3, 2));
+}
+
+#[test]
+fn test_remote_calls() {
+    let ping = Ping { ping_id: 7 };
+    assert_ser_de(&ping);
+    assert_ser_de(&GetFutureSalts { num: 2 });
+
+    assert_eq!(call_to_bytes(&ping).unwrap(), vec![
+        0xec, 0x77, 0xbe, 0x7a,         // id of ping
+        7, 0, 0, 0, 0, 0, 0, 0,         // ping_id
+    ]);
+
+    let pong = Boxed::new(Pong { msg_id: 4, ping_id: 7 });
+    assert_eq!(return_from_bytes::<Ping>(&to_bytes(&pong).unwrap()).unwrap(), pong);
+
+    let mut pending = PendingRequests::<Boxed<Pong>>::new();
+    assert!(!pending.insert_call::<Ping>(4));
+
+    let bytes = to_bytes(&Boxed::new(RpcResult { req_msg_id: 4, result: &pong })).unwrap();
+    assert_eq!(pending.decode(&bytes).unwrap().result, RpcResponse::Decoded(pong));
+}
@@ -0,0 +1,102 @@
+//! Testing seqno assignment and acknowledgment tracking.
+
+use std::time::Duration;
+
+use serde_mtproto::Boxed;
+use serde_mtproto::service::{
+    Message, MsgContainer, MsgsAck, Pong, RpcError, MSG_CONTAINER_ID, MSGS_ACK_ID, PONG_ID,
+};
+use serde_mtproto::session::{Session, is_content_related};
+
+
+const NOW_SECS: u64 = 1_500_000_000;
+
+
+fn new_session() -> Session<impl Fn() -> Duration> {
+    Session::with_clock(0x5e55_1011, || Duration::from_secs(NOW_SECS))
+}
+
+fn pong(ping_id: i64) -> Boxed<Pong> {
+    Boxed::new(Pong { msg_id: 0x5e0b_8000_0000_0004, ping_id })
+}
+
+
+#[test]
+fn test_is_content_related() {
+    assert!(is_content_related(PONG_ID));
+    assert!(!is_content_related(MSGS_ACK_ID));
+    assert!(!is_content_related(MSG_CONTAINER_ID));
+}
+
+#[test]
+fn test_seqno() {
+    let mut session = new_session();
+
+    let first = session.pack(pong(1));
+    let ack = session.pack(Boxed::new(MsgsAck { msg_ids: Boxed::new(vec![1]) }));
+    let second = session.pack(pong(2));
+    let container = session.pack(Boxed::new(MsgContainer { messages: vec![first.clone(), second.clone()] }));
+    let third = session.pack_as(RpcError { error_code: 400, error_message: "TEST".to_owned() }, false);
+    let fourth = session.pack(pong(3));
+
+    let seqnos = [first.seqno, ack.seqno, second.seqno, container.seqno, third.seqno, fourth.seqno];
+    assert_eq!(seqnos, [1, 2, 3, 4, 4, 5]);
+
+    let msg_ids = [first.msg_id, ack.msg_id, second.msg_id, container.msg_id, third.msg_id, fourth.msg_id];
+    assert!(msg_ids.windows(2).all(|pair| pair[0] < pair[1]));
+    assert!(msg_ids.iter().all(|msg_id| msg_id % 4 == 0));
+
+    session.reset(0x5e55_1012);
+    assert_eq!(session.session_id(), 0x5e55_1012);
+    assert_eq!(session.pack(pong(4)).seqno, 1);
+}
+
+#[test]
+fn test_awaiting_ack() {
+    let mut session = new_session();
+
+    let Message { msg_id: first_id, .. } = session.pack(pong(1));
+    let Message { msg_id: second_id, .. } = session.pack(pong(2));
+    let Message { msg_id: ack_id, .. } = session.pack(Boxed::new(MsgsAck { msg_ids: Boxed::new(vec![]) }));
+    assert_eq!(session.awaiting_ack(), [first_id, second_id]);
+    assert!(!session.is_awaiting_ack(ack_id));
+
+    session.handle_ack(&MsgsAck { msg_ids: Boxed::new(vec![first_id, 42]) });
+    assert_eq!(session.awaiting_ack(), [second_id]);
+
+    // A response acknowledges the query as well
+    assert!(session.acknowledge(second_id));
+    assert!(!session.acknowledge(second_id));
+    assert!(session.awaiting_ack().is_empty());
+}
+
+#[test]
+fn test_take_ack() {
+    let mut session = new_session();
+    assert_eq!(session.take_ack(), None);
+
+    session.receive(0x5e0b_8000_0000_0005, 1);
+    session.receive(0x5e0b_8000_0000_0001, 3);
+    // Containers and acknowledgments are not acknowledged
+    session.receive(0x5e0b_8000_0000_0009, 4);
+    assert_eq!(session.pending_ack_count(), 2);
+
+    let ack = session.take_ack().unwrap();
+    assert_eq!(*ack.msg_ids.inner(), [0x5e0b_8000_0000_0001, 0x5e0b_8000_0000_0005]);
+    assert_eq!(session.pending_ack_count(), 0);
+    assert_eq!(session.take_ack(), None);
+}
+
+#[cfg(feature = "crypto")]
+#[test]
+fn test_pack_plain() {
+    let mut session = new_session();
+    session.message_ids_mut().set_time_offset(10);
+
+    let plain_message = session.pack_plain(0x5a17, pong(1));
+    assert_eq!(plain_message.salt, 0x5a17);
+    assert_eq!(plain_message.session_id, 0x5e55_1011);
+    assert_eq!(plain_message.message_id >> 32, NOW_SECS as i64 + 10);
+    assert_eq!(plain_message.seq_no, 1);
+    assert!(session.is_awaiting_ack(plain_message.message_id));
+}
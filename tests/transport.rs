@@ -0,0 +1,198 @@
+//! Testing transport framing codecs.
+
+mod common;
+
+use std::io::Cursor;
+
+#[cfg(feature = "rand")]
+use rand::{SeedableRng, rngs::StdRng};
+use serde_mtproto::error::{ErrorKind, TransportErrorKind};
+#[cfg(feature = "rand")]
+use serde_mtproto::transport::PaddedIntermediate;
+use serde_mtproto::transport::{Abridged, Decoded, Full, Intermediate, Transport};
+
+use crate::common::assert_error_kind;
+
+
+fn payload(len: usize) -> Vec<u8> {
+    (0..len).map(|i| i as u8).collect()
+}
+
+fn decode_complete<T: Transport>(transport: &mut T, buf: &[u8]) -> (Vec<u8>, usize) {
+    match transport.decode_frame(buf).unwrap() {
+        Decoded::Frame { payload, consumed } => (payload, consumed),
+        Decoded::Incomplete { needed } => panic!("incomplete frame, {} bytes needed", needed),
+    }
+}
+
+
+#[test]
+fn test_tags() {
+    assert_eq!(Abridged::new().tag(), [0xef]);
+    assert_eq!(Intermediate::new().tag(), [0xee, 0xee, 0xee, 0xee]);
+    assert_eq!(Full::new().tag(), []);
+}
+
+#[cfg(feature = "rand")]
+#[test]
+fn test_padded_intermediate_tag() {
+    assert_eq!(PaddedIntermediate::new().tag(), [0xdd, 0xdd, 0xdd, 0xdd]);
+}
+
+#[test]
+fn test_abridged() {
+    let mut transport = Abridged::new();
+
+    let mut short = Vec::new();
+    transport.encode_frame(&payload(8), &mut short).unwrap();
+    assert_eq!(short[0], 2);
+    assert_eq!(decode_complete(&mut transport, &short), (payload(8), 9));
+
+    let mut long = Vec::new();
+    transport.encode_frame(&payload(0x7f * 4), &mut long).unwrap();
+    assert_eq!(long[0..4], [0x7f, 0x7f, 0, 0]);
+    assert_eq!(decode_complete(&mut transport, &long), (payload(0x7f * 4), 4 + 0x7f * 4));
+
+    let mut buf = Vec::new();
+    assert!(transport.encode_frame(&payload(7), &mut buf).is_err());
+}
+
+#[test]
+fn test_intermediate() {
+    let mut transport = Intermediate::new();
+
+    let mut buf = Vec::new();
+    transport.encode_frame(&payload(7), &mut buf).unwrap();
+    assert_eq!(buf[0..4], [7, 0, 0, 0]);
+    assert_eq!(decode_complete(&mut transport, &buf), (payload(7), 11));
+}
+
+#[cfg(feature = "rand")]
+#[test]
+fn test_padded_intermediate() {
+    let mut transport = PaddedIntermediate::new();
+
+    for _ in 0..32 {
+        let mut buf = Vec::new();
+        transport.encode_frame(&payload(20), &mut buf).unwrap();
+        assert!(buf.len() >= 4 + 20 && buf.len() < 4 + 20 + 16);
+
+        let (received, consumed) = decode_complete(&mut transport, &buf);
+        assert_eq!(received[..20], payload(20)[..]);
+        assert_eq!(consumed, buf.len());
+    }
+}
+
+#[cfg(feature = "rand")]
+#[test]
+fn test_padded_intermediate_with_rng() {
+    let mut first = PaddedIntermediate::with_rng(StdRng::seed_from_u64(42));
+    let mut second = PaddedIntermediate::with_rng(StdRng::seed_from_u64(42));
+
+    for _ in 0..8 {
+        let mut first_buf = Vec::new();
+        first.encode_frame(&payload(20), &mut first_buf).unwrap();
+        let mut second_buf = Vec::new();
+        second.encode_frame(&payload(20), &mut second_buf).unwrap();
+
+        assert_eq!(first_buf, second_buf);
+    }
+}
+
+#[test]
+fn test_full() {
+    let mut client = Full::new();
+    let mut server = Full::new();
+
+    let mut buf = Vec::new();
+    client.encode_frame(&payload(8), &mut buf).unwrap();
+    client.encode_frame(&payload(12), &mut buf).unwrap();
+    assert_eq!(client.send_seq_no(), 2);
+
+    // len = 8 + 12, seqno = 0, CRC32 of the first 16 bytes
+    assert_eq!(buf[0..8], [20, 0, 0, 0, 0, 0, 0, 0]);
+    assert_eq!(buf[16..20], [0xba, 0x8e, 0x25, 0x33]);
+
+    let (first, consumed) = decode_complete(&mut server, &buf);
+    assert_eq!((first, consumed), (payload(8), 20));
+    assert_eq!(decode_complete(&mut server, &buf[consumed..]), (payload(12), 24));
+    assert_eq!(server.recv_seq_no(), 2);
+}
+
+#[test]
+fn test_full_bad_crc() {
+    let mut buf = Vec::new();
+    Full::new().encode_frame(&payload(8), &mut buf).unwrap();
+    buf[10] ^= 0xff;
+
+    match Full::new().decode_frame(&buf).unwrap_err().kind() {
+        ErrorKind::Transport(TransportErrorKind::Crc32Mismatch(_, 0x33258eba)) => (),
+        kind => panic!("unexpected error kind: {:?}", kind),
+    }
+}
+
+#[test]
+fn test_full_out_of_order() {
+    let mut client = Full::new();
+    let mut buf = Vec::new();
+    client.encode_frame(&payload(8), &mut Vec::new()).unwrap();
+    client.encode_frame(&payload(8), &mut buf).unwrap();
+
+    assert_error_kind(Full::new().decode_frame(&buf), TransportErrorKind::SeqNoMismatch(0, 1));
+}
+
+#[test]
+fn test_full_bad_length() {
+    assert_error_kind(
+        Full::new().decode_frame(&[8, 0, 0, 0]),
+        TransportErrorKind::InvalidFrameLen(8));
+    assert_error_kind(
+        Full::new().decode_frame(&[14, 0, 0, 0]),
+        TransportErrorKind::InvalidFrameLen(14));
+    assert_error_kind(
+        Full::with_max_frame_len(4).decode_frame(&[20, 0, 0, 0]),
+        TransportErrorKind::FrameTooLong(8, 4));
+}
+
+#[test]
+fn test_frame_too_long() {
+    assert_error_kind(
+        Intermediate::with_max_frame_len(16).decode_frame(&[17, 0, 0, 0]),
+        TransportErrorKind::FrameTooLong(17, 16));
+    assert_error_kind(
+        Abridged::with_max_frame_len(16).decode_frame(&[0x7f, 0, 1, 0]),
+        TransportErrorKind::FrameTooLong(1024, 16));
+}
+
+#[test]
+fn test_incomplete() {
+    let mut buf = Vec::new();
+    Intermediate::new().encode_frame(&payload(8), &mut buf).unwrap();
+
+    let mut transport = Intermediate::new();
+    assert_eq!(transport.decode_frame(&buf[..2]).unwrap(), Decoded::Incomplete { needed: 4 });
+    assert_eq!(transport.decode_frame(&buf[..6]).unwrap(), Decoded::Incomplete { needed: 12 });
+    assert_eq!(Abridged::new().decode_frame(&[]).unwrap(), Decoded::Incomplete { needed: 1 });
+}
+
+#[test]
+fn test_server_error() {
+    // -404 as an intermediate transport frame
+    assert_error_kind(
+        Intermediate::new().decode_frame(&[4, 0, 0, 0, 0x6c, 0xfe, 0xff, 0xff]),
+        TransportErrorKind::ServerError(-404));
+}
+
+#[test]
+fn test_read_write_frame() {
+    let mut client = Full::new();
+    let mut stream = Vec::new();
+    client.write_frame(&mut stream, &payload(16)).unwrap();
+    client.write_frame(&mut stream, &payload(4)).unwrap();
+
+    let mut server = Full::new();
+    let mut reader = Cursor::new(stream);
+    assert_eq!(server.read_frame(&mut reader).unwrap(), payload(16));
+    assert_eq!(server.read_frame(&mut reader).unwrap(), payload(4));
+    assert!(server.read_frame(&mut reader).is_err());
+}
@@ -5,7 +5,7 @@ use quickcheck_derive::Arbitrary;
 use rand::Rng;
 use serde_derive::{Serialize, Deserialize};
 //use serde_mtproto::ByteBuf;
-use serde_mtproto::{Boxed, Identifiable, WithSize};
+use serde_mtproto::{Boxed, Identifiable, Int128, Int256, WithSize};
 use serde_mtproto_derive::{MtProtoIdentifiable, MtProtoSized};
 
 
@@ -55,6 +55,13 @@ quickcheck! {
         de == data
     }
 
+    fn int128_int256_ser_de_reversible(data: (Int128, Int256)) -> bool {
+        let ser = serde_mtproto::to_bytes(&data).unwrap();
+        let de = serde_mtproto::from_bytes::<(Int128, Int256)>(&ser, &[]).unwrap();
+
+        ser.len() == 48 && de == data
+    }
+
     fn de_ser_reversible(byte_buf: Vec<u8>) -> TestResult {
         if let Ok(de) = serde_mtproto::from_bytes::<SimpleStruct>(&byte_buf, &[]) {
             let ser = serde_mtproto::to_bytes(&de).unwrap();